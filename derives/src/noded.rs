@@ -12,6 +12,8 @@ pub fn noded_inner(ast: &DeriveInput) -> syn::Result<TokenStream> {
     let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
     let mut subscription_methods = None;
     let mut resets = Vec::new();
+    let mut sets = Vec::new();
+    let mut animations = Vec::new();
     let mut input_index: usize = 0;
 
     for field in fields.iter() {
@@ -20,6 +22,10 @@ pub fn noded_inner(ast: &DeriveInput) -> syn::Result<TokenStream> {
                 if let Some(type_ident) = path.segments.last().map(|segment| &segment.ident) {
                     if *type_ident == format_ident!("NodePin") {
                         resets.push(quote! { #input_index => self.#field_ident.reset() });
+                        sets.push(quote! {
+                            #input_index => crate::node::evaluator::apply_output(&mut self.#field_ident, output)
+                        });
+                        animations.push(quote! { changed |= self.#field_ident.animate(time) });
                         input_index += 1;
                     }
 
@@ -56,6 +62,33 @@ pub fn noded_inner(ast: &DeriveInput) -> syn::Result<TokenStream> {
         })
     };
 
+    let set_input_method = if sets.is_empty() {
+        None
+    } else {
+        Some(quote! {
+            #[inline]
+            fn set_input(&mut self, input: usize, output: &crate::node::evaluator::NodeOutput) -> bool {
+                match input {
+                    #(#sets,)*
+                    _ => false,
+                }
+            }
+        })
+    };
+
+    let animate_method = if animations.is_empty() {
+        None
+    } else {
+        Some(quote! {
+            #[inline]
+            fn animate(&mut self, time: f32) -> bool {
+                let mut changed = false;
+                #(#animations;)*
+                changed
+            }
+        })
+    };
+
     Ok(quote! {
         impl #impl_generics Noded for #struct_name #ty_generics #where_clause {
             #[inline]
@@ -74,6 +107,8 @@ pub fn noded_inner(ast: &DeriveInput) -> syn::Result<TokenStream> {
             }
 
             #reset_input_method
+            #set_input_method
+            #animate_method
             #subscription_methods
         }
     })
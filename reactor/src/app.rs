@@ -12,7 +12,9 @@ use egui_snarl::ui::SnarlWidget;
 use serde::{Deserialize, Serialize};
 
 use crate::node::Node;
+use crate::node::item::texture::texture_cache;
 use crate::node::viewer::NodeViewer;
+use crate::sequence::{SequenceRender, SequenceRenderError, find_render_node};
 use crate::settings::{AppSettings, EditMode};
 use crate::tabs::Tab;
 
@@ -24,12 +26,15 @@ pub enum UiIdKey {
     EditingArea,
     OverlayArea,
     OverlayBlocker,
+    GizmoArea,
 }
 
 pub struct AppContext {
     settings: AppSettings,
     snarl: Snarl<Node>,
     viewer: NodeViewer,
+    /// Animation render in progress, it renders a frame every update.
+    sequence: Option<SequenceRender>,
 }
 
 impl AppContext {
@@ -44,6 +49,89 @@ impl AppContext {
         self.settings.edit_modes.remove(tab.title());
         self.viewer.close_tab(tab, &mut self.snarl);
     }
+
+    fn show_sequence_render(&mut self, ui: &mut egui::Ui) {
+        let Some(sequence) = &self.sequence else {
+            self.settings.sequence.show(ui);
+            if ui.button("Render animation").clicked() {
+                self.start_sequence();
+            }
+            return;
+        };
+
+        let (rendered, total) = sequence.progress();
+        let cancel = ui
+            .horizontal(|ui| {
+                let cancel = ui.button("Cancel").clicked();
+                ui.add(
+                    egui::ProgressBar::new(rendered as f32 / total as f32).text(format!("Frame {rendered} of {total}")),
+                );
+                cancel
+            })
+            .inner;
+        if cancel {
+            self.finish_sequence();
+        }
+    }
+
+    fn start_sequence(&mut self) {
+        let render_state = self.viewer.render_state();
+        let sequence = find_render_node(&self.snarl)
+            .ok_or(SequenceRenderError::NoRenderNode)
+            .and_then(|render_id| {
+                SequenceRender::new(
+                    &render_state.device,
+                    &render_state.queue,
+                    render_id,
+                    self.settings.timeline.frame_range(),
+                    &self.settings.sequence,
+                    &mut self.snarl,
+                )
+            });
+        match sequence {
+            Ok(sequence) => self.sequence = Some(sequence),
+            Err(err) => tracing::error!("Failed to render the animation: {err}"),
+        }
+    }
+
+    /// Moves the render of the next frame of the animation along, once the graph is animated at that frame.
+    fn render_sequence_frame(&mut self, ctx: &egui::Context, animated_frame: Option<u32>) {
+        let Some(sequence) = &mut self.sequence else {
+            return;
+        };
+        ctx.request_repaint();
+        // A render started during this update didn't animate the graph yet.
+        if sequence.next_frame() != animated_frame {
+            return;
+        }
+
+        let render_state = self.viewer.render_state();
+        let device = &render_state.device;
+        match sequence.render_step(device, &render_state.queue, &mut self.snarl) {
+            Ok(_) if sequence.next_frame().is_some() => (),
+            Ok(_) => self.finish_sequence(),
+            Err(err) => {
+                tracing::error!("Failed to render the animation: {err}");
+                self.finish_sequence();
+            },
+        }
+    }
+
+    fn finish_sequence(&mut self) {
+        let Some(sequence) = self.sequence.take() else {
+            return;
+        };
+        let directory = sequence.directory().to_owned();
+        match sequence.finish(&mut self.snarl) {
+            Ok(Some(video)) => tracing::info!(
+                "Rendered the animation to {} and {}",
+                directory.display(),
+                video.display()
+            ),
+            Ok(None) => tracing::info!("Rendered the animation to {}", directory.display()),
+            Err(err) => tracing::error!("Failed to finish the animation video: {err}"),
+        }
+    }
 }
 
 impl TabViewer for AppContext {
@@ -95,10 +183,13 @@ impl TabViewer for AppContext {
                     };
                     editing_area_ui.set_opacity(opacity);
 
-                    SnarlWidget::new()
-                        .id(tab.id(UiIdKey::Nodes))
-                        .style(self.settings.snarl_style)
-                        .show(&mut self.snarl, &mut self.viewer, &mut editing_area_ui);
+                    self.viewer.with_palette_settings(&mut self.settings.palette, |viewer| {
+                        SnarlWidget::new()
+                            .id(tab.id(UiIdKey::Nodes))
+                            .style(self.settings.snarl_style)
+                            .show(&mut self.snarl, viewer, &mut editing_area_ui);
+                    });
+                    self.viewer.open_palette_on_shortcut(ui, last_panel_rect);
                 }
 
                 if let Some(EditMode::View) = self.settings.edit_modes.get(tab.title()) {
@@ -117,12 +208,35 @@ impl TabViewer for AppContext {
                             self.viewer.after_show(tab, ui, &overlay_response, &mut self.snarl);
                         });
                 }
+
+                // Gizmos of the selected node on the top of everything
+                egui::Area::new(tab.id(UiIdKey::GizmoArea))
+                    .fixed_pos(last_panel_rect.min)
+                    .order(Order::Foreground)
+                    .show(ui.ctx(), |ui| {
+                        self.viewer.show_gizmo(tab, ui, last_panel_rect, &mut self.snarl);
+                    });
             },
             Tab::Settings(_) => {
                 egui::ScrollArea::vertical().show(ui, |ui| {
                     egui_probe::Probe::new(&mut self.settings).show(ui);
                 });
             },
+            Tab::History(_) => {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.viewer.show_history(ui, &mut self.snarl);
+                });
+            },
+            Tab::Timeline(_) => {
+                self.settings.timeline.show(ui);
+                ui.separator();
+                self.show_sequence_render(ui);
+            },
+            Tab::Problems(_) => {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.viewer.show_problems(ui, &mut self.snarl);
+                });
+            },
         }
     }
 
@@ -191,6 +305,7 @@ impl ReactorApp {
                 settings,
                 snarl,
                 viewer,
+                sequence: None,
             },
             render_state,
             tabs_tree,
@@ -237,6 +352,7 @@ impl ReactorApp {
             settings,
             snarl,
             viewer,
+            sequence: None,
         };
         self.tabs_tree = tabs_tree;
         self.active_project_dir = Some(path);
@@ -281,6 +397,23 @@ impl App for ReactorApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.file_dialog.update(ctx);
 
+        self.ctx.viewer.poll_textures(&mut self.ctx.snarl);
+        self.ctx.viewer.handle_shortcuts(ctx, &mut self.ctx.snarl);
+        // The graph is animated at the frame which the animation render needs next.
+        let sequence_frame = self.ctx.sequence.as_ref().and_then(SequenceRender::next_frame);
+        if let Some(time) = self.ctx.sequence.as_ref().and_then(SequenceRender::next_time) {
+            self.ctx.settings.timeline.seek(time);
+        }
+        self.ctx.settings.timeline.advance(ctx);
+        self.ctx
+            .viewer
+            .animate(self.ctx.settings.timeline.time(), &mut self.ctx.snarl);
+        self.ctx.viewer.evaluate(&mut self.ctx.snarl);
+        self.ctx.viewer.validate(&self.ctx.snarl);
+        if texture_cache().is_loading() {
+            ctx.request_repaint();
+        }
+
         if let Some(path) = self.file_dialog.take_picked() {
             match self.file_dialog_mode.take() {
                 Some(mode) => {
@@ -324,7 +457,13 @@ impl App for ReactorApp {
                 ui.add_space(16.0);
 
                 ui.menu_button("View", |ui| {
-                    for mut tab in [Tab::new_viwport(), Tab::new_settings()] {
+                    for mut tab in [
+                        Tab::new_viwport(),
+                        Tab::new_settings(),
+                        Tab::new_history(),
+                        Tab::new_timeline(),
+                        Tab::new_problems(),
+                    ] {
                         if ui.button(tab.title()).clicked() {
                             while self.tabs_tree.find_tab(&tab).is_some() {
                                 tab.increment_title();
@@ -367,6 +506,14 @@ impl App for ReactorApp {
                 .secondary_button_context_menu(self.ctx.settings.tabs.secondary_button_context_menu)
                 .show_inside(ui, &mut self.ctx);
         });
+
+        self.ctx
+            .viewer
+            .with_palette_settings(&mut self.ctx.settings.palette, |viewer| {
+                viewer.show_palette_popup(ctx, &mut self.ctx.snarl);
+            });
+
+        self.ctx.render_sequence_frame(ctx, sequence_frame);
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
@@ -401,6 +548,6 @@ fn max_viewport_resolution(ctx: &egui::Context) -> u32 {
     max_viewport_resolution
 }
 
-fn storage_dir() -> Option<PathBuf> {
+pub(crate) fn storage_dir() -> Option<PathBuf> {
     ProjectDirs::from("free", "reactor", "reactor").map(|dirs| dirs.data_dir().to_path_buf())
 }
@@ -0,0 +1,213 @@
+//! Subcommands which run without the window.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use eframe::wgpu;
+use egui_snarl::{NodeId, Snarl};
+use thiserror::Error;
+use xrays::sequence::{FrameRange, ImageFormat, VideoFormat};
+
+use crate::node::Node;
+use crate::node::evaluator::Evaluator;
+use crate::sequence::{SequenceRender, SequenceRenderError, find_render_node};
+use crate::settings::AppSettings;
+
+pub const RENDER_ANIMATION: &str = "render-animation";
+
+const USAGE: &str = "usage: reactor render-animation <project directory> [--node <id>] [--start <seconds>] \
+                     [--end <seconds>] [--fps <frames>] [--output <directory>] [--format png|exr] [--video gif|y4m]";
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("{0}\n{USAGE}")]
+    Usage(String),
+    #[error("no graphics adapter is available")]
+    NoAdapter,
+    #[error(transparent)]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+    #[error("failed to read the project: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to parse the project: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Render(#[from] SequenceRenderError),
+}
+
+/// Arguments of the animation render, the options which are left out come from the project.
+#[derive(Debug, Default, PartialEq)]
+struct RenderAnimationArgs {
+    project: PathBuf,
+    node: Option<NodeId>,
+    start: Option<f32>,
+    end: Option<f32>,
+    fps: Option<u32>,
+    output: Option<String>,
+    image_format: Option<ImageFormat>,
+    video_format: Option<VideoFormat>,
+}
+
+impl RenderAnimationArgs {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut parsed = Self::default();
+        let mut project = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| CliError::Usage(format!("{arg} needs a value")))
+            };
+            match arg.as_str() {
+                "--node" => parsed.node = Some(NodeId(parse_value(arg, value()?)?)),
+                "--start" => parsed.start = Some(parse_value(arg, value()?)?),
+                "--end" => parsed.end = Some(parse_value(arg, value()?)?),
+                "--fps" => parsed.fps = Some(parse_value(arg, value()?)?),
+                "--output" => parsed.output = Some(value()?.to_owned()),
+                "--format" => {
+                    let value = value()?;
+                    let format = ImageFormat::ALL.into_iter().find(|format| format.extension() == value);
+                    parsed.image_format = Some(format.ok_or_else(|| invalid_value(arg, value))?);
+                },
+                "--video" => {
+                    let value = value()?;
+                    let format = VideoFormat::ALL.into_iter().find(|format| format.extension() == value);
+                    parsed.video_format = Some(format.ok_or_else(|| invalid_value(arg, value))?);
+                },
+                option if option.starts_with("--") => {
+                    return Err(CliError::Usage(format!("unknown option {option}")));
+                },
+                path if project.is_none() => project = Some(PathBuf::from(path)),
+                other => return Err(CliError::Usage(format!("unexpected argument `{other}`"))),
+            }
+        }
+
+        parsed.project = project.ok_or_else(|| CliError::Usage("the project directory is missing".to_string()))?;
+        Ok(parsed)
+    }
+}
+
+fn parse_value<T: FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| invalid_value(option, value))
+}
+
+fn invalid_value(option: &str, value: &str) -> CliError {
+    CliError::Usage(format!("invalid value `{value}` of {option}"))
+}
+
+/// Renders the animation of a saved project frame by frame, with the timeline and the animation render settings of
+/// the project unless the arguments override them.
+pub fn render_animation(args: &[String]) -> Result<(), CliError> {
+    let args = RenderAnimationArgs::parse(args)?;
+
+    let mut snarl: Snarl<Node> = serde_json::from_str(&fs::read_to_string(args.project.join("snarl.json"))?)?;
+    let settings = match fs::read_to_string(args.project.join("settings.json")) {
+        Ok(content) => serde_json::from_str(&content)?,
+        Err(_) => AppSettings::default(),
+    };
+
+    let timeline_range = settings.timeline.frame_range();
+    let range = FrameRange {
+        start: args.start.unwrap_or(timeline_range.start),
+        end: args.end.unwrap_or(timeline_range.end),
+        fps: args.fps.unwrap_or(timeline_range.fps),
+    };
+    let mut sequence_settings = settings.sequence;
+    if let Some(output) = args.output {
+        sequence_settings.directory = output;
+    }
+    if let Some(image_format) = args.image_format {
+        sequence_settings.image_format = image_format;
+    }
+    if args.video_format.is_some() {
+        sequence_settings.video_format = args.video_format;
+    }
+
+    // The render node takes its camera from the evaluated graph.
+    let mut evaluator = Evaluator::default();
+    evaluate_at(range.start, &mut evaluator, &mut snarl);
+    let render_id = args
+        .node
+        .or_else(|| find_render_node(&snarl))
+        .ok_or(SequenceRenderError::NoRenderNode)?;
+
+    let (device, queue) = request_device()?;
+    let mut sequence = SequenceRender::new(&device, &queue, render_id, range, &sequence_settings, &mut snarl)?;
+    while let Some(time) = sequence.next_time() {
+        evaluate_at(time, &mut evaluator, &mut snarl);
+        sequence.render_frame(&device, &queue, &mut snarl)?;
+
+        let (rendered, total) = sequence.progress();
+        tracing::info!("Rendered frame {rendered} of {total}");
+    }
+
+    let directory = sequence.directory().to_owned();
+    match sequence.finish(&mut snarl)? {
+        Some(video) => tracing::info!(
+            "Rendered the animation to {} and {}",
+            directory.display(),
+            video.display()
+        ),
+        None => tracing::info!("Rendered the animation to {}", directory.display()),
+    }
+    Ok(())
+}
+
+fn evaluate_at(time: f32, evaluator: &mut Evaluator, snarl: &mut Snarl<Node>) {
+    evaluator.animate(time, snarl);
+    if let Err(err) = evaluator.evaluate(snarl) {
+        tracing::warn!("Failed to evaluate the graph at {time} s: {err}");
+    }
+}
+
+fn request_device() -> Result<(wgpu::Device, wgpu::Queue), CliError> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        ..Default::default()
+    }))
+    .ok_or(CliError::NoAdapter)?;
+
+    Ok(pollster::block_on(
+        adapter.request_device(&crate::device_descriptor(&adapter), None),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_render_animation_args() {
+        let parsed = RenderAnimationArgs::parse(&args(&[
+            "project", "--end", "2.5", "--fps", "30", "--format", "exr", "--video", "y4m",
+        ]))
+        .unwrap();
+        assert_eq!(parsed, RenderAnimationArgs {
+            project: PathBuf::from("project"),
+            end: Some(2.5),
+            fps: Some(30),
+            image_format: Some(ImageFormat::Exr),
+            video_format: Some(VideoFormat::Y4m),
+            ..Default::default()
+        });
+
+        for invalid in [
+            &["--fps", "30"][..],
+            &["project", "--fps"],
+            &["project", "--video", "mp4"],
+            &["project", "other"],
+        ] {
+            assert!(matches!(
+                RenderAnimationArgs::parse(&args(invalid)),
+                Err(CliError::Usage(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! A small language for computing numbers and vectors from named variables, such as `sin(t) * r + 1`.
+
+use reactor_types::animation::Interpolate;
+use reactor_types::{Float, Vector, Vector4};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub use self::parser::ParseError;
+
+mod parser;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Value {
+    Number(Float),
+    Vector(Vector),
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Self::Number(0.0)
+    }
+}
+
+impl Interpolate for Value {
+    /// Numbers blended with vectors are splatted.
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        self.zip_map(*other, |a, b| a.interpolate(&b, t))
+    }
+}
+
+impl Value {
+    /// Vectors give their first component.
+    pub fn as_number(self) -> Float {
+        match self {
+            Self::Number(number) => number,
+            Self::Vector(vector) => vector[0],
+        }
+    }
+
+    /// Numbers are splatted into all components.
+    pub fn as_vector(self) -> Vector {
+        match self {
+            Self::Number(number) => Vector::from_scalar(number),
+            Self::Vector(vector) => vector,
+        }
+    }
+
+    fn is_finite(self) -> bool {
+        match self {
+            Self::Number(number) => number.is_finite(),
+            Self::Vector(vector) => (0..vector.len()).all(|idx| vector[idx].is_finite()),
+        }
+    }
+
+    fn map(self, f: impl Fn(Float) -> Float) -> Self {
+        match self {
+            Self::Number(number) => Self::Number(f(number)),
+            Self::Vector(vector) => Self::Vector(with_len(vector.len(), vector.as_dim4().map(f))),
+        }
+    }
+
+    /// Applies the function component-wise, numbers are splatted and shorter vectors are padded with zeros.
+    fn zip_map(self, other: Self, f: impl Fn(Float, Float) -> Float) -> Self {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => Self::Number(f(a, b)),
+            (a, b) => {
+                let len = match (a, b) {
+                    (Self::Vector(a), Self::Vector(b)) => a.len().max(b.len()),
+                    (Self::Vector(vector), _) | (_, Self::Vector(vector)) => vector.len(),
+                    _ => unreachable!(),
+                };
+                let (a, b) = (a.as_vector().as_dim4(), b.as_vector().as_dim4());
+                Self::Vector(with_len(len, a.zip_map(&b, f)))
+            },
+        }
+    }
+}
+
+fn with_len(len: usize, vector: Vector4) -> Vector {
+    match len {
+        2 => Vector::Dim2(vector.xy()),
+        3 => Vector::Dim3(vector.xyz()),
+        _ => Vector::Dim4(vector),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Sqrt,
+    Abs,
+    Floor,
+    Ceil,
+    Fract,
+    Exp,
+    Ln,
+    Min,
+    Max,
+    Pow,
+    Clamp,
+    Lerp,
+    Vec2,
+    Vec3,
+    Vec4,
+    Dot,
+    Cross,
+    Length,
+    Normalize,
+}
+
+impl Function {
+    const ALL: [Self; 26] = [
+        Self::Sin,
+        Self::Cos,
+        Self::Tan,
+        Self::Asin,
+        Self::Acos,
+        Self::Atan,
+        Self::Atan2,
+        Self::Sqrt,
+        Self::Abs,
+        Self::Floor,
+        Self::Ceil,
+        Self::Fract,
+        Self::Exp,
+        Self::Ln,
+        Self::Min,
+        Self::Max,
+        Self::Pow,
+        Self::Clamp,
+        Self::Lerp,
+        Self::Vec2,
+        Self::Vec3,
+        Self::Vec4,
+        Self::Dot,
+        Self::Cross,
+        Self::Length,
+        Self::Normalize,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|function| function.name() == name)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sin => "sin",
+            Self::Cos => "cos",
+            Self::Tan => "tan",
+            Self::Asin => "asin",
+            Self::Acos => "acos",
+            Self::Atan => "atan",
+            Self::Atan2 => "atan2",
+            Self::Sqrt => "sqrt",
+            Self::Abs => "abs",
+            Self::Floor => "floor",
+            Self::Ceil => "ceil",
+            Self::Fract => "fract",
+            Self::Exp => "exp",
+            Self::Ln => "ln",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Pow => "pow",
+            Self::Clamp => "clamp",
+            Self::Lerp => "lerp",
+            Self::Vec2 => "vec2",
+            Self::Vec3 => "vec3",
+            Self::Vec4 => "vec4",
+            Self::Dot => "dot",
+            Self::Cross => "cross",
+            Self::Length => "length",
+            Self::Normalize => "normalize",
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Self::Atan2 | Self::Min | Self::Max | Self::Pow | Self::Vec2 | Self::Dot | Self::Cross => 2,
+            Self::Clamp | Self::Lerp | Self::Vec3 => 3,
+            Self::Vec4 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Arguments are checked against the arity by the parser.
+    fn call(self, args: &[Value]) -> Result<Value, EvalError> {
+        let unary = |f: fn(Float) -> Float| Ok(args[0].map(f));
+        let binary = |f: fn(Float, Float) -> Float| Ok(args[0].zip_map(args[1], f));
+
+        match self {
+            Self::Sin => unary(Float::sin),
+            Self::Cos => unary(Float::cos),
+            Self::Tan => unary(Float::tan),
+            Self::Asin => unary(Float::asin),
+            Self::Acos => unary(Float::acos),
+            Self::Atan => unary(Float::atan),
+            Self::Atan2 => binary(Float::atan2),
+            Self::Sqrt => unary(Float::sqrt),
+            Self::Abs => unary(Float::abs),
+            Self::Floor => unary(Float::floor),
+            Self::Ceil => unary(Float::ceil),
+            Self::Fract => unary(|value| value - value.floor()),
+            Self::Exp => unary(Float::exp),
+            Self::Ln => unary(Float::ln),
+            Self::Min => binary(Float::min),
+            Self::Max => binary(Float::max),
+            Self::Pow => binary(Float::powf),
+            // `Float::clamp` panics on an inverted range.
+            Self::Clamp => Ok(args[0].zip_map(args[1], Float::max).zip_map(args[2], Float::min)),
+            Self::Lerp => {
+                let difference = args[1].zip_map(args[0], |b, a| b - a);
+                Ok(args[0].zip_map(difference.zip_map(args[2], |d, t| d * t), |a, d| a + d))
+            },
+            Self::Vec2 | Self::Vec3 | Self::Vec4 => {
+                let mut components = Vector4::zeros();
+                for (component, arg) in components.iter_mut().zip(args) {
+                    match arg {
+                        Value::Number(number) => *component = *number,
+                        Value::Vector(_) => return Err(EvalError::ExpectedNumber(self.name())),
+                    }
+                }
+                Ok(Value::Vector(with_len(args.len(), components)))
+            },
+            Self::Dot => {
+                let [a, b] = self.vector_args(args)?;
+                Ok(Value::Number(a.as_dim4().dot(&b.as_dim4())))
+            },
+            Self::Cross => {
+                let [a, b] = self.vector_args(args)?;
+                Ok(Value::Vector(Vector::Dim3(a.as_dim3().cross(&b.as_dim3()))))
+            },
+            Self::Length => {
+                let [vector] = self.vector_args(args)?;
+                Ok(Value::Number(vector.as_dim4().norm()))
+            },
+            Self::Normalize => {
+                let [vector] = self.vector_args(args)?;
+                let normalized = vector.as_dim4().try_normalize(0.0).unwrap_or_default();
+                Ok(Value::Vector(with_len(vector.len(), normalized)))
+            },
+        }
+    }
+
+    fn vector_args<const N: usize>(self, args: &[Value]) -> Result<[Vector; N], EvalError> {
+        let mut vectors = [Vector::from_scalar(0.0); N];
+        for (vector, arg) in vectors.iter_mut().zip(args) {
+            match arg {
+                Value::Vector(arg) => *vector = *arg,
+                Value::Number(_) => return Err(EvalError::ExpectedVector(self.name())),
+            }
+        }
+        Ok(vectors)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Ast {
+    Number(Float),
+    /// Index into the variables of the expression.
+    Variable(usize),
+    Negate(Box<Ast>),
+    Binary(BinaryOperator, Box<Ast>, Box<Ast>),
+    Call(Function, Vec<Ast>),
+}
+
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum EvalError {
+    #[error("no value for the variable `{0}`")]
+    MissingVariable(String),
+    #[error("`{0}` takes numbers, not vectors")]
+    ExpectedNumber(&'static str),
+    #[error("`{0}` takes vectors, not numbers")]
+    ExpectedVector(&'static str),
+    #[error("the result is not a finite number")]
+    NotFinite,
+}
+
+/// A parsed expression. Names which are not functions or the constants `pi` and `tau` are variables.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    root: Ast,
+    variables: Vec<String>,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        parser::parse(source)
+    }
+
+    /// Names of the variables in the order of their first use.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// `values` are the values of the variables in the order of [`Self::variables`].
+    pub fn evaluate(&self, values: &[Value]) -> Result<Value, EvalError> {
+        let value = self.evaluate_node(&self.root, values)?;
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(EvalError::NotFinite)
+        }
+    }
+
+    fn evaluate_node(&self, node: &Ast, values: &[Value]) -> Result<Value, EvalError> {
+        match node {
+            Ast::Number(number) => Ok(Value::Number(*number)),
+            Ast::Variable(idx) => values
+                .get(*idx)
+                .copied()
+                .ok_or_else(|| EvalError::MissingVariable(self.variables[*idx].clone())),
+            Ast::Negate(operand) => Ok(self.evaluate_node(operand, values)?.map(|value| -value)),
+            Ast::Binary(operator, lhs, rhs) => {
+                let (lhs, rhs) = (self.evaluate_node(lhs, values)?, self.evaluate_node(rhs, values)?);
+                Ok(match operator {
+                    BinaryOperator::Add => lhs.zip_map(rhs, |a, b| a + b),
+                    BinaryOperator::Subtract => lhs.zip_map(rhs, |a, b| a - b),
+                    BinaryOperator::Multiply => lhs.zip_map(rhs, |a, b| a * b),
+                    BinaryOperator::Divide => lhs.zip_map(rhs, |a, b| a / b),
+                    BinaryOperator::Power => lhs.zip_map(rhs, Float::powf),
+                })
+            },
+            Ast::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate_node(arg, values))
+                    .collect::<Result<Vec<_>, _>>()?;
+                function.call(&args)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reactor_types::Vector3;
+
+    use super::*;
+
+    fn evaluate(source: &str, values: &[Value]) -> Result<Value, EvalError> {
+        Expression::parse(source).unwrap().evaluate(values)
+    }
+
+    fn vector3(x: Float, y: Float, z: Float) -> Value {
+        Value::Vector(Vector::Dim3(Vector3::new(x, y, z)))
+    }
+
+    #[test]
+    fn test_number_arithmetic() {
+        let values = [Value::Number(0.5), Value::Number(2.0)];
+        assert_eq!(
+            evaluate("sin(t) * r + 1", &values),
+            Ok(Value::Number(0.5f64.sin() * 2.0 + 1.0))
+        );
+        assert_eq!(evaluate("-2 ^ 2", &[]), Ok(Value::Number(-4.0)));
+        assert_eq!(evaluate("2 ^ 3 ^ 2", &[]), Ok(Value::Number(512.0)));
+        assert_eq!(
+            evaluate("clamp(5, 0, 1) + lerp(2, 4, 0.5)", &[]),
+            Ok(Value::Number(4.0))
+        );
+    }
+
+    #[test]
+    fn test_vectors_broadcast_numbers() {
+        let values = [vector3(1.0, 2.0, 3.0)];
+        assert_eq!(evaluate("v * 2 + 1", &values), Ok(vector3(3.0, 5.0, 7.0)));
+        assert_eq!(evaluate("vec3(1, 0, 0) - v", &values), Ok(vector3(0.0, -2.0, -3.0)));
+        assert_eq!(evaluate("length(vec2(3, 4))", &[]), Ok(Value::Number(5.0)));
+        assert_eq!(
+            evaluate("cross(vec3(1, 0, 0), vec3(0, 1, 0))", &[]),
+            Ok(vector3(0.0, 0.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        assert_eq!(evaluate("1 / 0", &[]), Err(EvalError::NotFinite));
+        assert_eq!(evaluate("dot(1, 2)", &[]), Err(EvalError::ExpectedVector("dot")));
+        assert_eq!(
+            evaluate("vec2(v, 1)", &[vector3(1.0, 2.0, 3.0)]),
+            Err(EvalError::ExpectedNumber("vec2"))
+        );
+        assert_eq!(
+            evaluate("a + b", &[Value::Number(1.0)]),
+            Err(EvalError::MissingVariable("b".to_owned()))
+        );
+    }
+}
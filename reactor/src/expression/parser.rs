@@ -0,0 +1,431 @@
+use std::f64::consts::{PI, TAU};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use reactor_types::Float;
+use thiserror::Error;
+
+use super::{Ast, BinaryOperator, Expression, Function};
+
+/// Columns count characters from 1.
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("the expression is empty")]
+    Empty,
+    #[error("unexpected character `{0}` at column {1}")]
+    UnexpectedCharacter(char, usize),
+    #[error("invalid number `{0}` at column {1}")]
+    InvalidNumber(String, usize),
+    #[error("expected {expected} at column {column}, found {found}")]
+    Unexpected {
+        expected: &'static str,
+        found: String,
+        column: usize,
+    },
+    #[error("unknown function `{0}` at column {1}")]
+    UnknownFunction(String, usize),
+    #[error("`{name}` takes {expected} arguments, but {found} were given")]
+    Arity {
+        name: &'static str,
+        expected: usize,
+        found: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Float),
+    Name(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    OpenParen,
+    CloseParen,
+    Comma,
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Self::Number(number) => format!("`{number}`"),
+            Self::Name(name) => format!("`{name}`"),
+            Self::Plus => "`+`".to_owned(),
+            Self::Minus => "`-`".to_owned(),
+            Self::Star => "`*`".to_owned(),
+            Self::Slash => "`/`".to_owned(),
+            Self::Caret => "`^`".to_owned(),
+            Self::OpenParen => "`(`".to_owned(),
+            Self::CloseParen => "`)`".to_owned(),
+            Self::Comma => "`,`".to_owned(),
+            Self::End => "the end".to_owned(),
+        }
+    }
+}
+
+/// Splits the source into tokens with their columns, the last token is always [`Token::End`].
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let column_of = |offset: usize| source[..offset].chars().count() + 1;
+    let mut chars = source.char_indices().peekable();
+    let mut tokens = Vec::new();
+
+    while let Some(&(offset, char)) = chars.peek() {
+        let column = column_of(offset);
+        let token = match char {
+            _ if char.is_whitespace() => {
+                chars.next();
+                continue;
+            },
+            '0'..='9' | '.' => {
+                let text = take_number(source, &mut chars);
+                let number = text
+                    .parse()
+                    .map_err(|_| ParseError::InvalidNumber(text.to_owned(), column))?;
+                tokens.push((Token::Number(number), column));
+                continue;
+            },
+            _ if char.is_alphabetic() || char == '_' => {
+                let end = take_while(&mut chars, |char| char.is_alphanumeric() || char == '_').unwrap_or(source.len());
+                tokens.push((Token::Name(source[offset..end].to_owned()), column));
+                continue;
+            },
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '^' => Token::Caret,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            ',' => Token::Comma,
+            _ => return Err(ParseError::UnexpectedCharacter(char, column)),
+        };
+        chars.next();
+        tokens.push((token, column));
+    }
+
+    tokens.push((Token::End, column_of(source.len())));
+    Ok(tokens)
+}
+
+/// Consumes the characters while the predicate holds, returns the offset after them or `None` at the end.
+fn take_while(chars: &mut Peekable<CharIndices>, predicate: impl Fn(char) -> bool) -> Option<usize> {
+    while let Some(&(offset, char)) = chars.peek() {
+        if !predicate(char) {
+            return Some(offset);
+        }
+        chars.next();
+    }
+    None
+}
+
+/// Takes digits with an optional fraction and exponent, such as `1.5e-3`.
+fn take_number<'a>(source: &'a str, chars: &mut Peekable<CharIndices>) -> &'a str {
+    let start = chars.peek().map_or(source.len(), |&(offset, _)| offset);
+    let mut end = take_while(chars, |char| char.is_ascii_digit() || char == '.').unwrap_or(source.len());
+
+    let rest = &source[end..];
+    let exponent_digits = rest
+        .strip_prefix(['e', 'E'])
+        .map(|rest| rest.strip_prefix(['+', '-']).unwrap_or(rest));
+    if exponent_digits.is_some_and(|digits| digits.starts_with(|char: char| char.is_ascii_digit())) {
+        chars.next();
+        if chars.peek().is_some_and(|&(_, char)| char == '+' || char == '-') {
+            chars.next();
+        }
+        end = take_while(chars, |char| char.is_ascii_digit()).unwrap_or(source.len());
+    }
+
+    &source[start..end]
+}
+
+pub fn parse(source: &str) -> Result<Expression, ParseError> {
+    let tokens = tokenize(source)?;
+    if tokens.len() == 1 {
+        return Err(ParseError::Empty);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        variables: Vec::new(),
+    };
+    let root = parser.expression()?;
+    parser.expect(Token::End, "an operator")?;
+
+    Ok(Expression {
+        root,
+        variables: parser.variables,
+    })
+}
+
+/// Recursive descent parser, from the lowest precedence:
+///
+/// ```text
+/// expression = term (("+" | "-") term)*
+/// term = unary (("*" | "/") unary)*
+/// unary = ("-" | "+") unary | power
+/// power = primary ("^" unary)?
+/// primary = number | name | name "(" (expression ("," expression)*)? ")" | "(" expression ")"
+/// ```
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    variables: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.position].clone();
+        // `End` stays the current token.
+        self.position = (self.position + 1).min(self.tokens.len() - 1);
+        token
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ParseError {
+        let (token, column) = &self.tokens[self.position];
+        ParseError::Unexpected {
+            expected,
+            found: token.describe(),
+            column: *column,
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), ParseError> {
+        if *self.peek() == token {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn expression(&mut self) -> Result<Ast, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Token::Plus => BinaryOperator::Add,
+                Token::Minus => BinaryOperator::Subtract,
+                _ => return Ok(lhs),
+            };
+            self.next();
+            lhs = Ast::Binary(operator, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Ast, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Token::Star => BinaryOperator::Multiply,
+                Token::Slash => BinaryOperator::Divide,
+                _ => return Ok(lhs),
+            };
+            self.next();
+            lhs = Ast::Binary(operator, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Ast, ParseError> {
+        match self.peek() {
+            Token::Minus => {
+                self.next();
+                Ok(Ast::Negate(Box::new(self.unary()?)))
+            },
+            Token::Plus => {
+                self.next();
+                self.unary()
+            },
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Ast, ParseError> {
+        let base = self.primary()?;
+        if *self.peek() == Token::Caret {
+            self.next();
+            // Right associative and binds tighter than a unary minus on its left: `-2^2` is `-(2^2)`.
+            let exponent = self.unary()?;
+            Ok(Ast::Binary(BinaryOperator::Power, Box::new(base), Box::new(exponent)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> Result<Ast, ParseError> {
+        match self.peek().clone() {
+            Token::Number(number) => {
+                self.next();
+                Ok(Ast::Number(number))
+            },
+            Token::OpenParen => {
+                self.next();
+                let inner = self.expression()?;
+                self.expect(Token::CloseParen, "`)`")?;
+                Ok(inner)
+            },
+            Token::Name(name) => {
+                let (_, column) = self.next();
+                if *self.peek() == Token::OpenParen {
+                    self.next();
+                    self.call(name, column)
+                } else {
+                    Ok(self.name(name))
+                }
+            },
+            _ => Err(self.unexpected("a number, a name or `(`")),
+        }
+    }
+
+    fn name(&mut self, name: String) -> Ast {
+        match name.as_str() {
+            "pi" => Ast::Number(PI),
+            "tau" => Ast::Number(TAU),
+            _ => {
+                let idx = match self.variables.iter().position(|variable| *variable == name) {
+                    Some(idx) => idx,
+                    None => {
+                        self.variables.push(name);
+                        self.variables.len() - 1
+                    },
+                };
+                Ast::Variable(idx)
+            },
+        }
+    }
+
+    /// Parses the arguments after the opening parenthesis.
+    fn call(&mut self, name: String, column: usize) -> Result<Ast, ParseError> {
+        let function = Function::from_name(&name).ok_or(ParseError::UnknownFunction(name, column))?;
+
+        let mut args = Vec::new();
+        if *self.peek() == Token::CloseParen {
+            self.next();
+        } else {
+            loop {
+                args.push(self.expression()?);
+                match self.peek() {
+                    Token::Comma => self.next(),
+                    Token::CloseParen => {
+                        self.next();
+                        break;
+                    },
+                    _ => return Err(self.unexpected("`,` or `)`")),
+                };
+            }
+        }
+
+        if args.len() != function.arity() {
+            return Err(ParseError::Arity {
+                name: function.name(),
+                expected: function.arity(),
+                found: args.len(),
+            });
+        }
+        Ok(Ast::Call(function, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence() {
+        let expression = parse("1 + 2 * x ^ 2").unwrap();
+        assert_eq!(
+            expression.root,
+            Ast::Binary(
+                BinaryOperator::Add,
+                Box::new(Ast::Number(1.0)),
+                Box::new(Ast::Binary(
+                    BinaryOperator::Multiply,
+                    Box::new(Ast::Number(2.0)),
+                    Box::new(Ast::Binary(
+                        BinaryOperator::Power,
+                        Box::new(Ast::Variable(0)),
+                        Box::new(Ast::Number(2.0)),
+                    )),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_variables_in_order_of_first_use() {
+        let expression = parse("sin(t) * r + t / pi - r_2").unwrap();
+        assert_eq!(expression.variables(), ["t", "r", "r_2"]);
+    }
+
+    #[test]
+    fn test_numbers() {
+        for (source, number) in [
+            ("2", 2.0),
+            ("0.5", 0.5),
+            (".25", 0.25),
+            ("1e3", 1000.0),
+            ("1.5E-2", 0.015),
+        ] {
+            assert_eq!(parse(source).unwrap().root, Ast::Number(number), "{source}");
+        }
+        assert_eq!(parse("1.2.3"), Err(ParseError::InvalidNumber("1.2.3".to_owned(), 1)));
+        // Not an exponent, but a number followed by a name.
+        assert!(matches!(parse("2e"), Err(ParseError::Unexpected { column: 2, .. })));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_column() {
+        assert_eq!(parse("  "), Err(ParseError::Empty));
+        assert_eq!(parse("1 $ 2"), Err(ParseError::UnexpectedCharacter('$', 3)));
+        assert_eq!(
+            parse("(1 + 2"),
+            Err(ParseError::Unexpected {
+                expected: "`)`",
+                found: "the end".to_owned(),
+                column: 7,
+            })
+        );
+        assert_eq!(
+            parse("1 +"),
+            Err(ParseError::Unexpected {
+                expected: "a number, a name or `(`",
+                found: "the end".to_owned(),
+                column: 4,
+            })
+        );
+        assert_eq!(
+            parse("min(1 2)"),
+            Err(ParseError::Unexpected {
+                expected: "`,` or `)`",
+                found: "`2`".to_owned(),
+                column: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_function_calls_are_checked() {
+        assert_eq!(parse("foo(1)"), Err(ParseError::UnknownFunction("foo".to_owned(), 1)));
+        assert_eq!(
+            parse("1 + clamp(1, 2)"),
+            Err(ParseError::Arity {
+                name: "clamp",
+                expected: 3,
+                found: 2,
+            })
+        );
+        assert_eq!(
+            parse("vec4()"),
+            Err(ParseError::Arity {
+                name: "vec4",
+                expected: 4,
+                found: 0,
+            })
+        );
+    }
+}
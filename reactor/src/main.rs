@@ -8,41 +8,32 @@ use crate::app::ReactorApp;
 use crate::logger::LoggerConfig;
 
 mod app;
+mod cli;
+mod expression;
 mod logger;
 mod node;
+mod sequence;
 mod settings;
 mod tabs;
+mod timeline;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let logger_config = LoggerConfig::load(None)?;
     logger::init(&logger_config)?;
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|command| command == cli::RENDER_ANIMATION) {
+        cli::render_animation(&args[1..])?;
+        return Ok(());
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([800.0, 600.0])
             .with_min_inner_size([700.0, 520.0]),
         wgpu_options: WgpuConfiguration {
             wgpu_setup: WgpuSetup::CreateNew(WgpuSetupCreateNew {
-                device_descriptor: Arc::new(|adapter| {
-                    let mut base_limits = if adapter.get_info().backend == wgpu::Backend::Gl {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    };
-                    base_limits.max_storage_buffer_binding_size = 512 << 20;
-
-                    wgpu::DeviceDescriptor {
-                        label: Some("egui wgpu device"),
-                        required_features: wgpu::Features::default(),
-                        required_limits: wgpu::Limits {
-                            // When using a depth buffer, we have to be able to create a texture
-                            // large enough for the entire surface, and we want to support 4k+ displays.
-                            max_texture_dimension_2d: 8192,
-                            ..base_limits
-                        },
-                        memory_hints: wgpu::MemoryHints::default(),
-                    }
-                }),
+                device_descriptor: Arc::new(device_descriptor),
                 ..Default::default()
             }),
             ..Default::default()
@@ -58,3 +49,25 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Device with the limits which the renderers need, for the window and for the headless renders.
+fn device_descriptor(adapter: &wgpu::Adapter) -> wgpu::DeviceDescriptor<'static> {
+    let mut base_limits = if adapter.get_info().backend == wgpu::Backend::Gl {
+        wgpu::Limits::downlevel_webgl2_defaults()
+    } else {
+        wgpu::Limits::default()
+    };
+    base_limits.max_storage_buffer_binding_size = 512 << 20;
+
+    wgpu::DeviceDescriptor {
+        label: Some("egui wgpu device"),
+        required_features: wgpu::Features::default(),
+        required_limits: wgpu::Limits {
+            // When using a depth buffer, we have to be able to create a texture
+            // large enough for the entire surface, and we want to support 4k+ displays.
+            max_texture_dimension_2d: 8192,
+            ..base_limits
+        },
+        memory_hints: wgpu::MemoryHints::default(),
+    }
+}
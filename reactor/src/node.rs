@@ -6,21 +6,32 @@ use enum_dispatch::enum_dispatch;
 use message::InputMessage;
 use reactor_derives::EnumAs;
 use serde::{Deserialize, Serialize};
+use xrays::procedural::Pattern;
 
-use self::item::material::{CheckerboardNode, DielectricNode, EmissiveNode, LambertianNode, MetalNode};
+use self::evaluator::NodeOutput;
+use self::item::material::{
+    CheckerboardNode, DielectricNode, EmissiveNode, LambertianNode, MetalNode, MixMaterialNode,
+};
 use self::item::primitive::SphereNode;
 use self::item::render::{TriangleRenderNode, XraysRenderNode};
 use self::item::{
-    CameraNode, CollectionNode, ColorNode, MaterialNode, NumberNode, OutputNode, PrimitiveNode, RenderNode, SceneNode,
-    StringNode, TextureNode, VectorNode,
+    CameraNode, CollectionNode, ColorNode, ExpressionNode, GroupNode, MaterialNode, MathNode, MathOperation,
+    NumberNode, OutputNode, PrimitiveNode, ProceduralTextureNode, RenderNode, SceneNode, StringNode, TextureNode,
+    VectorMathNode, VectorNode, VectorOperation,
 };
 use self::message::{CommonNodeMessage, CommonNodeResponse, MessageHandling, SelfNodeMut};
 use self::subscribtion::Subscription;
 use self::viewer::NodeConfig;
+use self::viewer::gizmo::TransformPins;
 
+pub mod clipboard;
+pub mod evaluator;
+pub mod history;
 pub mod item;
 pub mod message;
+pub mod palette;
 pub mod subscribtion;
+pub mod validation;
 pub mod viewer;
 
 bitflags! {
@@ -39,9 +50,10 @@ bitflags! {
         const MATERIAL_LAMBERT = Self::MATERIAL_DIELECTRIC.bits() << 1;
         const MATERIAL_EMISSIVE = Self::MATERIAL_LAMBERT.bits() << 1;
         const MATERIAL_CHECKERBOARD = Self::MATERIAL_EMISSIVE.bits() << 1;
-        const MATERIALS = Self::MATERIAL_METAL.bits() | Self::MATERIAL_DIELECTRIC.bits() | Self::MATERIAL_LAMBERT.bits() | Self::MATERIAL_EMISSIVE.bits() | Self::MATERIAL_CHECKERBOARD.bits();
+        const MATERIAL_MIX = Self::MATERIAL_CHECKERBOARD.bits() << 1;
+        const MATERIALS = Self::MATERIAL_METAL.bits() | Self::MATERIAL_DIELECTRIC.bits() | Self::MATERIAL_LAMBERT.bits() | Self::MATERIAL_EMISSIVE.bits() | Self::MATERIAL_CHECKERBOARD.bits() | Self::MATERIAL_MIX.bits();
 
-        const TEXTURE = Self::MATERIAL_CHECKERBOARD.bits() << 1;
+        const TEXTURE = Self::MATERIAL_MIX.bits() << 1;
 
         const COLLECTION = Self::TEXTURE.bits() << 1;
         const CAMERA = Self::COLLECTION.bits() << 1;
@@ -68,6 +80,14 @@ pub trait Noded {
     fn reset_input(&mut self, _pin: &InPin) -> bool {
         false
     }
+    /// Sets the input from the output of the connected node, returns whether the input changed.
+    fn set_input(&mut self, _input: usize, _output: &NodeOutput) -> bool {
+        false
+    }
+    /// Sets the keyed inputs from their keyframes at the time in seconds, returns whether any input changed.
+    fn animate(&mut self, _time: f32) -> bool {
+        false
+    }
     fn subscription_ref(&self) -> Option<&Subscription> {
         None
     }
@@ -76,21 +96,27 @@ pub trait Noded {
     }
 }
 
-#[derive(Clone, EnumAs, Serialize, Deserialize)]
+#[derive(Clone, EnumAs, PartialEq, Serialize, Deserialize)]
 #[enum_dispatch(Noded)]
 pub enum Node {
     Number(NumberNode),
     String(StringNode),
     Vector(VectorNode),
     Color(ColorNode),
+    Math(MathNode),
+    VectorMath(VectorMathNode),
+    Expression(ExpressionNode),
     Primitive(PrimitiveNode),
     Material(MaterialNode),
+    MixMaterial(MixMaterialNode),
     Texture(TextureNode),
+    ProceduralTexture(ProceduralTextureNode),
     Collection(CollectionNode),
     Scene(SceneNode),
     Camera(CameraNode),
     Render(RenderNode),
     Output(OutputNode),
+    Group(GroupNode),
 }
 
 impl Node {
@@ -133,6 +159,108 @@ impl Node {
                 ColorNode::INPUTS.as_slice(),
                 ColorNode::OUTPUTS.as_slice(),
             ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Add.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Add)),
+                MathOperation::Add.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Subtract.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Subtract)),
+                MathOperation::Subtract.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Multiply.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Multiply)),
+                MathOperation::Multiply.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Divide.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Divide)),
+                MathOperation::Divide.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Power.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Power)),
+                MathOperation::Power.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Sine.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Sine)),
+                MathOperation::Sine.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Cosine.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Cosine)),
+                MathOperation::Cosine.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Clamp.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Clamp)),
+                MathOperation::Clamp.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Lerp.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Lerp)),
+                MathOperation::Lerp.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(MathNode::NAME, " ", MathOperation::Remap.name()),
+                |_| Node::Math(MathNode::new(MathOperation::Remap)),
+                MathOperation::Remap.inputs(),
+                MathNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(VectorMathNode::NAME, " ", VectorOperation::Dot.name()),
+                |_| Node::VectorMath(VectorMathNode::new(VectorOperation::Dot)),
+                VectorOperation::Dot.inputs(),
+                VectorOperation::Dot.outputs(),
+            ),
+            (
+                concatcp!(VectorMathNode::NAME, " ", VectorOperation::Cross.name()),
+                |_| Node::VectorMath(VectorMathNode::new(VectorOperation::Cross)),
+                VectorOperation::Cross.inputs(),
+                VectorOperation::Cross.outputs(),
+            ),
+            (
+                concatcp!(VectorMathNode::NAME, " ", VectorOperation::Normalize.name()),
+                |_| Node::VectorMath(VectorMathNode::new(VectorOperation::Normalize)),
+                VectorOperation::Normalize.inputs(),
+                VectorOperation::Normalize.outputs(),
+            ),
+            (
+                concatcp!(VectorMathNode::NAME, " ", VectorOperation::Length.name()),
+                |_| Node::VectorMath(VectorMathNode::new(VectorOperation::Length)),
+                VectorOperation::Length.inputs(),
+                VectorOperation::Length.outputs(),
+            ),
+            (
+                concatcp!(VectorMathNode::NAME, " ", VectorOperation::Compose.name()),
+                |_| Node::VectorMath(VectorMathNode::new(VectorOperation::Compose)),
+                VectorOperation::Compose.inputs(),
+                VectorOperation::Compose.outputs(),
+            ),
+            (
+                concatcp!(VectorMathNode::NAME, " ", VectorOperation::Decompose.name()),
+                |_| Node::VectorMath(VectorMathNode::new(VectorOperation::Decompose)),
+                VectorOperation::Decompose.inputs(),
+                VectorOperation::Decompose.outputs(),
+            ),
+            (
+                ExpressionNode::NAME,
+                |_| Node::Expression(ExpressionNode::default()),
+                ExpressionNode::DEFAULT_INPUTS.as_slice(),
+                ExpressionNode::OUTPUTS.as_slice(),
+            ),
             (
                 SphereNode::NAME,
                 |_| Node::Primitive(PrimitiveNode::Sphere(SphereNode::default())),
@@ -169,12 +297,48 @@ impl Node {
                 CheckerboardNode::INPUTS.as_slice(),
                 CheckerboardNode::OUTPUTS.as_slice(),
             ),
+            (
+                MixMaterialNode::NAME,
+                |_| Node::MixMaterial(MixMaterialNode::default()),
+                MixMaterialNode::INPUTS.as_slice(),
+                MixMaterialNode::OUTPUTS.as_slice(),
+            ),
             (
                 TextureNode::NAME,
                 |_| Node::Texture(TextureNode::default()),
                 TextureNode::INPUTS.as_slice(),
                 TextureNode::OUTPUTS.as_slice(),
             ),
+            (
+                concatcp!(ProceduralTextureNode::NAME, " Noise"),
+                |_| Node::ProceduralTexture(ProceduralTextureNode::new(Pattern::NOISE)),
+                ProceduralTextureNode::INPUTS.as_slice(),
+                ProceduralTextureNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(ProceduralTextureNode::NAME, " Voronoi"),
+                |_| Node::ProceduralTexture(ProceduralTextureNode::new(Pattern::VORONOI)),
+                ProceduralTextureNode::INPUTS.as_slice(),
+                ProceduralTextureNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(ProceduralTextureNode::NAME, " Gradient"),
+                |_| Node::ProceduralTexture(ProceduralTextureNode::new(Pattern::GRADIENT)),
+                ProceduralTextureNode::INPUTS.as_slice(),
+                ProceduralTextureNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(ProceduralTextureNode::NAME, " Bricks"),
+                |_| Node::ProceduralTexture(ProceduralTextureNode::new(Pattern::BRICKS)),
+                ProceduralTextureNode::INPUTS.as_slice(),
+                ProceduralTextureNode::OUTPUTS.as_slice(),
+            ),
+            (
+                concatcp!(ProceduralTextureNode::NAME, " Checker"),
+                |_| Node::ProceduralTexture(ProceduralTextureNode::new(Pattern::CHECKER)),
+                ProceduralTextureNode::INPUTS.as_slice(),
+                ProceduralTextureNode::OUTPUTS.as_slice(),
+            ),
             (
                 CollectionNode::NAME,
                 |_| Node::Collection(CollectionNode::default()),
@@ -223,6 +387,54 @@ impl Node {
         ]
     }
 
+    /// Value of the output with the given index, nodes without a plain value output a reference to themselves.
+    pub fn output(&self, self_id: NodeId, output: usize) -> NodeOutput {
+        match self {
+            Self::Number(number) => NodeOutput::Number(number.value()),
+            Self::String(string) => NodeOutput::String(string.value().clone()),
+            Self::Vector(vector) => NodeOutput::Vector(vector.value()),
+            Self::Color(color) => NodeOutput::Color(color.value()),
+            Self::Math(math) => NodeOutput::Number(math.value()),
+            Self::VectorMath(vector_math) => vector_math
+                .number(output)
+                .map(NodeOutput::Number)
+                .or_else(|| vector_math.vector().map(NodeOutput::Vector))
+                .unwrap_or(NodeOutput::Node(self_id)),
+            Self::Expression(expression) => expression.value().into(),
+            Self::Group(group) => group.output(self_id, output),
+            _ => NodeOutput::Node(self_id),
+        }
+    }
+
+    /// Returns the transform inputs of the node, which can be edited with the viewport gizmos.
+    pub fn transform_mut(&mut self) -> Option<TransformPins<'_>> {
+        match self {
+            Self::Primitive(PrimitiveNode::Sphere(sphere)) => Some(sphere.transform_mut()),
+            Self::Camera(camera) => Some(camera.transform_mut()),
+            _ => None,
+        }
+    }
+
+    /// Copy of the node for the edit history, without the state which the node widgets don't edit: the inner scene
+    /// of a scene node and the graph of a group node, but for the nodes of its exposed inputs. Node comparisons skip
+    /// that state too.
+    pub fn edit_snapshot(&self) -> Self {
+        match self {
+            Self::Scene(scene) => Self::Scene(scene.edit_snapshot()),
+            Self::Group(group) => Self::Group(group.edit_snapshot()),
+            node => node.clone(),
+        }
+    }
+
+    /// Replaces the node with a snapshot made by [`Self::edit_snapshot`], keeping the state the snapshot leaves out.
+    pub fn restore_snapshot(&mut self, snapshot: Self) {
+        match (self, snapshot) {
+            (Self::Scene(scene), Self::Scene(snapshot)) => scene.restore_snapshot(snapshot),
+            (Self::Group(group), Self::Group(snapshot)) => group.restore_snapshot(snapshot),
+            (node, snapshot) => *node = snapshot,
+        }
+    }
+
     pub fn call_handle_msg<'a>(
         self_id: NodeId,
         snarl: &mut Snarl<Node>,
@@ -240,14 +452,20 @@ impl Node {
             Self::String(_) => StringNode::handle_msg(self_node, msg),
             Self::Vector(_) => VectorNode::handle_msg(self_node, msg),
             Self::Color(_) => ColorNode::handle_msg(self_node, msg),
+            Self::Math(_) => MathNode::handle_msg(self_node, msg),
+            Self::VectorMath(_) => VectorMathNode::handle_msg(self_node, msg),
+            Self::Expression(_) => ExpressionNode::handle_msg(self_node, msg),
             Self::Primitive(_) => PrimitiveNode::handle_msg(self_node, msg),
             Self::Material(_) => MaterialNode::handle_msg(self_node, msg),
+            Self::MixMaterial(_) => MixMaterialNode::handle_msg(self_node, msg),
             Self::Texture(_) => TextureNode::handle_msg(self_node, msg),
+            Self::ProceduralTexture(_) => ProceduralTextureNode::handle_msg(self_node, msg),
             Self::Collection(_) => CollectionNode::handle_msg(self_node, msg),
             Self::Scene(_) => SceneNode::handle_msg(self_node, msg),
             Self::Camera(_) => CameraNode::handle_msg(self_node, msg),
             Self::Render(_) => RenderNode::handle_msg(self_node, msg),
             Self::Output(_) => OutputNode::handle_msg(self_node, msg),
+            Self::Group(_) => GroupNode::handle_msg(self_node, msg),
         }
     }
 }
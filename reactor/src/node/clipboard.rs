@@ -0,0 +1,259 @@
+use eframe::wgpu::naga::FastHashMap;
+use egui::{Pos2, Vec2};
+use egui_snarl::{InPin, InPinId, NodeId, OutPinId, Snarl};
+use serde::{Deserialize, Serialize};
+
+use super::history::{Command, Editor};
+use super::item::{CollectionNode, SceneNode};
+use super::subscribtion::Subscription;
+use super::{Node, Noded};
+
+/// Nodes copied from a graph together with the wires between them, saved to the clipboard as JSON.
+#[derive(Serialize, Deserialize)]
+pub struct Fragment {
+    nodes: Vec<FragmentNode>,
+    /// Wires between the copied nodes.
+    wires: Vec<FragmentWire>,
+    /// Wires into the copied nodes from nodes which weren't copied.
+    external_wires: Vec<ExternalWire>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FragmentNode {
+    pos: Pos2,
+    node: Node,
+}
+
+/// Wire between the nodes with the indices `from` and `to` in the fragment.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct FragmentWire {
+    from: usize,
+    output: usize,
+    to: usize,
+    input: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExternalWire {
+    from: OutPinId,
+    /// Name of the node the wire came from, it's only restored from a node with the same name.
+    name: String,
+    to: usize,
+    input: usize,
+}
+
+impl Fragment {
+    /// Copies the nodes which exist in the graph, ignoring the rest.
+    pub fn copy(node_ids: impl IntoIterator<Item = NodeId>, snarl: &Snarl<Node>) -> Self {
+        let mut copied = Vec::<NodeId>::new();
+        for node_id in node_ids {
+            if snarl.get_node(node_id).is_some() && !copied.contains(&node_id) {
+                copied.push(node_id);
+            }
+        }
+        let index = |node_id| copied.iter().position(|&copied_id| copied_id == node_id);
+
+        let nodes = copied
+            .iter()
+            .map(|&node_id| FragmentNode {
+                pos: snarl.get_node_info(node_id).map(|info| info.pos).unwrap_or_default(),
+                node: snarl[node_id].clone(),
+            })
+            .collect();
+
+        let mut wires = Vec::new();
+        let mut external_wires = Vec::new();
+        for (from, to) in snarl.wires() {
+            let Some(to_idx) = index(to.node) else {
+                continue;
+            };
+            match index(from.node) {
+                Some(from_idx) => wires.push(FragmentWire {
+                    from: from_idx,
+                    output: from.output,
+                    to: to_idx,
+                    input: to.input,
+                }),
+                None => external_wires.push(ExternalWire {
+                    from,
+                    name: snarl[from.node].name().to_owned(),
+                    to: to_idx,
+                    input: to.input,
+                }),
+            }
+        }
+
+        Self {
+            nodes,
+            wires,
+            external_wires,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(text: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(text)
+    }
+
+    /// Inserts the nodes moved by `offset` under fresh ids and wires them like the copied ones. Wires from nodes
+    /// outside the fragment are restored only with `keep_external_wires`, when a compatible node with the same name
+    /// is still at their end. Returns the new ids and the command which undoes the paste.
+    pub fn paste(
+        &self,
+        offset: Vec2,
+        keep_external_wires: bool,
+        editor: &mut impl Editor,
+        snarl: &mut Snarl<Node>,
+    ) -> (Vec<NodeId>, Command) {
+        let mut commands = Vec::new();
+
+        let node_ids = self
+            .nodes
+            .iter()
+            .map(|fragment_node| {
+                let pos = fragment_node.pos + offset;
+                let node_id = editor.insert_node(pos, detached(&fragment_node.node), snarl);
+                for input in 0..snarl[node_id].inputs().len() {
+                    snarl[node_id].reset_input(&InPin {
+                        id: InPinId { node: node_id, input },
+                        remotes: Default::default(),
+                    });
+                }
+
+                commands.push(Command::AddNode {
+                    node_id,
+                    pos,
+                    node: Box::new(snarl[node_id].clone()),
+                });
+                node_id
+            })
+            .collect::<Vec<_>>();
+
+        let internal = self.wires.iter().map(|wire| {
+            (
+                OutPinId {
+                    node: node_ids[wire.from],
+                    output: wire.output,
+                },
+                InPinId {
+                    node: node_ids[wire.to],
+                    input: wire.input,
+                },
+            )
+        });
+        let external = self
+            .external_wires
+            .iter()
+            .filter(|_| keep_external_wires)
+            .filter(|wire| {
+                snarl.get_node(wire.from.node).is_some_and(|node| {
+                    node.name() == wire.name
+                        && node.outputs().get(wire.from.output).is_some_and(|&output| {
+                            let input = self.nodes[wire.to].node.inputs().get(wire.input);
+                            input.is_some_and(|&input| input & output != 0)
+                        })
+                })
+            })
+            .map(|wire| {
+                (wire.from, InPinId {
+                    node: node_ids[wire.to],
+                    input: wire.input,
+                })
+            });
+
+        let mut wires = internal.chain(external).collect::<Vec<_>>();
+        wires.sort_by_key(|(_, to)| (to.node.0, to.input));
+
+        // Collections add an input for every connection, their wires are packed from the first input.
+        let mut packed = FastHashMap::<NodeId, usize>::default();
+        for (from, mut to) in wires {
+            if matches!(snarl[to.node], Node::Collection(_)) {
+                let count = packed.entry(to.node).or_default();
+                to.input = *count;
+                *count += 1;
+            }
+            editor.connect_pins(from, to, snarl);
+            commands.push(Command::Connect { from, to });
+        }
+
+        (node_ids, Command::Group(commands))
+    }
+}
+
+/// Copy of the node without the state it builds from the connected nodes, nor the subscriptions of other nodes.
+fn detached(node: &Node) -> Node {
+    let mut node = match node {
+        Node::Collection(_) => Node::Collection(CollectionNode::default()),
+        Node::Scene(_) => Node::Scene(SceneNode::default()),
+        node => node.clone(),
+    };
+    if let Some(subscription) = node.subscription_mut() {
+        *subscription = Subscription::default();
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::item::{MathNode, MathOperation, NumberNode};
+
+    fn wire(from: NodeId, to: NodeId, input: usize) -> (OutPinId, InPinId) {
+        (OutPinId { node: from, output: 0 }, InPinId { node: to, input })
+    }
+
+    /// Two numbers feeding a math node, only the second number and the math node are copied.
+    fn graph() -> (Snarl<Node>, [NodeId; 3]) {
+        let mut snarl = Snarl::new();
+        let first = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let second = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let math = snarl.insert_node(Pos2::new(100.0, 0.0), Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(first, math, 0);
+        snarl.connect(from, to);
+        let (from, to) = wire(second, math, 1);
+        snarl.connect(from, to);
+        (snarl, [first, second, math])
+    }
+
+    #[test]
+    fn test_paste_wires_copied_nodes_under_new_ids() {
+        let (mut snarl, [_, second, math]) = graph();
+        let text = Fragment::copy([second, math], &snarl).to_json().unwrap();
+        let fragment = Fragment::from_json(&text).unwrap();
+
+        let (node_ids, command) = fragment.paste(Vec2::splat(20.0), false, &mut PlainEditor, &mut snarl);
+        assert_eq!(node_ids.len(), 2);
+        assert!(!node_ids.contains(&second) && !node_ids.contains(&math));
+        assert_eq!(snarl[node_ids[1]].name(), MathNode::NAME);
+        assert_eq!(snarl.get_node_info(node_ids[1]).unwrap().pos, Pos2::new(120.0, 20.0));
+
+        let pasted_wires = snarl
+            .wires()
+            .filter(|(_, to)| to.node == node_ids[1])
+            .collect::<Vec<_>>();
+        assert_eq!(pasted_wires, [wire(node_ids[0], node_ids[1], 1)]);
+        assert!(matches!(command, Command::Group(commands) if commands.len() == 3));
+    }
+
+    #[test]
+    fn test_paste_keeps_external_wires_on_request() {
+        let (mut snarl, [first, second, math]) = graph();
+        let fragment = Fragment::copy([second, math], &snarl);
+
+        let (node_ids, _) = fragment.paste(Vec2::ZERO, true, &mut PlainEditor, &mut snarl);
+        assert!(snarl.wires().any(|pasted| pasted == wire(first, node_ids[1], 0)));
+
+        // The node at the end of the wire was replaced by another kind of node.
+        snarl.remove_node(first);
+        snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        let (node_ids, _) = fragment.paste(Vec2::ZERO, true, &mut PlainEditor, &mut snarl);
+        assert_eq!(snarl.wires().filter(|(_, to)| to.node == node_ids[1]).count(), 1);
+    }
+}
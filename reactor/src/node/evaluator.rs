@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+
+use eframe::wgpu::naga::{FastHashMap, FastHashSet};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use reactor_types::cast::ForceCast;
+use reactor_types::{Angle, Color, Float, NodePin, Vector, Vector4};
+use thiserror::Error;
+
+use super::item::InputMaterial;
+use super::message::SelfNodeMut;
+use super::subscribtion::Event;
+use super::{Node, Noded};
+use crate::expression::Value;
+
+/// Value of an output pin.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeOutput {
+    Number(Float),
+    Vector(Vector),
+    Color(Color),
+    String(String),
+    /// Materials, textures, scenes and other nodes without a plain value are read by the connected nodes directly.
+    Node(NodeId),
+}
+
+impl NodeOutput {
+    /// Vectors give their first component.
+    pub fn as_number(&self) -> Option<Float> {
+        match self {
+            Self::Number(number) => Some(*number),
+            Self::Vector(vector) => Some(vector[0]),
+            Self::Color(_) | Self::String(_) | Self::Node(_) => None,
+        }
+    }
+
+    /// Numbers are splatted and colors become 4D vectors.
+    pub fn as_vector(&self) -> Option<Vector> {
+        match self {
+            Self::Number(number) => Some(Vector::from_scalar(*number)),
+            Self::Vector(vector) => Some(*vector),
+            Self::Color(color) => Some(color_vector(*color)),
+            Self::String(_) | Self::Node(_) => None,
+        }
+    }
+
+    /// Numbers become gray and vectors are read as gray with alpha, RGB or RGBA.
+    pub fn as_color(&self) -> Option<Color> {
+        match self {
+            Self::Number(number) => Some(gray_color(*number)),
+            Self::Vector(vector) => Some(vector_color(*vector)),
+            Self::Color(color) => Some(*color),
+            Self::String(_) | Self::Node(_) => None,
+        }
+    }
+
+    pub fn as_value(&self) -> Option<Value> {
+        match self {
+            Self::Number(number) => Some(Value::Number(*number)),
+            Self::Vector(vector) => Some(Value::Vector(*vector)),
+            Self::Color(color) => Some(Value::Vector(color_vector(*color))),
+            Self::String(_) | Self::Node(_) => None,
+        }
+    }
+
+    pub fn as_node(&self) -> Option<NodeId> {
+        match self {
+            Self::Node(node_id) => Some(*node_id),
+            Self::Number(_) | Self::Vector(_) | Self::Color(_) | Self::String(_) => None,
+        }
+    }
+}
+
+impl From<Value> for NodeOutput {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Number(number) => Self::Number(number),
+            Value::Vector(vector) => Self::Vector(vector),
+        }
+    }
+}
+
+fn color_vector(color: Color) -> Vector {
+    let color = color.to_normalized_gamma_f32();
+    Vector::Dim4(Vector4::new(color[0] as _, color[1] as _, color[2] as _, color[3] as _))
+}
+
+fn gray_color(value: Float) -> Color {
+    Color::from_gray((value * 255.0).round() as u8)
+}
+
+fn vector_color(vector: Vector) -> Color {
+    match vector {
+        Vector::Dim2(vector) => {
+            let mut color = gray_color(vector.x);
+            color[3] = (vector.y * 255.0).round() as u8;
+            color
+        },
+        Vector::Dim3(vector) => Color::from_rgb(
+            (vector.x * 255.0).round() as u8,
+            (vector.y * 255.0).round() as u8,
+            (vector.z * 255.0).round() as u8,
+        ),
+        Vector::Dim4(vector) => Color::from_rgba_premultiplied(
+            (vector.x * 255.0).round() as u8,
+            (vector.y * 255.0).round() as u8,
+            (vector.z * 255.0).round() as u8,
+            (vector.w * 255.0).round() as u8,
+        ),
+    }
+}
+
+/// Pin values which can be taken from the output of another node.
+pub trait FromNodeOutput: Sized {
+    fn from_node_output(output: &NodeOutput) -> Option<Self>;
+}
+
+impl FromNodeOutput for Float {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_number()
+    }
+}
+
+impl FromNodeOutput for f32 {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_number().map(ForceCast::force_cast)
+    }
+}
+
+impl FromNodeOutput for u32 {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_number().map(ForceCast::force_cast)
+    }
+}
+
+impl FromNodeOutput for Angle {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_number().map(Angle::from)
+    }
+}
+
+impl FromNodeOutput for Vector {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_vector()
+    }
+}
+
+impl FromNodeOutput for Color {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_color()
+    }
+}
+
+impl FromNodeOutput for Value {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_value()
+    }
+}
+
+impl FromNodeOutput for Option<NodeId> {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_node().map(Some)
+    }
+}
+
+impl FromNodeOutput for InputMaterial {
+    fn from_node_output(output: &NodeOutput) -> Option<Self> {
+        output.as_node().map(InputMaterial::External)
+    }
+}
+
+/// Sets the pin from the output of the connected node, returns whether its value changed.
+pub fn apply_output<T>(pin: &mut NodePin<T>, output: &NodeOutput) -> bool
+where
+    T: FromNodeOutput + PartialEq,
+{
+    match T::from_node_output(output) {
+        Some(value) if value != *pin.as_ref() => {
+            pin.set(value);
+            true
+        },
+        _ => false,
+    }
+}
+
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum EvaluationError {
+    #[error("nodes {0:?} are part of a cycle or depend on one")]
+    Cycle(Vec<NodeId>),
+}
+
+#[derive(Clone)]
+struct CachedOutputs {
+    outputs: Vec<NodeOutput>,
+    dirty: bool,
+}
+
+/// Evaluates the graph from the inputs to the outputs, memoizing the outputs of every node until it's invalidated.
+#[derive(Clone, Default)]
+pub struct Evaluator {
+    cache: FastHashMap<NodeId, CachedOutputs>,
+}
+
+impl Evaluator {
+    /// Marks the node for reevaluation, the nodes connected to its outputs follow if its outputs change.
+    pub fn invalidate(&mut self, node_id: NodeId) {
+        if let Some(cached) = self.cache.get_mut(&node_id) {
+            cached.dirty = true;
+        }
+    }
+
+    pub fn remove(&mut self, node_id: NodeId) {
+        self.cache.remove(&node_id);
+    }
+
+    pub fn output(&self, pin: OutPinId) -> Option<&NodeOutput> {
+        self.cache.get(&pin.node)?.outputs.get(pin.output)
+    }
+
+    /// Sets the keyed inputs of the nodes at the time in seconds. The changed nodes are invalidated and notify their
+    /// subscribers, returns whether any node changed.
+    pub fn animate(&mut self, time: f32, snarl: &mut Snarl<Node>) -> bool {
+        let animated = snarl
+            .nodes_ids_mut()
+            .filter_map(|(node_id, node)| node.animate(time).then_some(node_id))
+            .collect::<Vec<_>>();
+        for &node_id in &animated {
+            self.invalidate(node_id);
+            if let Some(caller) = snarl[node_id]
+                .subscription_ref()
+                .and_then(|subscription| subscription.event_caller(Event::OnChange))
+            {
+                caller(SelfNodeMut::new(node_id, snarl));
+            }
+        }
+        !animated.is_empty()
+    }
+
+    /// Reevaluates the invalidated nodes and the nodes whose inputs changed, in topological order. Inputs which change
+    /// notify the subscribers of the node. Nodes in a cycle are skipped and reported.
+    pub fn evaluate(&mut self, snarl: &mut Snarl<Node>) -> Result<(), EvaluationError> {
+        let wires = snarl.wires().collect::<Vec<_>>();
+        let node_ids = snarl.node_ids().map(|(node_id, _)| node_id).collect::<Vec<_>>();
+        let existing = node_ids.iter().copied().collect::<FastHashSet<_>>();
+        self.cache.retain(|node_id, _| existing.contains(node_id));
+
+        let mut input_wires = FastHashMap::<NodeId, Vec<(OutPinId, InPinId)>>::default();
+        for &(from, to) in &wires {
+            input_wires.entry(to.node).or_default().push((from, to));
+        }
+
+        let (order, cycle) = topological_order(node_ids, wires);
+        let mut changed = FastHashSet::default();
+
+        for node_id in order {
+            let inputs = input_wires.get(&node_id).map_or(&[][..], Vec::as_slice);
+            let inputs_changed = inputs.iter().any(|(from, _)| changed.contains(&from.node));
+            if !inputs_changed && self.cache.get(&node_id).is_some_and(|cached| !cached.dirty) {
+                continue;
+            }
+
+            let mut pins_changed = false;
+            for &(from, to) in inputs {
+                if let Some(output) = self.output(from) {
+                    pins_changed |= snarl[node_id].set_input(to.input, output);
+                }
+            }
+            if pins_changed {
+                if let Some(caller) = snarl[node_id]
+                    .subscription_ref()
+                    .and_then(|subscription| subscription.event_caller(Event::OnChange))
+                {
+                    caller(SelfNodeMut::new(node_id, snarl));
+                }
+            }
+
+            let node = &snarl[node_id];
+            let outputs = (0..node.outputs().len())
+                .map(|output| node.output(node_id, output))
+                .collect::<Vec<_>>();
+            if self.cache.get(&node_id).is_none_or(|cached| cached.outputs != outputs) {
+                changed.insert(node_id);
+            }
+            self.cache.insert(node_id, CachedOutputs { outputs, dirty: false });
+        }
+
+        if cycle.is_empty() {
+            Ok(())
+        } else {
+            Err(EvaluationError::Cycle(cycle))
+        }
+    }
+}
+
+/// Orders the nodes so every node comes after the nodes connected to its inputs. Nodes which are part of a cycle or
+/// depend on one can't be ordered and are returned separately.
+fn topological_order(
+    node_ids: impl IntoIterator<Item = NodeId>,
+    wires: impl IntoIterator<Item = (OutPinId, InPinId)>,
+) -> (Vec<NodeId>, Vec<NodeId>) {
+    let node_ids = node_ids.into_iter().collect::<Vec<_>>();
+    let mut in_degrees = node_ids
+        .iter()
+        .map(|&node_id| (node_id, 0))
+        .collect::<FastHashMap<_, usize>>();
+    let mut downstream = FastHashMap::<NodeId, Vec<NodeId>>::default();
+
+    for (from, to) in wires {
+        *in_degrees.entry(to.node).or_default() += 1;
+        downstream.entry(from.node).or_default().push(to.node);
+    }
+
+    let mut ready = node_ids
+        .iter()
+        .copied()
+        .filter(|node_id| in_degrees[node_id] == 0)
+        .collect::<VecDeque<_>>();
+    let mut order = Vec::with_capacity(node_ids.len());
+
+    while let Some(node_id) = ready.pop_front() {
+        order.push(node_id);
+        for next in downstream.get(&node_id).into_iter().flatten() {
+            let in_degree = in_degrees.get_mut(next).expect("wires connect known nodes");
+            *in_degree -= 1;
+            if *in_degree == 0 {
+                ready.push_back(*next);
+            }
+        }
+    }
+
+    let cycle = node_ids.into_iter().filter(|node_id| in_degrees[node_id] > 0).collect();
+    (order, cycle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wire(from: usize, to: usize) -> (OutPinId, InPinId) {
+        (
+            OutPinId {
+                node: NodeId(from),
+                output: 0,
+            },
+            InPinId {
+                node: NodeId(to),
+                input: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn test_topological_order_puts_inputs_first() {
+        let nodes = [3, 2, 1, 0].map(NodeId);
+        let (order, cycle) = topological_order(nodes, [wire(0, 1), wire(1, 3), wire(0, 2), wire(2, 3)]);
+
+        let position = |node| order.iter().position(|&node_id| node_id == NodeId(node)).unwrap();
+        assert_eq!(order.len(), 4);
+        assert!(position(0) < position(1) && position(0) < position(2));
+        assert!(position(1) < position(3) && position(2) < position(3));
+        assert!(cycle.is_empty());
+    }
+
+    #[test]
+    fn test_topological_order_reports_cycles() {
+        let nodes = [0, 1, 2, 3].map(NodeId);
+        let (order, cycle) = topological_order(nodes, [wire(0, 1), wire(1, 2), wire(2, 1), wire(2, 3)]);
+
+        assert_eq!(order, [NodeId(0)]);
+        assert_eq!(cycle, [1, 2, 3].map(NodeId));
+    }
+
+    #[test]
+    fn test_output_conversions() {
+        let vector = NodeOutput::Vector(Vector::from_scalar(0.5));
+        assert_eq!(vector.as_number(), Some(0.5));
+        assert_eq!(NodeOutput::Number(1.0).as_color(), Some(Color::WHITE));
+        assert_eq!(NodeOutput::Node(NodeId(0)).as_vector(), None);
+        assert_eq!(
+            Option::<NodeId>::from_node_output(&NodeOutput::Node(NodeId(1))),
+            Some(Some(NodeId(1)))
+        );
+    }
+}
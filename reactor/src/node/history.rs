@@ -0,0 +1,382 @@
+use egui::{Id, Pos2, RichText, Ui};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use thiserror::Error;
+
+use super::{Node, Noded};
+
+/// Edits of the graph which the history applies and reverts, the editor keeps its own state in sync with the graph.
+///
+/// Commands keep the ids of their nodes: the graph reuses the most recently freed id, so undoing and redoing in order
+/// inserts removed nodes under their old ids. When it doesn't, the history is cleared with [`HistoryError`].
+pub trait Editor {
+    fn insert_node(&mut self, pos: Pos2, node: Node, snarl: &mut Snarl<Node>) -> NodeId;
+    fn take_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) -> Node;
+    fn connect_pins(&mut self, from: OutPinId, to: InPinId, snarl: &mut Snarl<Node>);
+    fn disconnect_pins(&mut self, from: OutPinId, to: InPinId, snarl: &mut Snarl<Node>);
+    /// Restores a snapshot of the node, see [`Node::restore_snapshot`].
+    fn replace_node(&mut self, node_id: NodeId, node: Node, snarl: &mut Snarl<Node>);
+}
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("the node {expected:?} was inserted as {actual:?}")]
+    NodeIdChanged { expected: NodeId, actual: NodeId },
+}
+
+pub enum Command {
+    AddNode {
+        node_id: NodeId,
+        pos: Pos2,
+        node: Box<Node>,
+    },
+    RemoveNode {
+        node_id: NodeId,
+        pos: Pos2,
+        node: Box<Node>,
+    },
+    Connect {
+        from: OutPinId,
+        to: InPinId,
+    },
+    Disconnect {
+        from: OutPinId,
+        to: InPinId,
+    },
+    /// Change of the pins or other settings of a node, kept as snapshots made by [`Node::edit_snapshot`].
+    EditNode {
+        node_id: NodeId,
+        before: Box<Node>,
+        after: Box<Node>,
+    },
+    /// Commands which are undone together, e.g. the removal of a node with its wires.
+    Group(Vec<Command>),
+}
+
+impl Command {
+    pub fn label(&self, snarl: &Snarl<Node>) -> String {
+        let name = |node_id| snarl.get_node(node_id).map_or("node", Noded::name);
+        match self {
+            Self::AddNode { node, .. } => format!("Add {}", node.name()),
+            Self::RemoveNode { node, .. } => format!("Remove {}", node.name()),
+            Self::Connect { from, to } => format!("Connect {} to {}", name(from.node), name(to.node)),
+            Self::Disconnect { from, to } => format!("Disconnect {} from {}", name(from.node), name(to.node)),
+            Self::EditNode { after, .. } => format!("Edit {}", after.name()),
+            Self::Group(commands) => commands
+                .iter()
+                .find(|command| !matches!(command, Self::Connect { .. } | Self::Disconnect { .. }))
+                .or(commands.last())
+                .map_or_else(String::new, |command| command.label(snarl)),
+        }
+    }
+
+    /// Inserts the node, the later commands refer to it by its old id.
+    fn insert_node(
+        node_id: NodeId,
+        pos: Pos2,
+        node: &Node,
+        editor: &mut impl Editor,
+        snarl: &mut Snarl<Node>,
+    ) -> Result<(), HistoryError> {
+        let new_id = editor.insert_node(pos, node.clone(), snarl);
+        if new_id != node_id {
+            return Err(HistoryError::NodeIdChanged {
+                expected: node_id,
+                actual: new_id,
+            });
+        }
+        Ok(())
+    }
+
+    fn apply(&self, editor: &mut impl Editor, snarl: &mut Snarl<Node>) -> Result<(), HistoryError> {
+        match self {
+            Self::AddNode { node_id, pos, node } => return Self::insert_node(*node_id, *pos, node, editor, snarl),
+            Self::RemoveNode { node_id, .. } => {
+                editor.take_node(*node_id, snarl);
+            },
+            Self::Connect { from, to } => editor.connect_pins(*from, *to, snarl),
+            Self::Disconnect { from, to } => editor.disconnect_pins(*from, *to, snarl),
+            Self::EditNode { node_id, after, .. } => editor.replace_node(*node_id, Node::clone(after), snarl),
+            Self::Group(commands) => return commands.iter().try_for_each(|command| command.apply(editor, snarl)),
+        }
+        Ok(())
+    }
+
+    fn revert(&self, editor: &mut impl Editor, snarl: &mut Snarl<Node>) -> Result<(), HistoryError> {
+        match self {
+            Self::AddNode { node_id, .. } => {
+                editor.take_node(*node_id, snarl);
+            },
+            Self::RemoveNode { node_id, pos, node } => return Self::insert_node(*node_id, *pos, node, editor, snarl),
+            Self::Connect { from, to } => editor.disconnect_pins(*from, *to, snarl),
+            Self::Disconnect { from, to } => editor.connect_pins(*from, *to, snarl),
+            Self::EditNode { node_id, before, .. } => editor.replace_node(*node_id, Node::clone(before), snarl),
+            Self::Group(commands) => {
+                return commands
+                    .iter()
+                    .rev()
+                    .try_for_each(|command| command.revert(editor, snarl));
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Editor of graphs which aren't shown, like the graphs inside groups.
+pub struct PlainEditor;
+
+impl Editor for PlainEditor {
+    fn insert_node(&mut self, pos: Pos2, node: Node, snarl: &mut Snarl<Node>) -> NodeId {
+        snarl.insert_node(pos, node)
+    }
+
+    fn take_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) -> Node {
+        snarl.remove_node(node_id)
+    }
+
+    fn connect_pins(&mut self, from: OutPinId, to: InPinId, snarl: &mut Snarl<Node>) {
+        snarl.connect(from, to);
+    }
+
+    fn disconnect_pins(&mut self, from: OutPinId, to: InPinId, snarl: &mut Snarl<Node>) {
+        snarl.disconnect(from, to);
+    }
+
+    fn replace_node(&mut self, node_id: NodeId, node: Node, snarl: &mut Snarl<Node>) {
+        snarl[node_id].restore_snapshot(node);
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+    /// Widget whose edits are merged into the latest command, e.g. a dragged number field.
+    coalescing: Option<Id>,
+}
+
+impl History {
+    pub fn undo_count(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn push(&mut self, command: Command) {
+        self.undo.push(command);
+        self.redo.clear();
+        self.coalescing = None;
+    }
+
+    /// Records an edit of the node, consecutive edits through the same widget are merged into one command.
+    pub fn push_edit(&mut self, node_id: NodeId, before: Node, after: Node, widget: Option<Id>) {
+        if widget.is_some() && widget == self.coalescing {
+            if let Some(Command::EditNode {
+                node_id: last_id,
+                after: last_after,
+                ..
+            }) = self.undo.last_mut()
+            {
+                if *last_id == node_id {
+                    *last_after = Box::new(after);
+                    return;
+                }
+            }
+        }
+
+        self.push(Command::EditNode {
+            node_id,
+            before: Box::new(before),
+            after: Box::new(after),
+        });
+        self.coalescing = widget;
+    }
+
+    /// Merges the commands pushed since the undo count was `start` into one.
+    pub fn group_since(&mut self, start: usize) {
+        if start + 1 < self.undo.len() {
+            let commands = self.undo.split_off(start);
+            self.undo.push(Command::Group(commands));
+        }
+    }
+
+    /// Returns false if there was nothing to undo or the undo failed, which clears the history: the remaining
+    /// commands may refer to the wrong nodes.
+    pub fn undo(&mut self, editor: &mut impl Editor, snarl: &mut Snarl<Node>) -> bool {
+        let Some(command) = self.undo.pop() else {
+            return false;
+        };
+        if let Err(err) = command.revert(editor, snarl) {
+            tracing::error!("Failed to undo `{}`, clearing the history: {err}", command.label(snarl));
+            self.clear();
+            return false;
+        }
+        self.redo.push(command);
+        self.coalescing = None;
+        true
+    }
+
+    /// Returns false if there was nothing to redo or the redo failed, see [`Self::undo`].
+    pub fn redo(&mut self, editor: &mut impl Editor, snarl: &mut Snarl<Node>) -> bool {
+        let Some(command) = self.redo.pop() else {
+            return false;
+        };
+        if let Err(err) = command.apply(editor, snarl) {
+            tracing::error!("Failed to redo `{}`, clearing the history: {err}", command.label(snarl));
+            self.clear();
+            return false;
+        }
+        self.undo.push(command);
+        self.coalescing = None;
+        true
+    }
+
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.coalescing = None;
+    }
+
+    /// Lists the commands, clicking one undoes or redoes the commands up to it.
+    pub fn show(&mut self, ui: &mut Ui, editor: &mut impl Editor, snarl: &mut Snarl<Node>) {
+        let mut target = None;
+
+        if ui.selectable_label(self.undo.is_empty(), "Initial state").clicked() {
+            target = Some(0);
+        }
+        for (idx, command) in self.undo.iter().enumerate() {
+            if ui
+                .selectable_label(idx + 1 == self.undo.len(), command.label(snarl))
+                .clicked()
+            {
+                target = Some(idx + 1);
+            }
+        }
+        for (idx, command) in self.redo.iter().rev().enumerate() {
+            if ui
+                .selectable_label(false, RichText::new(command.label(snarl)).weak())
+                .clicked()
+            {
+                target = Some(self.undo.len() + idx + 1);
+            }
+        }
+
+        if let Some(target) = target {
+            while self.undo.len() > target && self.undo(editor, snarl) {}
+            while self.undo.len() < target && self.redo(editor, snarl) {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::item::{MathNode, MathOperation, NumberNode, StringNode};
+
+    fn add_node(history: &mut History, snarl: &mut Snarl<Node>, node: Node) -> NodeId {
+        let node_id = snarl.insert_node(Pos2::ZERO, node.clone());
+        history.push(Command::AddNode {
+            node_id,
+            pos: Pos2::ZERO,
+            node: Box::new(node),
+        });
+        node_id
+    }
+
+    fn wire(from: NodeId, to: NodeId) -> (OutPinId, InPinId) {
+        (OutPinId { node: from, output: 0 }, InPinId { node: to, input: 0 })
+    }
+
+    #[test]
+    fn test_undo_and_redo_restore_the_graph() {
+        let mut snarl = Snarl::new();
+        let mut history = History::default();
+
+        let number = add_node(&mut history, &mut snarl, Node::Number(NumberNode::default()));
+        let math = add_node(&mut history, &mut snarl, Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(number, math);
+        snarl.connect(from, to);
+        history.push(Command::Connect { from, to });
+
+        assert!(history.undo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl.wires().count(), 0);
+        assert!(history.undo(&mut PlainEditor, &mut snarl));
+        assert!(history.undo(&mut PlainEditor, &mut snarl));
+        assert!(!history.undo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl.node_ids().count(), 0);
+
+        while history.redo(&mut PlainEditor, &mut snarl) {}
+        assert_eq!(snarl.node_ids().count(), 2);
+        assert_eq!(snarl.wires().collect::<Vec<_>>(), [(from, to)]);
+    }
+
+    #[test]
+    fn test_grouped_removal_restores_wires() {
+        let mut snarl = Snarl::new();
+        let mut history = History::default();
+
+        let number = add_node(&mut history, &mut snarl, Node::Number(NumberNode::default()));
+        let math = add_node(&mut history, &mut snarl, Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(number, math);
+        snarl.connect(from, to);
+
+        let start = history.undo_count();
+        snarl.disconnect(from, to);
+        history.push(Command::Disconnect { from, to });
+        let node = snarl.remove_node(number);
+        history.push(Command::RemoveNode {
+            node_id: number,
+            pos: Pos2::ZERO,
+            node: Box::new(node),
+        });
+        history.group_since(start);
+
+        assert_eq!(history.undo_count(), 3);
+        assert!(history.undo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl.node_ids().count(), 2);
+        assert_eq!(snarl.wires().count(), 1);
+    }
+
+    #[test]
+    fn test_undo_clears_the_history_when_the_node_id_changes() {
+        let mut snarl = Snarl::new();
+        let mut history = History::default();
+
+        let number = add_node(&mut history, &mut snarl, Node::Number(NumberNode::default()));
+        let node = snarl.remove_node(number);
+        history.push(Command::RemoveNode {
+            node_id: number,
+            pos: Pos2::ZERO,
+            node: Box::new(node),
+        });
+        // A node inserted outside the history takes the freed id.
+        let other = snarl.insert_node(Pos2::ZERO, Node::String(StringNode::default()));
+        assert_eq!(other, number);
+
+        assert!(!history.undo(&mut PlainEditor, &mut snarl));
+        assert_eq!(history.undo_count(), 0);
+        assert!(!history.redo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl[other].name(), StringNode::NAME);
+    }
+
+    #[test]
+    fn test_edits_through_the_same_widget_coalesce() {
+        let mut snarl = Snarl::new();
+        let mut history = History::default();
+        let node_id = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let number = snarl[node_id].clone();
+        let string = Node::String(StringNode::default());
+        let drag = Some(Id::new("drag"));
+
+        history.push_edit(node_id, number.clone(), string.clone(), drag);
+        history.push_edit(node_id, string.clone(), string.clone(), drag);
+        assert_eq!(history.undo_count(), 1);
+
+        snarl[node_id] = string.clone();
+        assert!(history.undo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl[node_id].name(), NumberNode::NAME);
+        assert!(history.redo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl[node_id].name(), StringNode::NAME);
+
+        // Undo and redo end the merging, even for the same widget.
+        history.push_edit(node_id, string, number, drag);
+        assert_eq!(history.undo_count(), 2);
+    }
+}
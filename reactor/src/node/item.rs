@@ -1,25 +1,35 @@
 pub mod camera;
 pub mod collection;
 pub mod color;
+pub mod expression;
+pub mod group;
 pub mod material;
+pub mod math;
 pub mod number;
 pub mod output;
 pub mod primitive;
+pub mod procedural_texture;
 pub mod render;
 pub mod scene;
 pub mod string;
 pub mod texture;
 pub mod vector;
+pub mod vector_math;
 
 pub use self::camera::CameraNode;
 pub use self::collection::CollectionNode;
 pub use self::color::ColorNode;
+pub use self::expression::ExpressionNode;
+pub use self::group::GroupNode;
 pub use self::material::{InputMaterial, MaterialNode};
+pub use self::math::{MathNode, MathOperation};
 pub use self::number::NumberNode;
 pub use self::output::OutputNode;
 pub use self::primitive::PrimitiveNode;
+pub use self::procedural_texture::ProceduralTextureNode;
 pub use self::render::RenderNode;
 pub use self::scene::SceneNode;
 pub use self::string::StringNode;
 pub use self::texture::TextureNode;
 pub use self::vector::VectorNode;
+pub use self::vector_math::{VectorMathNode, VectorOperation};
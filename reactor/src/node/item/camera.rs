@@ -8,10 +8,11 @@ use reactor_types::{Angle, Float, Matrix3, NodePin, Vector, Vector3};
 use serde::{Deserialize, Serialize};
 
 use crate::node::message::{MessageHandling, SelfNodeMut};
+use crate::node::viewer::gizmo::TransformPins;
 use crate::node::viewer::ui::{input, output};
 use crate::node::{Node, NodeFlags, Noded};
 
-#[derive(Clone, Serialize, Deserialize, Noded)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Noded)]
 pub struct CameraNode {
     pub position: NodePin<Vector>,
     pub yaw: NodePin<Angle>,
@@ -57,6 +58,14 @@ impl CameraNode {
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::CAMERA.bits()];
 
+    pub fn transform_mut(&mut self) -> TransformPins<'_> {
+        TransformPins {
+            position: Some((0, &mut self.position)),
+            rotation: Some((1, &mut self.yaw)),
+            ..Default::default()
+        }
+    }
+
     pub fn to_xrays_camera(&self) -> xrays::Camera {
         let orientation = self.orientation();
 
@@ -8,7 +8,7 @@ use crate::node::subscribtion::{Event, Subscription};
 use crate::node::viewer::ui::{input, output};
 use crate::node::{Node, NodeFlags, Noded, collect_for_node};
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct CollectionNode {
     nodes: Vec<NodeId>,
     inputs: Vec<u64>,
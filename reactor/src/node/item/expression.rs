@@ -0,0 +1,275 @@
+use egui::{TextEdit, Ui};
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, InPinId, NodeId, OutPin, Snarl};
+use reactor_types::NodePin;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::expression::{EvalError, Expression, ParseError, Value};
+use crate::node::evaluator::{self, NodeOutput};
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
+use crate::node::viewer::remote;
+use crate::node::viewer::ui::{input, output};
+use crate::node::{Node, NodeFlags, Noded};
+
+const MAX_VARIABLES: usize = 16;
+
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum ExpressionError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+    #[error("at most {MAX_VARIABLES} variables are supported")]
+    TooManyVariables,
+}
+
+/// Text of the expression together with its parse result, only the text is saved.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+struct Source {
+    text: String,
+    parsed: Result<Expression, ExpressionError>,
+}
+
+impl From<String> for Source {
+    fn from(text: String) -> Self {
+        let parsed = Expression::parse(&text)
+            .map_err(ExpressionError::from)
+            .and_then(|expression| {
+                if expression.variables().len() > MAX_VARIABLES {
+                    Err(ExpressionError::TooManyVariables)
+                } else {
+                    Ok(expression)
+                }
+            });
+        Self { text, parsed }
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl From<Source> for String {
+    fn from(source: Source) -> Self {
+        source.text
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Variable {
+    name: String,
+    value: NodePin<Value>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpressionNode {
+    source: Source,
+    /// An input for every variable of the last expression which was parsed successfully.
+    variables: Vec<Variable>,
+}
+
+impl Default for ExpressionNode {
+    fn default() -> Self {
+        let mut node = Self {
+            source: Source::from("x".to_owned()),
+            variables: Vec::new(),
+        };
+        node.update_variables();
+        node
+    }
+}
+
+impl ExpressionNode {
+    pub const NAME: &str = "Expression";
+    pub const INPUTS: [u64; MAX_VARIABLES] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits(); MAX_VARIABLES];
+    /// Inputs of the default expression.
+    pub const DEFAULT_INPUTS: [u64; 1] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits()];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::NUMBER.bits() | NodeFlags::VECTOR.bits()];
+
+    pub fn evaluate(&self) -> Result<Value, ExpressionError> {
+        let expression = self.source.parsed.as_ref().map_err(Clone::clone)?;
+        let values = self
+            .variables
+            .iter()
+            .map(|variable| variable.value.get())
+            .collect::<Vec<_>>();
+        Ok(expression.evaluate(&values)?)
+    }
+
+    /// The result of the expression, zero if it fails.
+    pub fn value(&self) -> Value {
+        self.evaluate().unwrap_or_default()
+    }
+
+    /// Replaces the variables by the ones of the parsed expression, keeping the values of variables with the same
+    /// names. Returns the new input index of every old variable.
+    fn update_variables(&mut self) -> Vec<Option<usize>> {
+        let names = match &self.source.parsed {
+            Ok(expression) => expression.variables().to_vec(),
+            Err(_) => return (0..self.variables.len()).map(Some).collect(),
+        };
+
+        let new_indices = self
+            .variables
+            .iter()
+            .map(|variable| names.iter().position(|name| *name == variable.name))
+            .collect();
+
+        let mut old_variables = std::mem::take(&mut self.variables);
+        self.variables = names
+            .into_iter()
+            .map(
+                |name| match old_variables.iter().position(|variable| variable.name == name) {
+                    Some(idx) => old_variables.swap_remove(idx),
+                    None => Variable {
+                        name,
+                        value: NodePin::default(),
+                    },
+                },
+            )
+            .collect();
+
+        new_indices
+    }
+}
+
+/// Moves the wires of the node inputs to their new indices, wires of removed inputs are dropped.
+fn move_input_wires(node_id: NodeId, new_indices: &[Option<usize>], snarl: &mut Snarl<Node>) {
+    let wires = snarl.wires().filter(|(_, to)| to.node == node_id).collect::<Vec<_>>();
+
+    for &(from, to) in &wires {
+        snarl.disconnect(from, to);
+    }
+    for (from, to) in wires {
+        if let Some(&Some(input)) = new_indices.get(to.input) {
+            snarl.connect(from, InPinId { node: node_id, input });
+        }
+    }
+}
+
+impl Noded for ExpressionNode {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn inputs(&self) -> &[u64] {
+        let inputs: &'static [u64; MAX_VARIABLES] = &Self::INPUTS;
+        &inputs[..self.variables.len()]
+    }
+
+    fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    fn reset_input(&mut self, pin: &InPin) -> bool {
+        match self.variables.get_mut(pin.id.input) {
+            Some(variable) => {
+                variable.value.reset();
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn set_input(&mut self, input: usize, output: &NodeOutput) -> bool {
+        self.variables
+            .get_mut(input)
+            .is_some_and(|variable| evaluator::apply_output(&mut variable.value, output))
+    }
+
+    fn animate(&mut self, time: f32) -> bool {
+        self.variables
+            .iter_mut()
+            .fold(false, |changed, variable| variable.value.animate(time) | changed)
+    }
+}
+
+impl MessageHandling for ExpressionNode {
+    fn handle_display_input(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let name = self_node
+            .node_ref()
+            .as_expression_ref()
+            .variables
+            .get(pin.id.input)?
+            .name
+            .clone();
+        let remote_value = remote::expression_value(pin, &name, self_node.snarl);
+        let variable = &mut self_node.node_mut().as_expression_mut().variables[pin.id.input];
+
+        Some(input::value_view(ui, &name, &mut variable.value, remote_value))
+    }
+
+    fn handle_display_body<'a>(
+        mut self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        let node = self_node.node_mut().as_expression_mut();
+
+        let response = ui.add(
+            TextEdit::singleline(&mut node.source.text)
+                .code_editor()
+                .desired_width(160.0),
+        );
+        if response.changed() {
+            node.source = Source::from(std::mem::take(&mut node.source.text));
+            if node.source.parsed.is_ok() {
+                let new_indices = node.update_variables();
+                move_input_wires(self_node.id, &new_indices, self_node.snarl);
+            }
+        }
+
+        if let Err(err) = self_node.node_ref().as_expression_ref().evaluate() {
+            ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+        }
+
+        None
+    }
+
+    fn handle_display_output(self_node: SelfNodeMut, pin: &OutPin, ui: &mut Ui) -> Option<PinInfo> {
+        if pin.id.output == 0 {
+            Some(output::value_label(
+                ui,
+                "",
+                self_node.node_ref().as_expression_ref().value(),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables_keep_values_by_name() {
+        let mut node = ExpressionNode {
+            source: Source::from("a + b".to_owned()),
+            variables: Vec::new(),
+        };
+        node.update_variables();
+        node.variables[1].value.set(Value::Number(2.0));
+
+        node.source = Source::from("b * c".to_owned());
+        assert_eq!(node.update_variables(), [None, Some(0)]);
+        assert_eq!(node.variables[0].value.get(), Value::Number(2.0));
+        assert_eq!(node.variables[1].value.get(), Value::default());
+        assert_eq!(node.evaluate(), Ok(Value::Number(0.0)));
+    }
+
+    #[test]
+    fn test_failed_parse_keeps_variables() {
+        let mut node = ExpressionNode::default();
+        node.source = Source::from("x +".to_owned());
+        assert_eq!(node.update_variables(), [Some(0)]);
+        assert_eq!(node.variables.len(), 1);
+        assert!(matches!(node.evaluate(), Err(ExpressionError::Parse(_))));
+    }
+}
@@ -0,0 +1,745 @@
+use std::path::PathBuf;
+use std::{fs, io};
+
+use eframe::wgpu::naga::FastIndexSet;
+use egui::{Pos2, TextEdit, Ui, Vec2};
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::node::clipboard::Fragment;
+use crate::node::evaluator::{Evaluator, NodeOutput};
+use crate::node::history::{Command, Editor, PlainEditor};
+use crate::node::message::{
+    CommonNodeResponse, DisplayMessage, DisplayResponse, MessageHandling, SelectedTab, SelfNodeMut,
+};
+use crate::node::subscribtion::{Event, Subscription};
+use crate::node::viewer::ui::{input, output, widget_events, widgets_changed_since};
+use crate::node::{Node, NodeFlags, Noded, collect_for_node};
+
+/// Only values are passed into a group, the ids of the nodes outside mean nothing in its graph.
+const INPUT_FLAGS: u64 =
+    NodeFlags::NUMBER.bits() | NodeFlags::STRING.bits() | NodeFlags::VECTOR.bits() | NodeFlags::COLOR.bits();
+/// Scenes walk into the group for the primitives of its outputs.
+const OUTPUT_FLAGS: u64 = INPUT_FLAGS | NodeFlags::PRIMITIVES.bits() | NodeFlags::COLLECTION.bits();
+
+#[derive(Error, Debug)]
+pub enum GroupError {
+    #[error("no nodes to group")]
+    Empty,
+    #[error("`{0}` nodes can't be grouped")]
+    Unsupported(String),
+    #[error("only values can be passed into a group, `{0}` gives something else")]
+    NodeInput(String),
+    #[error("only values, primitives and collections can be passed out of a group, `{0}` gives something else")]
+    NodeOutput(String),
+    #[error("there is no directory for the library")]
+    NoLibrary,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Subgraph shown as a single node, its inputs and outputs are pins of the inner nodes chosen by the user.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GroupNode {
+    name: String,
+    graph: Snarl<Node>,
+    exposed_inputs: Vec<InPinId>,
+    input_flags: Vec<u64>,
+    exposed_outputs: Vec<OutPinId>,
+    output_flags: Vec<u64>,
+
+    /// Pins chosen in the body, the viewer exposes them with [`Self::expose_pins`] because the wires move too.
+    #[serde(skip)]
+    pin_request: Option<(Vec<InPinId>, Vec<OutPinId>)>,
+
+    /// Inner nodes of the exposed inputs in the snapshots of [`Self::edit_snapshot`], which leave out the graph. Their
+    /// values are edited through the group inputs without a wire.
+    #[serde(skip)]
+    input_nodes: Vec<(NodeId, Node)>,
+
+    #[serde(skip)]
+    evaluator: Evaluator,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+/// Of the inner graph only the nodes of the exposed inputs are compared, like in the snapshots.
+impl PartialEq for GroupNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.exposed_inputs == other.exposed_inputs
+            && self.input_flags == other.input_flags
+            && self.exposed_outputs == other.exposed_outputs
+            && self.output_flags == other.output_flags
+            && self.input_nodes().eq(other.input_nodes())
+    }
+}
+
+/// Group made of nodes of a graph, with the wires which connect it to the rest of the graph.
+pub struct Collapsed {
+    pub group: GroupNode,
+    /// The grouped nodes, which the group replaces.
+    pub node_ids: Vec<NodeId>,
+    pub pos: Pos2,
+    /// Outputs connected to the group inputs, by input index.
+    pub inputs: Vec<(OutPinId, usize)>,
+    /// Inputs connected to the group outputs, by output index.
+    pub outputs: Vec<(usize, InPinId)>,
+}
+
+/// Nodes of an ungrouped group.
+pub struct Expanded {
+    pub node_ids: Vec<NodeId>,
+    /// Command which undoes the insertion of the nodes.
+    pub command: Command,
+    /// Pins which were exposed as the group inputs and outputs, in their order.
+    pub inputs: Vec<InPinId>,
+    pub outputs: Vec<OutPinId>,
+}
+
+impl GroupNode {
+    pub const NAME: &str = "Group";
+
+    fn new(name: String, mut graph: Snarl<Node>, exposed_inputs: Vec<InPinId>, exposed_outputs: Vec<OutPinId>) -> Self {
+        let mut evaluator = Evaluator::default();
+        if let Err(err) = evaluator.evaluate(&mut graph) {
+            tracing::warn!("Failed to evaluate the group `{name}`: {err}");
+        }
+
+        let mut group = Self {
+            name,
+            graph,
+            exposed_inputs,
+            input_flags: Vec::new(),
+            exposed_outputs,
+            output_flags: Vec::new(),
+            pin_request: None,
+            input_nodes: Vec::new(),
+            evaluator,
+            subscription: Subscription::default(),
+        };
+        group.update_flags();
+        group
+    }
+
+    pub(crate) fn edit_snapshot(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            graph: Snarl::new(),
+            exposed_inputs: self.exposed_inputs.clone(),
+            input_flags: self.input_flags.clone(),
+            exposed_outputs: self.exposed_outputs.clone(),
+            output_flags: self.output_flags.clone(),
+            pin_request: None,
+            input_nodes: self
+                .input_nodes()
+                .map(|(node_id, node)| (node_id, node.edit_snapshot()))
+                .collect(),
+            evaluator: Evaluator::default(),
+            subscription: Subscription::default(),
+        }
+    }
+
+    pub(crate) fn restore_snapshot(&mut self, snapshot: Self) {
+        self.name = snapshot.name;
+        self.exposed_inputs = snapshot.exposed_inputs;
+        self.input_flags = snapshot.input_flags;
+        self.exposed_outputs = snapshot.exposed_outputs;
+        self.output_flags = snapshot.output_flags;
+
+        for (node_id, node) in snapshot.input_nodes {
+            if let Some(inner) = self.graph.get_node_mut(node_id) {
+                inner.restore_snapshot(node);
+                self.reevaluate(node_id);
+            }
+        }
+    }
+
+    /// Inner nodes of the exposed inputs, each once. Snapshots keep them without the graph.
+    fn input_nodes(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        let node_ids = self
+            .exposed_inputs
+            .iter()
+            .map(|pin| pin.node)
+            .collect::<FastIndexSet<_>>();
+        node_ids
+            .into_iter()
+            .filter_map(|node_id| Some((node_id, self.graph.get_node(node_id)?)))
+            .chain(self.input_nodes.iter().map(|(node_id, node)| (*node_id, node)))
+    }
+
+    /// Moves the nodes into a new group. Wires from the other nodes are exposed as group inputs and wires to them as
+    /// group outputs.
+    pub fn collapse(node_ids: impl IntoIterator<Item = NodeId>, snarl: &Snarl<Node>) -> Result<Collapsed, GroupError> {
+        let mut grouped = Vec::<NodeId>::new();
+        for node_id in node_ids {
+            match snarl.get_node(node_id) {
+                // Renders and outputs are registered with the viewports, which only see the project graph.
+                Some(node @ (Node::Render(_) | Node::Output(_))) => {
+                    return Err(GroupError::Unsupported(node.name().to_owned()));
+                },
+                Some(_) if !grouped.contains(&node_id) => grouped.push(node_id),
+                _ => (),
+            }
+        }
+        if grouped.is_empty() {
+            return Err(GroupError::Empty);
+        }
+
+        let pos = grouped
+            .iter()
+            .filter_map(|&node_id| snarl.get_node_info(node_id))
+            .fold(Pos2::new(f32::INFINITY, f32::INFINITY), |min, info| min.min(info.pos));
+
+        let mut graph = Snarl::new();
+        let fragment = Fragment::copy(grouped.iter().copied(), snarl);
+        let (inner_ids, _) = fragment.paste(-pos.to_vec2(), false, &mut PlainEditor, &mut graph);
+        let inner_id = |node_id| {
+            grouped
+                .iter()
+                .position(|&grouped_id| grouped_id == node_id)
+                .map(|idx| inner_ids[idx])
+        };
+
+        let (mut exposed_inputs, mut exposed_outputs) = (Vec::new(), Vec::<OutPinId>::new());
+        let (mut inputs, mut outputs) = (Vec::new(), Vec::new());
+        for (from, to) in snarl.wires() {
+            let flags = snarl[from.node].outputs()[from.output] & snarl[to.node].inputs()[to.input];
+            match (inner_id(from.node), inner_id(to.node)) {
+                (None, Some(node)) => {
+                    // Collections renumber their inputs when they're copied.
+                    if flags & INPUT_FLAGS == 0 || matches!(snarl[to.node], Node::Collection(_)) {
+                        return Err(GroupError::NodeInput(snarl[from.node].name().to_owned()));
+                    }
+                    inputs.push((from, exposed_inputs.len()));
+                    exposed_inputs.push(InPinId { node, input: to.input });
+                },
+                (Some(node), None) => {
+                    if flags & OUTPUT_FLAGS == 0 {
+                        return Err(GroupError::NodeOutput(snarl[from.node].name().to_owned()));
+                    }
+                    let pin = OutPinId {
+                        node,
+                        output: from.output,
+                    };
+                    let output = exposed_outputs
+                        .iter()
+                        .position(|&exposed| exposed == pin)
+                        .unwrap_or_else(|| {
+                            exposed_outputs.push(pin);
+                            exposed_outputs.len() - 1
+                        });
+                    outputs.push((output, to));
+                },
+                _ => (),
+            }
+        }
+
+        Ok(Collapsed {
+            group: Self::new(Self::NAME.to_owned(), graph, exposed_inputs, exposed_outputs),
+            node_ids: grouped,
+            pos,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Inserts the nodes of the group moved by `offset` into the graph.
+    pub fn expand(&self, offset: Vec2, editor: &mut impl Editor, snarl: &mut Snarl<Node>) -> Expanded {
+        let inner_ids = self.graph.node_ids().map(|(node_id, _)| node_id).collect::<Vec<_>>();
+        let fragment = Fragment::copy(inner_ids.iter().copied(), &self.graph);
+        let (node_ids, command) = fragment.paste(offset, false, editor, snarl);
+        let outer_id = |inner: NodeId| node_ids[inner_ids.iter().position(|&node_id| node_id == inner).unwrap()];
+
+        let inputs = self
+            .exposed_inputs
+            .iter()
+            .map(|pin| InPinId {
+                node: outer_id(pin.node),
+                input: pin.input,
+            })
+            .collect();
+        let outputs = self
+            .exposed_outputs
+            .iter()
+            .map(|pin| OutPinId {
+                node: outer_id(pin.node),
+                output: pin.output,
+            })
+            .collect();
+
+        Expanded {
+            node_ids,
+            command,
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Value of the inner output, outputs of primitives and collections give the group itself.
+    pub fn output(&self, self_id: NodeId, output: usize) -> NodeOutput {
+        match self.exposed_outputs.get(output) {
+            Some(pin) => match self.graph[pin.node].output(pin.node, pin.output) {
+                NodeOutput::Node(_) => NodeOutput::Node(self_id),
+                value => value,
+            },
+            None => NodeOutput::Node(self_id),
+        }
+    }
+
+    /// Collects the inner nodes behind the outputs which give primitives or collections.
+    pub fn collect_scene_nodes(&mut self, predicate: &dyn Fn(&Node) -> bool) -> FastIndexSet<NodeId> {
+        let mut destination = FastIndexSet::default();
+        for (pin, flags) in self.exposed_outputs.iter().zip(&self.output_flags) {
+            if flags & (NodeFlags::PRIMITIVES.bits() | NodeFlags::COLLECTION.bits()) != 0 {
+                collect_for_node(Some(pin.node), predicate, &mut destination, &mut self.graph);
+            }
+        }
+        destination
+    }
+
+    pub fn graph_mut(&mut self) -> &mut Snarl<Node> {
+        &mut self.graph
+    }
+
+    pub fn save_to_library(&self) -> Result<PathBuf, GroupError> {
+        let dir = library_dir().ok_or(GroupError::NoLibrary)?;
+        fs::create_dir_all(&dir)?;
+
+        // The name may not leave the library directory.
+        let path = dir.join(format!("{}.json", self.name.replace(['/', '\\'], "_")));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load_from_library(name: &str) -> Result<Self, GroupError> {
+        let dir = library_dir().ok_or(GroupError::NoLibrary)?;
+        let content = fs::read_to_string(dir.join(format!("{name}.json")))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Names of the groups saved in the library.
+    pub fn library() -> Vec<String> {
+        let Some(entries) = library_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return Vec::new();
+        };
+
+        let mut names = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let is_json = path.extension().is_some_and(|extension| extension == "json");
+                is_json.then(|| path.file_stem()?.to_str().map(str::to_owned)).flatten()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn update_flags(&mut self) {
+        self.input_flags = self
+            .exposed_inputs
+            .iter()
+            .map(|pin| self.graph[pin.node].inputs()[pin.input] & INPUT_FLAGS)
+            .collect();
+        self.output_flags = self
+            .exposed_outputs
+            .iter()
+            .map(|pin| self.graph[pin.node].outputs()[pin.output] & OUTPUT_FLAGS)
+            .collect();
+    }
+
+    /// Reevaluates the inner graph after an exposed input changed and notifies the subscribers of the inner node.
+    fn input_changed(&mut self, node_id: NodeId) {
+        if let Some(caller) = self.graph[node_id]
+            .subscription_ref()
+            .and_then(|subscription| subscription.event_caller(Event::OnChange))
+        {
+            caller(SelfNodeMut::new(node_id, &mut self.graph));
+        }
+        self.reevaluate(node_id);
+    }
+
+    fn reevaluate(&mut self, node_id: NodeId) {
+        self.evaluator.invalidate(node_id);
+        if let Err(err) = self.evaluator.evaluate(&mut self.graph) {
+            tracing::warn!("Failed to evaluate the group `{}`: {err}", self.name);
+        }
+    }
+
+    fn pin_label(&self, node_id: NodeId, pin: usize) -> String {
+        format!("{} {}", self.graph[node_id].name(), pin + 1)
+    }
+
+    /// Lets the user expose or hide the inner pins, returns the pins to expose if they changed.
+    fn expose_pins_ui(&self, ui: &mut Ui) -> Option<(Vec<InPinId>, Vec<OutPinId>)> {
+        let wired_inputs = self.graph.wires().map(|(_, to)| to).collect::<Vec<_>>();
+        let mut new_inputs = self.exposed_inputs.clone();
+        let mut new_outputs = self.exposed_outputs.clone();
+
+        for (node_id, node) in self.graph.node_ids() {
+            let label = |pin| format!("{} {}", node.name(), pin + 1);
+
+            for (input, flags) in node.inputs().iter().enumerate() {
+                let pin = InPinId { node: node_id, input };
+                // Inputs wired inside the group already have a value.
+                if flags & INPUT_FLAGS == 0 || wired_inputs.contains(&pin) {
+                    continue;
+                }
+                let mut exposed = new_inputs.contains(&pin);
+                if ui.checkbox(&mut exposed, format!("In: {}", label(input))).changed() {
+                    toggle(&mut new_inputs, pin, exposed);
+                }
+            }
+            for (output, flags) in node.outputs().iter().enumerate() {
+                let pin = OutPinId { node: node_id, output };
+                if flags & OUTPUT_FLAGS == 0 {
+                    continue;
+                }
+                let mut exposed = new_outputs.contains(&pin);
+                if ui.checkbox(&mut exposed, format!("Out: {}", label(output))).changed() {
+                    toggle(&mut new_outputs, pin, exposed);
+                }
+            }
+        }
+
+        (new_inputs != self.exposed_inputs || new_outputs != self.exposed_outputs).then_some((new_inputs, new_outputs))
+    }
+
+    /// Takes the pins chosen in the body since the last call.
+    pub fn take_pin_request(&mut self) -> Option<(Vec<InPinId>, Vec<OutPinId>)> {
+        self.pin_request.take()
+    }
+
+    /// Exposes the pins of the group node, its wires move to the new indices of their pins and the wires of hidden pins
+    /// are dropped. The wires are disconnected before the pins change, so the inputs they set are the ones reset.
+    pub fn expose_pins(
+        node_id: NodeId,
+        (inputs, outputs): (Vec<InPinId>, Vec<OutPinId>),
+        editor: &mut impl Editor,
+        snarl: &mut Snarl<Node>,
+    ) {
+        let group = snarl[node_id].as_group_ref();
+        let input_indices = new_indices(&group.exposed_inputs, &inputs);
+        let output_indices = new_indices(&group.exposed_outputs, &outputs);
+        let moves = snarl
+            .wires()
+            .filter_map(|(from, to)| {
+                let moved = if to.node == node_id {
+                    let input = input_indices.get(to.input).copied().flatten();
+                    input.map(|input| (from, InPinId { input, ..to }))
+                } else if from.node == node_id {
+                    let output = output_indices.get(from.output).copied().flatten();
+                    output.map(|output| (OutPinId { output, ..from }, to))
+                } else {
+                    return None;
+                };
+                (moved != Some((from, to))).then_some(((from, to), moved))
+            })
+            .collect::<Vec<_>>();
+
+        for &((from, to), _) in &moves {
+            editor.disconnect_pins(from, to, snarl);
+        }
+        let group = snarl[node_id].as_group_mut();
+        group.exposed_inputs = inputs;
+        group.exposed_outputs = outputs;
+        group.update_flags();
+        for (from, to) in moves.into_iter().filter_map(|(_, moved)| moved) {
+            editor.connect_pins(from, to, snarl);
+        }
+    }
+}
+
+fn toggle<T: PartialEq>(pins: &mut Vec<T>, pin: T, exposed: bool) {
+    if exposed {
+        pins.push(pin);
+    } else {
+        pins.retain(|exposed_pin| *exposed_pin != pin);
+    }
+}
+
+fn new_indices<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Option<usize>> {
+    old.iter()
+        .map(|pin| new.iter().position(|new_pin| new_pin == pin))
+        .collect()
+}
+
+fn library_dir() -> Option<PathBuf> {
+    crate::app::storage_dir().map(|dir| dir.join("library"))
+}
+
+impl Noded for GroupNode {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn inputs(&self) -> &[u64] {
+        &self.input_flags
+    }
+
+    fn outputs(&self) -> &[u64] {
+        &self.output_flags
+    }
+
+    fn reset_input(&mut self, pin: &InPin) -> bool {
+        let Some(&inner) = self.exposed_inputs.get(pin.id.input) else {
+            return false;
+        };
+
+        let reset = self.graph[inner.node].reset_input(&InPin {
+            id: inner,
+            remotes: Vec::new(),
+        });
+        if reset {
+            self.input_changed(inner.node);
+        }
+        reset
+    }
+
+    fn set_input(&mut self, input: usize, output: &NodeOutput) -> bool {
+        let Some(&inner) = self.exposed_inputs.get(input) else {
+            return false;
+        };
+
+        let changed = self.graph[inner.node].set_input(inner.input, output);
+        if changed {
+            self.input_changed(inner.node);
+        }
+        changed
+    }
+
+    fn animate(&mut self, time: f32) -> bool {
+        let animated = self.evaluator.animate(time, &mut self.graph);
+        if animated && let Err(err) = self.evaluator.evaluate(&mut self.graph) {
+            tracing::warn!("Failed to evaluate the group `{}`: {err}", self.name);
+        }
+        animated
+    }
+
+    fn subscription_ref(&self) -> Option<&Subscription> {
+        Some(&self.subscription)
+    }
+
+    fn subscription_mut(&mut self) -> Option<&mut Subscription> {
+        Some(&mut self.subscription)
+    }
+}
+
+impl MessageHandling for GroupNode {
+    /// Shows the widget of the inner input, wired inputs get their value from the outer graph instead.
+    fn handle_display_input(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let group = self_node.node_mut().as_group_mut();
+        let inner = *group.exposed_inputs.get(pin.id.input)?;
+        if !pin.remotes.is_empty() {
+            return Some(input::empty_view(ui, group.pin_label(inner.node, inner.input)));
+        }
+
+        let inner_pin = InPin {
+            id: inner,
+            remotes: Vec::new(),
+        };
+        let events = widget_events(ui);
+        let pin_info = match Node::call_handle_msg(inner.node, &mut group.graph, DisplayMessage::Input {
+            pin: &inner_pin,
+            ui,
+        }) {
+            Some(CommonNodeResponse::Display(DisplayResponse::Info(pin_info))) => Some(pin_info),
+            _ => None,
+        };
+        if widgets_changed_since(ui, events) {
+            group.reevaluate(inner.node);
+        }
+        Some(pin_info.unwrap_or_else(|| input::empty_view(ui, group.pin_label(inner.node, inner.input))))
+    }
+
+    fn handle_display_body<'a>(
+        mut self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        let group = self_node.node_mut().as_group_mut();
+        ui.add(TextEdit::singleline(&mut group.name).desired_width(120.0));
+
+        let pins = ui
+            .collapsing("Pins", |ui| group.expose_pins_ui(ui))
+            .body_returned
+            .flatten();
+        if pins.is_some() {
+            group.pin_request = pins;
+        }
+
+        if ui.button("Save to library").clicked() {
+            match group.save_to_library() {
+                Ok(path) => tracing::info!("Saved the group to {}", path.display()),
+                Err(err) => tracing::warn!("Failed to save the group `{}`: {err}", group.name),
+            }
+        }
+
+        None
+    }
+
+    fn handle_display_output(self_node: SelfNodeMut, pin: &OutPin, ui: &mut Ui) -> Option<PinInfo> {
+        let group = self_node.node_ref().as_group_ref();
+        let inner = group.exposed_outputs.get(pin.id.output)?;
+        let label = group.pin_label(inner.node, inner.output);
+
+        Some(match group.output(self_node.id, pin.id.output) {
+            NodeOutput::Number(number) => output::number_label(ui, &label, number),
+            NodeOutput::Vector(vector) => output::vector_label(ui, &label, vector),
+            NodeOutput::Color(_) | NodeOutput::String(_) | NodeOutput::Node(_) => {
+                ui.label(label);
+                output::empty_view()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::history::History;
+    use crate::node::item::primitive::SphereNode;
+    use crate::node::item::{MathNode, MathOperation, NumberNode, PrimitiveNode, SceneNode};
+
+    fn wire(from: NodeId, output: usize, to: NodeId, input: usize) -> (OutPinId, InPinId) {
+        (OutPinId { node: from, output }, InPinId { node: to, input })
+    }
+
+    #[test]
+    fn test_collapse_exposes_boundary_wires() {
+        let mut snarl = Snarl::new();
+        let number = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let math = snarl.insert_node(Pos2::new(50.0, 10.0), Node::Math(MathNode::new(MathOperation::Add)));
+        let other = snarl.insert_node(Pos2::new(20.0, 30.0), Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(number, 0, math, 0);
+        snarl.connect(from, to);
+        let (from, to) = wire(math, 0, other, 0);
+        snarl.connect(from, to);
+
+        let collapsed = GroupNode::collapse([math, other], &snarl).unwrap();
+        assert_eq!(collapsed.node_ids, [math, other]);
+        assert_eq!(collapsed.pos, Pos2::new(20.0, 10.0));
+        assert_eq!(collapsed.inputs, [(
+            OutPinId {
+                node: number,
+                output: 0
+            },
+            0
+        )]);
+        assert!(collapsed.outputs.is_empty());
+        assert_eq!(collapsed.group.inputs(), [
+            NodeFlags::TYPICAL_NUMBER_INPUT.bits() & INPUT_FLAGS
+        ]);
+        assert_eq!(collapsed.group.graph.wires().count(), 1);
+    }
+
+    #[test]
+    fn test_group_forwards_inputs_to_its_graph() {
+        let mut snarl = Snarl::new();
+        let number = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let math = snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        let consumer = snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(number, 0, math, 0);
+        snarl.connect(from, to);
+        let (from, to) = wire(math, 0, consumer, 0);
+        snarl.connect(from, to);
+
+        let mut group = GroupNode::collapse([math], &snarl).unwrap().group;
+        assert!(group.set_input(0, &NodeOutput::Number(2.0)));
+        assert!(!group.set_input(0, &NodeOutput::Number(2.0)));
+        assert_eq!(group.output(NodeId(7), 0), NodeOutput::Number(2.0));
+
+        let expanded = group.expand(Vec2::ZERO, &mut PlainEditor, &mut snarl);
+        assert_eq!(expanded.node_ids.len(), 1);
+        assert_eq!(expanded.inputs, [InPinId {
+            node: expanded.node_ids[0],
+            input: 0,
+        }]);
+    }
+
+    #[test]
+    fn test_expose_pins_moves_the_wires() {
+        let mut snarl = Snarl::new();
+        let first = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let second = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let math = snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(first, 0, math, 0);
+        snarl.connect(from, to);
+        let (from, to) = wire(second, 0, math, 1);
+        snarl.connect(from, to);
+
+        let group = GroupNode::collapse([math], &snarl).unwrap().group;
+        let hidden = group.exposed_inputs[0];
+        let kept = group.exposed_inputs[1];
+        snarl.remove_node(math);
+        let group_id = snarl.insert_node(Pos2::ZERO, Node::Group(group));
+        let (from, to) = wire(first, 0, group_id, 0);
+        snarl.connect(from, to);
+        let (from, to) = wire(second, 0, group_id, 1);
+        snarl.connect(from, to);
+
+        GroupNode::expose_pins(group_id, (vec![kept], Vec::new()), &mut PlainEditor, &mut snarl);
+        assert_eq!(snarl.wires().collect::<Vec<_>>(), [wire(second, 0, group_id, 0)]);
+        let group = snarl[group_id].as_group_ref();
+        assert_eq!(group.exposed_inputs, [kept]);
+        assert_eq!(group.inputs().len(), 1);
+        assert_ne!(hidden, kept);
+    }
+
+    #[test]
+    fn test_undo_restores_exposed_input_values() {
+        let mut snarl = Snarl::new();
+        let number = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let math = snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        let consumer = snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        let (from, to) = wire(number, 0, math, 0);
+        snarl.connect(from, to);
+        let (from, to) = wire(math, 0, consumer, 0);
+        snarl.connect(from, to);
+
+        let group = GroupNode::collapse([math], &snarl).unwrap().group;
+        let inner = group.exposed_inputs[0];
+        let group_id = snarl.insert_node(Pos2::ZERO, Node::Group(group));
+        let initial = snarl[group_id].output(group_id, 0);
+
+        // The input has no wire, so its value is edited in the inner graph like through its widget.
+        let before = snarl[group_id].edit_snapshot();
+        let group = snarl[group_id].as_group_mut();
+        assert!(group.graph[inner.node].set_input(inner.input, &NodeOutput::Number(3.0)));
+        group.reevaluate(inner.node);
+        let edited = snarl[group_id].output(group_id, 0);
+        assert_ne!(edited, initial);
+        assert!(before != snarl[group_id]);
+
+        let mut history = History::default();
+        history.push_edit(group_id, before, snarl[group_id].edit_snapshot(), None);
+        assert!(history.undo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl[group_id].output(group_id, 0), initial);
+        assert!(history.redo(&mut PlainEditor, &mut snarl));
+        assert_eq!(snarl[group_id].output(group_id, 0), edited);
+    }
+
+    #[test]
+    fn test_collapse_rejects_node_inputs() {
+        let mut snarl = Snarl::new();
+        let sphere = snarl.insert_node(
+            Pos2::ZERO,
+            Node::Primitive(PrimitiveNode::Sphere(SphereNode::default())),
+        );
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(SceneNode::default()));
+        let (from, to) = wire(sphere, 0, scene, 0);
+        snarl.connect(from, to);
+
+        assert!(matches!(
+            GroupNode::collapse([scene], &snarl),
+            Err(GroupError::NodeInput(_))
+        ));
+        assert!(matches!(GroupNode::collapse([], &snarl), Err(GroupError::Empty)));
+    }
+}
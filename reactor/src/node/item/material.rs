@@ -1,9 +1,10 @@
 use egui_snarl::NodeId;
 use enum_dispatch::enum_dispatch;
 use reactor_derives::EnumAs;
-use reactor_types::Vector3;
+use reactor_types::animation::Interpolate;
+use reactor_types::{Float, NodePin, Vector3};
 use serde::{Deserialize, Serialize};
-use xrays::scene::TextureData;
+use xrays::scene::{NormalMap, NormalMapKind, TextureData};
 use xrays::texture::TextureId;
 
 pub use self::checkerboard::CheckerboardNode;
@@ -11,6 +12,7 @@ pub use self::dielectric::DielectricNode;
 pub use self::emissive::EmissiveNode;
 pub use self::lambertian::LambertianNode;
 pub use self::metal::MetalNode;
+pub use self::mix::MixMaterialNode;
 use crate::node::message::{CommonNodeMessage, CommonNodeResponse, MessageHandling, SelfNodeMut};
 
 pub mod checkerboard;
@@ -18,6 +20,7 @@ pub mod dielectric;
 pub mod emissive;
 pub mod lambertian;
 pub mod metal;
+pub mod mix;
 
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub enum InputMaterial {
@@ -25,6 +28,9 @@ pub enum InputMaterial {
     External(NodeId),
 }
 
+/// Keyed materials switch at the keyframes.
+impl Interpolate for InputMaterial {}
+
 impl Default for InputMaterial {
     fn default() -> Self {
         Self::Internal(MaterialNode::default())
@@ -41,6 +47,10 @@ pub enum MaterialNode {
     Checkerboard(CheckerboardNode),
 }
 
+fn default_normal_strength() -> NodePin<Float> {
+    NodePin::new(1.0)
+}
+
 impl Default for MaterialNode {
     fn default() -> Self {
         Self::Lambertian(Default::default())
@@ -68,7 +78,53 @@ impl MaterialNode {
         }
     }
 
-    pub fn to_xrays_material(&self, texture_id: Option<TextureId>, textures: &mut Vec<TextureData>) -> xrays::Material {
+    pub fn get_normal_map_node_id(&self) -> Option<NodeId> {
+        match self {
+            Self::Metal(metal) => metal.normal_map(),
+            Self::Lambertian(lambert) => lambert.normal_map(),
+            Self::Dielectric(_) | Self::Emissive(_) | Self::Checkerboard(_) => None,
+        }
+    }
+
+    pub fn get_bump_map_node_id(&self) -> Option<NodeId> {
+        match self {
+            Self::Metal(metal) => metal.bump_map(),
+            Self::Lambertian(lambert) => lambert.bump_map(),
+            Self::Dielectric(_) | Self::Emissive(_) | Self::Checkerboard(_) => None,
+        }
+    }
+
+    /// Returns the normal map of the material, a connected normal map takes precedence over a bump map.
+    pub fn to_xrays_normal_map(&self, texture_id_of: impl Fn(NodeId) -> Option<TextureId>) -> Option<NormalMap> {
+        let strength = match self {
+            Self::Metal(metal) => metal.normal_strength(),
+            Self::Lambertian(lambert) => lambert.normal_strength(),
+            Self::Dielectric(_) | Self::Emissive(_) | Self::Checkerboard(_) => return None,
+        };
+
+        let normal_map = self
+            .get_normal_map_node_id()
+            .and_then(&texture_id_of)
+            .map(|texture| (texture, NormalMapKind::TangentSpace));
+        let bump_map = || {
+            self.get_bump_map_node_id()
+                .and_then(&texture_id_of)
+                .map(|texture| (texture, NormalMapKind::Height))
+        };
+
+        normal_map.or_else(bump_map).map(|(texture, kind)| NormalMap {
+            texture,
+            kind,
+            strength: strength as f32,
+        })
+    }
+
+    pub fn to_xrays_material(
+        &self,
+        texture_id: Option<TextureId>,
+        normal_map: Option<NormalMap>,
+        textures: &mut Vec<TextureData>,
+    ) -> xrays::Material {
         match self {
             MaterialNode::Metal(metal_node) => xrays::Material::Metal {
                 albedo: texture_id.unwrap_or_else(|| {
@@ -78,9 +134,13 @@ impl MaterialNode {
                     textures.len() - 1
                 }),
                 fuzz: metal_node.fuzz() as _,
+                normal_map,
             },
             MaterialNode::Dielectric(dielectric_node) => xrays::Material::Dielectric {
                 refraction_index: dielectric_node.ior() as _,
+                absorption: dielectric_node.absorption(),
+                abbe_number: dielectric_node.abbe_number(),
+                thin_film: dielectric_node.thin_film(),
             },
             MaterialNode::Lambertian(lambertian_node) => xrays::Material::Lambertian {
                 albedo: texture_id.unwrap_or_else(|| {
@@ -89,6 +149,7 @@ impl MaterialNode {
                     textures.push(TextureData::new(texture));
                     textures.len() - 1
                 }),
+                normal_map,
             },
             MaterialNode::Emissive(emissive_node) => xrays::Material::Emissive {
                 emit: texture_id.unwrap_or_else(|| {
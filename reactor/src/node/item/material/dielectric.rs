@@ -2,30 +2,104 @@ use egui::Ui;
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, OutPin};
 use reactor_derives::Noded;
-use reactor_types::{Float, NodePin};
+use reactor_types::{Color, Float, NodePin};
 use serde::{Deserialize, Serialize};
+use xrays::Vector3;
+use xrays::scene::{Absorption, ThinFilm};
 
 use crate::node::message::{MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::Subscription;
 use crate::node::viewer::ui::{input, output};
 use crate::node::{NodeFlags, Noded};
 
-#[derive(Clone, Default, Serialize, Deserialize, Noded, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Noded, PartialEq)]
 pub struct DielectricNode {
     ior: NodePin<Float>,
+    #[serde(default = "default_absorption_color")]
+    absorption_color: NodePin<Color>,
+    #[serde(default = "default_absorption_distance")]
+    absorption_distance: NodePin<Float>,
+    /// 0 disables dispersion.
+    #[serde(default)]
+    abbe_number: NodePin<Float>,
+    /// In nanometers, 0 disables the thin film.
+    #[serde(default)]
+    film_thickness: NodePin<Float>,
+    #[serde(default = "default_film_ior")]
+    film_ior: NodePin<Float>,
 
     #[serde(skip)]
     subscription: Subscription,
 }
 
+fn default_absorption_color() -> NodePin<Color> {
+    NodePin::new(Color::WHITE)
+}
+
+fn default_absorption_distance() -> NodePin<Float> {
+    NodePin::new(1.0)
+}
+
+fn default_film_ior() -> NodePin<Float> {
+    NodePin::new(1.33)
+}
+
+impl Default for DielectricNode {
+    fn default() -> Self {
+        Self {
+            ior: NodePin::default(),
+            absorption_color: default_absorption_color(),
+            absorption_distance: default_absorption_distance(),
+            abbe_number: NodePin::default(),
+            film_thickness: NodePin::default(),
+            film_ior: default_film_ior(),
+            subscription: Subscription::default(),
+        }
+    }
+}
+
 impl DielectricNode {
     pub const NAME: &str = "Dielectric Material";
-    pub const INPUTS: [u64; 1] = [NodeFlags::TYPICAL_NUMBER_INPUT.bits()];
+    pub const INPUTS: [u64; 6] = [
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_DIELECTRIC.bits()];
 
     pub fn ior(&self) -> Float {
         self.ior.get()
     }
+
+    /// Clear white glass absorbs nothing.
+    pub fn absorption(&self) -> Option<Absorption> {
+        let color = self.absorption_color.get();
+        if color == Color::WHITE {
+            return None;
+        }
+
+        let [r, g, b, _] = color.to_normalized_gamma_f32();
+        Some(Absorption {
+            color: Vector3::new(r, g, b),
+            distance: self.absorption_distance.get() as _,
+        })
+    }
+
+    pub fn abbe_number(&self) -> Option<f32> {
+        let abbe_number = self.abbe_number.get();
+        (abbe_number > 0.0).then_some(abbe_number as _)
+    }
+
+    pub fn thin_film(&self) -> Option<ThinFilm> {
+        let thickness = self.film_thickness.get();
+        (thickness > 0.0).then(|| ThinFilm {
+            thickness: thickness as _,
+            refraction_index: self.film_ior.get() as _,
+        })
+    }
 }
 
 impl MessageHandling for DielectricNode {
@@ -34,6 +108,29 @@ impl MessageHandling for DielectricNode {
             0 => Some(input::display_number_field(ui, pin, self_node, "IOR", |node| {
                 &mut node.as_material_mut().as_dielectric_mut().ior
             })),
+            1 => Some(input::display_color_field(ui, pin, self_node, "Absorption", |node| {
+                &mut node.as_material_mut().as_dielectric_mut().absorption_color
+            })),
+            2 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Absorption Distance",
+                |node| &mut node.as_material_mut().as_dielectric_mut().absorption_distance,
+            )),
+            3 => Some(input::display_number_field(ui, pin, self_node, "Abbe Number", |node| {
+                &mut node.as_material_mut().as_dielectric_mut().abbe_number
+            })),
+            4 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Film Thickness",
+                |node| &mut node.as_material_mut().as_dielectric_mut().film_thickness,
+            )),
+            5 => Some(input::display_number_field(ui, pin, self_node, "Film IOR", |node| {
+                &mut node.as_material_mut().as_dielectric_mut().film_ior
+            })),
             _ => None,
         }
     }
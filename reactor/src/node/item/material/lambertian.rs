@@ -3,7 +3,7 @@ use egui::Ui;
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, NodeId, OutPin};
 use reactor_derives::Noded;
-use reactor_types::{Color, NodePin};
+use reactor_types::{Color, Float, NodePin};
 use serde::{Deserialize, Serialize};
 
 use crate::node::message::{MessageHandling, SelfNodeMut};
@@ -15,6 +15,12 @@ use crate::node::{Node, NodeFlags, Noded, collect_for_node};
 pub struct LambertianNode {
     albedo: NodePin<Color>,
     texture: NodePin<Option<NodeId>>,
+    #[serde(default)]
+    normal_map: NodePin<Option<NodeId>>,
+    #[serde(default)]
+    bump_map: NodePin<Option<NodeId>>,
+    #[serde(default = "super::default_normal_strength")]
+    normal_strength: NodePin<Float>,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -25,6 +31,9 @@ impl Default for LambertianNode {
         Self {
             albedo: NodePin::new(Color::LIGHT_GRAY),
             texture: NodePin::default(),
+            normal_map: NodePin::default(),
+            bump_map: NodePin::default(),
+            normal_strength: super::default_normal_strength(),
             subscription: Subscription::default(),
         }
     }
@@ -32,7 +41,13 @@ impl Default for LambertianNode {
 
 impl LambertianNode {
     pub const NAME: &str = "Lambertian Material";
-    pub const INPUTS: [u64; 2] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits(), NodeFlags::TEXTURE.bits()];
+    pub const INPUTS: [u64; 5] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_LAMBERT.bits()];
 
     pub fn albedo(&self) -> Color {
@@ -42,6 +57,18 @@ impl LambertianNode {
     pub fn texture(&self) -> Option<NodeId> {
         self.texture.get()
     }
+
+    pub fn normal_map(&self) -> Option<NodeId> {
+        self.normal_map.get()
+    }
+
+    pub fn bump_map(&self) -> Option<NodeId> {
+        self.bump_map.get()
+    }
+
+    pub fn normal_strength(&self) -> Float {
+        self.normal_strength.get()
+    }
 }
 
 impl MessageHandling for LambertianNode {
@@ -53,6 +80,19 @@ impl MessageHandling for LambertianNode {
             1 => Some(input::display_texture_field(ui, pin, self_node, "Texture", |node| {
                 &mut node.as_material_mut().as_lambertian_mut().texture
             })),
+            2 => Some(input::display_texture_field(ui, pin, self_node, "Normal Map", |node| {
+                &mut node.as_material_mut().as_lambertian_mut().normal_map
+            })),
+            3 => Some(input::display_texture_field(ui, pin, self_node, "Bump Map", |node| {
+                &mut node.as_material_mut().as_lambertian_mut().bump_map
+            })),
+            4 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Normal Strength",
+                |node| &mut node.as_material_mut().as_lambertian_mut().normal_strength,
+            )),
             _ => None,
         }
     }
@@ -66,11 +106,14 @@ impl MessageHandling for LambertianNode {
         predicate: &dyn Fn(&Node) -> bool,
         destination: &mut FastIndexSet<NodeId>,
     ) {
-        collect_for_node(
-            self_node.node_ref().as_material_ref().get_texture_node_id(),
-            predicate,
-            destination,
-            self_node.snarl,
-        );
+        let material = self_node.node_ref().as_material_ref();
+        let texture_ids = [
+            material.get_texture_node_id(),
+            material.get_normal_map_node_id(),
+            material.get_bump_map_node_id(),
+        ];
+        for texture_id in texture_ids {
+            collect_for_node(texture_id, predicate, destination, self_node.snarl);
+        }
     }
 }
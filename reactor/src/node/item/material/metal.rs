@@ -10,22 +10,45 @@ use crate::node::subscribtion::Subscription;
 use crate::node::viewer::ui::{input, output};
 use crate::node::{Node, NodeFlags, Noded, collect_for_node};
 
-#[derive(Clone, Default, Serialize, Deserialize, Noded, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Noded, PartialEq)]
 pub struct MetalNode {
     albedo: NodePin<Color>,
     fuzz: NodePin<Float>,
     texture: NodePin<Option<NodeId>>,
+    #[serde(default)]
+    normal_map: NodePin<Option<NodeId>>,
+    #[serde(default)]
+    bump_map: NodePin<Option<NodeId>>,
+    #[serde(default = "super::default_normal_strength")]
+    normal_strength: NodePin<Float>,
 
     #[serde(skip)]
     subscription: Subscription,
 }
 
+impl Default for MetalNode {
+    fn default() -> Self {
+        Self {
+            albedo: NodePin::default(),
+            fuzz: NodePin::default(),
+            texture: NodePin::default(),
+            normal_map: NodePin::default(),
+            bump_map: NodePin::default(),
+            normal_strength: super::default_normal_strength(),
+            subscription: Subscription::default(),
+        }
+    }
+}
+
 impl MetalNode {
     pub const NAME: &str = "Metal Material";
-    pub const INPUTS: [u64; 3] = [
+    pub const INPUTS: [u64; 6] = [
         NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_METAL.bits()];
 
@@ -40,6 +63,18 @@ impl MetalNode {
     pub fn texture(&self) -> Option<NodeId> {
         self.texture.get()
     }
+
+    pub fn normal_map(&self) -> Option<NodeId> {
+        self.normal_map.get()
+    }
+
+    pub fn bump_map(&self) -> Option<NodeId> {
+        self.bump_map.get()
+    }
+
+    pub fn normal_strength(&self) -> Float {
+        self.normal_strength.get()
+    }
 }
 
 impl MessageHandling for MetalNode {
@@ -54,6 +89,19 @@ impl MessageHandling for MetalNode {
             2 => Some(input::display_texture_field(ui, pin, self_node, "Texture", |node| {
                 &mut node.as_material_mut().as_metal_mut().texture
             })),
+            3 => Some(input::display_texture_field(ui, pin, self_node, "Normal Map", |node| {
+                &mut node.as_material_mut().as_metal_mut().normal_map
+            })),
+            4 => Some(input::display_texture_field(ui, pin, self_node, "Bump Map", |node| {
+                &mut node.as_material_mut().as_metal_mut().bump_map
+            })),
+            5 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Normal Strength",
+                |node| &mut node.as_material_mut().as_metal_mut().normal_strength,
+            )),
             _ => None,
         }
     }
@@ -67,11 +115,14 @@ impl MessageHandling for MetalNode {
         predicate: &dyn Fn(&Node) -> bool,
         destination: &mut eframe::wgpu::naga::FastIndexSet<NodeId>,
     ) {
-        collect_for_node(
-            self_node.node_ref().as_material_ref().get_texture_node_id(),
-            predicate,
-            destination,
-            self_node.snarl,
-        );
+        let material = self_node.node_ref().as_material_ref();
+        let texture_ids = [
+            material.get_texture_node_id(),
+            material.get_normal_map_node_id(),
+            material.get_bump_map_node_id(),
+        ];
+        for texture_id in texture_ids {
+            collect_for_node(texture_id, predicate, destination, self_node.snarl);
+        }
     }
 }
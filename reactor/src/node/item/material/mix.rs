@@ -0,0 +1,192 @@
+use eframe::wgpu::naga::FastIndexSet;
+use egui::{ComboBox, Ui};
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, NodeId, OutPin};
+use reactor_derives::Noded;
+use reactor_types::{Float, NodePin};
+use serde::{Deserialize, Serialize};
+use xrays::scene::{MaterialId, TextureData};
+use xrays::texture::TextureId;
+
+use super::MaterialNode;
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
+use crate::node::subscribtion::{Event, Subscription};
+use crate::node::viewer::ui::{input, output};
+use crate::node::{Node, NodeFlags, Noded, collect_for_node};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MixMode {
+    /// The second material replaces the first one by the factor or the mask.
+    #[default]
+    Mix,
+    /// The second material coats the first one, mostly at grazing angles.
+    Layered,
+}
+
+impl MixMode {
+    pub const ALL: [Self; 2] = [Self::Mix, Self::Layered];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mix => "Mix",
+            Self::Layered => "Layered",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Noded)]
+pub struct MixMaterialNode {
+    first: NodePin<Option<NodeId>>,
+    second: NodePin<Option<NodeId>>,
+    factor: NodePin<Float>,
+    mask: NodePin<Option<NodeId>>,
+    coat_ior: NodePin<Float>,
+    mode: MixMode,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for MixMaterialNode {
+    fn default() -> Self {
+        Self {
+            first: NodePin::default(),
+            second: NodePin::default(),
+            factor: NodePin::new(0.5),
+            mask: NodePin::default(),
+            coat_ior: NodePin::new(1.5),
+            mode: MixMode::default(),
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl MixMaterialNode {
+    pub const NAME: &str = "Mix Material";
+    pub const INPUTS: [u64; 5] = [
+        NodeFlags::MATERIALS.bits(),
+        NodeFlags::MATERIALS.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_MIX.bits()];
+
+    pub fn materials(&self) -> [Option<NodeId>; 2] {
+        [self.first.get(), self.second.get()]
+    }
+
+    pub fn mask(&self) -> Option<NodeId> {
+        self.mask.get()
+    }
+
+    /// Disconnected inputs are replaced by default materials appended to `materials`.
+    pub fn to_xrays_material(
+        &self,
+        material_id_of: impl Fn(NodeId) -> Option<MaterialId>,
+        texture_id_of: impl Fn(NodeId) -> Option<TextureId>,
+        materials: &mut Vec<xrays::Material>,
+        textures: &mut Vec<TextureData>,
+    ) -> xrays::Material {
+        let [first, second] = self.materials().map(|node_id| {
+            node_id.and_then(&material_id_of).unwrap_or_else(|| {
+                materials.push(MaterialNode::default().to_xrays_material(None, None, textures));
+                materials.len() - 1
+            })
+        });
+
+        match self.mode {
+            MixMode::Mix => xrays::Material::Mix {
+                first,
+                second,
+                factor: self.factor.get() as _,
+                mask: self.mask().and_then(texture_id_of),
+            },
+            MixMode::Layered => xrays::Material::Layered {
+                base: first,
+                coat: second,
+                refraction_index: self.coat_ior.get() as _,
+                weight: self.factor.get() as _,
+            },
+        }
+    }
+}
+
+impl MessageHandling for MixMaterialNode {
+    fn handle_display_input(self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let layered = self_node.node_ref().as_mix_material_ref().mode == MixMode::Layered;
+
+        match pin.id.input {
+            0 => Some(input::display_material_node_field(
+                ui,
+                pin,
+                self_node,
+                if layered { "Base" } else { "First" },
+                |node| &mut node.as_mix_material_mut().first,
+            )),
+            1 => Some(input::display_material_node_field(
+                ui,
+                pin,
+                self_node,
+                if layered { "Coat" } else { "Second" },
+                |node| &mut node.as_mix_material_mut().second,
+            )),
+            2 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                if layered { "Coat Weight" } else { "Factor" },
+                |node| &mut node.as_mix_material_mut().factor,
+            )),
+            3 => Some(input::display_texture_field(ui, pin, self_node, "Mask", |node| {
+                &mut node.as_mix_material_mut().mask
+            })),
+            4 => Some(input::display_number_field(ui, pin, self_node, "Coat IOR", |node| {
+                &mut node.as_mix_material_mut().coat_ior
+            })),
+            _ => None,
+        }
+    }
+
+    fn handle_display_body<'a>(
+        mut self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        let node = self_node.node_mut().as_mix_material_mut();
+
+        let old_mode = node.mode;
+        ComboBox::from_id_salt("Mode")
+            .selected_text(node.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in MixMode::ALL {
+                    ui.selectable_value(&mut node.mode, mode, mode.name());
+                }
+            });
+
+        if old_mode != node.mode
+            && let Some(caller) = node.subscription.event_caller(Event::OnChange)
+        {
+            caller(self_node);
+        }
+
+        None
+    }
+
+    fn handle_display_output(_self_node: SelfNodeMut, _pin: &OutPin, _ui: &mut Ui) -> Option<PinInfo> {
+        Some(output::empty_view())
+    }
+
+    fn handle_input_collect_ids(
+        self_node: SelfNodeMut,
+        predicate: &dyn Fn(&Node) -> bool,
+        destination: &mut FastIndexSet<NodeId>,
+    ) {
+        let node = self_node.node_ref().as_mix_material_ref();
+        let [first, second] = node.materials();
+        for node_id in [first, second, node.mask()] {
+            collect_for_node(node_id, predicate, destination, self_node.snarl);
+        }
+    }
+}
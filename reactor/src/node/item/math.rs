@@ -0,0 +1,229 @@
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use reactor_types::{Float, NodePin};
+use serde::{Deserialize, Serialize};
+
+use crate::node::evaluator::{self, NodeOutput};
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
+use crate::node::viewer::ui::{input, output};
+use crate::node::{NodeFlags, Noded};
+
+const MAX_OPERANDS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MathOperation {
+    Add,
+    Subtract,
+    Multiply,
+    /// Division by zero gives zero.
+    Divide,
+    Power,
+    /// Angle in radians.
+    Sine,
+    /// Angle in radians.
+    Cosine,
+    Clamp,
+    Lerp,
+    /// Maps the value from one range to another, a degenerate source range gives the start of the target one.
+    Remap,
+}
+
+impl MathOperation {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Add => "Add",
+            Self::Subtract => "Subtract",
+            Self::Multiply => "Multiply",
+            Self::Divide => "Divide",
+            Self::Power => "Power",
+            Self::Sine => "Sine",
+            Self::Cosine => "Cosine",
+            Self::Clamp => "Clamp",
+            Self::Lerp => "Lerp",
+            Self::Remap => "Remap",
+        }
+    }
+
+    pub fn operand_labels(self) -> &'static [&'static str] {
+        match self {
+            Self::Add | Self::Subtract | Self::Multiply | Self::Divide => &["A", "B"],
+            Self::Power => &["Base", "Exponent"],
+            Self::Sine | Self::Cosine => &["Angle"],
+            Self::Clamp => &["Value", "Min", "Max"],
+            Self::Lerp => &["A", "B", "Factor"],
+            Self::Remap => &["Value", "From Min", "From Max", "To Min", "To Max"],
+        }
+    }
+
+    pub fn inputs(self) -> &'static [u64] {
+        let inputs: &'static [u64; MAX_OPERANDS] = &MathNode::INPUTS;
+        &inputs[..self.operand_labels().len()]
+    }
+
+    /// Initial values of the operands, the identity of the operation where there is one.
+    fn initial_operands(self) -> [Float; MAX_OPERANDS] {
+        match self {
+            Self::Multiply | Self::Divide | Self::Power => [0.0, 1.0, 0.0, 0.0, 0.0],
+            Self::Clamp => [0.0, 0.0, 1.0, 0.0, 0.0],
+            Self::Lerp => [0.0, 1.0, 0.5, 0.0, 0.0],
+            Self::Remap => [0.0, 0.0, 1.0, 0.0, 1.0],
+            Self::Add | Self::Subtract | Self::Sine | Self::Cosine => [0.0; MAX_OPERANDS],
+        }
+    }
+
+    /// Operands past the arity of the operation are ignored.
+    pub fn evaluate(self, operands: [Float; MAX_OPERANDS]) -> Float {
+        let [a, b, c, d, e] = operands;
+        match self {
+            Self::Add => a + b,
+            Self::Subtract => a - b,
+            Self::Multiply => a * b,
+            Self::Divide => {
+                if b == 0.0 {
+                    0.0
+                } else {
+                    a / b
+                }
+            },
+            Self::Power => a.powf(b),
+            Self::Sine => a.sin(),
+            Self::Cosine => a.cos(),
+            // `Float::clamp` panics on an inverted range.
+            Self::Clamp => a.max(b).min(c),
+            Self::Lerp => a + (b - a) * c,
+            Self::Remap => {
+                if b == c {
+                    d
+                } else {
+                    d + (a - b) / (c - b) * (e - d)
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct MathNode {
+    operation: MathOperation,
+    operands: [NodePin<Float>; MAX_OPERANDS],
+}
+
+impl MathNode {
+    pub const NAME: &str = "Math";
+    pub const INPUTS: [u64; MAX_OPERANDS] = [NodeFlags::TYPICAL_NUMBER_INPUT.bits(); MAX_OPERANDS];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::NUMBER.bits()];
+
+    pub fn new(operation: MathOperation) -> Self {
+        Self {
+            operation,
+            operands: operation.initial_operands().map(NodePin::new),
+        }
+    }
+
+    pub fn value(&self) -> Float {
+        self.operation.evaluate(self.operands.each_ref().map(NodePin::get))
+    }
+}
+
+impl Noded for MathNode {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn inputs(&self) -> &[u64] {
+        self.operation.inputs()
+    }
+
+    fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    fn reset_input(&mut self, pin: &InPin) -> bool {
+        match self.operands.get_mut(pin.id.input) {
+            Some(operand) => {
+                operand.reset();
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn set_input(&mut self, input: usize, output: &NodeOutput) -> bool {
+        self.operands
+            .get_mut(input)
+            .is_some_and(|operand| evaluator::apply_output(operand, output))
+    }
+
+    fn animate(&mut self, time: f32) -> bool {
+        self.operands
+            .iter_mut()
+            .fold(false, |changed, operand| operand.animate(time) | changed)
+    }
+}
+
+impl MessageHandling for MathNode {
+    fn handle_display_input(self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let label = *self_node
+            .node_ref()
+            .as_math_ref()
+            .operation
+            .operand_labels()
+            .get(pin.id.input)?;
+
+        Some(input::display_number_field(ui, pin, self_node, label, |node| {
+            &mut node.as_math_mut().operands[pin.id.input]
+        }))
+    }
+
+    fn handle_display_body<'a>(
+        self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        ui.label(self_node.node_ref().as_math_ref().operation.name());
+        None
+    }
+
+    fn handle_display_output(self_node: SelfNodeMut, pin: &OutPin, ui: &mut Ui) -> Option<PinInfo> {
+        if pin.id.output == 0 {
+            Some(output::number_label(ui, "", self_node.node_ref().as_math_ref().value()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_operands_keep_the_value() {
+        for operation in [
+            MathOperation::Add,
+            MathOperation::Multiply,
+            MathOperation::Divide,
+            MathOperation::Power,
+        ] {
+            let mut operands = operation.initial_operands();
+            operands[0] = 3.0;
+            assert_eq!(operation.evaluate(operands), 3.0, "{operation:?}");
+        }
+    }
+
+    #[test]
+    fn test_degenerate_operands() {
+        assert_eq!(MathOperation::Divide.evaluate([1.0, 0.0, 0.0, 0.0, 0.0]), 0.0);
+        assert_eq!(MathOperation::Remap.evaluate([5.0, 1.0, 1.0, 2.0, 3.0]), 2.0);
+        assert_eq!(MathOperation::Clamp.evaluate([0.5, 1.0, 0.0, 0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_remap() {
+        assert_eq!(MathOperation::Remap.evaluate([0.25, 0.0, 1.0, 10.0, 20.0]), 12.5);
+        assert_eq!(MathOperation::Remap.evaluate([2.0, 0.0, 1.0, 1.0, 0.0]), -1.0);
+        assert_eq!(MathOperation::Lerp.evaluate([2.0, 4.0, 0.75, 0.0, 0.0]), 3.5);
+    }
+}
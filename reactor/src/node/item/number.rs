@@ -9,7 +9,7 @@ use crate::node::message::{MessageHandling, SelfNodeMut};
 use crate::node::viewer::ui::output;
 use crate::node::{NodeFlags, Noded};
 
-#[derive(Clone, Copy, Default, Serialize, Deserialize, Noded)]
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize, Noded)]
 pub struct NumberNode {
     value: Float,
 }
@@ -9,7 +9,7 @@ use crate::node::viewer::ui::input;
 use crate::node::{NodeFlags, Noded};
 use crate::tabs::Tab;
 
-#[derive(Clone, Default, Serialize, Deserialize, Noded)]
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Noded)]
 pub struct OutputNode {
     tab_titles: Vec<String>,
     selected_title: Option<String>,
@@ -7,7 +7,7 @@ use crate::node::message::{CommonNodeMessage, CommonNodeResponse, MessageHandlin
 
 pub mod sphere;
 
-#[derive(Clone, EnumAs, Serialize, Deserialize)]
+#[derive(Clone, EnumAs, PartialEq, Serialize, Deserialize)]
 #[enum_dispatch(Noded)]
 pub enum PrimitiveNode {
     Sphere(SphereNode),
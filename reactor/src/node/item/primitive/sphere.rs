@@ -9,10 +9,11 @@ use serde::{Deserialize, Serialize};
 use crate::node::item::InputMaterial;
 use crate::node::message::{MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::Subscription;
+use crate::node::viewer::gizmo::TransformPins;
 use crate::node::viewer::ui::{input, output};
 use crate::node::{Node, NodeFlags, Noded, collect_for_node};
 
-#[derive(Clone, Serialize, Deserialize, Noded)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Noded)]
 pub struct SphereNode {
     center: NodePin<Vector>,
     radius: NodePin<Float>,
@@ -46,6 +47,14 @@ impl SphereNode {
         self.material.as_ref()
     }
 
+    pub fn transform_mut(&mut self) -> TransformPins<'_> {
+        TransformPins {
+            position: Some((0, &mut self.center)),
+            scale: Some((1, &mut self.radius)),
+            ..Default::default()
+        }
+    }
+
     pub fn to_xrays_sphere(&self, material_idx: u32) -> xrays::Sphere {
         let center = self.center.get().as_dim3();
         xrays::Sphere::new(convert_vector3_down(&center), self.radius.get() as _, material_idx)
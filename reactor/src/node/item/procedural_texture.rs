@@ -0,0 +1,142 @@
+use egui::{DragValue, Ui};
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use reactor_derives::Noded;
+use reactor_types::{Color, Float, NodePin, Vector3};
+use serde::{Deserialize, Serialize};
+use xrays::procedural::{Pattern, ProceduralTexture};
+
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
+use crate::node::subscribtion::{Event, Subscription};
+use crate::node::viewer::ui::{input, output};
+use crate::node::{NodeFlags, Noded};
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Noded)]
+pub struct ProceduralTextureNode {
+    first_color: NodePin<Color>,
+    second_color: NodePin<Color>,
+    scale: NodePin<Float>,
+    pattern: Pattern,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl ProceduralTextureNode {
+    pub const NAME: &str = "Procedural Texture";
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::TEXTURE.bits()];
+
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            first_color: NodePin::new(Color::BLACK),
+            second_color: NodePin::new(Color::WHITE),
+            scale: NodePin::new(1.0),
+            pattern,
+            subscription: Default::default(),
+        }
+    }
+
+    pub fn to_xrays_texture(&self) -> ProceduralTexture {
+        let color = |color: Color| {
+            let [r, g, b, _] = color.to_normalized_gamma_f32();
+            Vector3::new(r, g, b)
+        };
+
+        ProceduralTexture {
+            pattern: self.pattern,
+            colors: [color(self.first_color.get()), color(self.second_color.get())],
+            scale: self.scale.get() as f32,
+        }
+    }
+}
+
+impl MessageHandling for ProceduralTextureNode {
+    fn handle_display_input(self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        match pin.id.input {
+            0 => Some(input::display_color_field(ui, pin, self_node, "First", |node| {
+                &mut node.as_procedural_texture_mut().first_color
+            })),
+            1 => Some(input::display_color_field(ui, pin, self_node, "Second", |node| {
+                &mut node.as_procedural_texture_mut().second_color
+            })),
+            2 => Some(input::display_number_field(ui, pin, self_node, "Scale", |node| {
+                &mut node.as_procedural_texture_mut().scale
+            })),
+            _ => None,
+        }
+    }
+
+    fn handle_display_body<'a>(
+        mut self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        let node = self_node.node_mut().as_procedural_texture_mut();
+        let old_pattern = node.pattern;
+
+        ui.label(node.pattern.name());
+        match &mut node.pattern {
+            Pattern::Noise {
+                octaves,
+                lacunarity,
+                gain,
+            } => {
+                ui.horizontal(|ui| {
+                    ui.label("Octaves");
+                    ui.add(DragValue::new(octaves).range(1..=16));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Lacunarity");
+                    ui.add(DragValue::new(lacunarity).speed(0.01).range(1.0..=4.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Gain");
+                    ui.add(DragValue::new(gain).speed(0.01).range(0.0..=1.0));
+                });
+            },
+            Pattern::Voronoi { jitter } => {
+                ui.horizontal(|ui| {
+                    ui.label("Jitter");
+                    ui.add(DragValue::new(jitter).speed(0.01).range(0.0..=1.0));
+                });
+            },
+            Pattern::Gradient { direction } => {
+                ui.horizontal(|ui| {
+                    ui.label("Direction");
+                    for component in direction {
+                        ui.add(DragValue::new(component).speed(0.01));
+                    }
+                });
+            },
+            Pattern::Bricks { mortar, row_offset } => {
+                ui.horizontal(|ui| {
+                    ui.label("Mortar");
+                    ui.add(DragValue::new(mortar).speed(0.005).range(0.0..=0.5));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Row offset");
+                    ui.add(DragValue::new(row_offset).speed(0.01).range(0.0..=1.0));
+                });
+            },
+            Pattern::Checker => (),
+        }
+
+        if old_pattern != node.pattern
+            && let Some(caller) = node.subscription.event_caller(Event::OnChange)
+        {
+            caller(self_node);
+        }
+
+        None
+    }
+
+    fn handle_display_output(_self_node: SelfNodeMut, _pin: &OutPin, _ui: &mut Ui) -> Option<PinInfo> {
+        Some(output::empty_view())
+    }
+}
@@ -10,7 +10,7 @@ use crate::node::message::{CommonNodeMessage, CommonNodeResponse, MessageHandlin
 pub mod triangle;
 pub mod xrays;
 
-#[derive(Clone, EnumAs, Serialize, Deserialize)]
+#[derive(Clone, EnumAs, PartialEq, Serialize, Deserialize)]
 #[enum_dispatch(Noded)]
 pub enum RenderNode {
     TriangleRender(TriangleRenderNode),
@@ -11,7 +11,7 @@ use crate::node::message::{MessageHandling, SelfNodeMut};
 use crate::node::viewer::ui::{input, output};
 use crate::node::{NodeFlags, Noded};
 
-#[derive(Clone, Default, Serialize, Deserialize, Noded)]
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Noded)]
 pub struct TriangleRenderNode {
     angle: NodePin<Float>,
 }
@@ -1,17 +1,23 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+
 use eframe::egui_wgpu::{Callback, CallbackResources, CallbackTrait, RenderState, ScreenDescriptor};
 use eframe::wgpu;
-use egui::{PaintCallbackInfo, Ui};
+use egui::{ComboBox, DragValue, PaintCallbackInfo, PointerButton, Stroke, Ui};
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, NodeId, OutPin, Snarl};
 use reactor_types::NodePin;
 use reactor_types::rect::RectSize;
 use serde::{Deserialize, Serialize};
-use xrays::scene::Scene;
-use xrays::{RenderParams, SamplingParams};
+use xrays::denoise::{DenoiseParams, MAX_DENOISE_ITERATIONS};
+use xrays::scene::{Scene, SceneDirtyFlags};
+use xrays::tile::{PixelRect, TiledRender};
+use xrays::{RenderParams, RenderPass, SamplingParams};
 
+use crate::node::evaluator::{self, NodeOutput};
 use crate::node::item::camera::{CameraNode, camera_node_by_id};
 use crate::node::item::scene::{SceneNode, SceneNodeResponse};
-use crate::node::message::{MessageHandling, SelfNodeMut};
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
 use crate::node::viewer::remote;
 use crate::node::viewer::ui::{input, output};
 use crate::node::{Node, NodeFlags, Noded, collect_for_node};
@@ -23,12 +29,65 @@ pub struct XraysRenderNode {
     num_bounces: NodePin<u32>,
     camera: NodePin<Option<NodeId>>,
     scene: Option<NodeId>,
+    #[serde(default = "default_adaptive_threshold")]
+    adaptive_threshold: NodePin<f32>,
+    #[serde(default = "default_min_samples_per_pixel")]
+    min_samples_per_pixel: NodePin<u32>,
+    /// Number of denoiser iterations, zero disables denoising.
+    #[serde(default)]
+    denoise_iterations: NodePin<u32>,
+    #[serde(default)]
+    display_pass: RenderPass,
+    /// Renders the auxiliary passes even if the viewport shows the beauty pass.
+    #[serde(default)]
+    aovs: bool,
+    #[serde(default = "default_export_directory")]
+    export_directory: String,
+    /// Part of the viewport which is rendered, in viewport pixels.
+    #[serde(default)]
+    region: Option<PixelRect>,
+    /// Size of the image rendered tile by tile on export.
+    #[serde(default = "default_tiled_size")]
+    tiled_size: RectSize<u32>,
 
     max_viewport_resolution: u32,
     #[serde(skip)]
     force_redraw: bool,
+    #[serde(skip)]
+    export_request: Option<ExportKind>,
+    #[serde(skip)]
+    region_drag_start: Option<egui::Pos2>,
+}
+
+/// Export requests and the region drag in progress aren't compared, they aren't part of the edit history.
+impl PartialEq for XraysRenderNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_samples_per_pixel == other.max_samples_per_pixel
+            && self.num_samples_per_pixel == other.num_samples_per_pixel
+            && self.num_bounces == other.num_bounces
+            && self.camera == other.camera
+            && self.scene == other.scene
+            && self.adaptive_threshold == other.adaptive_threshold
+            && self.min_samples_per_pixel == other.min_samples_per_pixel
+            && self.denoise_iterations == other.denoise_iterations
+            && self.display_pass == other.display_pass
+            && self.aovs == other.aovs
+            && self.export_directory == other.export_directory
+            && self.region == other.region
+            && self.tiled_size == other.tiled_size
+            && self.max_viewport_resolution == other.max_viewport_resolution
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExportKind {
+    Viewport,
+    Tiled,
 }
 
+/// Largest side of a tile of the tiled render.
+const MAX_TILE_SIDE: u32 = 1024;
+
 impl XraysRenderNode {
     pub fn new(max_viewport_resolution: u32) -> Self {
         let sampling = SamplingParams::default();
@@ -38,9 +97,19 @@ impl XraysRenderNode {
             num_bounces: NodePin::new(sampling.num_bounces),
             camera: Default::default(),
             scene: Default::default(),
+            adaptive_threshold: NodePin::new(sampling.adaptive_threshold),
+            min_samples_per_pixel: NodePin::new(sampling.min_samples_per_pixel),
+            denoise_iterations: NodePin::new(0),
+            display_pass: RenderPass::default(),
+            aovs: false,
+            export_directory: default_export_directory(),
+            region: None,
+            tiled_size: default_tiled_size(),
 
             max_viewport_resolution,
             force_redraw: true,
+            export_request: None,
+            region_drag_start: None,
         }
     }
 
@@ -54,23 +123,150 @@ impl XraysRenderNode {
             .and_then(|camera_id| camera_node_by_id(camera_id, snarl))
     }
 
+    /// Returns the primitive node visible at the given position of the viewport.
+    pub fn pick(&self, snarl: &Snarl<Node>, viewport: egui::Rect, pos: egui::Pos2) -> Option<NodeId> {
+        let camera = self.camera_node(snarl)?.to_xrays_camera();
+        let scene_node = snarl.get_node(self.scene?).and_then(Node::scene_ref)?;
+
+        let viewport_size = RectSize {
+            width: viewport.width() as u32,
+            height: viewport.height() as u32,
+        };
+        let u = (pos.x - viewport.min.x) / viewport.width();
+        let v = 1.0 - (pos.y - viewport.min.y) / viewport.height();
+
+        scene_node.pick(&camera.make_ray(viewport_size, u, v))
+    }
+
+    /// Shift and drag draws the render region, shift and click clears it.
+    /// Returns `true` if the pointer interaction was used for the region.
+    pub fn edit_region(&mut self, response: &egui::Response, shift: bool) -> bool {
+        if shift && response.drag_started_by(PointerButton::Primary) {
+            self.region_drag_start = response.interact_pointer_pos();
+        }
+
+        if let Some(start) = self.region_drag_start {
+            if let Some(pointer_pos) = response.interact_pointer_pos() {
+                let rect = egui::Rect::from_two_pos(start, pointer_pos).intersect(response.rect);
+                self.region = Some(PixelRect {
+                    x: (rect.min.x - response.rect.min.x).max(0.0) as u32,
+                    y: (rect.min.y - response.rect.min.y).max(0.0) as u32,
+                    width: rect.width().max(0.0) as u32,
+                    height: rect.height().max(0.0) as u32,
+                });
+            }
+            if response.drag_stopped() {
+                self.region_drag_start = None;
+            }
+            return true;
+        }
+
+        if shift && response.clicked() {
+            self.region = None;
+            return true;
+        }
+        false
+    }
+
+    pub fn paint_region(&self, painter: &egui::Painter, viewport: egui::Rect) {
+        if let Some(region) = self.region {
+            let min = viewport.min + egui::vec2(region.x as f32, region.y as f32);
+            let rect = egui::Rect::from_min_size(min, egui::vec2(region.width as f32, region.height as f32));
+            painter.rect_stroke(
+                rect,
+                0.0,
+                Stroke::new(1.0, egui::Color32::WHITE),
+                egui::StrokeKind::Outside,
+            );
+        }
+    }
+
+    pub fn scene_id(&self) -> Option<NodeId> {
+        self.scene
+    }
+
+    pub fn max_viewport_resolution(&self) -> u32 {
+        self.max_viewport_resolution
+    }
+
+    /// Params of the image rendered tile by tile at the tiled size, `None` without a camera.
+    pub fn tiled_render_params(&self, snarl: &Snarl<Node>) -> Option<RenderParams> {
+        self.camera_node(snarl).map(|camera_node| RenderParams {
+            region: None,
+            ..self.render_params(camera_node, self.tiled_size)
+        })
+    }
+
+    /// Largest square tile which fits into the frame buffers of the viewport.
+    pub fn tile_size(&self) -> RectSize<u32> {
+        RectSize::from(((self.max_viewport_resolution as f64).sqrt() as u32).min(MAX_TILE_SIDE))
+    }
+
+    fn render_params(&self, camera_node: &CameraNode, viewport_size: RectSize<u32>) -> RenderParams {
+        RenderParams {
+            camera: camera_node.to_xrays_camera(),
+            viewport_size,
+            sky: Default::default(),
+            sampling: self.sampling_params(),
+            denoise: self.denoise_params(),
+            aovs: self.aovs,
+            display_pass: self.display_pass,
+            region: self.region,
+        }
+    }
+
     fn sampling_params(&self) -> SamplingParams {
         SamplingParams {
             max_samples_per_pixel: self.max_samples_per_pixel.get(),
             num_samples_per_pixel: self.num_samples_per_pixel.get(),
             num_bounces: self.num_bounces.get(),
+            // A negative threshold, typed in or wired from another node, disables adaptive sampling like zero does.
+            adaptive_threshold: self.adaptive_threshold.get().max(0.0),
+            min_samples_per_pixel: self.min_samples_per_pixel.get(),
         }
     }
+
+    fn denoise_params(&self) -> Option<DenoiseParams> {
+        match self.denoise_iterations.get() {
+            0 => None,
+            iterations => Some(DenoiseParams {
+                iterations: iterations.min(MAX_DENOISE_ITERATIONS),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+fn default_tiled_size() -> RectSize<u32> {
+    RectSize {
+        width: 3840,
+        height: 2160,
+    }
+}
+
+fn default_export_directory() -> String {
+    "render".to_string()
+}
+
+fn default_adaptive_threshold() -> NodePin<f32> {
+    NodePin::new(SamplingParams::default().adaptive_threshold)
+}
+
+fn default_min_samples_per_pixel() -> NodePin<u32> {
+    NodePin::new(SamplingParams::default().min_samples_per_pixel)
 }
 
 impl XraysRenderNode {
     pub const NAME: &str = "Xrays Render";
-    pub const INPUTS: [u64; 5] = [
+    pub const INPUTS: [u64; 8] = [
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::CAMERA.bits(),
         NodeFlags::SCENE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::RENDER_XRAYS.bits()];
 
@@ -98,11 +294,13 @@ impl XraysRenderNode {
                 width: viewport.width() as u32,
                 height: viewport.height() as u32,
             };
-            RenderParams {
-                camera: camera_node.to_xrays_camera(),
-                viewport_size,
-                sky: Default::default(),
-                sampling: node.sampling_params(),
+            node.render_params(camera_node, viewport_size)
+        });
+        let export = node.export_request.map(|kind| {
+            let tiled = (kind == ExportKind::Tiled).then(|| (node.tiled_size, node.tile_size()));
+            Export {
+                directory: PathBuf::from(&node.export_directory),
+                tiled,
             }
         });
 
@@ -112,25 +310,31 @@ impl XraysRenderNode {
                 || SceneNodeResponse::Recalculated
                     == SceneNode::handle_recalculate(SelfNodeMut::new(scene_node_id, self_node.snarl))
             {
-                Some(self_node.snarl[scene_node_id].as_scene_ref().as_scene().clone())
+                let scene_node = self_node.snarl[scene_node_id].as_scene_ref();
+                let changes = if force_redraw {
+                    SceneDirtyFlags::ALL
+                } else {
+                    scene_node.changes()
+                };
+                Some((scene_node.as_scene().clone(), changes))
             } else {
                 None
             }
         } else if force_redraw {
-            Some(Scene::stub())
+            Some((Scene::stub(), SceneDirtyFlags::ALL))
         } else {
             None
         };
 
-        if force_redraw {
-            self_node.snarl[self_node.id]
-                .as_render_mut()
-                .as_xrays_render_mut()
-                .force_redraw = false;
+        {
+            let node = self_node.snarl[self_node.id].as_render_mut().as_xrays_render_mut();
+            node.force_redraw = false;
+            node.export_request = None;
         }
 
         if let Some(render_params) = render_params {
-            let callback = Callback::new_paint_callback(viewport, Drawer::new(render_params, scene));
+            let drawer = Drawer::new(render_params, scene, export, painter.ctx().clone());
+            let callback = Callback::new_paint_callback(viewport, drawer);
             painter.add(callback);
         }
     }
@@ -159,10 +363,40 @@ impl Noded for XraysRenderNode {
                 self.scene = None;
                 self.force_redraw = true
             },
+            5 => self.adaptive_threshold.reset(),
+            6 => self.min_samples_per_pixel.reset(),
+            7 => self.denoise_iterations.reset(),
             _ => return false,
         }
         true
     }
+
+    /// The scene input is left to its display, which also manages the subscription to the scene node.
+    fn set_input(&mut self, input: usize, output: &NodeOutput) -> bool {
+        match input {
+            0 => evaluator::apply_output(&mut self.max_samples_per_pixel, output),
+            1 => evaluator::apply_output(&mut self.num_samples_per_pixel, output),
+            2 => evaluator::apply_output(&mut self.num_bounces, output),
+            3 => evaluator::apply_output(&mut self.camera, output),
+            5 => evaluator::apply_output(&mut self.adaptive_threshold, output),
+            6 => evaluator::apply_output(&mut self.min_samples_per_pixel, output),
+            7 => evaluator::apply_output(&mut self.denoise_iterations, output),
+            _ => false,
+        }
+    }
+
+    fn animate(&mut self, time: f32) -> bool {
+        let changed = [
+            self.max_samples_per_pixel.animate(time),
+            self.num_samples_per_pixel.animate(time),
+            self.num_bounces.animate(time),
+            self.camera.animate(time),
+            self.adaptive_threshold.animate(time),
+            self.min_samples_per_pixel.animate(time),
+            self.denoise_iterations.animate(time),
+        ];
+        changed.contains(&true)
+    }
 }
 
 impl MessageHandling for XraysRenderNode {
@@ -215,10 +449,74 @@ impl MessageHandling for XraysRenderNode {
                 }
                 input::empty_view(ui, LABEL)
             }),
+            5 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Adaptive threshold",
+                |node| &mut node.as_render_mut().as_xrays_render_mut().adaptive_threshold,
+            )),
+            6 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Min samples per pixel",
+                |node| &mut node.as_render_mut().as_xrays_render_mut().min_samples_per_pixel,
+            )),
+            7 => Some(input::display_number_field(
+                ui,
+                pin,
+                self_node,
+                "Denoise iterations",
+                |node| &mut node.as_render_mut().as_xrays_render_mut().denoise_iterations,
+            )),
             _ => None,
         }
     }
 
+    fn handle_display_body<'a>(
+        mut self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        let node = self_node.node_mut().as_render_mut().as_xrays_render_mut();
+
+        ui.horizontal(|ui| {
+            ui.label("Pass");
+            ComboBox::from_id_salt("Pass")
+                .selected_text(node.display_pass.name())
+                .show_ui(ui, |ui| {
+                    for pass in RenderPass::ALL {
+                        ui.selectable_value(&mut node.display_pass, pass, pass.name());
+                    }
+                });
+        });
+        ui.checkbox(&mut node.aovs, "Render AOVs");
+
+        if node.region.is_some() && ui.button("Clear render region").clicked() {
+            node.region = None;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Export to");
+            ui.text_edit_singleline(&mut node.export_directory);
+            if ui.button("Export").clicked() {
+                node.export_request = Some(ExportKind::Viewport);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Tiled size");
+            ui.add(DragValue::new(&mut node.tiled_size.width).range(1..=u16::MAX as u32));
+            ui.add(DragValue::new(&mut node.tiled_size.height).range(1..=u16::MAX as u32));
+            if ui.button("Render tiled").clicked() {
+                node.export_request = Some(ExportKind::Tiled);
+            }
+        });
+
+        None
+    }
+
     fn handle_display_output(_self_node: SelfNodeMut, _pin: &OutPin, _ui: &mut Ui) -> Option<PinInfo> {
         Some(output::empty_view())
     }
@@ -236,14 +534,34 @@ impl MessageHandling for XraysRenderNode {
     }
 }
 
+/// Rendered passes are exported before the next frame is prepared.
+struct Export {
+    directory: PathBuf,
+    /// Size of the whole image and of a tile, if the image is rendered tile by tile.
+    tiled: Option<(RectSize<u32>, RectSize<u32>)>,
+}
+
 struct Drawer {
     render_params: RenderParams,
-    scene: Option<Scene>,
+    scene: Option<(Scene, SceneDirtyFlags)>,
+    export: Option<Export>,
+    /// Repaints while a tiled render is in progress.
+    ctx: egui::Context,
 }
 
 impl Drawer {
-    fn new(render_params: RenderParams, scene: Option<Scene>) -> Self {
-        Self { render_params, scene }
+    fn new(
+        render_params: RenderParams,
+        scene: Option<(Scene, SceneDirtyFlags)>,
+        export: Option<Export>,
+        ctx: egui::Context,
+    ) -> Self {
+        Self {
+            render_params,
+            scene,
+            export,
+            ctx,
+        }
     }
 }
 
@@ -257,7 +575,30 @@ impl CallbackTrait for Drawer {
         callback_resources: &mut CallbackResources,
     ) -> Vec<wgpu::CommandBuffer> {
         if let Some(resources) = callback_resources.get_mut::<RaytracerRenderResources>() {
-            resources.prepare(device, queue, encoder, &self.render_params, self.scene.as_ref());
+            match &self.export {
+                Some(Export {
+                    directory,
+                    tiled: Some((image_size, tile_size)),
+                }) => {
+                    let render_params = RenderParams {
+                        viewport_size: *image_size,
+                        region: None,
+                        ..self.render_params
+                    };
+                    resources.start_tiled_export(&render_params, *tile_size, directory);
+                },
+                Some(Export { directory, tiled: None }) => resources.export(device, queue, directory),
+                None => (),
+            }
+
+            let scene = self.scene.as_ref().map(|(scene, changes)| (scene, *changes));
+            resources.continue_tiled_export(device, queue);
+            if resources.is_exporting_tiled() {
+                resources.defer_scene(scene);
+                self.ctx.request_repaint();
+            } else {
+                resources.prepare(device, queue, encoder, &self.render_params, scene);
+            }
         }
         Vec::new()
     }
@@ -274,19 +615,39 @@ impl CallbackTrait for Drawer {
     }
 }
 
+/// The viewport doesn't render while a tiled render is in progress, it shows the tiles as they are rendered.
 pub struct RaytracerRenderResources {
     renderer: xrays::Renderer,
+    tiled_export: Option<TiledExport>,
+    /// Scene changes which came during the tiled render, every tile shows the scene as it was when the render started.
+    deferred_scene: Option<(Scene, SceneDirtyFlags)>,
+}
+
+/// Tiled render which is exported once its last tile is done.
+struct TiledExport {
+    render: TiledRender,
+    directory: PathBuf,
 }
 
 impl RaytracerRenderResources {
     pub fn new(render_state: &RenderState, render_params: &RenderParams, max_viewport_resolution: u32) -> Self {
         let device = &render_state.device;
+        let queue = &render_state.queue;
         let target_format = render_state.target_format;
         let scene = Scene::stub();
 
         Self {
-            renderer: xrays::Renderer::new(device, target_format, &scene, render_params, max_viewport_resolution)
-                .expect("Xrays renderer creation failed"),
+            renderer: xrays::Renderer::new(
+                device,
+                queue,
+                target_format,
+                &scene,
+                render_params,
+                max_viewport_resolution,
+            )
+            .expect("Xrays renderer creation failed"),
+            tiled_export: None,
+            deferred_scene: None,
         }
     }
 
@@ -296,6 +657,10 @@ impl RaytracerRenderResources {
             viewport_size,
             sky: Default::default(),
             sampling: node.sampling_params(),
+            denoise: node.denoise_params(),
+            aovs: node.aovs,
+            display_pass: node.display_pass,
+            region: None,
         };
 
         render_state.renderer.write().callback_resources.insert(Self::new(
@@ -315,12 +680,86 @@ impl RaytracerRenderResources {
         queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
         render_params: &RenderParams,
-        scene: Option<&Scene>,
+        scene: Option<(&Scene, SceneDirtyFlags)>,
     ) {
+        let deferred = self.deferred_scene.take();
+        let scene = match (scene, &deferred) {
+            (Some((scene, changes)), Some((_, deferred_changes))) => Some((scene, changes | *deferred_changes)),
+            (scene, deferred) => scene.or(deferred.as_ref().map(|(scene, changes)| (scene, *changes))),
+        };
         self.renderer
             .prepare_frame(device, queue, encoder, render_params, scene);
     }
 
+    fn defer_scene(&mut self, scene: Option<(&Scene, SceneDirtyFlags)>) {
+        if let Some((scene, changes)) = scene {
+            let deferred_changes = self
+                .deferred_scene
+                .take()
+                .map_or(SceneDirtyFlags::empty(), |(_, changes)| changes);
+            self.deferred_scene = Some((scene.clone(), changes | deferred_changes));
+        }
+    }
+
+    pub fn export(&self, device: &wgpu::Device, queue: &wgpu::Queue, directory: &Path) {
+        // The frame buffers hold a tile of the tiled render instead of the viewport.
+        if self.is_exporting_tiled() {
+            tracing::warn!("Failed to export render passes: a tiled render is in progress");
+            return;
+        }
+
+        match self
+            .renderer
+            .read_passes(device, queue)
+            .and_then(|passes| passes.save(directory))
+        {
+            Ok(()) => tracing::info!("Exported render passes to {}", directory.display()),
+            Err(err) => tracing::error!("Failed to export render passes to {}: {err}", directory.display()),
+        }
+    }
+
+    pub fn is_exporting_tiled(&self) -> bool {
+        self.tiled_export.is_some()
+    }
+
+    /// Starts a tiled render which is exported once it's done, a tiled render in progress is dropped.
+    pub fn start_tiled_export(&mut self, render_params: &RenderParams, tile_size: RectSize<u32>, directory: &Path) {
+        match self.renderer.start_tiled(render_params, tile_size) {
+            Ok(render) => {
+                self.tiled_export = Some(TiledExport {
+                    render,
+                    directory: directory.to_owned(),
+                })
+            },
+            Err(err) => tracing::error!("Failed to export tiled render to {}: {err}", directory.display()),
+        }
+    }
+
+    /// Queues the next sampling batches of the tiled render, the GPU renders them while the UI stays responsive. Once
+    /// the last tile is done the image is denoised and saved in the background.
+    pub fn continue_tiled_export(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let Some(export) = &mut self.tiled_export else {
+            return;
+        };
+
+        match self.renderer.render_tiled_step(device, queue, &mut export.render) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(err) => {
+                tracing::error!("Failed to export tiled render to {}: {err}", export.directory.display());
+                self.tiled_export = None;
+                return;
+            },
+        }
+
+        if let Some(TiledExport { render, directory }) = self.tiled_export.take() {
+            thread::spawn(move || match render.finish().save(&directory) {
+                Ok(()) => tracing::info!("Exported tiled render to {}", directory.display()),
+                Err(err) => tracing::error!("Failed to export tiled render to {}: {err}", directory.display()),
+            });
+        }
+    }
+
     pub fn paint(&self, rpass: &mut wgpu::RenderPass<'static>) {
         self.renderer.render_frame(rpass);
     }
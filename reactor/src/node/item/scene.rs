@@ -1,16 +1,16 @@
 use std::collections::HashMap;
 use std::mem;
 
-use bitflags::bitflags;
 use eframe::wgpu::naga::FastIndexSet;
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, NodeId, OutPin};
+use egui_snarl::{InPin, NodeId, OutPin, Snarl};
 use reactor_types::NodePin;
 use serde::{Deserialize, Serialize};
-use xrays::scene::{Scene, TextureData};
+use xrays::Ray;
+use xrays::scene::{Scene, SceneDirtyFlags, TextureData};
 
-use crate::node::item::material::InputMaterial;
+use crate::node::item::material::{InputMaterial, MaterialNode};
 use crate::node::item::primitive::PrimitiveNode;
 use crate::node::message::{
     CommonNodeMessage, CommonNodeResponse, EventMessage, EventResponse, InputMessage, MessageHandling, SelfNodeMut,
@@ -20,31 +20,6 @@ use crate::node::viewer::remote;
 use crate::node::viewer::ui::{input, output};
 use crate::node::{Node, NodeFlags, Noded, collect_for_node};
 
-bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,Serialize, Deserialize)]
-    pub struct SceneDirtyFlags: u32 {
-        const NONE = 0;
-
-        const TEXTURE_VALUE = 1;
-        const TEXTURE_LAYOUT = Self::TEXTURE_VALUE.bits() << 1;
-
-        const MATERIAL_VALUE = Self::TEXTURE_LAYOUT.bits() << 1;
-        const MATERIAL_LAYOUT = Self::MATERIAL_VALUE.bits() << 1;
-
-        const PRIMITIVE_VALUE = Self::MATERIAL_LAYOUT.bits() << 1;
-        const PRIMITIVE_LAYOUT = Self::PRIMITIVE_VALUE.bits() << 1;
-
-        const ALL = u32::MAX;
-        const INIT = Self::ALL.bits() - 1;
-    }
-}
-
-impl Default for SceneDirtyFlags {
-    fn default() -> Self {
-        SceneDirtyFlags::INIT
-    }
-}
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SceneNodeResponse {
     Recalculated,
@@ -60,14 +35,44 @@ pub struct SceneNode {
     #[serde(skip)]
     tracked_nodes: FastIndexSet<NodeId>,
 
+    /// Primitive node for each sphere of the inner scene, indexed the same way as `Scene::spheres`.
+    #[serde(skip)]
+    primitive_nodes: Vec<NodeId>,
+
+    /// Materials of the spheres with their own material, as the inner scene was built with them.
+    #[serde(skip)]
+    internal_materials: HashMap<NodeId, MaterialNode>,
+
     #[serde(skip)]
     dirty: SceneDirtyFlags,
+
+    /// Parts of the inner scene changed by the latest recalculation.
+    #[serde(skip)]
+    changes: SceneDirtyFlags,
 }
 
 impl SceneNode {
     pub const NAME: &str = "Scene";
     pub const INPUTS: [u64; 1] = [NodeFlags::PRIMITIVES.bits() | NodeFlags::COLLECTION.bits()];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::SCENE.bits()];
+
+    pub(crate) fn edit_snapshot(&self) -> Self {
+        Self {
+            scene_data: self.scene_data.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn restore_snapshot(&mut self, snapshot: Self) {
+        self.scene_data = snapshot.scene_data;
+    }
+}
+
+/// Only the input is compared, the inner scene is derived from the graph.
+impl PartialEq for SceneNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.scene_data == other.scene_data
+    }
 }
 
 impl Noded for SceneNode {
@@ -90,7 +95,7 @@ impl MessageHandling for SceneNode {
             const LABEL: &str = "Scene Data";
 
             let remote_value = remote::node(pin, LABEL, self_node.snarl, |remote_node| {
-                matches!(remote_node, Node::Primitive(_) | Node::Collection(_))
+                matches!(remote_node, Node::Primitive(_) | Node::Collection(_) | Node::Group(_))
             });
 
             if let Some(node_id) = remote_value {
@@ -140,10 +145,156 @@ impl SceneNode {
         &self.inner_scene
     }
 
+    pub fn changes(&self) -> SceneDirtyFlags {
+        self.changes
+    }
+
+    /// Parts of the scene which can be affected by a change of the node.
+    fn changes_of(&self, node_id: NodeId, node: &Node) -> SceneDirtyFlags {
+        match node {
+            Node::Texture(_) | Node::ProceduralTexture(_) => SceneDirtyFlags::TEXTURE_VALUE,
+            // Material colors are stored as textures, the renderer compares them with the uploaded ones on material
+            // changes.
+            Node::Material(_) | Node::MixMaterial(_) => SceneDirtyFlags::MATERIAL_VALUE,
+            Node::Primitive(PrimitiveNode::Sphere(sphere_node)) => match sphere_node.material() {
+                InputMaterial::Internal(material_node)
+                    if self.internal_materials.get(&node_id) != Some(material_node) =>
+                {
+                    SceneDirtyFlags::PRIMITIVE_VALUE | SceneDirtyFlags::MATERIAL_VALUE
+                },
+                _ => SceneDirtyFlags::PRIMITIVE_VALUE,
+            },
+            _ => SceneDirtyFlags::ALL,
+        }
+    }
+
     pub fn register_in_render(&mut self) {
         self.dirty = SceneDirtyFlags::ALL;
     }
 
+    /// Returns the primitive node that produced the closest object hit by the ray.
+    pub fn pick(&self, ray: &Ray) -> Option<NodeId> {
+        self.inner_scene
+            .pick(ray)
+            .and_then(|sphere_idx| self.primitive_nodes.get(sphere_idx).copied())
+    }
+
+    fn is_scene_part(node: &Node) -> bool {
+        matches!(
+            node,
+            Node::Primitive(_)
+                | Node::Material(_)
+                | Node::MixMaterial(_)
+                | Node::Texture(_)
+                | Node::ProceduralTexture(_)
+                | Node::Collection(_)
+                | Node::Group(_)
+        )
+    }
+
+    /// Adds the textures, materials and spheres of the nodes to the scene. Groups add the nodes inside them, whose
+    /// spheres are picked as the outermost group `owner`.
+    fn build(
+        nodes: FastIndexSet<NodeId>,
+        owner: Option<NodeId>,
+        snarl: &mut Snarl<Node>,
+        scene: &mut Scene,
+        primitive_nodes: &mut Vec<NodeId>,
+        internal_materials: &mut HashMap<NodeId, MaterialNode>,
+    ) {
+        let mut texture_indices = HashMap::new();
+        let mut material_indices = HashMap::new();
+
+        for node_id in nodes {
+            if let Node::Group(group) = &mut snarl[node_id] {
+                let inner_nodes = group.collect_scene_nodes(&Self::is_scene_part);
+                Self::build(
+                    inner_nodes,
+                    Some(owner.unwrap_or(node_id)),
+                    group.graph_mut(),
+                    scene,
+                    primitive_nodes,
+                    internal_materials,
+                );
+                continue;
+            }
+
+            match &snarl[node_id] {
+                Node::Texture(texture_node) => {
+                    let eq_predicate = |data: &TextureData| {
+                        data.key.as_deref() == Some(texture_node.path())
+                            && data.scale == texture_node.scale() as f32
+                            && data.color_space == texture_node.color_space()
+                            && data.sampler == texture_node.sampler()
+                    };
+
+                    if let Some(texture_id) = scene.textures.iter().position(eq_predicate) {
+                        texture_indices.insert(node_id, texture_id);
+                    } else {
+                        scene.textures.push(TextureData {
+                            source: texture_node.cached_texture().into(),
+                            key: Some(texture_node.path().to_string().into()),
+                            scale: texture_node.scale() as f32,
+                            color_space: texture_node.color_space(),
+                            sampler: texture_node.sampler(),
+                        });
+                        texture_indices.insert(node_id, scene.textures.len() - 1);
+                    }
+                },
+                Node::ProceduralTexture(procedural_node) => {
+                    scene
+                        .textures
+                        .push(TextureData::new_procedural(procedural_node.to_xrays_texture()));
+                    texture_indices.insert(node_id, scene.textures.len() - 1);
+                },
+                Node::Material(material_node) => {
+                    let texture_id = material_node
+                        .get_texture_node_id()
+                        .and_then(|node_id| texture_indices.get(&node_id).copied());
+                    let normal_map =
+                        material_node.to_xrays_normal_map(|node_id| texture_indices.get(&node_id).copied());
+                    let material = material_node.to_xrays_material(texture_id, normal_map, &mut scene.textures);
+                    scene.materials.push(material);
+                    material_indices.insert(node_id, scene.materials.len() - 1);
+                },
+                Node::MixMaterial(mix_node) => {
+                    let material = mix_node.to_xrays_material(
+                        |node_id| material_indices.get(&node_id).copied(),
+                        |node_id| texture_indices.get(&node_id).copied(),
+                        &mut scene.materials,
+                        &mut scene.textures,
+                    );
+                    scene.materials.push(material);
+                    material_indices.insert(node_id, scene.materials.len() - 1);
+                },
+                Node::Primitive(PrimitiveNode::Sphere(sphere_node)) => {
+                    let material_idx = match sphere_node.material() {
+                        InputMaterial::Internal(material_node) => {
+                            let texture_id = material_node
+                                .get_texture_node_id()
+                                .and_then(|node_id| texture_indices.get(&node_id).copied());
+                            let normal_map =
+                                material_node.to_xrays_normal_map(|node_id| texture_indices.get(&node_id).copied());
+                            let material = material_node.to_xrays_material(texture_id, normal_map, &mut scene.textures);
+                            scene.materials.push(material);
+                            // The spheres inside groups change through the group, whose changes rebuild everything.
+                            if owner.is_none() {
+                                internal_materials.insert(node_id, material_node.clone());
+                            }
+                            scene.materials.len() - 1
+                        },
+                        InputMaterial::External(node_id) => material_indices[node_id],
+                    };
+
+                    let sphere = sphere_node.to_xrays_sphere(material_idx as u32);
+                    scene.spheres.push(sphere);
+                    primitive_nodes.push(owner.unwrap_or(node_id));
+                },
+                _ => (),
+            }
+        }
+    }
+
     pub fn handle_recalculate(mut self_node: SelfNodeMut) -> SceneNodeResponse {
         let old_data = {
             let node = self_node.node_mut().as_scene_mut();
@@ -154,17 +305,18 @@ impl SceneNode {
             }
         };
 
-        if let Some((mut old_scene, old_nodes)) = old_data {
+        if let Some((old_scene, old_nodes)) = old_data {
+            let old_lengths = (
+                old_scene.textures.len(),
+                old_scene.materials.len(),
+                old_scene.spheres.len(),
+            );
+
             let mut nodes = FastIndexSet::default();
             Self::handle_msg(
                 SelfNodeMut::new(self_node.id, self_node.snarl),
                 CommonNodeMessage::Input(InputMessage::CollectIds {
-                    predicate: &|node| {
-                        matches!(
-                            node,
-                            Node::Primitive(_) | Node::Material(_) | Node::Texture(_) | Node::Collection(_)
-                        )
-                    },
+                    predicate: &Self::is_scene_part,
                     destination: &mut nodes,
                 }),
             );
@@ -184,19 +336,22 @@ impl SceneNode {
                     Node::handle_msg(SelfNodeMut::new(*node_id, self_node.snarl), EventMessage::Subscribe {
                         node_id: self_node.id,
                         event: Event::OnChange,
-                        callback: |self_node: SelfNodeMut, subscriber_id: NodeId| match self_node
-                            .snarl
-                            .get_node_mut(subscriber_id)
-                        {
-                            Some(Node::Scene(node)) => {
-                                node.dirty = SceneDirtyFlags::ALL;
-                            },
-                            _ => {
-                                Node::handle_msg(self_node, EventMessage::Unsubscribe {
-                                    node_id: subscriber_id,
-                                    event: Event::OnChange,
-                                });
-                            },
+                        callback: |self_node: SelfNodeMut, subscriber_id: NodeId| {
+                            let changes = match self_node.snarl.get_node(subscriber_id) {
+                                Some(Node::Scene(node)) => Some(node.changes_of(self_node.id, self_node.node_ref())),
+                                _ => None,
+                            };
+                            match changes {
+                                Some(changes) => {
+                                    self_node.snarl[subscriber_id].as_scene_mut().dirty |= changes;
+                                },
+                                None => {
+                                    Node::handle_msg(self_node, EventMessage::Unsubscribe {
+                                        node_id: subscriber_id,
+                                        event: Event::OnChange,
+                                    });
+                                },
+                            }
                         },
                     });
                 }
@@ -214,69 +369,34 @@ impl SceneNode {
                 }
             }
 
-            let mut textures: Vec<TextureData> = Vec::new();
-            let mut texture_indices = HashMap::new();
-
-            let mut materials = Vec::new();
-            let mut material_indices = HashMap::new();
-
-            let mut spheres = Vec::new();
-
-            for node_id in nodes {
-                match self_node.node_by_id_ref(node_id) {
-                    Node::Texture(texture_node) => {
-                        let eq_predicate = |data: &TextureData| {
-                            data.key.as_deref() == Some(texture_node.path())
-                                && data.scale == texture_node.scale() as f32
-                        };
-
-                        if let Some(texture_id) = textures.iter().position(eq_predicate) {
-                            texture_indices.insert(node_id, texture_id);
-                        } else if let Some(texture_id) = old_scene.textures.iter().position(eq_predicate) {
-                            let data = old_scene.textures.remove(texture_id);
-                            textures.push(data);
-                            texture_indices.insert(node_id, textures.len() - 1);
-                        } else {
-                            let data =
-                                TextureData::load_scaled(texture_node.path().to_string(), texture_node.scale() as _);
-                            textures.push(data);
-                            texture_indices.insert(node_id, textures.len() - 1);
-                        }
-                    },
-                    Node::Material(material_node) => {
-                        let texture_id = material_node
-                            .get_texture_node_id()
-                            .and_then(|node_id| texture_indices.get(&node_id).copied());
-                        let material = material_node.to_xrays_material(texture_id, &mut textures);
-                        materials.push(material);
-                        material_indices.insert(node_id, materials.len() - 1);
-                    },
-                    Node::Primitive(PrimitiveNode::Sphere(sphere_node)) => {
-                        let material_idx = match sphere_node.material() {
-                            InputMaterial::Internal(material_node) => {
-                                let texture_id = material_node
-                                    .get_texture_node_id()
-                                    .and_then(|node_id| texture_indices.get(&node_id).copied());
-                                let material = material_node.to_xrays_material(texture_id, &mut textures);
-                                materials.push(material);
-                                materials.len() - 1
-                            },
-                            InputMaterial::External(node_id) => material_indices[node_id],
-                        };
-
-                        let sphere = sphere_node.to_xrays_sphere(material_idx as u32);
-                        spheres.push(sphere);
-                    },
-                    _ => (),
-                }
-            }
+            let mut scene = Scene::default();
+            let mut primitive_nodes = Vec::new();
+            let mut internal_materials = HashMap::new();
+            Self::build(
+                nodes,
+                None,
+                self_node.snarl,
+                &mut scene,
+                &mut primitive_nodes,
+                &mut internal_materials,
+            );
 
             let node = self_node.node_mut().as_scene_mut();
-            node.inner_scene = Scene {
-                spheres,
-                materials,
-                textures,
-            };
+            let mut changes = node.dirty;
+            if old_lengths.0 != scene.textures.len() {
+                changes |= SceneDirtyFlags::TEXTURE_LAYOUT;
+            }
+            if old_lengths.1 != scene.materials.len() {
+                changes |= SceneDirtyFlags::MATERIAL_LAYOUT;
+            }
+            if old_lengths.2 != scene.spheres.len() {
+                changes |= SceneDirtyFlags::PRIMITIVE_LAYOUT;
+            }
+
+            node.inner_scene = scene;
+            node.primitive_nodes = primitive_nodes;
+            node.internal_materials = internal_materials;
+            node.changes = changes;
 
             // Самый первый рендер с флагом инициализации не проходит до конца,
             // поэтому нужен будет повторный. В дальнейшем эта ошибка не повторяется.
@@ -292,3 +412,44 @@ impl SceneNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use egui::Pos2;
+
+    use super::*;
+    use crate::node::evaluator::NodeOutput;
+    use crate::node::item::primitive::SphereNode;
+
+    #[test]
+    fn test_radius_change_keeps_the_internal_material() {
+        let mut snarl = Snarl::new();
+        let sphere = snarl.insert_node(
+            Pos2::ZERO,
+            Node::Primitive(PrimitiveNode::Sphere(SphereNode::default())),
+        );
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(SceneNode::default()));
+        let scene_node = snarl[scene].as_scene_mut();
+        scene_node.scene_data.set(Some(sphere));
+        scene_node.register_in_render();
+        assert_eq!(
+            SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl)),
+            SceneNodeResponse::Recalculated
+        );
+
+        assert!(snarl[sphere].set_input(1, &NodeOutput::Number(2.0)));
+        let changes = snarl[scene].as_scene_ref().changes_of(sphere, &snarl[sphere]);
+        assert_eq!(changes, SceneDirtyFlags::PRIMITIVE_VALUE);
+
+        // The scene was built with another material.
+        let scene_node = snarl[scene].as_scene_mut();
+        scene_node
+            .internal_materials
+            .insert(sphere, MaterialNode::Metal(Default::default()));
+        let changes = snarl[scene].as_scene_ref().changes_of(sphere, &snarl[sphere]);
+        assert_eq!(
+            changes,
+            SceneDirtyFlags::PRIMITIVE_VALUE | SceneDirtyFlags::MATERIAL_VALUE
+        );
+    }
+}
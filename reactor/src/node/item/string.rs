@@ -8,7 +8,7 @@ use crate::node::message::{MessageHandling, SelfNodeMut};
 use crate::node::viewer::ui::output;
 use crate::node::{NodeFlags, Noded};
 
-#[derive(Clone, Default, Serialize, Deserialize, Noded)]
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, Noded)]
 pub struct StringNode {
     value: String,
 }
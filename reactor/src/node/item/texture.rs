@@ -1,19 +1,40 @@
-use egui::Ui;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+
+use egui::{ComboBox, DragValue, Ui};
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, OutPin};
 use reactor_derives::Noded;
 use reactor_types::{Float, NodePin};
 use serde::{Deserialize, Serialize};
+use xrays::Texture;
+use xrays::texture::{ColorSpace, TextureFilter, TextureSampler, TextureWrap};
+use xrays::texture_cache::{CachedTexture, TextureCache, TextureKey};
 
-use crate::node::message::{MessageHandling, SelfNodeMut};
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
 use crate::node::subscribtion::{Event, Subscription};
 use crate::node::viewer::ui::{input, output};
 use crate::node::{NodeFlags, Noded};
 
-#[derive(Clone, Serialize, Deserialize, Noded)]
+/// Images of all texture nodes, shared by every scene.
+static TEXTURE_CACHE: LazyLock<Mutex<TextureCache>> = LazyLock::new(Default::default);
+
+/// Color of textures which are still being decoded.
+const LOADING_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+/// Color of textures which failed to load.
+const MISSING_COLOR: [f32; 3] = [1.0, 0.0, 1.0];
+
+pub fn texture_cache() -> MutexGuard<'static, TextureCache> {
+    TEXTURE_CACHE.lock().expect("Texture cache lock is poisoned")
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Noded)]
 pub struct TextureNode {
     path: String,
     scale: NodePin<Float>,
+    #[serde(default)]
+    color_space: ColorSpace,
+    #[serde(default)]
+    sampler: TextureSampler,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -24,6 +45,8 @@ impl Default for TextureNode {
         Self {
             path: Default::default(),
             scale: NodePin::new(1.0),
+            color_space: Default::default(),
+            sampler: Default::default(),
             subscription: Default::default(),
         }
     }
@@ -41,6 +64,44 @@ impl TextureNode {
     pub fn scale(&self) -> Float {
         self.scale.get()
     }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn sampler(&self) -> TextureSampler {
+        self.sampler
+    }
+
+    pub fn uses(&self, key: &TextureKey) -> bool {
+        TextureKey::new(&self.path, self.scale() as f32, self.color_space).is_ok_and(|node_key| node_key == *key)
+    }
+
+    /// Returns the error of the latest attempt to load the image, if it failed.
+    pub fn load_error(&self) -> Option<String> {
+        if self.path.is_empty() {
+            return None;
+        }
+
+        match texture_cache().peek(&self.path, self.scale() as f32, self.color_space) {
+            Some(CachedTexture::Failed(err)) => Some(err.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns the image from the texture cache, or a placeholder color while it is loading or if it failed to load.
+    pub fn cached_texture(&self) -> Arc<Texture> {
+        let placeholder = |[r, g, b]: [f32; 3]| Arc::new(Texture::new_from_color(xrays::Color::new(r, g, b)));
+
+        match texture_cache().get(&self.path, self.scale() as f32, self.color_space) {
+            CachedTexture::Ready(texture) => texture,
+            CachedTexture::Loading => placeholder(LOADING_COLOR),
+            CachedTexture::Failed(err) => {
+                tracing::warn!("Failed to load texture {}: {err}", self.path);
+                placeholder(MISSING_COLOR)
+            },
+        }
+    }
 }
 
 impl MessageHandling for TextureNode {
@@ -53,6 +114,72 @@ impl MessageHandling for TextureNode {
         }
     }
 
+    fn handle_display_body<'a>(
+        mut self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        let node = self_node.node_mut().as_texture_mut();
+
+        let old_color_space = node.color_space;
+        let old_sampler = node.sampler;
+        ui.horizontal(|ui| {
+            ui.label("Color space");
+            ComboBox::from_id_salt("Color space")
+                .selected_text(node.color_space.name())
+                .show_ui(ui, |ui| {
+                    for color_space in ColorSpace::ALL {
+                        ui.selectable_value(&mut node.color_space, color_space, color_space.name());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Filter");
+            ComboBox::from_id_salt("Filter")
+                .selected_text(node.sampler.filter.name())
+                .show_ui(ui, |ui| {
+                    for filter in TextureFilter::ALL {
+                        ui.selectable_value(&mut node.sampler.filter, filter, filter.name());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Wrap");
+            ComboBox::from_id_salt("Wrap")
+                .selected_text(node.sampler.wrap.name())
+                .show_ui(ui, |ui| {
+                    for wrap in TextureWrap::ALL {
+                        ui.selectable_value(&mut node.sampler.wrap, wrap, wrap.name());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("UV scale");
+            ui.add(DragValue::new(&mut node.sampler.uv_scale[0]).speed(0.01));
+            ui.add(DragValue::new(&mut node.sampler.uv_scale[1]).speed(0.01));
+        });
+        ui.horizontal(|ui| {
+            ui.label("UV offset");
+            ui.add(DragValue::new(&mut node.sampler.uv_offset[0]).speed(0.01));
+            ui.add(DragValue::new(&mut node.sampler.uv_offset[1]).speed(0.01));
+        });
+        ui.checkbox(&mut node.sampler.mipmaps, "Mipmaps");
+
+        if let Some(err) = node.load_error() {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+
+        if (old_color_space != node.color_space || old_sampler != node.sampler)
+            && let Some(caller) = node.subscription.event_caller(Event::OnChange)
+        {
+            caller(self_node);
+        }
+
+        None
+    }
+
     fn handle_display_output(mut self_node: SelfNodeMut, pin: &OutPin, ui: &mut Ui) -> Option<PinInfo> {
         if pin.id.output == 0 {
             let node = self_node.node_mut().as_texture_mut();
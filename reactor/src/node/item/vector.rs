@@ -9,7 +9,7 @@ use crate::node::message::{MessageHandling, SelfNodeMut};
 use crate::node::viewer::ui::output;
 use crate::node::{NodeFlags, Noded};
 
-#[derive(Clone, Copy, Serialize, Deserialize, Noded)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Noded)]
 pub struct VectorNode {
     value: Vector,
 }
@@ -0,0 +1,266 @@
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use reactor_types::{Float, NodePin, Vector, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::node::evaluator::{self, NodeOutput};
+use crate::node::message::{MessageHandling, SelectedTab, SelfNodeMut};
+use crate::node::viewer::ui::{input, output};
+use crate::node::{NodeFlags, Noded};
+
+const COMPONENT_LABELS: [&str; 3] = ["X", "Y", "Z"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VectorOperation {
+    Dot,
+    Cross,
+    /// A zero vector stays zero.
+    Normalize,
+    Length,
+    /// Builds a 3D vector from numbers.
+    Compose,
+    /// Splits a vector into the numbers of its first three components.
+    Decompose,
+}
+
+impl VectorOperation {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Dot => "Dot",
+            Self::Cross => "Cross",
+            Self::Normalize => "Normalize",
+            Self::Length => "Length",
+            Self::Compose => "Compose",
+            Self::Decompose => "Decompose",
+        }
+    }
+
+    pub fn input_labels(self) -> &'static [&'static str] {
+        match self {
+            Self::Dot | Self::Cross => &["A", "B"],
+            Self::Normalize | Self::Length | Self::Decompose => &["Vector"],
+            Self::Compose => &COMPONENT_LABELS,
+        }
+    }
+
+    pub fn inputs(self) -> &'static [u64] {
+        let vector_inputs: &'static [u64; 2] = &VectorMathNode::VECTOR_INPUTS;
+        match self {
+            Self::Dot | Self::Cross => vector_inputs,
+            Self::Normalize | Self::Length | Self::Decompose => &vector_inputs[..1],
+            Self::Compose => &VectorMathNode::COMPONENT_INPUTS,
+        }
+    }
+
+    pub fn outputs(self) -> &'static [u64] {
+        match self {
+            Self::Dot | Self::Length => &VectorMathNode::NUMBER_OUTPUT,
+            Self::Cross | Self::Normalize | Self::Compose => &VectorMathNode::VECTOR_OUTPUT,
+            Self::Decompose => &VectorMathNode::COMPONENT_OUTPUTS,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorMathNode {
+    operation: VectorOperation,
+    vectors: [NodePin<Vector>; 2],
+    components: [NodePin<Float>; 3],
+}
+
+impl VectorMathNode {
+    pub const NAME: &str = "Vector Math";
+    pub const VECTOR_INPUTS: [u64; 2] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits(); 2];
+    pub const COMPONENT_INPUTS: [u64; 3] = [NodeFlags::TYPICAL_NUMBER_INPUT.bits(); 3];
+    pub const NUMBER_OUTPUT: [u64; 1] = [NodeFlags::NUMBER.bits()];
+    pub const VECTOR_OUTPUT: [u64; 1] = [NodeFlags::VECTOR.bits()];
+    pub const COMPONENT_OUTPUTS: [u64; 3] = [NodeFlags::NUMBER.bits(); 3];
+
+    pub fn new(operation: VectorOperation) -> Self {
+        Self {
+            operation,
+            vectors: std::array::from_fn(|_| NodePin::new(Vector::Dim3(Vector3::zeros()))),
+            components: Default::default(),
+        }
+    }
+
+    /// Value of a number output, `None` for a vector output.
+    pub fn number(&self, output: usize) -> Option<Float> {
+        let [a, b] = self.vectors.each_ref().map(NodePin::get);
+        match self.operation {
+            VectorOperation::Dot => Some(a.as_dim4().dot(&b.as_dim4())),
+            VectorOperation::Length => Some(a.as_dim4().norm()),
+            VectorOperation::Decompose => a.as_dim3().get(output).copied(),
+            VectorOperation::Cross | VectorOperation::Normalize | VectorOperation::Compose => None,
+        }
+    }
+
+    /// Value of the vector output, `None` for operations with number outputs.
+    pub fn vector(&self) -> Option<Vector> {
+        let [a, b] = self.vectors.each_ref().map(NodePin::get);
+        match self.operation {
+            VectorOperation::Cross => Some(Vector::Dim3(a.as_dim3().cross(&b.as_dim3()))),
+            VectorOperation::Normalize => Some(normalize(a)),
+            VectorOperation::Compose => Some(Vector::Dim3(Vector3::from(
+                self.components.each_ref().map(NodePin::get),
+            ))),
+            VectorOperation::Dot | VectorOperation::Length | VectorOperation::Decompose => None,
+        }
+    }
+}
+
+fn normalize(vector: Vector) -> Vector {
+    match vector {
+        Vector::Dim2(vector) => Vector::Dim2(vector.try_normalize(0.0).unwrap_or_default()),
+        Vector::Dim3(vector) => Vector::Dim3(vector.try_normalize(0.0).unwrap_or_default()),
+        Vector::Dim4(vector) => Vector::Dim4(vector.try_normalize(0.0).unwrap_or_default()),
+    }
+}
+
+impl Noded for VectorMathNode {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn inputs(&self) -> &[u64] {
+        self.operation.inputs()
+    }
+
+    fn outputs(&self) -> &[u64] {
+        self.operation.outputs()
+    }
+
+    fn reset_input(&mut self, pin: &InPin) -> bool {
+        let idx = pin.id.input;
+        if self.operation == VectorOperation::Compose {
+            match self.components.get_mut(idx) {
+                Some(component) => component.reset(),
+                None => return false,
+            }
+        } else {
+            match self.vectors.get_mut(idx) {
+                Some(vector) => vector.reset(),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    fn set_input(&mut self, input: usize, output: &NodeOutput) -> bool {
+        if self.operation == VectorOperation::Compose {
+            self.components
+                .get_mut(input)
+                .is_some_and(|component| evaluator::apply_output(component, output))
+        } else {
+            self.vectors
+                .get_mut(input)
+                .is_some_and(|vector| evaluator::apply_output(vector, output))
+        }
+    }
+
+    /// Pins of the other operations keep their keyframes, but only the used ones follow them.
+    fn animate(&mut self, time: f32) -> bool {
+        if self.operation == VectorOperation::Compose {
+            self.components
+                .iter_mut()
+                .fold(false, |changed, component| component.animate(time) | changed)
+        } else {
+            self.vectors
+                .iter_mut()
+                .fold(false, |changed, vector| vector.animate(time) | changed)
+        }
+    }
+}
+
+impl MessageHandling for VectorMathNode {
+    fn handle_display_input(self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let operation = self_node.node_ref().as_vector_math_ref().operation;
+        let label = *operation.input_labels().get(pin.id.input)?;
+
+        if operation == VectorOperation::Compose {
+            Some(input::display_number_field(ui, pin, self_node, label, |node| {
+                &mut node.as_vector_math_mut().components[pin.id.input]
+            }))
+        } else {
+            Some(input::display_vector_field(ui, pin, self_node, label, |node| {
+                &mut node.as_vector_math_mut().vectors[pin.id.input]
+            }))
+        }
+    }
+
+    fn handle_display_body<'a>(
+        self_node: SelfNodeMut<'a>,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+    ) -> Option<SelectedTab<'a>> {
+        ui.label(self_node.node_ref().as_vector_math_ref().operation.name());
+        None
+    }
+
+    fn handle_display_output(self_node: SelfNodeMut, pin: &OutPin, ui: &mut Ui) -> Option<PinInfo> {
+        let node = self_node.node_ref().as_vector_math_ref();
+        let label = match node.operation {
+            VectorOperation::Decompose => *COMPONENT_LABELS.get(pin.id.output)?,
+            _ => "",
+        };
+
+        match (node.number(pin.id.output), node.vector()) {
+            (Some(number), _) => Some(output::number_label(ui, label, number)),
+            (None, Some(vector)) if pin.id.output == 0 => Some(output::vector_label(ui, label, vector)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector_math(operation: VectorOperation, a: Vector3, b: Vector3) -> VectorMathNode {
+        let mut node = VectorMathNode::new(operation);
+        node.vectors[0].set(Vector::Dim3(a));
+        node.vectors[1].set(Vector::Dim3(b));
+        node
+    }
+
+    #[test]
+    fn test_output_kinds_match_flags() {
+        for operation in [
+            VectorOperation::Dot,
+            VectorOperation::Cross,
+            VectorOperation::Normalize,
+            VectorOperation::Length,
+            VectorOperation::Compose,
+            VectorOperation::Decompose,
+        ] {
+            let node = VectorMathNode::new(operation);
+            for (output, &flags) in operation.outputs().iter().enumerate() {
+                assert_eq!(node.number(output).is_some(), flags == NodeFlags::NUMBER.bits());
+                assert_eq!(node.vector().is_some(), flags == NodeFlags::VECTOR.bits());
+            }
+        }
+    }
+
+    #[test]
+    fn test_vector_operations() {
+        let (x, y) = (Vector3::x(), Vector3::y());
+        let cross = vector_math(VectorOperation::Cross, x, y).vector().unwrap();
+        assert_eq!(cross.as_dim3(), Vector3::z());
+        assert_eq!(vector_math(VectorOperation::Dot, x, y).number(0), Some(0.0));
+
+        let decompose = vector_math(VectorOperation::Decompose, Vector3::new(1.0, 2.0, 3.0), y);
+        assert_eq!(decompose.number(2), Some(3.0));
+        assert_eq!(decompose.number(3), None);
+    }
+
+    #[test]
+    fn test_normalize_keeps_zero_vectors() {
+        let zero = vector_math(VectorOperation::Normalize, Vector3::zeros(), Vector3::zeros());
+        assert_eq!(zero.vector().unwrap().as_dim3(), Vector3::zeros());
+
+        let length = vector_math(VectorOperation::Length, Vector3::new(3.0, 0.0, 4.0), Vector3::zeros());
+        assert_eq!(length.number(0), Some(5.0));
+    }
+}
@@ -0,0 +1,374 @@
+//! Searchable list of the nodes which can be added to the graph.
+
+use egui::{CollapsingHeader, Key, Modifiers, Pos2, ScrollArea, TextEdit, Ui};
+use serde::{Deserialize, Serialize};
+
+use super::viewer::NodeConfig;
+use super::{Node, NodeFlags};
+
+/// Number of the recently added nodes which are remembered.
+const MAX_RECENT: usize = 8;
+/// Number of the search results which are shown.
+const MAX_RESULTS: usize = 12;
+const WIDTH: f32 = 220.0;
+const MAX_HEIGHT: f32 = 360.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Values,
+    Primitives,
+    Materials,
+    Textures,
+    Scene,
+    Renders,
+    Output,
+}
+
+impl Category {
+    pub const ALL: [Self; 7] = [
+        Self::Values,
+        Self::Primitives,
+        Self::Materials,
+        Self::Textures,
+        Self::Scene,
+        Self::Renders,
+        Self::Output,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Values => "Values",
+            Self::Primitives => "Primitives",
+            Self::Materials => "Materials",
+            Self::Textures => "Textures",
+            Self::Scene => "Scene",
+            Self::Renders => "Renders",
+            Self::Output => "Output",
+        }
+    }
+
+    /// Category of a node by the values of its outputs, the nodes without outputs are outputs themselves.
+    pub fn of(outputs: &[u64]) -> Self {
+        let flags = NodeFlags::from_bits_retain(outputs.iter().fold(0, |flags, &output| flags | output));
+        if flags.intersects(NodeFlags::PRIMITIVES) {
+            Self::Primitives
+        } else if flags.intersects(NodeFlags::MATERIALS) {
+            Self::Materials
+        } else if flags.intersects(NodeFlags::TEXTURE) {
+            Self::Textures
+        } else if flags.intersects(NodeFlags::COLLECTION | NodeFlags::CAMERA | NodeFlags::SCENE) {
+            Self::Scene
+        } else if flags.intersects(NodeFlags::RENDERS) {
+            Self::Renders
+        } else if flags.is_empty() {
+            Self::Output
+        } else {
+            Self::Values
+        }
+    }
+}
+
+/// Node which can be added from the palette.
+#[derive(Clone, Copy)]
+pub struct PaletteEntry {
+    pub name: &'static str,
+    pub factory: fn(&NodeConfig) -> Node,
+    pub category: Category,
+    /// Pin of the new node which the dropped wire connects to.
+    pub pin: usize,
+}
+
+impl PaletteEntry {
+    /// Entries of the nodes for which `pin` picks a pin from their inputs and outputs.
+    pub fn collect(pin: impl Fn(&[u64], &[u64]) -> Option<usize>) -> Vec<Self> {
+        Node::fabrics()
+            .into_iter()
+            .filter_map(|(name, factory, inputs, outputs)| {
+                pin(inputs, outputs).map(|pin| Self {
+                    name,
+                    factory,
+                    category: Category::of(outputs),
+                    pin,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Favorite and recently added nodes, by their names.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PaletteSettings {
+    pub favorites: Vec<String>,
+    /// The most recently added node comes first.
+    pub recent: Vec<String>,
+}
+
+impl PaletteSettings {
+    pub fn is_favorite(&self, name: &str) -> bool {
+        self.favorites.iter().any(|favorite| favorite == name)
+    }
+
+    pub fn toggle_favorite(&mut self, name: &str) {
+        if self.is_favorite(name) {
+            self.favorites.retain(|favorite| favorite != name);
+        } else {
+            self.favorites.push(name.to_string());
+        }
+    }
+
+    pub fn push_recent(&mut self, name: &str) {
+        self.recent.retain(|recent| recent != name);
+        self.recent.insert(0, name.to_string());
+        self.recent.truncate(MAX_RECENT);
+    }
+}
+
+/// Search field with the matching nodes. The graph menus show it inline, Tab or Space opens it as a popup.
+#[derive(Default)]
+pub struct NodePalette {
+    pub settings: PaletteSettings,
+    query: String,
+    /// Index of the entry which Enter adds, among the shown ones.
+    highlighted: usize,
+    /// Pass in which the palette was shown last, the search starts over when it's shown again after a break.
+    shown_pass: u64,
+    /// Screen position of the popup while it's open, with the position in the graph where the picked node goes.
+    popup: Option<(Pos2, Pos2)>,
+}
+
+impl NodePalette {
+    pub fn popup(&self) -> Option<(Pos2, Pos2)> {
+        self.popup
+    }
+
+    pub fn open_popup(&mut self, pos: Pos2, graph_pos: Pos2) {
+        self.popup = Some((pos, graph_pos));
+    }
+
+    pub fn close_popup(&mut self) {
+        self.popup = None;
+    }
+
+    /// Shows the search field with the favorite and recent nodes, or with the best matches of the search, and all
+    /// the entries by category. Returns the entry which was clicked or picked with Enter.
+    pub fn show(&mut self, ui: &mut Ui, entries: &[PaletteEntry]) -> Option<PaletteEntry> {
+        ui.set_min_width(WIDTH);
+
+        let pass = ui.ctx().cumulative_pass_nr();
+        let reopened = pass > self.shown_pass + 1;
+        self.shown_pass = pass;
+        if reopened {
+            self.query.clear();
+            self.highlighted = 0;
+        }
+
+        let search = ui.add(TextEdit::singleline(&mut self.query).hint_text("Search nodes"));
+        if reopened {
+            search.request_focus();
+        }
+        if search.changed() {
+            self.highlighted = 0;
+        }
+
+        let shown = self.shown_entries(entries);
+        // The arrows are consumed so they don't move the cursor of the search field.
+        let (up, down, enter) = ui.input_mut(|input| {
+            (
+                input.consume_key(Modifiers::NONE, Key::ArrowUp),
+                input.consume_key(Modifiers::NONE, Key::ArrowDown),
+                input.key_pressed(Key::Enter),
+            )
+        });
+        if down {
+            self.highlighted = (self.highlighted + 1).min(shown.len().saturating_sub(1));
+        }
+        if up {
+            self.highlighted = self.highlighted.saturating_sub(1);
+        }
+        let mut picked = enter.then(|| shown.get(self.highlighted).copied()).flatten();
+
+        ScrollArea::vertical().max_height(MAX_HEIGHT).show(ui, |ui| {
+            if self.query.is_empty() {
+                let favorites = shown
+                    .iter()
+                    .filter(|entry| self.settings.is_favorite(entry.name))
+                    .count();
+                for (index, &entry) in shown.iter().enumerate() {
+                    if index == 0 && favorites > 0 {
+                        ui.weak("Favorites");
+                    } else if index == favorites {
+                        ui.weak("Recent");
+                    }
+                    if self.show_entry(ui, entry, index == self.highlighted) {
+                        picked = Some(entry);
+                    }
+                }
+                if !shown.is_empty() {
+                    ui.separator();
+                }
+
+                for category in Category::ALL {
+                    let mut in_category = entries.iter().filter(|entry| entry.category == category).peekable();
+                    if in_category.peek().is_none() {
+                        continue;
+                    }
+                    CollapsingHeader::new(category.name()).show(ui, |ui| {
+                        for &entry in in_category {
+                            if self.show_entry(ui, entry, false) {
+                                picked = Some(entry);
+                            }
+                        }
+                    });
+                }
+            } else if shown.is_empty() {
+                ui.weak("No matching nodes");
+            } else {
+                for (index, &entry) in shown.iter().enumerate() {
+                    if self.show_entry(ui, entry, index == self.highlighted) {
+                        picked = Some(entry);
+                    }
+                }
+            }
+        });
+
+        if let Some(entry) = picked {
+            self.settings.push_recent(entry.name);
+            self.popup = None;
+        }
+        picked
+    }
+
+    /// Entries which Up, Down and Enter pick from: the favorites and the recent nodes without a search, otherwise the
+    /// best matches.
+    fn shown_entries(&self, entries: &[PaletteEntry]) -> Vec<PaletteEntry> {
+        if self.query.is_empty() {
+            let find = |name: &String| entries.iter().find(|entry| entry.name == name).copied();
+            let favorites = self.settings.favorites.iter().filter_map(find);
+            let recent = self
+                .settings
+                .recent
+                .iter()
+                .filter(|name| !self.settings.is_favorite(name))
+                .filter_map(find);
+            return favorites.chain(recent).collect();
+        }
+
+        let mut matches = entries
+            .iter()
+            .filter_map(|entry| fuzzy_score(&self.query, entry.name).map(|score| (score, entry)))
+            .collect::<Vec<_>>();
+        matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then_with(|| a.name.cmp(b.name)));
+        matches.into_iter().take(MAX_RESULTS).map(|(_, &entry)| entry).collect()
+    }
+
+    /// Shows the entry with its favorite toggle, returns whether the entry was clicked.
+    fn show_entry(&mut self, ui: &mut Ui, entry: PaletteEntry, highlighted: bool) -> bool {
+        ui.horizontal(|ui| {
+            let favorite = self.settings.is_favorite(entry.name);
+            let (icon, hint) = if favorite {
+                ("★", "Remove from favorites")
+            } else {
+                ("☆", "Add to favorites")
+            };
+            if ui.small_button(icon).on_hover_text(hint).clicked() {
+                self.settings.toggle_favorite(entry.name);
+            }
+
+            let clicked = ui.selectable_label(highlighted, entry.name).clicked();
+            if !self.query.is_empty() {
+                ui.weak(entry.category.name());
+            }
+            clicked
+        })
+        .inner
+    }
+}
+
+/// Scores how well the query matches the name, `None` unless the letters of the query appear in the name in order.
+/// Matches at the starts of words and runs of consecutive letters score higher, whitespace in the query is ignored.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<u32> {
+    let name = name.chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous = None;
+
+    for query_char in query.chars().filter(|char| !char.is_whitespace()) {
+        let index = start
+            + name[start..]
+                .iter()
+                .position(|char| char.to_lowercase().eq(query_char.to_lowercase()))?;
+
+        score += 1;
+        if index > 0 && previous == Some(index - 1) {
+            score += 4;
+        }
+        let word_start = index == 0
+            || !name[index - 1].is_alphanumeric()
+            || (name[index - 1].is_lowercase() && name[index].is_uppercase());
+        if word_start {
+            score += 6;
+        }
+
+        previous = Some(index);
+        start = index + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "Math Add"), Some(0));
+        assert!(fuzzy_score("mad", "Math Add").is_some());
+        assert!(fuzzy_score("MATH add", "Math Add").is_some());
+        assert_eq!(fuzzy_score("dam", "Math Add"), None);
+        assert_eq!(fuzzy_score("sphere", "Sphere"), fuzzy_score("Sphere", "sphere"));
+
+        let mut names = ["Camera", "Mix Material", "Math Add"]
+            .into_iter()
+            .filter_map(|name| fuzzy_score("mat", name).map(|score| (score, name)))
+            .collect::<Vec<_>>();
+        names.sort_by(|a, b| b.cmp(a));
+        assert_eq!(names.iter().map(|(_, name)| *name).collect::<Vec<_>>(), [
+            "Math Add",
+            "Mix Material"
+        ]);
+
+        // The starts of the words weigh more than the letters inside them.
+        assert!(fuzzy_score("vm", "Vector Math Dot") > fuzzy_score("vm", "Vector 2D Remap"));
+    }
+
+    #[test]
+    fn test_categories() {
+        let category = |name| {
+            PaletteEntry::collect(|_, _| Some(0))
+                .into_iter()
+                .find(|entry| entry.name == name)
+                .map(|entry| entry.category)
+        };
+        assert_eq!(category("Number"), Some(Category::Values));
+        assert_eq!(category("Sphere Primitive"), Some(Category::Primitives));
+        assert_eq!(category("Texture"), Some(Category::Textures));
+        assert_eq!(category("Camera"), Some(Category::Scene));
+        assert_eq!(category("Output"), Some(Category::Output));
+    }
+
+    #[test]
+    fn test_recent_nodes() {
+        let mut settings = PaletteSettings::default();
+        for index in 0..MAX_RECENT + 2 {
+            settings.push_recent(&index.to_string());
+        }
+        settings.push_recent("3");
+        assert_eq!(settings.recent.len(), MAX_RECENT);
+        assert_eq!(settings.recent[..3], ["3", "9", "8"]);
+
+        settings.toggle_favorite("3");
+        assert!(settings.is_favorite("3"));
+        settings.toggle_favorite("3");
+        assert!(settings.favorites.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+//! Static checks of the wires against the pin flags of the nodes, with messages which explain the problems.
+
+use std::fmt;
+
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+
+use super::{Node, NodeFlags, Noded};
+
+/// Kinds of values named in the messages, a pin which takes any value of a group takes the group.
+const VALUE_KINDS: [(NodeFlags, &str); 12] = [
+    (NodeFlags::NUMBER, "a number"),
+    (NodeFlags::STRING, "a string"),
+    (NodeFlags::VECTOR, "a vector"),
+    (NodeFlags::COLOR, "a color"),
+    (NodeFlags::PRIMITIVES, "a primitive"),
+    (NodeFlags::MATERIALS, "a material"),
+    (NodeFlags::TEXTURE, "a texture"),
+    (NodeFlags::COLLECTION, "a collection"),
+    (NodeFlags::CAMERA, "a camera"),
+    (NodeFlags::SCENE, "a scene"),
+    (NodeFlags::RENDERS, "a render"),
+    (NodeFlags::OUTPUT, "an output"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn icon(self) -> &'static str {
+        match self {
+            Self::Warning => "⚠",
+            Self::Error => "⛔",
+        }
+    }
+
+    pub fn color(self) -> egui::Color32 {
+        match self {
+            Self::Warning => egui::Color32::from_rgb(0xe0, 0xb0, 0x30),
+            Self::Error => egui::Color32::from_rgb(0xe0, 0x30, 0x30),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The output gives none of the values which the input takes.
+    IncompatibleWire,
+    /// The wire ends at an input which the node doesn't have anymore.
+    MissingInput,
+    /// The wire starts at an output which the node doesn't have anymore.
+    MissingOutput,
+    /// The collection passes the item only to inputs which take none of its values, so they ignore it.
+    IgnoredItem,
+}
+
+impl Problem {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::IncompatibleWire | Self::MissingInput | Self::MissingOutput => Severity::Error,
+            Self::IgnoredItem => Severity::Warning,
+        }
+    }
+}
+
+/// Problem of a wire, reported on the node at its end.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub node: NodeId,
+    pub from: OutPinId,
+    pub to: InPinId,
+    pub problem: Problem,
+    message: String,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.problem.severity()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Checks every wire of the graph, the errors come first.
+pub fn validate(snarl: &Snarl<Node>) -> Vec<Diagnostic> {
+    let wires = snarl.wires().collect::<Vec<_>>();
+
+    let mut diagnostics = wires
+        .iter()
+        .filter_map(|&(from, to)| check_wire(from, to, snarl).err())
+        .collect::<Vec<_>>();
+
+    for (collection_id, node) in snarl.node_ids() {
+        if let Node::Collection(_) = node {
+            diagnostics.extend(check_collection(collection_id, &wires, snarl));
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| (std::cmp::Reverse(diagnostic.severity()), diagnostic.node.0));
+    diagnostics
+}
+
+/// Checks whether the output can feed the input, the error explains why it can't.
+pub fn check_wire(from: OutPinId, to: InPinId, snarl: &Snarl<Node>) -> Result<(), Diagnostic> {
+    let (from_node, to_node) = (&snarl[from.node], &snarl[to.node]);
+    let diagnostic = |problem, message| Diagnostic {
+        node: to.node,
+        from,
+        to,
+        problem,
+        message,
+    };
+
+    let Some(&output) = from_node.outputs().get(from.output) else {
+        return Err(diagnostic(
+            Problem::MissingOutput,
+            format!(
+                "The wire into input {} starts at output {} which `{}` doesn't have",
+                to.input + 1,
+                from.output + 1,
+                from_node.name()
+            ),
+        ));
+    };
+    let Some(&input) = to_node.inputs().get(to.input) else {
+        return Err(diagnostic(
+            Problem::MissingInput,
+            format!(
+                "The wire from `{}` ends at input {} which `{}` doesn't have",
+                from_node.name(),
+                to.input + 1,
+                to_node.name()
+            ),
+        ));
+    };
+
+    if output & input == 0 {
+        return Err(diagnostic(
+            Problem::IncompatibleWire,
+            format!(
+                "`{}` gives {} but input {} of `{}` takes {}",
+                from_node.name(),
+                describe_flags(output),
+                to.input + 1,
+                to_node.name(),
+                describe_flags(input)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Collections take anything, the items are checked against the inputs which the collection feeds.
+fn check_collection(collection_id: NodeId, wires: &[(OutPinId, InPinId)], snarl: &Snarl<Node>) -> Vec<Diagnostic> {
+    let accepted = wires
+        .iter()
+        .filter(|(from, _)| from.node == collection_id)
+        .filter_map(|(_, to)| snarl[to.node].inputs().get(to.input))
+        .fold(0, |accepted, &input| accepted | input);
+    if accepted == 0 {
+        return Vec::new();
+    }
+
+    wires
+        .iter()
+        .filter(|(_, to)| to.node == collection_id)
+        .filter_map(|&(from, to)| {
+            let item = &snarl[from.node];
+            let &output = item.outputs().get(from.output)?;
+            (output & accepted == 0).then(|| Diagnostic {
+                node: collection_id,
+                from,
+                to,
+                problem: Problem::IgnoredItem,
+                message: format!(
+                    "`{}` gives {} but the collection is only used as {}, so the item is ignored",
+                    item.name(),
+                    describe_flags(output),
+                    describe_flags(accepted)
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Names the kinds of values of the pin flags, like "a number or a vector".
+pub fn describe_flags(flags: u64) -> String {
+    if flags == NodeFlags::ALL.bits() {
+        return "anything".to_string();
+    }
+
+    let names = VALUE_KINDS
+        .iter()
+        .filter(|(kind, _)| kind.bits() & flags != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+    match names.as_slice() {
+        [] => "nothing".to_string(),
+        [name] => name.to_string(),
+        [names @ .., last] => format!("{} or {last}", names.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Pos2;
+
+    use super::*;
+    use crate::node::item::{CollectionNode, MathNode, MathOperation, NumberNode, SceneNode, StringNode};
+
+    fn connect(snarl: &mut Snarl<Node>, from: NodeId, to: NodeId, input: usize) {
+        snarl.connect(OutPinId { node: from, output: 0 }, InPinId { node: to, input });
+    }
+
+    #[test]
+    fn test_incompatible_and_missing_pins() {
+        let mut snarl = Snarl::new();
+        let string = snarl.insert_node(Pos2::ZERO, Node::String(StringNode::default()));
+        let number = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let math = snarl.insert_node(Pos2::ZERO, Node::Math(MathNode::new(MathOperation::Add)));
+        connect(&mut snarl, number, math, 0);
+        assert!(validate(&snarl).is_empty());
+
+        connect(&mut snarl, string, math, 1);
+        let diagnostics = validate(&snarl);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].node, math);
+        assert_eq!(diagnostics[0].severity(), Severity::Error);
+        assert_eq!(diagnostics[0].problem, Problem::IncompatibleWire);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "`String` gives a string but input 2 of `Math` takes a number"
+        );
+
+        connect(&mut snarl, number, math, 7);
+        let problems = validate(&snarl)
+            .into_iter()
+            .map(|diagnostic| diagnostic.problem)
+            .collect::<Vec<_>>();
+        assert!(problems.contains(&Problem::MissingInput));
+    }
+
+    #[test]
+    fn test_collection_items_are_checked_against_their_use() {
+        let mut snarl = Snarl::new();
+        let number = snarl.insert_node(Pos2::ZERO, Node::Number(NumberNode::default()));
+        let collection = snarl.insert_node(Pos2::ZERO, Node::Collection(CollectionNode::default()));
+        connect(&mut snarl, number, collection, 0);
+        assert!(validate(&snarl).is_empty());
+
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(SceneNode::default()));
+        connect(&mut snarl, collection, scene, 0);
+        let diagnostics = validate(&snarl);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].node, collection);
+        assert_eq!(diagnostics[0].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_describe_flags() {
+        assert_eq!(describe_flags(NodeFlags::TYPICAL_NUMBER_INPUT.bits()), "a number");
+        assert_eq!(
+            describe_flags(NodeFlags::TYPICAL_VECTOR_INPUT.bits()),
+            "a number, a vector or a color"
+        );
+        assert_eq!(describe_flags(NodeFlags::MATERIAL_METAL.bits()), "a material");
+        assert_eq!(describe_flags(NodeFlags::ALL.bits()), "anything");
+    }
+}
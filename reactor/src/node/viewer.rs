@@ -1,19 +1,42 @@
 use eframe::egui_wgpu::RenderState;
 use eframe::wgpu::naga::{FastHashSet, FastIndexSet};
-use egui::Ui;
+use egui::emath::TSTransform;
+use egui::{Key, KeyboardShortcut, Modifiers, Ui};
 use egui_snarl::ui::{AnyPins, PinInfo, SnarlViewer};
 use egui_snarl::{InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
 
+pub mod gizmo;
 pub mod remote;
 pub mod ui;
 pub mod widget;
 
+use self::gizmo::GizmoMode;
+use self::ui::{widget_events, widgets_changed_since};
+use super::clipboard::Fragment;
+use super::evaluator::{EvaluationError, Evaluator};
+use super::history::{Command, Editor, History};
 use super::item::render::XraysRenderNode;
+use super::item::texture::texture_cache;
+use super::item::{CameraNode, GroupNode};
 use super::message::SelfNodeMut;
+use super::palette::{NodePalette, PaletteEntry, PaletteSettings};
+use super::subscribtion::Event;
+use super::validation::{self, Diagnostic};
 use crate::node::message::{CommonNodeResponse, DisplayMessage, DisplayResponse, InputMessage, InterfaceMessage};
 use crate::node::{Node, Noded, RenderNode};
 use crate::tabs::{Tab, ViewportTab};
 
+const SELECTED_NODE_COLOR: egui::Color32 = egui::Color32::from_rgb(0xff, 0xa5, 0x00);
+const CYCLIC_NODE_COLOR: egui::Color32 = egui::Color32::from_rgb(0xe0, 0x30, 0x30);
+const UNDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+const DUPLICATE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::D);
+const DUPLICATE_WIRED_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::D);
+const GROUP_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::G);
+/// Offset of pasted nodes from the copied ones, so they don't cover each other.
+const PASTE_OFFSET: egui::Vec2 = egui::vec2(40.0, 40.0);
+
 pub struct NodeConfig {
     pub render_state: RenderState,
     pub max_viewport_resolution: u32,
@@ -63,6 +86,22 @@ pub struct NodeViewer {
     config: NodeConfig,
     output_nodes: FastHashSet<NodeId>,
     render_nodes: FastIndexSet<RenderNodeData>,
+    /// The last selected node is the active one, its gizmo is shown.
+    selected_nodes: FastIndexSet<NodeId>,
+    gizmo_mode: GizmoMode,
+    evaluator: Evaluator,
+    history: History,
+    /// Nodes skipped by the latest evaluation because of a cycle.
+    cyclic_nodes: FastHashSet<NodeId>,
+    /// Time the keyed inputs were set for, they keep the edits made until the time changes.
+    animated_time: Option<f32>,
+    /// Problems of the wires found by the latest validation.
+    diagnostics: Vec<Diagnostic>,
+    /// Why the latest wire drawn by the user wasn't connected.
+    rejected_wire: Option<Diagnostic>,
+    palette: NodePalette,
+    /// Transform from the graph to the screen of the latest shown graph.
+    graph_to_screen: TSTransform,
 }
 
 impl NodeViewer {
@@ -85,6 +124,16 @@ impl NodeViewer {
         let mut viewer = Self {
             output_nodes,
             render_nodes: Default::default(),
+            selected_nodes: Default::default(),
+            gizmo_mode: GizmoMode::default(),
+            evaluator: Evaluator::default(),
+            history: History::default(),
+            cyclic_nodes: Default::default(),
+            animated_time: None,
+            diagnostics: Vec::new(),
+            rejected_wire: None,
+            palette: NodePalette::default(),
+            graph_to_screen: TSTransform::IDENTITY,
             config: NodeConfig {
                 render_state,
                 max_viewport_resolution,
@@ -108,6 +157,10 @@ impl NodeViewer {
         viewer
     }
 
+    pub fn render_state(&self) -> &RenderState {
+        &self.config.render_state
+    }
+
     pub fn open_tab(&mut self, tab: &Tab, snarl: &mut Snarl<Node>) {
         if let Tab::Viewport(tab) = tab {
             self.config.viewport_tab_titles.insert(tab.title().to_string());
@@ -155,6 +208,18 @@ impl NodeViewer {
                         render.recalc_angle(drag as _);
                     },
                     RenderNode::XraysRender(render) => {
+                        let shift = ui.input(|i| i.modifiers.shift);
+                        let editing_region = render.edit_region(response, shift);
+                        render.paint_region(ui.painter(), response.rect);
+
+                        let render = snarl[render_node_data.id].as_render_ref().as_xrays_render_ref();
+                        if response.clicked() && !editing_region {
+                            if let Some(pointer_pos) = response.interact_pointer_pos() {
+                                self.selected_nodes =
+                                    render.pick(snarl, response.rect, pointer_pos).into_iter().collect();
+                            }
+                        }
+
                         if let Some(camera) = render
                             .camera_id()
                             .and_then(|camera_id| snarl.get_node_mut(camera_id).and_then(Node::camera_mut))
@@ -171,6 +236,214 @@ impl NodeViewer {
         }
     }
 
+    /// Sets the keyed inputs from their keyframes when the time changes, the changed nodes are reevaluated.
+    pub fn animate(&mut self, time: f32, snarl: &mut Snarl<Node>) {
+        if self.animated_time == Some(time) {
+            return;
+        }
+        self.animated_time = Some(time);
+        self.evaluator.animate(time, snarl);
+    }
+
+    /// Evaluates the graph before it's drawn, so the renders don't depend on which node widgets are visible.
+    pub fn evaluate(&mut self, snarl: &mut Snarl<Node>) {
+        match self.evaluator.evaluate(snarl) {
+            Ok(()) => self.cyclic_nodes.clear(),
+            Err(err) => {
+                let EvaluationError::Cycle(node_ids) = &err;
+                let cyclic_nodes = node_ids.iter().copied().collect::<FastHashSet<_>>();
+                if cyclic_nodes != self.cyclic_nodes {
+                    tracing::warn!("Failed to evaluate the graph: {err}");
+                    self.cyclic_nodes = cyclic_nodes;
+                }
+            },
+        }
+    }
+
+    /// Checks the wires of the graph for the badges of the nodes and the problems panel.
+    pub fn validate(&mut self, snarl: &Snarl<Node>) {
+        self.diagnostics = validation::validate(snarl);
+    }
+
+    /// Lists the problems of the graph, clicking a node name selects the node and the one at the other end of the
+    /// wire.
+    pub fn show_problems(&mut self, ui: &mut Ui, snarl: &mut Snarl<Node>) {
+        if let Some(rejected) = &self.rejected_wire {
+            let dismissed = ui
+                .horizontal(|ui| {
+                    let dismissed = ui.small_button("🗙").on_hover_text("Dismiss").clicked();
+                    ui.label(format!("Rejected wire: {rejected}"));
+                    dismissed
+                })
+                .inner;
+            if dismissed {
+                self.rejected_wire = None;
+            }
+            ui.separator();
+        }
+
+        if self.diagnostics.is_empty() {
+            ui.weak("No problems found");
+            return;
+        }
+
+        let mut selected = None;
+        let mut disconnected = None;
+        egui::Grid::new("problems").striped(true).show(ui, |ui| {
+            for diagnostic in &self.diagnostics {
+                let severity = diagnostic.severity();
+                ui.colored_label(severity.color(), severity.icon());
+                let name = snarl.get_node(diagnostic.node).map_or("", |node| node.name());
+                if ui.link(name).on_hover_text("Select the node").clicked() {
+                    selected = Some(diagnostic);
+                }
+                ui.label(diagnostic.to_string());
+                if ui.small_button("Disconnect").clicked() {
+                    disconnected = Some((diagnostic.from, diagnostic.to));
+                }
+                ui.end_row();
+            }
+        });
+
+        if let Some(diagnostic) = selected {
+            // The node with the problem is the last selected one, so it's the active node.
+            self.selected_nodes = [diagnostic.from.node, diagnostic.node].into_iter().collect();
+        }
+        if let Some((from, to)) = disconnected {
+            self.disconnect_pins(from, to, snarl);
+            self.history.push(Command::Disconnect { from, to });
+            self.validate(snarl);
+        }
+    }
+
+    /// Lends the favorite and recent nodes of the settings to the node palette while `f` runs.
+    pub fn with_palette_settings<R>(&mut self, settings: &mut PaletteSettings, f: impl FnOnce(&mut Self) -> R) -> R {
+        std::mem::swap(&mut self.palette.settings, settings);
+        let result = f(self);
+        std::mem::swap(&mut self.palette.settings, settings);
+        result
+    }
+
+    /// Opens the node palette at the pointer with Tab or Space while the pointer is over the editor. Called after the
+    /// graph of the editor is shown, so the pointer maps to the graph through its transform.
+    pub fn open_palette_on_shortcut(&mut self, ui: &Ui, editor: egui::Rect) {
+        if ui.memory(|memory| memory.focused().is_some()) {
+            return;
+        }
+
+        let pointer = ui
+            .input(|input| input.pointer.hover_pos())
+            .filter(|&pos| editor.contains(pos));
+        if let Some(pos) = pointer {
+            let pressed = ui.input_mut(|input| {
+                input.consume_key(Modifiers::NONE, Key::Tab) || input.consume_key(Modifiers::NONE, Key::Space)
+            });
+            if pressed {
+                self.palette.open_popup(pos, self.graph_to_screen.inverse() * pos);
+            }
+        }
+    }
+
+    /// Shows the node palette popup. The picked node is added to the graph where the popup was opened. Escape or a
+    /// click outside closes the popup.
+    pub fn show_palette_popup(&mut self, ctx: &egui::Context, snarl: &mut Snarl<Node>) {
+        let Some((pos, graph_pos)) = self.palette.popup() else {
+            return;
+        };
+
+        let entries = PaletteEntry::collect(|_, _| Some(0));
+        let mut picked = None;
+        let response = egui::Area::new(egui::Id::new("node_palette"))
+            .fixed_pos(pos)
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    picked = self.palette.show(ui, &entries);
+                });
+            })
+            .response;
+
+        let Some(entry) = picked else {
+            if ctx.input(|input| input.key_pressed(Key::Escape)) || response.clicked_elsewhere() {
+                self.palette.close_popup();
+            }
+            return;
+        };
+
+        let node_id = self.create_node(graph_pos, entry.factory, snarl);
+        self.selected_nodes = [node_id].into_iter().collect();
+    }
+
+    /// Fires change events of the texture nodes whose images have been loaded or changed on disk.
+    pub fn poll_textures(&mut self, snarl: &mut Snarl<Node>) {
+        let updated_keys = texture_cache().poll();
+        if updated_keys.is_empty() {
+            return;
+        }
+
+        let texture_node_ids = snarl
+            .node_ids()
+            .filter_map(|(node_id, node)| match node {
+                Node::Texture(texture_node) if updated_keys.iter().any(|key| texture_node.uses(key)) => Some(node_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for node_id in texture_node_ids {
+            if let Some(caller) = snarl[node_id]
+                .subscription_ref()
+                .and_then(|subscription| subscription.event_caller(Event::OnChange))
+            {
+                caller(SelfNodeMut::new(node_id, snarl));
+            }
+        }
+    }
+
+    /// Shows transform gizmos of the selected node over the viewport rendered with a camera.
+    pub fn show_gizmo(&mut self, tab: &ViewportTab, ui: &mut Ui, viewport: egui::Rect, snarl: &mut Snarl<Node>) {
+        let Some(&node_id) = self.selected_nodes.last() else {
+            return;
+        };
+
+        let selector = RenderSelector::ByTargetTitle(tab.title());
+        let Some(camera) = self
+            .render_nodes
+            .iter()
+            .filter(|render_node_data| render_node_data.select(selector))
+            .find_map(|render_node_data| {
+                snarl
+                    .get_node(render_node_data.id)
+                    .and_then(Node::render_ref)
+                    .and_then(RenderNode::xrays_render_ref)
+                    .and_then(|render| render.camera_node(snarl))
+                    .map(CameraNode::to_xrays_camera)
+            })
+        else {
+            return;
+        };
+
+        let wired_inputs = snarl
+            .wires()
+            .filter(|(_, to_pin)| to_pin.node == node_id)
+            .map(|(_, to_pin)| to_pin.input)
+            .collect::<FastHashSet<_>>();
+
+        let Some(pins) = snarl.get_node_mut(node_id).and_then(Node::transform_mut) else {
+            return;
+        };
+
+        let id = ui.id().with(("gizmo", node_id));
+        if gizmo::show(ui, id, &mut self.gizmo_mode, viewport, &camera, pins, &wired_inputs) {
+            self.evaluator.invalidate(node_id);
+            if let Some(caller) = snarl[node_id]
+                .subscription_ref()
+                .and_then(|subscription| subscription.event_caller(Event::OnChange))
+            {
+                caller(SelfNodeMut::new(node_id, snarl));
+            }
+        }
+    }
+
     fn register_render_if_needed(&mut self, from_node_id: NodeId, to_node_id: NodeId, snarl: &mut Snarl<Node>) {
         if let Some(output_node) = snarl[to_node_id].output_ref() {
             let selected_title = output_node.selected_title().cloned();
@@ -220,6 +493,217 @@ impl NodeViewer {
 
     fn create_node(&mut self, pos: egui::Pos2, factory: fn(&NodeConfig) -> Node, snarl: &mut Snarl<Node>) -> NodeId {
         let node = factory(&self.config);
+        self.add_node(pos, node, snarl)
+    }
+
+    fn add_node(&mut self, pos: egui::Pos2, node: Node, snarl: &mut Snarl<Node>) -> NodeId {
+        let node_id = self.insert_node(pos, node.clone(), snarl);
+        self.history.push(Command::AddNode {
+            node_id,
+            pos,
+            node: Box::new(node),
+        });
+        node_id
+    }
+
+    fn remove_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) {
+        let pos = snarl.get_node_info(node_id).map(|info| info.pos).unwrap_or_default();
+        let node = self.take_node(node_id, snarl);
+        self.history.push(Command::RemoveNode {
+            node_id,
+            pos,
+            node: Box::new(node),
+        });
+    }
+
+    /// Disconnects the wires of the node before removing it, all as one command.
+    fn remove_with_wires(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) {
+        let start = self.history.undo_count();
+        for (from, to) in node_wires(node_id, snarl) {
+            let (from, to) = (snarl.out_pin(from), snarl.in_pin(to));
+            self.disconnect(&from, &to, snarl);
+        }
+        self.remove_node(node_id, snarl);
+        self.history.group_since(start);
+    }
+
+    /// Replaces the nodes by a group of them, as one command.
+    fn group_nodes(&mut self, node_ids: impl IntoIterator<Item = NodeId>, snarl: &mut Snarl<Node>) {
+        let collapsed = match GroupNode::collapse(node_ids, snarl) {
+            Ok(collapsed) => collapsed,
+            Err(err) => {
+                tracing::warn!("Failed to group the nodes: {err}");
+                return;
+            },
+        };
+
+        let start = self.history.undo_count();
+        for &node_id in &collapsed.node_ids {
+            self.remove_with_wires(node_id, snarl);
+        }
+        let group_id = self.add_node(collapsed.pos, Node::Group(collapsed.group), snarl);
+
+        for (from, input) in collapsed.inputs {
+            let to = InPinId { node: group_id, input };
+            self.connect(&snarl.out_pin(from), &snarl.in_pin(to), snarl);
+        }
+        for (output, to) in collapsed.outputs {
+            let from = OutPinId { node: group_id, output };
+            self.connect(&snarl.out_pin(from), &snarl.in_pin(to), snarl);
+        }
+        self.history.group_since(start);
+        self.selected_nodes = [group_id].into_iter().collect();
+    }
+
+    /// Replaces the group by the nodes inside it, as one command.
+    fn ungroup(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) {
+        let Some(group) = snarl[node_id].group_ref().cloned() else {
+            return;
+        };
+        let pos = snarl.get_node_info(node_id).map(|info| info.pos).unwrap_or_default();
+        let wires = node_wires(node_id, snarl);
+
+        let start = self.history.undo_count();
+        self.remove_with_wires(node_id, snarl);
+        let expanded = group.expand(pos.to_vec2(), self, snarl);
+        self.history.push(expanded.command);
+
+        for (from, to) in wires {
+            let (from, to) = if to.node == node_id {
+                (from, expanded.inputs[to.input])
+            } else {
+                (expanded.outputs[from.output], to)
+            };
+            self.connect(&snarl.out_pin(from), &snarl.in_pin(to), snarl);
+        }
+        self.history.group_since(start);
+        self.selected_nodes = expanded.node_ids.into_iter().collect();
+    }
+
+    /// Records the changes made by the widgets of the node, `before` is the snapshot of the node made before they were
+    /// shown. Changes of its wires are recorded too if the wires before are given.
+    fn record_edit(
+        &mut self,
+        node_id: NodeId,
+        before: Node,
+        wires_before: Option<&[(OutPinId, InPinId)]>,
+        ui: &Ui,
+        snarl: &Snarl<Node>,
+    ) {
+        let start = self.history.undo_count();
+        let wires = wires_before.map(|wires_before| (wires_before, node_wires(node_id, snarl)));
+
+        // The old wires belong to the pins before the edit and the new wires to the pins after it.
+        if let Some((wires_before, wires_after)) = &wires {
+            for &(from, to) in wires_before.iter().filter(|wire| !wires_after.contains(wire)) {
+                self.history.push(Command::Disconnect { from, to });
+            }
+        }
+        if before != snarl[node_id] {
+            let widget = ui.ctx().dragged_id().or_else(|| ui.memory(|memory| memory.focused()));
+            self.history
+                .push_edit(node_id, before, snarl[node_id].edit_snapshot(), widget);
+        }
+        if let Some((wires_before, wires_after)) = &wires {
+            for &(from, to) in wires_after.iter().filter(|wire| !wires_before.contains(wire)) {
+                self.history.push(Command::Connect { from, to });
+            }
+        }
+        self.history.group_since(start);
+    }
+
+    /// Undoes with Ctrl+Z and redoes with Ctrl+Shift+Z, copies the selected nodes with Ctrl+C, pastes them with Ctrl+V,
+    /// duplicates them with Ctrl+D and groups them with Ctrl+G. Holding Shift while pasting or duplicating keeps the
+    /// wires from other nodes.
+    /// Text fields with the focus handle these shortcuts themselves.
+    pub fn handle_shortcuts(&mut self, ctx: &egui::Context, snarl: &mut Snarl<Node>) {
+        if ctx.memory(|memory| memory.focused().is_some()) {
+            return;
+        }
+
+        // Redo first, undo would match its shortcut too.
+        if ctx.input_mut(|input| input.consume_shortcut(&REDO_SHORTCUT)) {
+            self.with_history(|history, viewer| history.redo(viewer, snarl));
+        } else if ctx.input_mut(|input| input.consume_shortcut(&UNDO_SHORTCUT)) {
+            self.with_history(|history, viewer| history.undo(viewer, snarl));
+        }
+
+        let keep_external_wires = if ctx.input_mut(|input| input.consume_shortcut(&DUPLICATE_WIRED_SHORTCUT)) {
+            Some(true)
+        } else if ctx.input_mut(|input| input.consume_shortcut(&DUPLICATE_SHORTCUT)) {
+            Some(false)
+        } else {
+            None
+        };
+        if let Some(keep_external_wires) = keep_external_wires {
+            let fragment = Fragment::copy(self.selected_nodes.iter().copied(), snarl);
+            self.paste(&fragment, keep_external_wires, snarl);
+        }
+        if ctx.input_mut(|input| input.consume_shortcut(&GROUP_SHORTCUT)) {
+            let node_ids = self.selected_nodes.iter().copied().collect::<Vec<_>>();
+            self.group_nodes(node_ids, snarl);
+        }
+
+        let (copy, paste) = ctx.input(|input| {
+            let copy = input.events.iter().any(|event| matches!(event, egui::Event::Copy));
+            let paste = input.events.iter().find_map(|event| match event {
+                egui::Event::Paste(text) => Some((text.clone(), input.modifiers.shift)),
+                _ => None,
+            });
+            (copy, paste)
+        });
+        if copy {
+            self.copy(ctx, snarl);
+        }
+        if let Some((text, keep_external_wires)) = paste {
+            // Any other text on the clipboard is ignored.
+            if let Ok(fragment) = Fragment::from_json(&text) {
+                self.paste(&fragment, keep_external_wires, snarl);
+            }
+        }
+    }
+
+    fn copy(&self, ctx: &egui::Context, snarl: &Snarl<Node>) {
+        let fragment = Fragment::copy(self.selected_nodes.iter().copied(), snarl);
+        if fragment.is_empty() {
+            return;
+        }
+
+        match fragment.to_json() {
+            Ok(text) => ctx.copy_text(text),
+            Err(err) => tracing::warn!("Failed to copy the nodes: {err}"),
+        }
+    }
+
+    /// Pastes the nodes as one history command and selects them.
+    fn paste(&mut self, fragment: &Fragment, keep_external_wires: bool, snarl: &mut Snarl<Node>) {
+        if fragment.is_empty() {
+            return;
+        }
+
+        let (node_ids, command) = fragment.paste(PASTE_OFFSET, keep_external_wires, self, snarl);
+        self.history.push(command);
+        self.selected_nodes = node_ids.into_iter().collect();
+    }
+
+    pub fn show_history(&mut self, ui: &mut Ui, snarl: &mut Snarl<Node>) {
+        self.with_history(|history, viewer| history.show(ui, viewer, snarl));
+    }
+
+    fn with_history<R>(&mut self, f: impl FnOnce(&mut History, &mut Self) -> R) -> R {
+        let mut history = std::mem::take(&mut self.history);
+        let result = f(&mut history, self);
+        self.history = history;
+        result
+    }
+
+    pub fn unregister_render_nodes(&mut self, snarl: &mut Snarl<Node>) {
+        self.unregister_render_if_needed(RenderSelector::All, snarl);
+    }
+}
+
+impl Editor for NodeViewer {
+    fn insert_node(&mut self, pos: egui::Pos2, node: Node, snarl: &mut Snarl<Node>) -> NodeId {
         let is_output_node = node.output_ref().is_some();
         let node_id = snarl.insert_node(pos, node);
 
@@ -229,7 +713,10 @@ impl NodeViewer {
         node_id
     }
 
-    fn remove_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) -> Node {
+    fn take_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) -> Node {
+        self.selected_nodes.shift_remove(&node_id);
+        self.evaluator.remove(node_id);
+
         if snarl[node_id].output_ref().is_some() {
             self.output_nodes.remove(&node_id);
             self.unregister_render_if_needed(RenderSelector::ByOutputId(node_id), snarl);
@@ -237,45 +724,136 @@ impl NodeViewer {
         snarl.remove_node(node_id)
     }
 
-    pub fn unregister_render_nodes(&mut self, snarl: &mut Snarl<Node>) {
-        self.unregister_render_if_needed(RenderSelector::All, snarl);
+    fn connect_pins(&mut self, from: OutPinId, to: InPinId, snarl: &mut Snarl<Node>) {
+        snarl.connect(from, to);
+        self.evaluator.invalidate(to.node);
+        let (from_pin, to_pin) = (snarl.out_pin(from), snarl.in_pin(to));
+        Node::call_handle_msg(to.node, snarl, InputMessage::Connect {
+            from: &from_pin,
+            to: &to_pin,
+        });
+
+        self.register_render_if_needed(from.node, to.node, snarl);
+    }
+
+    fn disconnect_pins(&mut self, from: OutPinId, to: InPinId, snarl: &mut Snarl<Node>) {
+        let (from_pin, to_pin) = (snarl.out_pin(from), snarl.in_pin(to));
+        Node::call_handle_msg(to.node, snarl, InputMessage::Disconnect {
+            from: &from_pin,
+            to: &to_pin,
+        });
+        snarl.disconnect(from, to);
+        self.evaluator.invalidate(to.node);
+        self.unregister_render_if_needed(RenderSelector::ById(from.node), snarl);
+    }
+
+    /// Keeps the subscriptions of the current node, they belong to the nodes connected to it.
+    fn replace_node(&mut self, node_id: NodeId, mut node: Node, snarl: &mut Snarl<Node>) {
+        if let (Some(subscription), Some(current)) = (node.subscription_mut(), snarl[node_id].subscription_ref()) {
+            *subscription = current.clone();
+        }
+        snarl[node_id].restore_snapshot(node);
+        self.evaluator.invalidate(node_id);
+
+        if let Some(caller) = snarl[node_id]
+            .subscription_ref()
+            .and_then(|subscription| subscription.event_caller(Event::OnChange))
+        {
+            caller(SelfNodeMut::new(node_id, snarl));
+        }
     }
 }
 
 impl SnarlViewer<Node> for NodeViewer {
+    fn current_transform(&mut self, to_global: &mut TSTransform, _snarl: &mut Snarl<Node>) {
+        self.graph_to_screen = *to_global;
+    }
+
     #[inline]
     fn connect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
-        // Validate connection
-        if snarl[from.id.node].outputs()[from.id.output] & snarl[to.id.node].inputs()[to.id.input] != 0 {
-            for &remote in &to.remotes {
-                let out_pin = snarl.out_pin(remote);
-                self.disconnect(&out_pin, to, snarl);
-            }
+        if let Err(diagnostic) = validation::check_wire(from.id, to.id, snarl) {
+            self.rejected_wire = Some(diagnostic);
+            return;
+        }
 
-            snarl.connect(from.id, to.id);
-            Node::call_handle_msg(to.id.node, snarl, InputMessage::Connect { from, to });
-            // snarl[from.id.node].connect_output(from, to);
+        self.rejected_wire = None;
 
-            self.register_render_if_needed(from.id.node, to.id.node, snarl);
+        let start = self.history.undo_count();
+        for &remote in &to.remotes {
+            let out_pin = snarl.out_pin(remote);
+            self.disconnect(&out_pin, to, snarl);
         }
+
+        self.connect_pins(from.id, to.id, snarl);
+        self.history.push(Command::Connect {
+            from: from.id,
+            to: to.id,
+        });
+        self.history.group_since(start);
     }
 
     #[inline]
     fn disconnect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
-        Node::call_handle_msg(to.id.node, snarl, InputMessage::Disconnect { from, to });
-        snarl.disconnect(from.id, to.id);
-        self.unregister_render_if_needed(RenderSelector::ById(from.id.node), snarl);
+        self.disconnect_pins(from.id, to.id, snarl);
+        self.history.push(Command::Disconnect {
+            from: from.id,
+            to: to.id,
+        });
     }
 
     #[inline]
     fn drop_inputs(&mut self, pin: &InPin, snarl: &mut Snarl<Node>) {
-        snarl.drop_inputs(pin.id);
+        let start = self.history.undo_count();
+        for &remote in &pin.remotes {
+            let out_pin = snarl.out_pin(remote);
+            self.disconnect(&out_pin, pin, snarl);
+        }
+        self.history.group_since(start);
+    }
+
+    #[inline]
+    fn drop_outputs(&mut self, pin: &OutPin, snarl: &mut Snarl<Node>) {
+        let start = self.history.undo_count();
+        for &remote in &pin.remotes {
+            let in_pin = snarl.in_pin(remote);
+            self.disconnect(pin, &in_pin, snarl);
+        }
+        self.history.group_since(start);
     }
 
     fn title(&mut self, node: &Node) -> String {
         node.name().to_owned()
     }
 
+    fn show_header(
+        &mut self,
+        node: NodeId,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+        snarl: &mut Snarl<Node>,
+    ) {
+        let title = self.title(&snarl[node]);
+        if ui.add(egui::Label::new(title).sense(egui::Sense::click())).clicked() {
+            // Shift or Ctrl toggles the node in the selection.
+            if ui.input(|input| input.modifiers.shift || input.modifiers.command) {
+                if !self.selected_nodes.shift_remove(&node) {
+                    self.selected_nodes.insert(node);
+                }
+            } else {
+                self.selected_nodes.clear();
+                self.selected_nodes.insert(node);
+            }
+        }
+
+        let problems = self.diagnostics.iter().filter(|diagnostic| diagnostic.node == node);
+        if let Some(severity) = problems.clone().map(Diagnostic::severity).max() {
+            let text = problems.map(ToString::to_string).collect::<Vec<_>>().join("\n");
+            ui.label(egui::RichText::new(severity.icon()).color(severity.color()))
+                .on_hover_text(text);
+        }
+    }
+
     fn inputs(&mut self, node: &Node) -> usize {
         node.inputs().len()
     }
@@ -286,42 +864,79 @@ impl SnarlViewer<Node> for NodeViewer {
 
     #[allow(refining_impl_trait)]
     fn show_input(&mut self, pin: &InPin, ui: &mut Ui, snarl: &mut Snarl<Node>) -> PinInfo {
-        let response = Node::call_handle_msg(pin.id.node, snarl, DisplayMessage::Input { pin, ui });
-        match response {
+        // Wired inputs only follow the connected outputs, which isn't an edit.
+        let before = (is_interacting(ui) && pin.remotes.is_empty()).then(|| snarl[pin.id.node].edit_snapshot());
+        let events = widget_events(ui);
+        let pin_info = match Node::call_handle_msg(pin.id.node, snarl, DisplayMessage::Input { pin, ui }) {
             Some(CommonNodeResponse::Display(DisplayResponse::Info(pin_info))) => pin_info,
             _ => unreachable!("{} node has no inputs", snarl[pin.id.node].name()),
+        };
+        if widgets_changed_since(ui, events) {
+            self.evaluator.invalidate(pin.id.node);
+            if let Some(before) = before {
+                self.record_edit(pin.id.node, before, None, ui, snarl);
+            }
         }
+        pin_info
     }
 
     #[allow(refining_impl_trait)]
     fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, snarl: &mut Snarl<Node>) -> PinInfo {
-        let response = Node::call_handle_msg(pin.id.node, snarl, DisplayMessage::Output { pin, ui });
-        match response {
+        let before = is_interacting(ui).then(|| snarl[pin.id.node].edit_snapshot());
+        let events = widget_events(ui);
+        let pin_info = match Node::call_handle_msg(pin.id.node, snarl, DisplayMessage::Output { pin, ui }) {
             Some(CommonNodeResponse::Display(DisplayResponse::Info(pin_info))) => pin_info,
             _ => unreachable!("{} node has no outputs", snarl[pin.id.node].name()),
+        };
+        if widgets_changed_since(ui, events) {
+            self.evaluator.invalidate(pin.id.node);
+            if let Some(before) = before {
+                self.record_edit(pin.id.node, before, None, ui, snarl);
+            }
         }
+        pin_info
     }
 
     fn has_body(&mut self, node: &Node) -> bool {
         node.output_ref().is_some()
+            || node.math_ref().is_some()
+            || node.vector_math_ref().is_some()
+            || node.expression_ref().is_some()
+            || node.texture_ref().is_some()
+            || node.procedural_texture_ref().is_some()
+            || node.mix_material_ref().is_some()
+            || node.group_ref().is_some()
+            || node.render_ref().and_then(RenderNode::xrays_render_ref).is_some()
     }
 
     fn show_body(&mut self, node: NodeId, inputs: &[InPin], outputs: &[OutPin], ui: &mut Ui, snarl: &mut Snarl<Node>) {
-        match Node::call_handle_msg(node, snarl, DisplayMessage::Body { inputs, outputs, ui }) {
-            Some(CommonNodeResponse::Display(DisplayResponse::Selected(selected_tab))) => {
-                let output_node_id = selected_tab.node_id;
-
-                self.unregister_render_if_needed(RenderSelector::ByOutputId(output_node_id), snarl);
-
-                for (from_pin, to_pin) in snarl
-                    .wires()
-                    .filter(|&(_, to_pin)| to_pin.node == output_node_id)
-                    .collect::<Vec<_>>()
-                {
-                    self.register_render_if_needed(from_pin.node, to_pin.node, snarl);
-                }
-            },
-            _ => (),
+        // The body may also move wires, e.g. when the variables of an expression or the pins of a group change.
+        let before = is_interacting(ui).then(|| (snarl[node].edit_snapshot(), node_wires(node, snarl)));
+        let events = widget_events(ui);
+        let selected = match Node::call_handle_msg(node, snarl, DisplayMessage::Body { inputs, outputs, ui }) {
+            Some(CommonNodeResponse::Display(DisplayResponse::Selected(selected_tab))) => Some(selected_tab.node_id),
+            _ => None,
+        };
+        if let Some(pins) = snarl[node].group_mut().and_then(GroupNode::take_pin_request) {
+            GroupNode::expose_pins(node, pins, self, snarl);
+        }
+        if widgets_changed_since(ui, events) {
+            self.evaluator.invalidate(node);
+            if let Some((before, wires_before)) = before {
+                self.record_edit(node, before, Some(&wires_before), ui, snarl);
+            }
+        }
+
+        if let Some(output_node_id) = selected {
+            self.unregister_render_if_needed(RenderSelector::ByOutputId(output_node_id), snarl);
+
+            for (from_pin, to_pin) in snarl
+                .wires()
+                .filter(|&(_, to_pin)| to_pin.node == output_node_id)
+                .collect::<Vec<_>>()
+            {
+                self.register_render_if_needed(from_pin.node, to_pin.node, snarl);
+            }
         }
     }
 
@@ -331,11 +946,27 @@ impl SnarlViewer<Node> for NodeViewer {
 
     fn show_graph_menu(&mut self, pos: egui::Pos2, ui: &mut Ui, snarl: &mut Snarl<Node>) {
         ui.label("Add node");
-        for (name, factory, ..) in Node::fabrics() {
-            if ui.button(name).clicked() {
-                self.create_node(pos, factory, snarl);
-                ui.close_menu();
-            }
+        let entries = PaletteEntry::collect(|_, _| Some(0));
+        if let Some(entry) = self.palette.show(ui, &entries) {
+            self.create_node(pos, entry.factory, snarl);
+            ui.close_menu();
+        }
+
+        let library = GroupNode::library();
+        if !library.is_empty() {
+            ui.menu_button("Library", |ui| {
+                for name in library {
+                    if ui.button(&name).clicked() {
+                        match GroupNode::load_from_library(&name) {
+                            Ok(group) => {
+                                self.add_node(pos, Node::Group(group), snarl);
+                            },
+                            Err(err) => tracing::warn!("Failed to load the group `{name}`: {err}"),
+                        }
+                        ui.close_menu();
+                    }
+                }
+            });
         }
     }
 
@@ -347,65 +978,71 @@ impl SnarlViewer<Node> for NodeViewer {
         ui.label("Add node");
         match src_pins {
             AnyPins::Out(src_pin_ids) => {
-                for src_pin_id in src_pin_ids {
-                    let src_out = snarl[src_pin_id.node].outputs()[src_pin_id.output];
-                    let dst_in_candidates = Node::fabrics().into_iter().filter_map(|(name, factory, inputs, _)| {
-                        inputs
-                            .iter()
-                            .position(|input| *input & src_out != 0)
-                            .map(|idx| (name, factory, idx))
-                    });
-
-                    for (name, factory, idx) in dst_in_candidates {
-                        if ui.button(name).clicked() {
-                            // Create new node.
-                            let node_id = self.create_node(pos, factory, snarl);
-
-                            // Connect the wire.
-                            let src_pin = snarl.out_pin(*src_pin_id);
-                            let dst_pin = InPin {
-                                id: InPinId {
-                                    node: node_id,
-                                    input: idx,
-                                },
-                                remotes: Default::default(),
-                            };
-                            self.connect(&src_pin, &dst_pin, snarl);
-
-                            ui.close_menu();
-                        }
+                // The palette offers the nodes with an input which takes the values of all the dropped wires.
+                let src_outs = src_pin_ids
+                    .iter()
+                    .map(|src_pin_id| snarl[src_pin_id.node].outputs()[src_pin_id.output])
+                    .collect::<Vec<_>>();
+                let entries = PaletteEntry::collect(|inputs, _| {
+                    inputs
+                        .iter()
+                        .position(|input| src_outs.iter().all(|src_out| *input & src_out != 0))
+                });
+
+                if let Some(entry) = self.palette.show(ui, &entries) {
+                    // Create new node.
+                    let start = self.history.undo_count();
+                    let node_id = self.create_node(pos, entry.factory, snarl);
+
+                    // Connect the wires.
+                    for src_pin_id in src_pin_ids {
+                        let src_pin = snarl.out_pin(*src_pin_id);
+                        let dst_pin = InPin {
+                            id: InPinId {
+                                node: node_id,
+                                input: entry.pin,
+                            },
+                            remotes: Default::default(),
+                        };
+                        self.connect(&src_pin, &dst_pin, snarl);
                     }
+                    self.history.group_since(start);
+
+                    ui.close_menu();
                 }
             },
             AnyPins::In(src_pin_ids) => {
-                for src_pin_id in src_pin_ids {
-                    let src_in = snarl[src_pin_id.node].inputs()[src_pin_id.input];
-                    let dst_out_candidates = Node::fabrics().into_iter().filter_map(|(name, factory, _, outputs)| {
-                        outputs
-                            .iter()
-                            .position(|output| *output & src_in != 0)
-                            .map(|idx| (name, factory, idx))
-                    });
-
-                    for (name, factory, idx) in dst_out_candidates {
-                        if ui.button(name).clicked() {
-                            // Create new node.
-                            let node_id = self.create_node(pos, factory, snarl);
-
-                            // Connect the wire.
-                            let dst_pin = OutPin {
-                                id: OutPinId {
-                                    node: node_id,
-                                    output: idx,
-                                },
-                                remotes: Default::default(),
-                            };
-                            let src_pin = snarl.in_pin(*src_pin_id);
-                            self.connect(&dst_pin, &src_pin, snarl);
-
-                            ui.close_menu();
-                        }
+                // The palette offers the nodes with an output which all the dropped wires take.
+                let src_ins = src_pin_ids
+                    .iter()
+                    .map(|src_pin_id| snarl[src_pin_id.node].inputs()[src_pin_id.input])
+                    .collect::<Vec<_>>();
+                let entries = PaletteEntry::collect(|_, outputs| {
+                    outputs
+                        .iter()
+                        .position(|output| src_ins.iter().all(|src_in| *output & src_in != 0))
+                });
+
+                if let Some(entry) = self.palette.show(ui, &entries) {
+                    // Create new node.
+                    let start = self.history.undo_count();
+                    let node_id = self.create_node(pos, entry.factory, snarl);
+
+                    // Connect the wires.
+                    for src_pin_id in src_pin_ids {
+                        let dst_pin = OutPin {
+                            id: OutPinId {
+                                node: node_id,
+                                output: entry.pin,
+                            },
+                            remotes: Default::default(),
+                        };
+                        let src_pin = snarl.in_pin(*src_pin_id);
+                        self.connect(&dst_pin, &src_pin, snarl);
                     }
+                    self.history.group_since(start);
+
+                    ui.close_menu();
                 }
             },
         };
@@ -418,29 +1055,32 @@ impl SnarlViewer<Node> for NodeViewer {
     fn show_node_menu(
         &mut self,
         node_id: NodeId,
-        inputs: &[InPin],
-        outputs: &[OutPin],
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
         ui: &mut Ui,
         snarl: &mut Snarl<Node>,
     ) {
         ui.label("Node menu");
-        if ui.button("Remove").clicked() {
-            for in_pin in inputs {
-                for out_pin_id in &in_pin.remotes {
-                    let out_pin = snarl.out_pin(*out_pin_id);
-                    self.disconnect(&out_pin, in_pin, snarl);
-                }
-            }
-
-            for out_pin in outputs {
-                for in_pin_id in &out_pin.remotes {
-                    let in_pin = snarl.in_pin(*in_pin_id);
-                    self.disconnect(out_pin, &in_pin, snarl);
-                }
+        if ui.button("Duplicate").clicked() {
+            let fragment = Fragment::copy([node_id], snarl);
+            self.paste(&fragment, false, snarl);
+            ui.close_menu();
+        }
+        if ui.button("Group").clicked() {
+            if self.selected_nodes.contains(&node_id) {
+                let node_ids = self.selected_nodes.iter().copied().collect::<Vec<_>>();
+                self.group_nodes(node_ids, snarl);
+            } else {
+                self.group_nodes([node_id], snarl);
             }
-
-            self.remove_node(node_id, snarl);
-
+            ui.close_menu();
+        }
+        if snarl[node_id].group_ref().is_some() && ui.button("Ungroup").clicked() {
+            self.ungroup(node_id, snarl);
+            ui.close_menu();
+        }
+        if ui.button("Remove").clicked() {
+            self.remove_with_wires(node_id, snarl);
             ui.close_menu();
         }
     }
@@ -470,9 +1110,21 @@ impl SnarlViewer<Node> for NodeViewer {
             Node::Color(_) => {
                 ui.label("Outputs color value");
             },
+            Node::Math(_) => {
+                ui.label("Outputs the result of an operation on numbers");
+            },
+            Node::VectorMath(_) => {
+                ui.label("Outputs the result of an operation on vectors");
+            },
+            Node::Expression(_) => {
+                ui.label("Outputs the number or vector computed by the expression from its variables");
+            },
             Node::Output(_) => {
                 ui.label("Displays anything connected to it");
             },
+            Node::Group(_) => {
+                ui.label("Outputs the values and primitives of the nodes inside it");
+            },
             _ => {
                 ui.label("<No description available>");
             },
@@ -487,8 +1139,16 @@ impl SnarlViewer<Node> for NodeViewer {
         _outputs: &[OutPin],
         snarl: &Snarl<Node>,
     ) -> egui::Frame {
+        let frame = if self.selected_nodes.contains(&node) {
+            frame.stroke(egui::Stroke::new(2.0, SELECTED_NODE_COLOR))
+        } else if self.cyclic_nodes.contains(&node) {
+            frame.stroke(egui::Stroke::new(2.0, CYCLIC_NODE_COLOR))
+        } else {
+            frame
+        };
+
         match snarl[node] {
-            Node::Number(_) => frame.fill(egui::Color32::from_rgb(70, 40, 40)),
+            Node::Number(_) | Node::Math(_) | Node::Expression(_) => frame.fill(egui::Color32::from_rgb(70, 40, 40)),
             Node::String(_) => frame.fill(egui::Color32::from_rgb(40, 70, 40)),
             Node::Output(_) => frame.fill(egui::Color32::from_rgb(70, 70, 80)),
             _ => frame.fill(egui::Color32::from_rgb(40, 40, 70)),
@@ -496,6 +1156,30 @@ impl SnarlViewer<Node> for NodeViewer {
     }
 }
 
+/// Node widgets can only change values while the user presses buttons or types, the node is only copied then.
+fn is_interacting(ui: &Ui) -> bool {
+    ui.input(|input| {
+        input.pointer.any_down()
+            || input.events.iter().any(|event| {
+                matches!(
+                    event,
+                    egui::Event::PointerButton { .. }
+                        | egui::Event::Key { .. }
+                        | egui::Event::Text(_)
+                        | egui::Event::Paste(_)
+                )
+            })
+    })
+}
+
+/// Wires from and to the node.
+fn node_wires(node_id: NodeId, snarl: &Snarl<Node>) -> Vec<(OutPinId, InPinId)> {
+    snarl
+        .wires()
+        .filter(|(from, to)| from.node == node_id || to.node == node_id)
+        .collect()
+}
+
 pub fn format_float(value: f64) -> String {
     let value = (value * 1000.0).round() / 1000.0;
     format!("{value}")
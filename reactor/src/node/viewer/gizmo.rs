@@ -0,0 +1,208 @@
+use std::f32::consts::{PI, TAU};
+
+use eframe::wgpu::naga::FastHashSet;
+use egui::{Color32, Id, Pos2, Rect, Sense, Stroke, Ui, Vec2};
+use reactor_types::rect::RectSize;
+use reactor_types::vector::convert_vector3_down;
+use reactor_types::{Angle, Float, NodePin, Vector, Vector3};
+
+const AXES: [(Vector3, Color32); 3] = [
+    (Vector3::new(1.0, 0.0, 0.0), Color32::from_rgb(0xe0, 0x40, 0x40)),
+    (Vector3::new(0.0, 1.0, 0.0), Color32::from_rgb(0x40, 0xc0, 0x40)),
+    (Vector3::new(0.0, 0.0, 1.0), Color32::from_rgb(0x40, 0x70, 0xe0)),
+];
+const HANDLE_COLOR: Color32 = Color32::from_rgb(0xff, 0xa5, 0x00);
+const DISABLED_COLOR: Color32 = Color32::GRAY;
+
+/// Length of the translation handles relative to the distance between the camera and the object.
+const HANDLE_LENGTH_FACTOR: Float = 0.15;
+const HANDLE_RADIUS: f32 = 6.0;
+const ROTATION_RING_RADIUS: f32 = 60.0;
+const MIN_SCALE: Float = 0.001;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    pub const ALL: [Self; 3] = [Self::Translate, Self::Rotate, Self::Scale];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Translate => "Move",
+            Self::Rotate => "Rotate",
+            Self::Scale => "Scale",
+        }
+    }
+}
+
+/// Transform inputs of a node which can be edited with gizmos, paired with their input indices.
+#[derive(Default)]
+pub struct TransformPins<'a> {
+    pub position: Option<(usize, &'a mut NodePin<Vector>)>,
+    pub rotation: Option<(usize, &'a mut NodePin<Angle>)>,
+    pub scale: Option<(usize, &'a mut NodePin<Float>)>,
+}
+
+/// Draws the mode toolbar and the gizmo of the current mode over the viewport.
+/// Inputs with connected wires are drawn disabled, because their values come from other nodes.
+/// Returns `true` if any of the transform values has changed.
+pub fn show(
+    ui: &mut Ui,
+    id: Id,
+    mode: &mut GizmoMode,
+    viewport: Rect,
+    camera: &xrays::Camera,
+    pins: TransformPins,
+    wired_inputs: &FastHashSet<usize>,
+) -> bool {
+    ui.horizontal(|ui| {
+        for gizmo_mode in GizmoMode::ALL {
+            ui.selectable_value(mode, gizmo_mode, gizmo_mode.label());
+        }
+    });
+
+    let Some((position_input, position)) = pins.position else {
+        return false;
+    };
+
+    let projector = Projector { viewport, camera };
+    let center = position.get().as_dim3();
+    let Some(origin) = projector.project(center) else {
+        return false;
+    };
+
+    let painter = ui.painter_at(viewport);
+    let is_enabled = |input: usize| !wired_inputs.contains(&input);
+
+    match *mode {
+        GizmoMode::Translate => {
+            let enabled = is_enabled(position_input);
+            let eye = camera.eye_pos.cast::<Float>();
+            let handle_length = (center - eye).magnitude() * HANDLE_LENGTH_FACTOR;
+            let mut changed = false;
+
+            for (axis_idx, (axis, color)) in AXES.into_iter().enumerate() {
+                let Some(end) = projector.project(center + axis * handle_length) else {
+                    continue;
+                };
+                let color = if enabled { color } else { DISABLED_COLOR };
+                let response = handle(ui, id.with(("translate", axis_idx)), end, enabled);
+                let width = if response.hovered() || response.dragged() {
+                    3.0
+                } else {
+                    2.0
+                };
+
+                painter.line_segment([origin, end], Stroke::new(width, color));
+                painter.circle_filled(end, HANDLE_RADIUS, color);
+
+                let screen_axis = end - origin;
+                if response.dragged() && screen_axis.length_sq() > f32::EPSILON {
+                    let shift = response.drag_delta().dot(screen_axis) / screen_axis.length_sq();
+                    let shift = axis * shift as Float * handle_length;
+                    let vector = position.as_mut();
+                    for i in 0..vector.len().min(3) {
+                        vector[i] += shift[i];
+                    }
+                    changed |= shift != Vector3::zeros();
+                }
+            }
+            changed
+        },
+        GizmoMode::Rotate => {
+            let Some((rotation_input, rotation)) = pins.rotation else {
+                return false;
+            };
+            let enabled = is_enabled(rotation_input);
+            let color = if enabled { HANDLE_COLOR } else { DISABLED_COLOR };
+            let handle_pos = origin + Vec2::angled(-rotation.get().as_radians() as f32) * ROTATION_RING_RADIUS;
+            let response = handle(ui, id.with("rotate"), handle_pos, enabled);
+
+            painter.circle_stroke(origin, ROTATION_RING_RADIUS, Stroke::new(2.0, color));
+            painter.circle_filled(handle_pos, HANDLE_RADIUS, color);
+
+            match response.interact_pointer_pos() {
+                Some(pointer_pos) if response.dragged() => {
+                    let current = pointer_pos - origin;
+                    let previous = current - response.drag_delta();
+                    let mut delta = previous.angle() - current.angle();
+                    if delta > PI {
+                        delta -= TAU;
+                    } else if delta < -PI {
+                        delta += TAU;
+                    }
+                    if delta != 0.0 {
+                        *rotation.as_mut() = rotation.get() + Angle::radians(delta as Float);
+                        true
+                    } else {
+                        false
+                    }
+                },
+                _ => false,
+            }
+        },
+        GizmoMode::Scale => {
+            let Some((scale_input, scale)) = pins.scale else {
+                return false;
+            };
+            let enabled = is_enabled(scale_input);
+            let color = if enabled { HANDLE_COLOR } else { DISABLED_COLOR };
+            let right = camera.eye_dir.cross(&camera.up).normalize().cast::<Float>();
+            let Some(edge) = projector.project(center + right * scale.get()) else {
+                return false;
+            };
+            let screen_radius = (edge - origin).length();
+            let response = handle(ui, id.with("scale"), edge, enabled);
+
+            painter.circle_stroke(origin, screen_radius, Stroke::new(1.0, color));
+            painter.line_segment([origin, edge], Stroke::new(2.0, color));
+            painter.rect_filled(
+                Rect::from_center_size(edge, Vec2::splat(2.0 * HANDLE_RADIUS)),
+                0.0,
+                color,
+            );
+
+            if response.dragged() && screen_radius > f32::EPSILON {
+                let direction = (edge - origin) / screen_radius;
+                let factor = 1.0 + response.drag_delta().dot(direction) / screen_radius;
+                let new_scale = (scale.get() * factor as Float).max(MIN_SCALE);
+                if new_scale != scale.get() {
+                    *scale.as_mut() = new_scale;
+                    return true;
+                }
+            }
+            false
+        },
+    }
+}
+
+fn handle(ui: &mut Ui, id: Id, pos: Pos2, enabled: bool) -> egui::Response {
+    let rect = Rect::from_center_size(pos, Vec2::splat(3.0 * HANDLE_RADIUS));
+    let sense = if enabled { Sense::drag() } else { Sense::hover() };
+    ui.interact(rect, id, sense)
+}
+
+struct Projector<'a> {
+    viewport: Rect,
+    camera: &'a xrays::Camera,
+}
+
+impl Projector<'_> {
+    fn project(&self, point: Vector3) -> Option<Pos2> {
+        let viewport_size = RectSize {
+            width: self.viewport.width() as u32,
+            height: self.viewport.height() as u32,
+        };
+        let (u, v) = self.camera.project(viewport_size, convert_vector3_down(&point))?;
+
+        Some(Pos2::new(
+            self.viewport.min.x + u * self.viewport.width(),
+            self.viewport.min.y + (1.0 - v) * self.viewport.height(),
+        ))
+    }
+}
@@ -1,60 +1,41 @@
 use egui_snarl::{InPin, NodeId, OutPinId, Snarl};
 use reactor_types::cast::ForceCast;
-use reactor_types::{Color, Float, Vector, Vector4};
+use reactor_types::{Color, Float, Vector};
 
+use crate::expression::Value;
+use crate::node::evaluator::NodeOutput;
 use crate::node::{Node, Noded};
 
 pub fn number<'a, N>(pin: &InPin, name: &str, snarl: &'a Snarl<Node>) -> Option<N>
 where
     Float: ForceCast<N>,
 {
-    value(pin, name, |remote| match &snarl[remote.node] {
-        Node::Number(number) => Ok(number.value().force_cast()),
-        node => Err(node),
-    })
+    output(pin, name, snarl, |output| output.as_number().map(ForceCast::force_cast))
 }
 
 pub fn vector(pin: &InPin, name: &str, snarl: &Snarl<Node>) -> Option<Vector> {
-    value(pin, name, |remote| match &snarl[remote.node] {
-        Node::Number(number) => Ok(Vector::from_scalar(number.value())),
-        Node::Vector(vector) => Ok(vector.value()),
-        Node::Color(color) => {
-            let color = color.value().to_normalized_gamma_f32();
-            Ok(Vector::Dim4(Vector4::new(
-                color[0] as _,
-                color[1] as _,
-                color[2] as _,
-                color[3] as _,
-            )))
-        },
-        node => Err(node),
-    })
+    output(pin, name, snarl, NodeOutput::as_vector)
 }
 
 pub fn color(pin: &InPin, name: &str, snarl: &Snarl<Node>) -> Option<Color> {
-    value(pin, name, |remote| match &snarl[remote.node] {
-        Node::Number(number) => Ok(Color::from_gray((number.value() * 255.0).round() as u8)),
-        Node::Color(color) => Ok(color.value()),
-        Node::Vector(vector) => match vector.value() {
-            Vector::Dim2(vector) => {
-                let mut color = Color::from_gray((vector.x * 255.0).round() as u8);
-                color[4] = (vector.y * 255.0).round() as u8;
-                Ok(color)
-            },
-            Vector::Dim3(vector) => Ok(Color::from_rgb(
-                (vector.x * 255.0).round() as u8,
-                (vector.y * 255.0).round() as u8,
-                (vector.z * 255.0).round() as u8,
-            )),
-            Vector::Dim4(vector) => Ok(Color::from_rgba_premultiplied(
-                (vector.x * 255.0).round() as u8,
-                (vector.y * 255.0).round() as u8,
-                (vector.z * 255.0).round() as u8,
-                (vector.w * 255.0).round() as u8,
-            )),
-        },
+    output(pin, name, snarl, NodeOutput::as_color)
+}
 
-        node => Err(node),
+/// Value of a number, vector or color output, colors become 4D vectors.
+pub fn expression_value(pin: &InPin, name: &str, snarl: &Snarl<Node>) -> Option<Value> {
+    output(pin, name, snarl, NodeOutput::as_value)
+}
+
+/// Converts the output connected to the pin, the conversion failing means the connection is invalid.
+fn output<T>(
+    pin: &InPin,
+    name: &str,
+    snarl: &Snarl<Node>,
+    convert: impl FnOnce(&NodeOutput) -> Option<T>,
+) -> Option<T> {
+    value(pin, name, |remote| {
+        let node = &snarl[remote.node];
+        convert(&node.output(remote.node, remote.output)).ok_or(node)
     })
 }
 
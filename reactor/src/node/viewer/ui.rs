@@ -1,3 +1,4 @@
+use egui::output::OutputEvent;
 use egui::{Color32, Ui};
 
 pub mod input;
@@ -17,3 +18,20 @@ pub fn horizontal(ui: &mut Ui, label: &str, controls: impl FnOnce(&mut Ui)) {
         controls(ui);
     });
 }
+
+/// Number of the widget events of the pass so far, the start for [`widgets_changed_since`].
+pub fn widget_events(ui: &Ui) -> usize {
+    ui.ctx().output(|output| output.events.len())
+}
+
+/// Whether a widget shown since the `start` event changed its value or was clicked, e.g. a dragged number, a toggled
+/// checkbox or a picked combo box entry. Hovering and moving the pointer over widgets changes nothing.
+pub fn widgets_changed_since(ui: &Ui, start: usize) -> bool {
+    ui.ctx().output(|output| {
+        output
+            .events
+            .iter()
+            .skip(start)
+            .any(|event| matches!(event, OutputEvent::ValueChanged(_) | OutputEvent::Clicked(_)))
+    })
+}
@@ -7,11 +7,13 @@ use reactor_types::cast::ForceCast;
 use reactor_types::{Color, Float, NodePin, Vector};
 
 use super::{MATERIAL_COLOR, NUMBER_COLOR, UNTYPED_COLOR, VECTOR_COLOR, horizontal};
+use crate::expression::Value;
 use crate::node::item::InputMaterial;
 use crate::node::message::SelfNodeMut;
 use crate::node::subscribtion::Event;
 use crate::node::viewer::remote;
 use crate::node::viewer::widget::color_picker::{Alpha, color_button, color_edit_button_srgba};
+use crate::node::viewer::widget::keyframe::key_button;
 use crate::node::{Node, Noded};
 
 pub fn number_view<N>(ui: &mut Ui, label: &str, node_pin: &mut NodePin<N>, remote_value: Option<N>) -> PinInfo
@@ -27,13 +29,16 @@ where
             },
         };
         ui.add_enabled(enabled, egui::DragValue::new(node_pin.as_mut()));
+        if enabled {
+            key_button(ui, node_pin);
+        }
     });
     PinInfo::circle().with_fill(NUMBER_COLOR)
 }
 
 pub fn as_number_view<N, M>(ui: &mut Ui, label: &str, node_pin: &mut NodePin<N>, remote_value: Option<M>) -> PinInfo
 where
-    N: AsMut<Float>,
+    N: AsMut<Float> + Clone + PartialEq,
     M: Into<N>,
 {
     horizontal(ui, label, |ui| {
@@ -45,6 +50,9 @@ where
             },
         };
         ui.add_enabled(enabled, egui::DragValue::new(node_pin.as_mut().as_mut()));
+        if enabled {
+            key_button(ui, node_pin);
+        }
     });
     PinInfo::circle().with_fill(NUMBER_COLOR)
 }
@@ -67,6 +75,35 @@ pub fn vector_view(ui: &mut Ui, label: &str, node_pin: &mut NodePin<Vector>, rem
                 ui.add_enabled(enabled, egui::DragValue::new(&mut vector[3]));
             }
         }
+        if enabled {
+            key_button(ui, node_pin);
+        }
+    });
+    PinInfo::circle().with_fill(VECTOR_COLOR)
+}
+
+pub fn value_view(ui: &mut Ui, label: &str, node_pin: &mut NodePin<Value>, remote_value: Option<Value>) -> PinInfo {
+    horizontal(ui, label, |ui| {
+        let enabled = match remote_value {
+            None => true,
+            Some(remote) => {
+                node_pin.set(remote);
+                false
+            },
+        };
+        match node_pin.as_mut() {
+            Value::Number(number) => {
+                ui.add_enabled(enabled, egui::DragValue::new(number));
+            },
+            Value::Vector(vector) => {
+                for idx in 0..vector.len() {
+                    ui.add_enabled(enabled, egui::DragValue::new(&mut vector[idx]));
+                }
+            },
+        }
+        if enabled {
+            key_button(ui, node_pin);
+        }
     });
     PinInfo::circle().with_fill(VECTOR_COLOR)
 }
@@ -75,6 +112,7 @@ pub fn color_view(ui: &mut Ui, label: &str, node_pin: &mut NodePin<Color>, remot
     horizontal(ui, label, |ui| match remote_value {
         None => {
             color_edit_button_srgba(ui, node_pin.as_mut(), Alpha::BlendOrAdditive);
+            key_button(ui, node_pin);
         },
         Some(remote) => {
             node_pin.set(remote);
@@ -243,8 +281,10 @@ pub fn display_material_field(
     label: &str,
     field_accessor: impl FnOnce(&mut Node) -> &mut NodePin<InputMaterial>,
 ) -> PinInfo {
-    let remote_value = remote::node(pin, label, self_node.snarl, |node| matches!(node, Node::Material(_)))
-        .map(InputMaterial::External);
+    let remote_value = remote::node(pin, label, self_node.snarl, |node| {
+        matches!(node, Node::Material(_) | Node::MixMaterial(_))
+    })
+    .map(InputMaterial::External);
     let node = self_node.node_mut();
     let field = field_accessor(node);
 
@@ -274,7 +314,25 @@ pub fn display_texture_field(
         pin,
         self_node,
         label,
-        |remote_node| matches!(remote_node, Node::Texture(_)),
+        |remote_node| matches!(remote_node, Node::Texture(_) | Node::ProceduralTexture(_)),
+        field_accessor,
+    )
+}
+
+pub fn display_material_node_field(
+    ui: &mut Ui,
+    pin: &InPin,
+    self_node: SelfNodeMut,
+    label: &str,
+    field_accessor: impl FnOnce(&mut Node) -> &mut NodePin<Option<NodeId>>,
+) -> PinInfo {
+    display_node_field(
+        ui,
+        pin,
+        self_node,
+        label,
+        |remote_node| matches!(remote_node, Node::Material(_) | Node::MixMaterial(_)),
         field_accessor,
     )
+    .with_fill(MATERIAL_COLOR)
 }
@@ -1,9 +1,11 @@
 use egui::emath::Numeric;
 use egui::{Color32, TextBuffer, Ui};
 use egui_snarl::ui::{PinInfo, WireStyle};
-use reactor_types::Vector;
+use reactor_types::{Float, Vector};
 
 use super::{NUMBER_COLOR, STRING_COLOR, UNTYPED_COLOR, VECTOR_COLOR, horizontal};
+use crate::expression::Value;
+use crate::node::viewer::format_float;
 use crate::node::viewer::widget::color_picker::{Alpha, color_edit_button_srgba};
 
 pub fn number_view<N>(ui: &mut Ui, label: &str, value: &mut N) -> PinInfo
@@ -55,3 +57,28 @@ pub fn color_view(ui: &mut Ui, label: &str, value: &mut Color32) -> PinInfo {
 pub fn empty_view() -> PinInfo {
     PinInfo::circle().with_fill(UNTYPED_COLOR)
 }
+
+/// Read-only view of a computed number.
+pub fn number_label(ui: &mut Ui, label: &str, value: Float) -> PinInfo {
+    horizontal(ui, label, |ui| {
+        ui.label(format_float(value));
+    });
+    PinInfo::circle().with_fill(NUMBER_COLOR)
+}
+
+/// Read-only view of a computed vector.
+pub fn vector_label(ui: &mut Ui, label: &str, vector: Vector) -> PinInfo {
+    horizontal(ui, label, |ui| {
+        for idx in 0..vector.len() {
+            ui.label(format_float(vector[idx]));
+        }
+    });
+    PinInfo::circle().with_fill(VECTOR_COLOR)
+}
+
+pub fn value_label(ui: &mut Ui, label: &str, value: Value) -> PinInfo {
+    match value {
+        Value::Number(number) => number_label(ui, label, number),
+        Value::Vector(vector) => vector_label(ui, label, vector),
+    }
+}
@@ -1,2 +1,3 @@
 pub mod color_picker;
 pub mod fixed_cache;
+pub mod keyframe;
@@ -514,6 +514,7 @@ pub fn color_edit_button_hsva(ui: &mut Ui, hsva: &mut Hsva, alpha: Alpha) -> Res
                 Frame::popup(ui.style()).show(ui, |ui| {
                     if color_picker_hsva_2d(ui, hsva, alpha) {
                         button_response.mark_changed();
+                        button_response.widget_info(|| WidgetInfo::new(WidgetType::ColorButton));
                     }
                 });
             })
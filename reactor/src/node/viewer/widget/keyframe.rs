@@ -0,0 +1,80 @@
+//! Keyframe button of the animated inputs.
+
+use egui::{Color32, Painter, Pos2, Sense, Shape, Stroke, Ui, WidgetInfo, WidgetType, vec2};
+use reactor_types::NodePin;
+use reactor_types::animation::Interpolation;
+
+use crate::timeline;
+
+pub const KEY_COLOR: Color32 = Color32::from_rgb(0xff, 0xc0, 0x30);
+
+/// Paints the diamond of a keyframe, filled if the value is keyed at the current time.
+pub fn paint_key(painter: &Painter, center: Pos2, radius: f32, filled: bool, color: Color32) {
+    let points = vec![
+        center + vec2(0.0, -radius),
+        center + vec2(radius, 0.0),
+        center + vec2(0.0, radius),
+        center + vec2(-radius, 0.0),
+    ];
+    let fill = if filled { color } else { Color32::TRANSPARENT };
+    painter.add(Shape::convex_polygon(points, fill, Stroke::new(1.0, color)));
+}
+
+/// Keys the initial value of the pin at the current time of the timeline, or removes the key which is there. A key at
+/// the current time follows the edits of the value. The context menu sets the interpolation towards the next key.
+pub fn key_button<T>(ui: &mut Ui, node_pin: &mut NodePin<T>)
+where
+    T: Clone + PartialEq,
+{
+    let time = timeline::current_time(ui.ctx());
+    let initial = node_pin.initial().clone();
+    let keyed = match node_pin.key_mut(time) {
+        Some(key) => {
+            if *key.value() != initial {
+                key.set_value(initial);
+            }
+            true
+        },
+        None => false,
+    };
+    let animated = node_pin.track().is_some();
+
+    let size = ui.spacing().interact_size.y;
+    let (rect, response) = ui.allocate_exact_size(vec2(size * 0.6, size), Sense::click());
+    let color = if response.hovered() || keyed {
+        KEY_COLOR
+    } else if animated {
+        ui.visuals().text_color()
+    } else {
+        ui.visuals().weak_text_color()
+    };
+    paint_key(ui.painter(), rect.center(), size * 0.25, keyed, color);
+
+    let hover_text = if keyed { "Remove the key" } else { "Key the value" };
+    let response = response.on_hover_text(hover_text);
+    response.widget_info(|| WidgetInfo::new(WidgetType::Button));
+    if response.clicked() {
+        if keyed {
+            node_pin.remove_key(time);
+        } else {
+            node_pin.insert_key(time);
+        }
+    }
+
+    response.context_menu(|ui| {
+        if let Some(key) = node_pin.key_mut(time) {
+            for interpolation in [Interpolation::Linear, Interpolation::EASE_IN_OUT, Interpolation::Step] {
+                let selected = key.interpolation().name() == interpolation.name();
+                if ui.radio(selected, interpolation.name()).clicked() {
+                    key.set_interpolation(interpolation);
+                    ui.close_menu();
+                }
+            }
+            ui.separator();
+        }
+        if ui.add_enabled(animated, egui::Button::new("Clear keys")).clicked() {
+            node_pin.clear_keys();
+            ui.close_menu();
+        }
+    });
+}
@@ -0,0 +1,331 @@
+//! Offline render of the animation into an image sequence.
+
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use eframe::wgpu;
+use egui::{ComboBox, Ui};
+use egui_snarl::{NodeId, Snarl};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use xrays::RenderParamsValidationError;
+use xrays::scene::{Scene, SceneDirtyFlags};
+use xrays::sequence::{FrameRange, ImageFormat, SequenceError, SequenceWriter, VideoFormat};
+use xrays::tile::{TiledRender, TiledRenderError};
+
+use crate::node::Node;
+use crate::node::item::render::RenderNode;
+use crate::node::item::render::xrays::XraysRenderNode;
+use crate::node::item::scene::SceneNode;
+use crate::node::item::texture::texture_cache;
+use crate::node::message::SelfNodeMut;
+
+/// How often the decoding textures are checked while a frame waits for them.
+const TEXTURE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where and how the frames of the animation are written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequenceSettings {
+    pub directory: String,
+    pub image_format: ImageFormat,
+    pub video_format: Option<VideoFormat>,
+}
+
+impl Default for SequenceSettings {
+    fn default() -> Self {
+        Self {
+            directory: "animation".to_string(),
+            image_format: ImageFormat::default(),
+            video_format: None,
+        }
+    }
+}
+
+impl SequenceSettings {
+    pub fn show(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Render to");
+            ui.text_edit_singleline(&mut self.directory);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Images");
+            ComboBox::from_id_salt("Images")
+                .selected_text(self.image_format.name())
+                .show_ui(ui, |ui| {
+                    for format in ImageFormat::ALL {
+                        ui.selectable_value(&mut self.image_format, format, format.name());
+                    }
+                });
+
+            ui.label("Video");
+            ComboBox::from_id_salt("Video")
+                .selected_text(self.video_format.map_or("None", VideoFormat::name))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.video_format, None, "None");
+                    for format in VideoFormat::ALL {
+                        ui.selectable_value(&mut self.video_format, Some(format), format.name());
+                    }
+                });
+        });
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SequenceRenderError {
+    #[error("there is no ray tracing render node with a camera")]
+    NoRenderNode,
+    #[error("node {0:?} is not a ray tracing render node")]
+    NotRenderNode(NodeId),
+    #[error("the render node has no camera")]
+    NoCamera,
+    #[error("the frame range has no frames")]
+    EmptyRange,
+    #[error(transparent)]
+    RenderParams(#[from] RenderParamsValidationError),
+    #[error(transparent)]
+    Render(#[from] TiledRenderError),
+    #[error(transparent)]
+    Sequence(#[from] SequenceError),
+}
+
+/// Renders the frames of the animation one by one through a ray tracing render node, every frame gets all the
+/// samples of the tiled render. The caller animates and evaluates the graph at [`SequenceRender::next_time`] before
+/// every frame, so the same graph state drives the viewports and the frames.
+pub struct SequenceRender {
+    render_id: NodeId,
+    range: FrameRange,
+    renderer: xrays::Renderer,
+    directory: PathBuf,
+    /// Tiled render of the next frame, started from the graph as it was evaluated then.
+    frame: Option<TiledRender>,
+    /// Number of the rendered frames, the last of them may still be written.
+    rendered: u32,
+    writer: Option<SequenceWriter>,
+    /// Denoises and writes the last rendered frame in the background, the writer comes back once it's written.
+    writing: Option<JoinHandle<(SequenceWriter, Result<(), SequenceError>)>>,
+}
+
+impl SequenceRender {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        render_id: NodeId,
+        range: FrameRange,
+        settings: &SequenceSettings,
+        snarl: &mut Snarl<Node>,
+    ) -> Result<Self, SequenceRenderError> {
+        if range.is_empty() {
+            return Err(SequenceRenderError::EmptyRange);
+        }
+
+        let node = xrays_render_node(render_id, snarl)?;
+        let render_params = node.tiled_render_params(snarl).ok_or(SequenceRenderError::NoCamera)?;
+        // The renderer never draws to a surface, any target format does.
+        let renderer = xrays::Renderer::new(
+            device,
+            queue,
+            wgpu::TextureFormat::Rgba8Unorm,
+            &Scene::stub(),
+            &render_params,
+            node.max_viewport_resolution(),
+        )?;
+        let writer = SequenceWriter::new(
+            &settings.directory,
+            settings.image_format,
+            settings.video_format,
+            range.fps,
+        )?;
+        // The scene of a loaded graph isn't built nor subscribed to the animated nodes until it's marked.
+        mark_scene(render_id, snarl);
+
+        Ok(Self {
+            render_id,
+            range,
+            renderer,
+            directory: writer.directory().to_owned(),
+            frame: None,
+            rendered: 0,
+            writer: Some(writer),
+            writing: None,
+        })
+    }
+
+    /// Index of the frame which is rendered next, `None` after the last frame.
+    pub fn next_frame(&self) -> Option<u32> {
+        (self.rendered < self.range.len()).then_some(self.rendered)
+    }
+
+    /// Time of the frame which is rendered next, `None` after the last frame.
+    pub fn next_time(&self) -> Option<f32> {
+        self.next_frame().map(|frame| self.range.time(frame))
+    }
+
+    /// Number of the rendered frames and of all the frames.
+    pub fn progress(&self) -> (u32, u32) {
+        (self.rendered, self.range.len())
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Queues the next sampling batches of the next frame without waiting for the GPU. The render of the frame starts
+    /// from the graph as it's evaluated once the textures of the scene are decoded, until then the calls return right
+    /// away. Returns `true` once the frame is rendered, it's denoised and written in the background.
+    pub fn render_step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snarl: &mut Snarl<Node>,
+    ) -> Result<bool, SequenceRenderError> {
+        self.step(device, queue, snarl, false)
+    }
+
+    /// Renders the next frame from the graph as it's evaluated now. Blocks until the frame is rendered.
+    pub fn render_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snarl: &mut Snarl<Node>,
+    ) -> Result<(), SequenceRenderError> {
+        while !self.step(device, queue, snarl, true)? {}
+        Ok(())
+    }
+
+    fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snarl: &mut Snarl<Node>,
+        wait: bool,
+    ) -> Result<bool, SequenceRenderError> {
+        if self.frame.is_none() {
+            match self.start_frame(device, queue, snarl, wait)? {
+                Some(frame) => self.frame = Some(frame),
+                None => return Ok(false),
+            }
+        }
+        let frame = self.frame.as_mut().expect("Frame should be in progress");
+        while !self.renderer.render_tiled_step(device, queue, frame)? {
+            if !wait {
+                return Ok(false);
+            }
+            let _ = device.poll(wgpu::Maintain::Wait);
+        }
+
+        let frame = self.frame.take().expect("Frame should be in progress");
+        let mut writer = self.take_writer()?;
+        self.writing = Some(thread::spawn(move || {
+            let result = writer.write_frame(&frame.finish());
+            (writer, result)
+        }));
+        self.rendered += 1;
+        Ok(true)
+    }
+
+    /// Finishes the video, returns its path when one was written. Frames which weren't rendered are skipped.
+    pub fn finish(mut self, snarl: &mut Snarl<Node>) -> Result<Option<PathBuf>, SequenceRenderError> {
+        // Building the scene here may have consumed changes which the viewports didn't upload yet.
+        mark_scene(self.render_id, snarl);
+        Ok(self.take_writer()?.finish()?)
+    }
+
+    /// Uploads the scene as it's evaluated now and starts the tiled render of the next frame. Returns `None` while
+    /// the textures of the scene are decoded, unless it waits for them.
+    fn start_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snarl: &mut Snarl<Node>,
+        wait_for_textures: bool,
+    ) -> Result<Option<TiledRender>, SequenceRenderError> {
+        let node = xrays_render_node(self.render_id, snarl)?;
+        let render_params = node.tiled_render_params(snarl).ok_or(SequenceRenderError::NoCamera)?;
+        let tile_size = node.tile_size();
+        let scene_id = node
+            .scene_id()
+            .filter(|&scene_id| matches!(snarl.get_node(scene_id), Some(Node::Scene(_))));
+
+        // The scene is uploaded whole, the changes since the last frame belong to the viewports.
+        let stub;
+        let scene = match scene_id {
+            Some(scene_id) => {
+                if !build_scene(scene_id, snarl, wait_for_textures) {
+                    return Ok(None);
+                }
+                snarl[scene_id].as_scene_ref().as_scene()
+            },
+            None => {
+                stub = Scene::stub();
+                &stub
+            },
+        };
+        self.renderer.update_scene(device, queue, scene, SceneDirtyFlags::ALL);
+
+        Ok(Some(self.renderer.start_tiled(&render_params, tile_size)?))
+    }
+
+    /// Waits until the last rendered frame is written and takes the writer back.
+    fn take_writer(&mut self) -> Result<SequenceWriter, SequenceRenderError> {
+        if let Some(writing) = self.writing.take() {
+            let (writer, result) = writing.join().expect("Frame writing should not panic");
+            self.writer = Some(writer);
+            result?;
+        }
+        Ok(self
+            .writer
+            .take()
+            .expect("Writer should be back once the last frame is written"))
+    }
+}
+
+/// First ray tracing render node of the graph which has a camera.
+pub fn find_render_node(snarl: &Snarl<Node>) -> Option<NodeId> {
+    snarl
+        .node_ids()
+        .filter(|(_, node)| {
+            matches!(node.render_ref(), Some(RenderNode::XraysRender(render)) if render.camera_id().is_some())
+        })
+        .map(|(node_id, _)| node_id)
+        .min_by_key(|node_id| node_id.0)
+}
+
+fn xrays_render_node(node_id: NodeId, snarl: &Snarl<Node>) -> Result<&XraysRenderNode, SequenceRenderError> {
+    match snarl.get_node(node_id).and_then(Node::render_ref) {
+        Some(RenderNode::XraysRender(node)) => Ok(node),
+        _ => Err(SequenceRenderError::NotRenderNode(node_id)),
+    }
+}
+
+/// Marks the whole scene of the render node as changed for its next build.
+fn mark_scene(render_id: NodeId, snarl: &mut Snarl<Node>) {
+    if let Some(scene_id) = xrays_render_node(render_id, snarl)
+        .ok()
+        .and_then(XraysRenderNode::scene_id)
+        && let Some(Node::Scene(scene)) = snarl.get_node_mut(scene_id)
+    {
+        scene.register_in_render();
+    }
+}
+
+/// Rebuilds the changed parts of the scene. The textures which the scene requested are decoded in the background,
+/// the scene is rebuilt again once they are ready so the frame doesn't show their placeholders. Returns `false` while
+/// they are decoded, unless it waits for them.
+fn build_scene(scene_id: NodeId, snarl: &mut Snarl<Node>, wait_for_textures: bool) -> bool {
+    SceneNode::handle_recalculate(SelfNodeMut::new(scene_id, snarl));
+
+    loop {
+        if !texture_cache().poll().is_empty() {
+            snarl[scene_id].as_scene_mut().register_in_render();
+            SceneNode::handle_recalculate(SelfNodeMut::new(scene_id, snarl));
+        }
+        if !texture_cache().is_loading() {
+            return true;
+        }
+        if !wait_for_textures {
+            return false;
+        }
+        thread::sleep(TEXTURE_POLL_INTERVAL);
+    }
+}
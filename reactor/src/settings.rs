@@ -3,6 +3,10 @@ use egui_dock::AllowedSplits;
 use egui_snarl::ui::{NodeLayout, PinPlacement, SnarlStyle};
 use serde::{Deserialize, Serialize};
 
+use crate::node::palette::PaletteSettings;
+use crate::sequence::SequenceSettings;
+use crate::timeline::Timeline;
+
 #[derive(Debug, Default, Copy, Clone, Deserialize, Serialize, egui_probe::EguiProbe)]
 pub enum EditMode {
     #[default]
@@ -68,6 +72,16 @@ pub struct AppSettings {
     pub animation_time: f32,
     pub tabs: TabsSettings,
     pub snarl_style: SnarlStyle,
+
+    #[serde(default)]
+    #[egui_probe(skip)]
+    pub timeline: Timeline,
+    #[serde(default)]
+    #[egui_probe(skip)]
+    pub sequence: SequenceSettings,
+    #[serde(default)]
+    #[egui_probe(skip)]
+    pub palette: PaletteSettings,
 }
 
 impl Default for AppSettings {
@@ -80,6 +94,9 @@ impl Default for AppSettings {
             animation_time: 0.2,
             tabs: Default::default(),
             snarl_style: default_snarl_style(),
+            timeline: Timeline::default(),
+            sequence: SequenceSettings::default(),
+            palette: PaletteSettings::default(),
         }
     }
 }
@@ -7,6 +7,9 @@ use crate::app::UiIdKey;
 pub enum Tab {
     Viewport(ViewportTab),
     Settings(String),
+    History(String),
+    Timeline(String),
+    Problems(String),
 }
 
 impl Tab {
@@ -18,16 +21,35 @@ impl Tab {
         Self::Settings("Settings".into())
     }
 
+    pub fn new_history() -> Self {
+        Self::History("History".into())
+    }
+
+    pub fn new_timeline() -> Self {
+        Self::Timeline("Timeline".into())
+    }
+
+    pub fn new_problems() -> Self {
+        Self::Problems("Problems".into())
+    }
+
     pub fn name(&self) -> &'static str {
         match self {
             Self::Viewport(_) => "Viewport",
             Self::Settings(_) => "Settings",
+            Self::History(_) => "History",
+            Self::Timeline(_) => "Timeline",
+            Self::Problems(_) => "Problems",
         }
     }
 
     pub fn title(&self) -> &str {
         match self {
-            Self::Viewport(ViewportTab { title, .. }) | Self::Settings(title) => title.as_str(),
+            Self::Viewport(ViewportTab { title, .. })
+            | Self::Settings(title)
+            | Self::History(title)
+            | Self::Timeline(title)
+            | Self::Problems(title) => title.as_str(),
         }
     }
 
@@ -35,7 +57,11 @@ impl Tab {
         let name = self.name();
 
         match self {
-            Self::Viewport(ViewportTab { title, .. }) | Self::Settings(title) => {
+            Self::Viewport(ViewportTab { title, .. })
+            | Self::Settings(title)
+            | Self::History(title)
+            | Self::Timeline(title)
+            | Self::Problems(title) => {
                 let mut num = title.trim_start_matches(name).trim().parse::<usize>().unwrap_or(0);
                 num += 1;
                 *title = format!("{name} {num}");
@@ -0,0 +1,134 @@
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+use xrays::sequence::FrameRange;
+
+/// Id of the current time in the temporary data of the context, the keyframe buttons of the inputs read it.
+const TIME_ID: &str = "timeline_time";
+/// Longest step of the time while playing, so a stalled frame doesn't skip a part of the animation.
+const MAX_TIME_STEP: f32 = 0.1;
+
+/// Current time of the animation, in seconds, which drives the keyed inputs of the nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    time: f32,
+    duration: f32,
+    /// Frames per second, scrubbing snaps the time to the frames.
+    fps: u32,
+    looping: bool,
+    #[serde(skip)]
+    playing: bool,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            duration: 5.0,
+            fps: 24,
+            looping: true,
+            playing: false,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Frames from the start to the end of the timeline.
+    pub fn frame_range(&self) -> FrameRange {
+        FrameRange {
+            start: 0.0,
+            end: self.duration,
+            fps: self.fps,
+        }
+    }
+
+    /// Jumps to the time and stops the playback.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+        self.playing = false;
+    }
+
+    /// Moves the time while playing and publishes it for the keyframe buttons. The time wraps around at the end when
+    /// looping, otherwise the playback stops there.
+    pub fn advance(&mut self, ctx: &egui::Context) {
+        if self.playing {
+            self.time += ctx.input(|input| input.stable_dt).min(MAX_TIME_STEP);
+            if self.time > self.duration {
+                if self.looping && self.duration > 0.0 {
+                    self.time %= self.duration;
+                } else {
+                    self.time = self.duration;
+                    self.playing = false;
+                }
+            }
+            ctx.request_repaint();
+        }
+        ctx.data_mut(|data| data.insert_temp(egui::Id::new(TIME_ID), self.time));
+    }
+
+    /// Shows the playback controls and the time slider.
+    pub fn show(&mut self, ui: &mut Ui) {
+        let old_time = self.time;
+
+        ui.horizontal(|ui| {
+            if ui.button("⏮").on_hover_text("To the start").clicked() {
+                self.time = 0.0;
+            }
+            let play_text = if self.playing { "⏸" } else { "▶" };
+            if ui.button(play_text).on_hover_text("Play or pause").clicked() {
+                if !self.playing && self.time >= self.duration {
+                    self.time = 0.0;
+                }
+                self.playing = !self.playing;
+            }
+            if ui.button("⏭").on_hover_text("To the end").clicked() {
+                self.time = self.duration;
+                self.playing = false;
+            }
+            ui.toggle_value(&mut self.looping, "🔁")
+                .on_hover_text("Loop the playback");
+
+            ui.separator();
+            ui.label(format!("Frame {}", (self.time * self.fps as f32).round()));
+            ui.add(
+                egui::DragValue::new(&mut self.duration)
+                    .range(0.0..=f32::MAX)
+                    .speed(0.1)
+                    .suffix(" s"),
+            )
+            .on_hover_text("Duration");
+            ui.add(egui::DragValue::new(&mut self.fps).range(1..=240).suffix(" fps"));
+        });
+
+        ui.spacing_mut().slider_width =
+            ui.available_width() - ui.spacing().interact_size.x - ui.spacing().item_spacing.x;
+        let slider = egui::Slider::new(&mut self.time, 0.0..=self.duration)
+            .step_by(1.0 / self.fps as f64)
+            .suffix(" s");
+        if ui.add(slider).dragged() {
+            self.playing = false;
+        }
+
+        self.time = self.time.clamp(0.0, self.duration);
+        if self.time != old_time {
+            ui.ctx().request_repaint();
+        }
+    }
+}
+
+/// Time of the timeline in the current frame.
+pub fn current_time(ctx: &egui::Context) -> f32 {
+    ctx.data(|data| data.get_temp(egui::Id::new(TIME_ID)))
+        .unwrap_or_default()
+}
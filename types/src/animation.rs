@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+
+use crate::angle::AngleInner;
+use crate::{Angle, Color, Float, Vector};
+
+/// Keys closer in time than this are the same key.
+pub const TIME_EPSILON: f32 = 1e-3;
+
+/// Values which can be blended between two keyframes.
+pub trait Interpolate: Clone {
+    /// Blends from `self` at `t = 0` to `other` at `t = 1`. Values which can't be blended switch to `other` at the end.
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        if t < 1.0 { self.clone() } else { other.clone() }
+    }
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        self + (other - self) * t as f32
+    }
+}
+
+impl Interpolate for u32 {
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        Float::from(*self).interpolate(&Float::from(*other), t).round() as u32
+    }
+}
+
+impl<T> Interpolate for Angle<T>
+where
+    T: AngleInner + Interpolate + Copy,
+{
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        Self::degrees(self.as_degrees().interpolate(&other.as_degrees(), t))
+    }
+}
+
+impl Interpolate for Vector {
+    /// Vectors of different dimensions can't be blended.
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        match (self, other) {
+            (Self::Dim2(a), Self::Dim2(b)) => Self::Dim2(a.lerp(b, t)),
+            (Self::Dim3(a), Self::Dim3(b)) => Self::Dim3(a.lerp(b, t)),
+            (Self::Dim4(a), Self::Dim4(b)) => Self::Dim4(a.lerp(b, t)),
+            _ if t < 1.0 => *self,
+            _ => *other,
+        }
+    }
+}
+
+impl Interpolate for Color {
+    /// Blends the gamma encoded channels.
+    fn interpolate(&self, other: &Self, t: Float) -> Self {
+        let [r, g, b, a] =
+            std::array::from_fn(|idx| Float::from(self[idx]).interpolate(&Float::from(other[idx]), t).round() as u8);
+        Self::from_rgba_premultiplied(r, g, b, a)
+    }
+}
+
+impl<T: Clone> Interpolate for Option<T> {}
+
+/// How the value changes from a keyframe to the next one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Cubic Bézier curve from `(0, 0)` to `(1, 1)` with the control points `[x1, y1, x2, y2]`, like the CSS
+    /// `cubic-bezier` easing.
+    Bezier([f32; 4]),
+    /// Holds the value until the next keyframe.
+    Step,
+}
+
+impl Interpolation {
+    pub const EASE_IN_OUT: Self = Self::Bezier([0.42, 0.0, 0.58, 1.0]);
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Bezier(_) => "Bezier",
+            Self::Step => "Step",
+        }
+    }
+
+    /// Progress of the value for the progress `t` of the time between two keyframes, both from 0 to 1.
+    pub fn ease(&self, t: f32) -> f32 {
+        match self {
+            Self::Linear => t,
+            Self::Bezier([x1, y1, x2, y2]) => {
+                // The curve is monotonic in x while the control points are inside the unit square, so the curve
+                // parameter of `t` is found by bisection.
+                let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..32 {
+                    let mid = (low + high) / 2.0;
+                    if cubic_bezier(x1, x2, mid) < t {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                cubic_bezier(*y1, *y2, (low + high) / 2.0)
+            },
+            Self::Step => 0.0,
+        }
+    }
+}
+
+/// Coordinate of the cubic Bézier curve from 0 to 1 with the control coordinates `p1` and `p2`.
+fn cubic_bezier(p1: f32, p2: f32, s: f32) -> f32 {
+    let r = 1.0 - s;
+    3.0 * r * r * s * p1 + 3.0 * r * s * s * p2 + s * s * s
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe<T> {
+    time: f32,
+    value: T,
+    /// Interpolation towards the next keyframe.
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+impl<T> Keyframe<T> {
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn set_value(&mut self, value: T) {
+        self.value = value;
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+}
+
+/// Keyframes of a value, ordered by time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self { keyframes: Vec::new() }
+    }
+}
+
+impl<T> Track<T> {
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Keys the value at the time, replacing the value of the keyframe which is already there.
+    pub fn insert(&mut self, time: f32, value: T) {
+        match self.key_mut(time) {
+            Some(keyframe) => keyframe.value = value,
+            None => {
+                let idx = self.keyframes.partition_point(|keyframe| keyframe.time < time);
+                self.keyframes.insert(idx, Keyframe {
+                    time,
+                    value,
+                    interpolation: Interpolation::default(),
+                });
+            },
+        }
+    }
+
+    pub fn remove(&mut self, time: f32) -> Option<Keyframe<T>> {
+        let idx = self.position(time)?;
+        Some(self.keyframes.remove(idx))
+    }
+
+    pub fn key(&self, time: f32) -> Option<&Keyframe<T>> {
+        self.position(time).map(|idx| &self.keyframes[idx])
+    }
+
+    pub fn key_mut(&mut self, time: f32) -> Option<&mut Keyframe<T>> {
+        self.position(time).map(|idx| &mut self.keyframes[idx])
+    }
+
+    fn position(&self, time: f32) -> Option<usize> {
+        self.keyframes
+            .iter()
+            .position(|keyframe| (keyframe.time - time).abs() < TIME_EPSILON)
+    }
+}
+
+impl<T: Interpolate> Track<T> {
+    /// Value at the time, the first and the last keyframes hold their values before and after the track.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        let (from, to) = match next {
+            0 => return self.keyframes.first().map(|keyframe| keyframe.value.clone()),
+            next if next == self.keyframes.len() => {
+                return self.keyframes.last().map(|keyframe| keyframe.value.clone());
+            },
+            next => (&self.keyframes[next - 1], &self.keyframes[next]),
+        };
+
+        let t = (time - from.time) / (to.time - from.time);
+        let eased = from.interpolation.ease(t);
+        Some(from.value.interpolate(&to.value, Float::from(eased)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(keys: &[(f32, Float, Interpolation)]) -> Track<Float> {
+        let mut track = Track::default();
+        for &(time, value, interpolation) in keys {
+            track.insert(time, value);
+            track.key_mut(time).unwrap().set_interpolation(interpolation);
+        }
+        track
+    }
+
+    #[test]
+    fn test_track_keeps_keyframes_ordered() {
+        let mut track = Track::default();
+        track.insert(2.0, 2.0);
+        track.insert(0.0, 0.0);
+        track.insert(1.0, 1.0);
+        track.insert(1.0 + TIME_EPSILON / 2.0, 3.0);
+
+        let times = track.keyframes().iter().map(Keyframe::time).collect::<Vec<_>>();
+        assert_eq!(times, [0.0, 1.0, 2.0]);
+        assert_eq!(track.key(1.0).map(Keyframe::value), Some(&3.0));
+        assert!(track.remove(1.0).is_some());
+        assert_eq!(track.keyframes().len(), 2);
+    }
+
+    #[test]
+    fn test_track_sample_interpolations() {
+        let linear = track(&[(1.0, 0.0, Interpolation::Linear), (3.0, 10.0, Interpolation::Linear)]);
+        assert_eq!(linear.sample(0.0), Some(0.0));
+        assert_eq!(linear.sample(2.0), Some(5.0));
+        assert_eq!(linear.sample(4.0), Some(10.0));
+
+        let step = track(&[(0.0, 0.0, Interpolation::Step), (1.0, 10.0, Interpolation::Step)]);
+        assert_eq!(step.sample(0.99), Some(0.0));
+        assert_eq!(step.sample(1.0), Some(10.0));
+
+        let bezier = track(&[
+            (0.0, 0.0, Interpolation::EASE_IN_OUT),
+            (1.0, 10.0, Interpolation::Linear),
+        ]);
+        let early = bezier.sample(0.25).unwrap();
+        assert!(early > 0.0 && early < 2.5);
+        assert!((bezier.sample(0.5).unwrap() - 5.0).abs() < 1e-3);
+
+        assert_eq!(Track::<Float>::default().sample(0.0), None);
+    }
+
+    #[test]
+    fn test_pin_follows_keys() {
+        let mut pin = crate::NodePin::new(1.0);
+        pin.insert_key(0.0);
+        pin.set_initial(3.0);
+        pin.insert_key(2.0);
+
+        assert!(pin.animate(1.0));
+        assert_eq!(*pin.initial(), 2.0);
+        assert!(!pin.animate(1.0));
+
+        pin.remove_key(0.0);
+        pin.remove_key(2.0);
+        assert!(pin.track().is_none());
+        assert!(!pin.animate(0.0));
+    }
+
+    #[test]
+    fn test_interpolate_values() {
+        assert_eq!(
+            Vector::Dim2([0.0, 2.0].into()).interpolate(&Vector::Dim2([2.0, 4.0].into()), 0.5),
+            Vector::Dim2([1.0, 3.0].into())
+        );
+        assert_eq!(Color::BLACK.interpolate(&Color::WHITE, 0.5), Color::from_gray(128));
+        assert_eq!(Some(1).interpolate(&None, 0.5), Some(1));
+        assert_eq!(10_u32.interpolate(&20, 0.26), 13);
+    }
+}
@@ -2,15 +2,25 @@ use std::ops;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+use crate::animation::{Interpolate, Keyframe, Track};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct NodePin<T> {
     initial: T,
     value: Option<T>,
+    /// Keyframes of the initial value, which follows them when the time changes. Pins saved without keyframes
+    /// deserialize as missing options do.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track: Option<Track<T>>,
 }
 
 impl<T> NodePin<T> {
     pub fn new(initial: T) -> Self {
-        Self { initial, value: None }
+        Self {
+            initial,
+            value: None,
+            track: None,
+        }
     }
 
     pub fn set(&mut self, value: T) {
@@ -25,6 +35,11 @@ impl<T> NodePin<T> {
         self.value = None;
     }
 
+    /// Value of the pin while no wire sets it.
+    pub fn initial(&self) -> &T {
+        &self.initial
+    }
+
     pub fn as_ref(&self) -> &T {
         self.value.as_ref().unwrap_or(&self.initial)
     }
@@ -32,6 +47,53 @@ impl<T> NodePin<T> {
     pub fn as_mut(&mut self) -> &mut T {
         self.value.as_mut().unwrap_or(&mut self.initial)
     }
+
+    pub fn track(&self) -> Option<&Track<T>> {
+        self.track.as_ref()
+    }
+
+    pub fn key(&self, time: f32) -> Option<&Keyframe<T>> {
+        self.track.as_ref()?.key(time)
+    }
+
+    pub fn key_mut(&mut self, time: f32) -> Option<&mut Keyframe<T>> {
+        self.track.as_mut()?.key_mut(time)
+    }
+
+    /// Removes the keyframe at the time, the pin stops being animated with the last one.
+    pub fn remove_key(&mut self, time: f32) {
+        if let Some(track) = &mut self.track {
+            track.remove(time);
+            if track.is_empty() {
+                self.track = None;
+            }
+        }
+    }
+
+    pub fn clear_keys(&mut self) {
+        self.track = None;
+    }
+}
+
+impl<T: Clone> NodePin<T> {
+    /// Keys the initial value at the time.
+    pub fn insert_key(&mut self, time: f32) {
+        let initial = self.initial.clone();
+        self.track.get_or_insert_default().insert(time, initial);
+    }
+}
+
+impl<T: Interpolate + PartialEq> NodePin<T> {
+    /// Sets the initial value from the keyframes at the time, returns whether it changed.
+    pub fn animate(&mut self, time: f32) -> bool {
+        match self.track.as_ref().and_then(|track| track.sample(time)) {
+            Some(initial) if initial != self.initial => {
+                self.initial = initial;
+                true
+            },
+            _ => false,
+        }
+    }
 }
 
 impl ops::Deref for NodePin<f64> {
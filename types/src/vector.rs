@@ -23,7 +23,7 @@ where
     pub w: Vector3<T>,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum Vector {
     Dim2(Vector2),
     Dim3(Vector3),
@@ -49,6 +49,22 @@ fn main() {
             ..Default::default()
         })
         .expect("Failed to add shader");
+    composer
+        .add_composable_module(ComposableModuleDescriptor {
+            source: include_str!("shader/compute/procedural.wgsl"),
+            file_path: "shader/compute/procedural.wgsl",
+            additional_imports: Default::default(),
+            ..Default::default()
+        })
+        .expect("Failed to add shader");
+    composer
+        .add_composable_module(ComposableModuleDescriptor {
+            source: include_str!("shader/compute/spectrum.wgsl"),
+            file_path: "shader/compute/spectrum.wgsl",
+            additional_imports: Default::default(),
+            ..Default::default()
+        })
+        .expect("Failed to add shader");
     composer
         .add_composable_module(ComposableModuleDescriptor {
             source: include_str!("shader/render/tonemap.wgsl"),
@@ -74,6 +90,14 @@ fn main() {
         })
         .expect("Failed to compose render shader");
 
+    let denoise_shader_module = composer
+        .make_naga_module(NagaModuleDescriptor {
+            source: include_str!("shader/denoise/main.wgsl"),
+            file_path: "shader/denoise/main.wgsl",
+            ..Default::default()
+        })
+        .expect("Failed to compose denoise shader");
+
     let out_dir = env::var("OUT_DIR").expect("OUT_DIR must be set");
     generate_shader_file(
         &mut composer,
@@ -85,6 +109,11 @@ fn main() {
         &render_shader_module,
         Path::new(&out_dir).join("render_shader.wgsl"),
     );
+    generate_shader_file(
+        &mut composer,
+        &denoise_shader_module,
+        Path::new(&out_dir).join("denoise_shader.wgsl"),
+    );
 
     println!("cargo:rerun-if-changed=shader");
 }
@@ -1,11 +1,29 @@
-#import consts::{EPSILON, PI, FRAC_1_PI, CHANNEL_R, CHANNEL_G, CHANNEL_B}
-#import object::{intersection, Intersection, Sphere, spheres}
+#import consts::{EPSILON, PI, FRAC_1_PI, MAX_T, CHANNEL_R, CHANNEL_G, CHANNEL_B}
+#import object::{intersection, sphere_uv_footprint, Intersection, Sphere, spheres, scene_counts}
+#import procedural
 #import rng
-#import sampling::SamplingParams
+#import sampling::{SamplingParams, PixelStats}
+#import spectrum
 #import types::Ray
 
 @group(1) @binding(0) var<uniform> frame_data: vec4<u32>;
 @group(1) @binding(1) var<storage, read_write> image_buffer: array<array<f32, 3>>;
+@group(1) @binding(2) var<storage, read_write> pixel_stats: array<PixelStats>;
+@group(1) @binding(3) var<storage, read_write> feature_buffer: array<array<f32, 6>>;
+// Holds no elements when the auxiliary passes are disabled.
+@group(1) @binding(5) var<storage, read_write> aov_buffer: array<array<f32, 9>>;
+@group(1) @binding(6) var<uniform> tile: Tile;
+// Number of the sampled pixels which are converged, the renderer stops once all of them are.
+@group(1) @binding(7) var<storage, read_write> converged_pixels: atomic<u32>;
+
+// Placement of the image buffer within the rendered image, which can be larger than the buffer.
+// Only pixels of the buffer between `region_min` and `region_max` are sampled.
+struct Tile {
+    offset: vec2<u32>,
+    image_size: vec2<u32>,
+    region_min: vec2<u32>,
+    region_max: vec2<u32>,
+}
 
 @group(2) @binding(0) var<uniform> sampling_params: SamplingParams;
 @group(2) @binding(1) var<uniform> camera: Camera;
@@ -13,42 +31,161 @@
 
 // @group(3) @binding(0) var<storage, read> spheres: array<Sphere>;
 @group(3) @binding(1) var<storage, read> materials: array<Material>;
-@group(3) @binding(2) var<storage, read> textures: array<array<f32, 3>>;
+// Atlas placements (layer, x, y, atlas) of image mip levels, or bits of procedural texture parameters.
+@group(3) @binding(2) var<storage, read> texture_info: array<vec4<u32>>;
 @group(3) @binding(3) var<storage, read> lights: array<u32>;
+@group(3) @binding(5) var ldr_atlas: texture_2d_array<f32>;
+@group(3) @binding(6) var hdr_atlas: texture_2d_array<f32>;
 
 @compute @workgroup_size(8, 8)
 fn cs_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let image_width = frame_data.x;
-    let image_height = frame_data.y;
     let frame_number = frame_data.z;
 
-    let x = global_id.x;
-    let y = global_id.y;
+    let x = tile.region_min.x + global_id.x;
+    let y = tile.region_min.y + global_id.y;
 
-    if (x >= image_width || y >= image_height) {
+    if (x >= tile.region_max.x || y >= tile.region_max.y) {
         return;
     }
     let idx = image_width * y + x;
+    let image_pos = tile.offset + vec2(x, y);
 
-    var rng_state = rng::init(vec2(x, y), vec2(image_width, image_height), frame_number);
+    var rng_state = rng::init(image_pos, tile.image_size, frame_number);
     var pixel = vec3(image_buffer[idx][0], image_buffer[idx][1], image_buffer[idx][2]);
+    var stats = pixel_stats[idx];
+    let has_aovs = sampling_params.aovs == 1u;
+    var features = load_features(idx, has_aovs);
     {
         if sampling_params.clear_accumulated_samples == 1 {
             pixel = vec3(0f);
+            stats = PixelStats();
+            features = Features();
+        }
+
+        if stats.converged == 1u {
+            atomicAdd(&converged_pixels, 1u);
+            return;
         }
 
-        let rgb = sample_pixel(x, y, &rng_state);
+        let rgb = sample_pixel(image_pos.x, image_pos.y, &rng_state, &stats, &features);
         pixel += rgb;
+        stats.converged = select(0u, 1u, is_converged(stats));
+        if stats.converged == 1u {
+            atomicAdd(&converged_pixels, 1u);
+        }
     }
 
     image_buffer[idx] = array<f32, 3>(pixel.r, pixel.g, pixel.b);
+    pixel_stats[idx] = stats;
+    store_features(idx, has_aovs, features);
 }
 
-fn sample_pixel(x: u32, y: u32, rng_state: ptr<function, u32>) -> vec3<f32> {
-    let image_width = frame_data.x;
-    let image_height = frame_data.y;
-    let inv_width = 1f / f32(image_width);
-    let inv_height = 1f / f32(image_height);
+// Sums of the first bounce albedo and normal, used by the denoiser to find edges, and of the auxiliary passes.
+struct Features {
+    albedo: vec3<f32>,
+    normal: vec3<f32>,
+    // Depth, material index and sphere index of the first sample, they make no sense averaged.
+    first_hit: vec3<f32>,
+    direct: vec3<f32>,
+    indirect: vec3<f32>,
+}
+
+fn load_features(idx: u32, has_aovs: bool) -> Features {
+    let stored = feature_buffer[idx];
+    var features = Features();
+    features.albedo = vec3(stored[0], stored[1], stored[2]);
+    features.normal = vec3(stored[3], stored[4], stored[5]);
+
+    if has_aovs {
+        let aov = aov_buffer[idx];
+        features.first_hit = vec3(aov[0], aov[1], aov[2]);
+        features.direct = vec3(aov[3], aov[4], aov[5]);
+        features.indirect = vec3(aov[6], aov[7], aov[8]);
+    }
+    return features;
+}
+
+fn store_features(idx: u32, has_aovs: bool, features: Features) {
+    feature_buffer[idx] = array<f32, 6>(
+        features.albedo.r, features.albedo.g, features.albedo.b,
+        features.normal.x, features.normal.y, features.normal.z
+    );
+
+    if has_aovs {
+        aov_buffer[idx] = array<f32, 9>(
+            features.first_hit.x, features.first_hit.y, features.first_hit.z,
+            features.direct.r, features.direct.g, features.direct.b,
+            features.indirect.r, features.indirect.g, features.indirect.b
+        );
+    }
+}
+
+struct FirstHit {
+    albedo: vec3<f32>,
+    normal: vec3<f32>,
+    depth: f32,
+    material_idx: f32,
+    sphere_idx: f32,
+}
+
+// The sky has neither albedo nor normal, and it is infinitely far away.
+fn sky_hit() -> FirstHit {
+    return FirstHit(vec3(0f), vec3(0f), MAX_T, -1f, -1f);
+}
+
+fn material_albedo(hit: Intersection, material: Material) -> vec3<f32> {
+    switch material.id {
+        case 0u, 1u, 4u: {
+            return texture_lookup(material.desc1, hit);
+        }
+
+        case 2u: {
+            return vec3(1f);
+        }
+
+        case 3u: {
+            let sines = sin(5f * hit.point.x) * sin(5f * hit.point.y) * sin(5f * hit.point.z);
+            if sines < 0f {
+                return texture_lookup(material.desc1, hit);
+            } else {
+                return texture_lookup(material.desc2, hit);
+            }
+        }
+
+        default: {
+            return vec3(0.9921f, 0.24705f, 0.57254f);
+        }
+    }
+}
+
+// A pixel is converged when the relative standard error of its mean luminance falls below the adaptive threshold.
+fn is_converged(stats: PixelStats) -> bool {
+    if sampling_params.adaptive_threshold <= 0f || stats.num_samples < max(sampling_params.min_samples_per_pixel, 2u) {
+        return false;
+    }
+
+    let n = f32(stats.num_samples);
+    let mean = stats.luminance_sum / n;
+    let variance = max(stats.luminance_sq_sum / n - mean * mean, 0f);
+    let standard_error = sqrt(variance / n);
+
+    return standard_error / max(mean, EPSILON) < sampling_params.adaptive_threshold;
+}
+
+fn luminance(rgb: vec3<f32>) -> f32 {
+    return dot(rgb, vec3(0.2126f, 0.7152f, 0.0722f));
+}
+
+fn sample_pixel(
+    x: u32,
+    y: u32,
+    rng_state: ptr<function, u32>,
+    stats: ptr<function, PixelStats>,
+    features: ptr<function, Features>
+) -> vec3<f32> {
+    let inv_width = 1f / f32(tile.image_size.x);
+    let inv_height = 1f / f32(tile.image_size.y);
 
     let num_samples = sampling_params.num_samples_per_pixel;
     var pixel_color = vec3(0f);
@@ -57,32 +194,84 @@ fn sample_pixel(x: u32, y: u32, rng_state: ptr<function, u32>) -> vec3<f32> {
         let v = (f32(y) + rng::next_float(rng_state)) * inv_height;
 
         let primary_ray = camera_make_ray(camera, rng_state, u, 1f - v);
-        pixel_color += ray_color(primary_ray, rng_state);
+        var is_direct = false;
+        var hit = sky_hit();
+        let sample_color = ray_color(primary_ray, rng_state, &is_direct, &hit);
+
+        (*features).albedo += hit.albedo;
+        (*features).normal += hit.normal;
+        if (*stats).num_samples == 0u && i == 0u {
+            (*features).first_hit = vec3(hit.depth, hit.material_idx, hit.sphere_idx);
+        }
+        if is_direct {
+            (*features).direct += sample_color;
+        } else {
+            (*features).indirect += sample_color;
+        }
+
+        let sample_luminance = luminance(sample_color);
+        pixel_color += sample_color;
+        (*stats).luminance_sum += sample_luminance;
+        (*stats).luminance_sq_sum += sample_luminance * sample_luminance;
     }
+    (*stats).num_samples += num_samples;
 
     return pixel_color;
 }
 
-fn ray_color(primary_ray: Ray, rng_state: ptr<function, u32>) -> vec3<f32> {
+// Angle between the rays through the centers of two neighboring pixels.
+fn pixel_spread_angle() -> f32 {
+    let image_center = camera.lower_left_corner + 0.5f * (camera.horizontal + camera.vertical);
+    return length(camera.vertical) / (f32(tile.image_size.y) * length(image_center - camera.eye));
+}
+
+// Light which reached the camera after at most one bounce is reported as direct. The surface of the first bounce is
+// stored in `first_hit`, which is left as it is when the ray misses.
+fn ray_color(
+    primary_ray: Ray,
+    rng_state: ptr<function, u32>,
+    is_direct: ptr<function, bool>,
+    first_hit: ptr<function, FirstHit>
+) -> vec3<f32> {
     var ray = primary_ray;
 
     var color = vec3(0f);
     var throughput = vec3(1f);
 
+    // The ray cone keeps the spread of the primary ray, surface curvature and roughness are not accounted for.
+    let cone_spread = pixel_spread_angle();
+    var cone_width = 0f;
+    // Wavelength in nanometers once a dispersive material splits the path, 0 while it carries all colors.
+    var wavelength = 0f;
+
     for (var bounce = 0u; bounce < sampling_params.num_bounces; bounce += 1u) {
         var intersection = Intersection();
 
         if intersection(ray, &intersection) {
-            let material = materials[intersection.material_idx];
+            cone_width += cone_spread * intersection.t * length(ray.direction);
+            intersection.uv_footprint = sphere_uv_footprint(intersection, cone_width);
+            let material = materials[resolve_material(intersection.material_idx, ray, intersection, rng_state)];
+            intersection.normal = shading_normal(intersection, material);
+
+            if bounce == 0u {
+                *first_hit = FirstHit(
+                    material_albedo(intersection, material),
+                    intersection.normal,
+                    intersection.t * length(ray.direction),
+                    f32(intersection.material_idx),
+                    f32(intersection.sphere_idx)
+                );
+            }
 
             if material.id == 4u {
                 let emission_texture = material.desc1;
-                let emission_color = texture_lookup(emission_texture, intersection.u, intersection.v);
+                let emission_color = texture_lookup(emission_texture, intersection);
                 color += throughput * emission_color;
+                *is_direct = bounce <= 1u;
                 break;
             }
 
-            var scatter = scatter_ray(ray, intersection, material, rng_state);
+            var scatter = scatter_ray(ray, intersection, material, &wavelength, rng_state);
             ray = scatter.ray;
             throughput *= scatter.throughput;
         } else {
@@ -98,6 +287,7 @@ fn ray_color(primary_ray: Ray, rng_state: ptr<function, u32>) -> vec3<f32> {
                 radiance(theta, gamma, CHANNEL_G),
                 radiance(theta, gamma, CHANNEL_B)
             );
+            *is_direct = bounce <= 1u;
 
             break;
         }
@@ -106,7 +296,13 @@ fn ray_color(primary_ray: Ray, rng_state: ptr<function, u32>) -> vec3<f32> {
     return color;
 }
 
-fn scatter_ray(wo: Ray, hit: Intersection, material: Material, rng_state: ptr<function, u32>) -> Scatter {
+fn scatter_ray(
+    wo: Ray,
+    hit: Intersection,
+    material: Material,
+    wavelength: ptr<function, f32>,
+    rng_state: ptr<function, u32>
+) -> Scatter {
     switch material.id {
         case 0u: {
             let texture = material.desc1;
@@ -120,8 +316,7 @@ fn scatter_ray(wo: Ray, hit: Intersection, material: Material, rng_state: ptr<fu
         }
 
         case 2u: {
-            let refraction_index = material.x;
-            return scatter_dielectric(wo, hit, refraction_index, rng_state);
+            return scatter_dielectric(wo, hit, material, wavelength, rng_state);
         }
 
         case 3u: {
@@ -154,7 +349,7 @@ fn sample_mixture_density(hit: Intersection, rng_state: ptr<function, u32>) -> v
 }
 
 fn eval_lambertian(hit: Intersection, texture: TextureDescriptor, wi: vec3<f32>) -> vec3<f32> {
-    return texture_lookup(texture, hit.u, hit.v) * FRAC_1_PI * max(EPSILON, dot(hit.normal, wi));
+    return texture_lookup(texture, hit) * FRAC_1_PI * max(EPSILON, dot(hit.normal, wi));
 }
 
 fn sample_lambertian(hit: Intersection, rng_state: ptr<function, u32>) -> vec3<f32> {
@@ -169,7 +364,7 @@ fn pdf_lambertian(hit: Intersection, wi: vec3<f32>) -> f32 {
 
 fn sample_light(hit: Intersection, rng_state: ptr<function, u32>) -> vec3<f32> {
     // Select a random light using a uniform distribution.
-    let num_lights = arrayLength(&lights);   // TODO: what about when there are no lights?
+    let num_lights = scene_counts.num_lights;   // TODO: what about when there are no lights?
     let light_idx = rng::next_uint_in_range(rng_state, 0u, num_lights - 1u);
     let sphere_idx = lights[light_idx];
     let sphere = spheres[sphere_idx];
@@ -198,7 +393,7 @@ fn pdf_light(hit: Intersection, wi: vec3<f32>) -> f32 {
     if intersection(ray, &light_hit) {
         let sphere_idx = light_hit.sphere_idx;
         let sphere = spheres[sphere_idx];
-        let num_spheres = arrayLength(&spheres);
+        let num_spheres = scene_counts.num_spheres;
         let to_light = light_hit.point - hit.point;
         let length_sqr = dot(to_light, to_light);
         let cosine = abs(dot(wi, light_hit.normal));
@@ -225,38 +420,79 @@ fn pixar_onb(n: vec3<f32>) -> mat3x3<f32> {
 
 fn scatter_metal(wo: Ray, hit: Intersection, texture: TextureDescriptor, fuzz: f32, rng_state: ptr<function, u32>) -> Scatter {
     let scatter_direction = reflect(wo.direction, hit.normal) + fuzz * rng::next_vec3_in_unit_sphere(rng_state);
-    let albedo = texture_lookup(texture, hit.u, hit.v);
+    let albedo = texture_lookup(texture, hit);
     return Scatter(Ray(hit.point, scatter_direction), albedo);
 }
 
-fn scatter_dielectric(rayIn: Ray, hit: Intersection, refraction_index: f32, rng_state: ptr<function, u32>) -> Scatter {
-    let wo = rayIn.direction;
-    var outward_normal = vec3(0f);
-    var ni_over_nt = 0f;
-    var cosine = 0f;
-    if dot(wo, hit.normal) > 0f {
+fn scatter_dielectric(
+    ray_in: Ray,
+    hit: Intersection,
+    material: Material,
+    wavelength: ptr<function, f32>,
+    rng_state: ptr<function, u32>
+) -> Scatter {
+    let wo = ray_in.direction;
+    let entering = dot(wo, hit.normal) <= 0f;
+    var throughput = vec3(1f);
+
+    // Rays leaving the material have traveled through it since they entered.
+    if !entering {
+        let absorption = vec3(material.absorption_r, material.absorption_g, material.absorption_b);
+        throughput = exp(-absorption * hit.t * length(wo));
+    }
+
+    var refraction_index = material.x;
+    let abbe_number = material.y;
+    if abbe_number > 0f {
+        if *wavelength == 0f {
+            *wavelength = spectrum::sample_wavelength(rng::next_float(rng_state));
+            throughput *= spectrum::wavelength_to_rgb(*wavelength);
+        }
+        refraction_index = spectrum::cauchy_refraction_index(refraction_index, abbe_number, *wavelength);
+    }
+
+    var outward_normal = hit.normal;
+    var ni_over_nt = 1f / refraction_index;
+    var cosine = dot(normalize(-wo), hit.normal);
+    if !entering {
         outward_normal = -hit.normal;
         ni_over_nt = refraction_index;
-        cosine = refraction_index * dot(normalize(wo), hit.normal);
-    } else {
-        outward_normal = hit.normal;
-        ni_over_nt = 1f / refraction_index;
-        cosine = dot(normalize(-wo), hit.normal);
-    };
+        cosine = min(refraction_index * dot(normalize(wo), hit.normal), 1f);
+    }
 
     var refracted_direction = vec3(0f);
-    if refract(wo, outward_normal, ni_over_nt, &refracted_direction) {
-        let reflection_prob = schlick(cosine, refraction_index);
-        var wi = refracted_direction;
-        if rng::next_float(rng_state) < reflection_prob {
-            reflect(wo, hit.normal);
-        }
+    if !refract(wo, outward_normal, ni_over_nt, &refracted_direction) {
+        return Scatter(Ray(hit.point, reflect(wo, hit.normal)), throughput);
+    }
 
-        return Scatter(Ray(hit.point, wi), vec3(1f));
+    var reflectance = vec3(schlick(cosine, refraction_index));
+    if material.film_thickness > 0f {
+        let cos_incident = abs(dot(normalize(wo), hit.normal));
+        let outer_ior = select(refraction_index, 1f, entering);
+        let inner_ior = select(1f, refraction_index, entering);
+        var wavelengths = spectrum::RGB_WAVELENGTHS;
+        if *wavelength != 0f {
+            wavelengths = vec3(*wavelength);
+        }
+        for (var channel = 0u; channel < 3u; channel += 1u) {
+            reflectance[channel] = spectrum::thin_film_reflectance(
+                cos_incident,
+                outer_ior,
+                material.film_refraction_index,
+                inner_ior,
+                material.film_thickness,
+                wavelengths[channel]
+            );
+        }
     }
 
-    let wi = reflect(wo, hit.normal);
-    return Scatter(Ray(hit.point, wi), vec3(1f));
+    // Colored reflectance picks a direction by its average and weights the channels.
+    let reflection_prob = clamp((reflectance.r + reflectance.g + reflectance.b) / 3f, 0f, 1f);
+    if rng::next_float(rng_state) < reflection_prob {
+        return Scatter(Ray(hit.point, reflect(wo, hit.normal)), throughput * reflectance / reflection_prob);
+    }
+    let transmittance = (1f - reflectance) / (1f - reflection_prob);
+    return Scatter(Ray(hit.point, refracted_direction), throughput * transmittance);
 }
 
 fn refract(v: vec3<f32>, n: vec3<f32>, ni_over_nt: f32, refract_direction: ptr<function, vec3<f32>>) -> bool {
@@ -276,7 +512,7 @@ fn refract(v: vec3<f32>, n: vec3<f32>, ni_over_nt: f32, refract_direction: ptr<f
 fn schlick(cosine: f32, refraction_index: f32) -> f32 {
     var r0 = (1f - refraction_index) / (1f + refraction_index);
     r0 = r0 * r0;
-    return r0 + pow((1f - r0) * (1f - cosine), 5f);
+    return r0 + (1f - r0) * pow(1f - cosine, 5f);
 }
 
 fn scatter_checkerboard(hit: Intersection, texture1: TextureDescriptor, texture2: TextureDescriptor, rng_state: ptr<function, u32>) -> Scatter {
@@ -335,24 +571,200 @@ struct Material {
     desc1: TextureDescriptor,
     desc2: TextureDescriptor,
     x: f32,
+    normal_map: TextureDescriptor,
+    // 0 is no normal map, 1 is a tangent space normal map and 2 is a height map.
+    normal_map_kind: u32,
+    normal_strength: f32,
+    // Blended materials of mix and layered materials.
+    material1: u32,
+    material2: u32,
+    y: f32,
+    // Beer-Lambert absorption coefficients of dielectrics.
+    absorption_r: f32,
+    absorption_g: f32,
+    absorption_b: f32,
+    // 0 is no thin film, otherwise in nanometers.
+    film_thickness: f32,
+    film_refraction_index: f32,
+}
+
+// Mix and layered materials may refer to each other, deeper ones are shown as missing.
+const MAX_MATERIAL_DEPTH: u32 = 8u;
+
+// Picks one of the materials blended by mix and layered materials with the probability of its weight.
+fn resolve_material(material_idx: u32, wo: Ray, hit: Intersection, rng_state: ptr<function, u32>) -> u32 {
+    var idx = material_idx;
+    for (var depth = 0u; depth < MAX_MATERIAL_DEPTH; depth += 1u) {
+        let material = materials[idx];
+        switch material.id {
+            case 5u: {
+                var weight = material.x;
+                if material.desc1.levels > 0u {
+                    weight = luminance(texture_lookup(material.desc1, hit));
+                }
+                idx = select(material.material1, material.material2, rng::next_float(rng_state) < weight);
+            }
+
+            case 6u: {
+                let cosine = abs(dot(normalize(wo.direction), hit.normal));
+                let weight = material.y * schlick(cosine, material.x);
+                idx = select(material.material1, material.material2, rng::next_float(rng_state) < weight);
+            }
+
+            default: {
+                return idx;
+            }
+        }
+    }
+
+    return idx;
+}
+
+// The normal of the hit perturbed by the normal map of the material.
+fn shading_normal(hit: Intersection, material: Material) -> vec3<f32> {
+    let tangent = hit.tangent;
+    // v grows from the top of the image to the bottom, like the rows.
+    let bitangent = cross(tangent, hit.normal);
+
+    switch material.normal_map_kind {
+        case 1u: {
+            let texel = 2f * texture_lookup(material.normal_map, hit) - 1f;
+            let local = vec3(material.normal_strength * texel.xy, max(texel.z, EPSILON));
+            return normalize(local.x * tangent - local.y * bitangent + local.z * hit.normal);
+        }
+
+        case 2u: {
+            // One texel of the image, or a small step for procedural textures which have no texels.
+            let du = select(1e-3f, 1f / f32(material.normal_map.width), material.normal_map.width > 0u);
+            let dv = select(1e-3f, 1f / f32(material.normal_map.height), material.normal_map.height > 0u);
+
+            var hit_u = hit;
+            hit_u.u += du;
+            var hit_v = hit;
+            hit_v.v += dv;
+
+            let height = luminance(texture_lookup(material.normal_map, hit));
+            let slope_u = (luminance(texture_lookup(material.normal_map, hit_u)) - height) / du;
+            let slope_v = (luminance(texture_lookup(material.normal_map, hit_v)) - height) / dv;
+
+            // Slopes are per unit of texture coordinates, which makes the strength independent of the resolution.
+            let scale = 0.01f * material.normal_strength;
+            return normalize(hit.normal - scale * (slope_u * tangent + slope_v * bitangent));
+        }
+
+        default: {
+            return hit.normal;
+        }
+    }
 }
 
 struct TextureDescriptor {
+    // 0 is an image, others are procedural patterns with parameters at `offset`.
+    kind: u32,
     width: u32,
     height: u32,
+    // Index of the first entry in `texture_info`.
     offset: u32,
+    // Number of mip levels, each with its atlas placement in the entries following `offset`.
+    levels: u32,
+    // 0 is nearest, 1 is bilinear.
+    filter_mode: u32,
+    // 0 is repeat, 1 is clamp, 2 is mirror.
+    wrap_mode: u32,
+    uv_scale_x: f32,
+    uv_scale_y: f32,
+    uv_offset_x: f32,
+    uv_offset_y: f32,
+}
+
+fn texture_lookup(desc: TextureDescriptor, hit: Intersection) -> vec3<f32> {
+    if desc.kind != 0u {
+        return procedural_lookup(desc, hit.point);
+    }
+
+    let uv_scale = vec2(desc.uv_scale_x, desc.uv_scale_y);
+    let uv = vec2(hit.u, hit.v) * uv_scale + vec2(desc.uv_offset_x, desc.uv_offset_y);
+
+    // The mip level whose texels are about as large as the ray cone.
+    let footprint = hit.uv_footprint * abs(uv_scale) * vec2(f32(desc.width), f32(desc.height));
+    let level = min(u32(max(log2(max(footprint.x, footprint.y)), 0f)), max(desc.levels, 1u) - 1u);
+
+    let placement = texture_info[desc.offset + level];
+    var size = vec2(desc.width, desc.height);
+    for (var i = 0u; i < level; i += 1u) {
+        size = max(size / 2u, vec2(1u));
+    }
+
+    // Image rows go from top to bottom.
+    let position = vec2(uv.x, 1f - uv.y) * vec2<f32>(size);
+    if desc.filter_mode == 1u {
+        let corner = floor(position - 0.5f);
+        let weight = position - 0.5f - corner;
+        let texel00 = vec2<i32>(corner);
+
+        let top = mix(
+            texel(placement, size, desc.wrap_mode, texel00),
+            texel(placement, size, desc.wrap_mode, texel00 + vec2(1, 0)),
+            weight.x
+        );
+        let bottom = mix(
+            texel(placement, size, desc.wrap_mode, texel00 + vec2(0, 1)),
+            texel(placement, size, desc.wrap_mode, texel00 + vec2(1, 1)),
+            weight.x
+        );
+        return mix(top, bottom, weight.y);
+    }
+
+    return texel(placement, size, desc.wrap_mode, vec2<i32>(floor(position)));
 }
 
-fn texture_lookup(desc: TextureDescriptor, arg_u: f32, arg_v: f32) -> vec3<f32> {
-    let u = clamp(arg_u, 0f, 1f);
-    let v = 1f - clamp(arg_v, 0f, 1f);
+// Procedural textures store both colors, then the scale and the pattern parameters.
+fn procedural_lookup(desc: TextureDescriptor, point: vec3<f32>) -> vec3<f32> {
+    let first = procedural_element(desc.offset);
+    let second = procedural_element(desc.offset + 1u);
+    let scale_params = procedural_element(desc.offset + 2u);
+    let params = procedural_element(desc.offset + 3u);
+
+    let t = procedural::pattern(
+        desc.kind,
+        scale_params.x * point,
+        array<f32, 5>(scale_params.y, scale_params.z, params.x, params.y, params.z)
+    );
+    return mix(first, second, t);
+}
 
-    let j = u32(u * f32(desc.width));
-    let i = u32(v * f32(desc.height));
-    let idx = i * desc.width + j;
+fn texel(placement: vec4<u32>, size: vec2<u32>, wrap: u32, position: vec2<i32>) -> vec3<f32> {
+    let x = u32(wrap_coordinate(position.x, i32(size.x), wrap));
+    let y = u32(wrap_coordinate(position.y, i32(size.y), wrap));
+    let coords = placement.yz + vec2(x, y);
 
-    let elem = textures[desc.offset + idx];
-    return vec3(elem[0u], elem[1u], elem[2u]);
+    // The sRGB atlas is decoded to linear values on load.
+    if placement.w == 1u {
+        return textureLoad(hdr_atlas, coords, placement.x, 0).rgb;
+    }
+    return textureLoad(ldr_atlas, coords, placement.x, 0).rgb;
+}
+
+fn procedural_element(idx: u32) -> vec3<f32> {
+    return bitcast<vec3<f32>>(texture_info[idx].xyz);
+}
+
+fn wrap_coordinate(coordinate: i32, size: i32, wrap: u32) -> i32 {
+    switch wrap {
+        case 0u: {
+            return ((coordinate % size) + size) % size;
+        }
+
+        case 2u: {
+            let period = 2 * size;
+            let mirrored = ((coordinate % period) + period) % period;
+            return select(mirrored, period - 1 - mirrored, mirrored >= size);
+        }
+
+        default: {
+            return clamp(coordinate, 0, size - 1);
+        }
+    }
 }
 
 struct Scatter {
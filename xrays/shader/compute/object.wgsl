@@ -4,6 +4,13 @@
 #import types::Ray
 
 @group(3) @binding(0) var<storage, read> spheres: array<Sphere>;
+// Scene buffers can be larger than the scene, so the number of elements comes from here.
+@group(3) @binding(4) var<uniform> scene_counts: SceneCounts;
+
+struct SceneCounts {
+    num_spheres: u32,
+    num_lights: u32,
+}
 
 struct Sphere {
     center_and_pad: vec4<f32>,
@@ -19,13 +26,17 @@ struct Intersection {
     t: f32,
     material_idx: u32,
     sphere_idx: u32,
+    // Width of the ray cone at the point in texture coordinates, selects the mip level of texture lookups.
+    uv_footprint: vec2<f32>,
+    // Direction in which u grows, together with the normal it makes the tangent frame of normal maps.
+    tangent: vec3<f32>,
 }
 
 fn intersection(ray: Ray, intersection: ptr<function, Intersection>) -> bool {
     var closest_t = MAX_T;
     var closest_intersection = Intersection();
 
-    for (var idx = 0u; idx < arrayLength(&spheres); idx = idx + 1u) {
+    for (var idx = 0u; idx < scene_counts.num_spheres; idx = idx + 1u) {
         var test_intersect = Intersection();
         if ray_intersect_sphere(ray, idx, MIN_T, closest_t, &test_intersect) {
             closest_t = test_intersect.t;
@@ -75,7 +86,22 @@ fn sphere_intersection(ray: Ray, sphere: Sphere, sphere_idx: u32, t: f32) -> Int
     let v = FRAC_1_PI * theta;
 
     // TODO: passing sphere_idx in here just to pass it to Intersection
-    return Intersection(p, n, u, v, t, sphere.material_idx, sphere_idx);
+    return Intersection(p, n, u, v, t, sphere.material_idx, sphere_idx, vec2(0f), sphere_tangent(n));
+}
+
+// The tangent frame is degenerate at the poles, any direction perpendicular to the normal works there.
+fn sphere_tangent(normal: vec3<f32>) -> vec3<f32> {
+    let tangent = cross(vec3(0f, 1f, 0f), normal);
+    if dot(tangent, tangent) < 1e-8f {
+        return vec3(1f, 0f, 0f);
+    }
+    return normalize(tangent);
+}
+
+// The u coordinate goes around the equator of a sphere and v goes from pole to pole.
+fn sphere_uv_footprint(hit: Intersection, cone_width: f32) -> vec2<f32> {
+    let radius = spheres[hit.sphere_idx].radius;
+    return cone_width / (PI * radius) * vec2(0.5f, 1f);
 }
 
 fn ray_point_at_parameter(ray: Ray, t: f32) -> vec3<f32> {
@@ -0,0 +1,107 @@
+#define_import_path procedural
+
+// Blend factor between the two colors of a procedural texture at the point, in 0..=1.
+// `params` holds the parameters of the pattern, see `Pattern::parameters`.
+fn pattern(kind: u32, point: vec3<f32>, params: array<f32, 5>) -> f32 {
+    switch kind {
+        case 1u: {
+            return fbm(point, u32(params[0]), params[1], params[2]);
+        }
+
+        case 2u: {
+            return voronoi(point, params[0]);
+        }
+
+        case 3u: {
+            let direction = vec3(params[0], params[1], params[2]);
+            return clamp(0.5f + 0.5f * dot(point, direction), 0f, 1f);
+        }
+
+        case 4u: {
+            return bricks(point, params[0], params[1]);
+        }
+
+        default: {
+            return checker(point);
+        }
+    }
+}
+
+fn hash(cell: vec3<i32>) -> u32 {
+    let seed = (bitcast<u32>(cell.x) * 73856093u) ^ (bitcast<u32>(cell.y) * 19349663u) ^ (bitcast<u32>(cell.z) * 83492791u);
+    // The same permutation as the PCG generator in `rng::next_int`.
+    let state = seed * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// A random point of the unit cube for the cell.
+fn random_vec3(cell: vec3<i32>) -> vec3<f32> {
+    let h = hash(cell);
+    return vec3(f32(h & 1023u), f32((h >> 10u) & 1023u), f32((h >> 20u) & 1023u)) / 1023f;
+}
+
+fn perlin_corner(cell: vec3<i32>, position: vec3<f32>, corner: vec3<i32>) -> f32 {
+    let gradient = 2f * random_vec3(cell + corner) - 1f;
+    return dot(gradient, position - vec3<f32>(corner));
+}
+
+// Gradient noise, roughly in -1..=1.
+fn perlin(point: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(point));
+    let f = fract(point);
+    let w = f * f * f * (f * (f * 6f - 15f) + 10f);
+
+    let x00 = mix(perlin_corner(cell, f, vec3(0, 0, 0)), perlin_corner(cell, f, vec3(1, 0, 0)), w.x);
+    let x10 = mix(perlin_corner(cell, f, vec3(0, 1, 0)), perlin_corner(cell, f, vec3(1, 1, 0)), w.x);
+    let x01 = mix(perlin_corner(cell, f, vec3(0, 0, 1)), perlin_corner(cell, f, vec3(1, 0, 1)), w.x);
+    let x11 = mix(perlin_corner(cell, f, vec3(0, 1, 1)), perlin_corner(cell, f, vec3(1, 1, 1)), w.x);
+
+    return mix(mix(x00, x10, w.y), mix(x01, x11, w.y), w.z);
+}
+
+fn fbm(point: vec3<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    var sum = 0f;
+    var amplitude = 1f;
+    var frequency = 1f;
+    var total_amplitude = 0f;
+
+    for (var i = 0u; i < clamp(octaves, 1u, 16u); i += 1u) {
+        sum += amplitude * perlin(point * frequency);
+        total_amplitude += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+
+    return clamp(0.5f + 0.5f * sum / max(total_amplitude, 1e-6f), 0f, 1f);
+}
+
+fn voronoi(point: vec3<f32>, jitter: f32) -> f32 {
+    let cell = vec3<i32>(floor(point));
+    var closest = 2f;
+
+    for (var x = -1; x <= 1; x += 1) {
+        for (var y = -1; y <= 1; y += 1) {
+            for (var z = -1; z <= 1; z += 1) {
+                let neighbor = cell + vec3(x, y, z);
+                let feature = vec3<f32>(neighbor) + 0.5f + jitter * (random_vec3(neighbor) - 0.5f);
+                closest = min(closest, distance(point, feature));
+            }
+        }
+    }
+
+    return clamp(closest, 0f, 1f);
+}
+
+// 1 on the mortar, 0 on the bricks.
+fn bricks(point: vec3<f32>, mortar: f32, row_offset: f32) -> f32 {
+    let row = floor(point.y);
+    let brick = vec3(0.5f * point.x + row * row_offset, point.y, 0.5f * point.z + row * row_offset);
+    let is_mortar = any(fract(brick) < vec3(0.5f * mortar, mortar, 0.5f * mortar));
+    return select(0f, 1f, is_mortar);
+}
+
+fn checker(point: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(point));
+    return f32((cell.x + cell.y + cell.z) & 1);
+}
@@ -0,0 +1,88 @@
+#define_import_path spectrum
+
+#import consts::PI
+
+// Range of sampled wavelengths in nanometers.
+const MIN_WAVELENGTH: f32 = 380f;
+const MAX_WAVELENGTH: f32 = 780f;
+
+// Representative wavelengths of the RGB channels, for paths which carry all of them.
+const RGB_WAVELENGTHS: vec3<f32> = vec3(650f, 532f, 450f);
+
+// Fraunhofer d, F and C lines used to define the Abbe number.
+const WAVELENGTH_D: f32 = 587.6f;
+const WAVELENGTH_F: f32 = 486.1f;
+const WAVELENGTH_C: f32 = 656.3f;
+
+fn sample_wavelength(u: f32) -> f32 {
+    return mix(MIN_WAVELENGTH, MAX_WAVELENGTH, u);
+}
+
+fn lobe(x: f32, mean: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let t = (x - mean) / select(sigma_high, sigma_low, x < mean);
+    return exp(-0.5f * t * t);
+}
+
+// Linear sRGB weight of a uniformly sampled wavelength, averages to white over the sampled range.
+// The CIE 1931 matching functions use the multi-lobe fit from "Simple Analytic Approximations to the CIE XYZ
+// Color Matching Functions" by Wyman et al.
+fn wavelength_to_rgb(wavelength: f32) -> vec3<f32> {
+    let x = 1.056f * lobe(wavelength, 599.8f, 37.9f, 31f) + 0.362f * lobe(wavelength, 442f, 16f, 26.7f)
+        - 0.065f * lobe(wavelength, 501.1f, 20.4f, 26.2f);
+    let y = 0.821f * lobe(wavelength, 568.8f, 46.9f, 40.5f) + 0.286f * lobe(wavelength, 530.9f, 16.3f, 31.1f);
+    let z = 1.217f * lobe(wavelength, 437f, 11.8f, 36f) + 0.681f * lobe(wavelength, 459f, 26f, 13.8f);
+
+    let rgb = vec3(
+        3.2406f * x - 1.5372f * y - 0.4986f * z,
+        -0.9689f * x + 1.8758f * y + 0.0415f * z,
+        0.0557f * x - 0.2040f * y + 1.0570f * z
+    );
+    // Inverse averages of the clamped weights over the sampled range.
+    return max(rgb, vec3(0f)) * vec3(2.27037f, 3.46661f, 3.65978f);
+}
+
+// Refraction index at the wavelength by the Cauchy equation `n = A + B / λ²`, fitted to the refraction index at the
+// d line and the Abbe number.
+fn cauchy_refraction_index(refraction_index: f32, abbe_number: f32, wavelength: f32) -> f32 {
+    let inv_sq = 1e6f / (wavelength * wavelength);
+    let inv_sq_f = 1e6f / (WAVELENGTH_F * WAVELENGTH_F);
+    let inv_sq_c = 1e6f / (WAVELENGTH_C * WAVELENGTH_C);
+    let inv_sq_d = 1e6f / (WAVELENGTH_D * WAVELENGTH_D);
+
+    let b = (refraction_index - 1f) / (abbe_number * (inv_sq_f - inv_sq_c));
+    return refraction_index + b * (inv_sq - inv_sq_d);
+}
+
+// Reflectance of a thin film between two media, averaged over both polarizations. Light reflected from both sides
+// of the film interferes, which makes the colors of soap bubbles and oil slicks.
+fn thin_film_reflectance(
+    cos_incident: f32,
+    outer_ior: f32,
+    film_ior: f32,
+    inner_ior: f32,
+    thickness: f32,
+    wavelength: f32
+) -> f32 {
+    let sin_sq_incident = 1f - cos_incident * cos_incident;
+    let sin_sq_film = sin_sq_incident * (outer_ior * outer_ior) / (film_ior * film_ior);
+    let sin_sq_inner = sin_sq_incident * (outer_ior * outer_ior) / (inner_ior * inner_ior);
+    if sin_sq_film >= 1f || sin_sq_inner >= 1f {
+        return 1f;
+    }
+    let cos_film = sqrt(1f - sin_sq_film);
+    let cos_inner = sqrt(1f - sin_sq_inner);
+
+    let phase = 4f * PI * film_ior * thickness * cos_film / wavelength;
+
+    let r12_s = (outer_ior * cos_incident - film_ior * cos_film) / (outer_ior * cos_incident + film_ior * cos_film);
+    let r23_s = (film_ior * cos_film - inner_ior * cos_inner) / (film_ior * cos_film + inner_ior * cos_inner);
+    let r12_p = (film_ior * cos_incident - outer_ior * cos_film) / (film_ior * cos_incident + outer_ior * cos_film);
+    let r23_p = (inner_ior * cos_film - film_ior * cos_inner) / (inner_ior * cos_film + film_ior * cos_inner);
+
+    return 0.5f * (airy_reflectance(r12_s, r23_s, phase) + airy_reflectance(r12_p, r23_p, phase));
+}
+
+fn airy_reflectance(r12: f32, r23: f32, phase: f32) -> f32 {
+    let interference = 2f * r12 * r23 * cos(phase);
+    return (r12 * r12 + r23 * r23 + interference) / (1f + r12 * r12 * r23 * r23 + interference);
+}
@@ -0,0 +1,103 @@
+#import sampling::PixelStats
+
+// One iteration of the edge-avoiding à-trous wavelet filter (Dammertz et al. 2010).
+// Must be kept in sync with `denoise::denoise_iteration` on the CPU side.
+
+struct DenoiseParams {
+    image_width: u32,
+    image_height: u32,
+    step_width: u32,
+    read_image: u32,
+    color_phi: f32,
+    normal_phi: f32,
+    albedo_phi: f32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: DenoiseParams;
+
+// Ping-pong buffers, swapped between the iterations.
+@group(1) @binding(1) var<storage, read> src_buffer: array<array<f32, 3>>;
+@group(1) @binding(2) var<storage, read_write> dst_buffer: array<array<f32, 3>>;
+
+@group(2) @binding(0) var<storage, read> image_buffer: array<array<f32, 3>>;
+@group(2) @binding(1) var<storage, read> pixel_stats: array<PixelStats>;
+@group(2) @binding(2) var<storage, read> feature_buffer: array<array<f32, 6>>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let image_width = params.image_width;
+    let image_height = params.image_height;
+
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if (x >= image_width || y >= image_height) {
+        return;
+    }
+    let idx = image_width * y + x;
+
+    let color_p = color(idx);
+    let albedo_p = albedo(idx);
+    let normal_p = normal(idx);
+
+    var color_sum = vec3(0f);
+    var weight_sum = 0f;
+    for (var j = -2; j <= 2; j += 1) {
+        for (var i = -2; i <= 2; i += 1) {
+            let qx = clamp(i32(x) + i * i32(params.step_width), 0, i32(image_width) - 1);
+            let qy = clamp(i32(y) + j * i32(params.step_width), 0, i32(image_height) - 1);
+            let q = image_width * u32(qy) + u32(qx);
+
+            let color_q = color(q);
+            let color_diff = color_p - color_q;
+            let albedo_diff = albedo_p - albedo(q);
+            let normal_diff = normal_p - normal(q);
+
+            let weight = kernel_weight(i) * kernel_weight(j)
+                * exp(-dot(color_diff, color_diff) / params.color_phi)
+                * exp(-dot(albedo_diff, albedo_diff) / params.albedo_phi)
+                * exp(-dot(normal_diff, normal_diff) / params.normal_phi);
+
+            color_sum += weight * color_q;
+            weight_sum += weight;
+        }
+    }
+
+    let result = color_sum / weight_sum;
+    dst_buffer[idx] = array<f32, 3>(result.r, result.g, result.b);
+}
+
+// B3 spline coefficients: 1/16, 1/4, 3/8, 1/4, 1/16.
+fn kernel_weight(offset: i32) -> f32 {
+    switch abs(offset) {
+        case 0: {
+            return 3f / 8f;
+        }
+        case 1: {
+            return 1f / 4f;
+        }
+        default: {
+            return 1f / 16f;
+        }
+    }
+}
+
+fn inv_num_samples(idx: u32) -> f32 {
+    return 1f / f32(max(pixel_stats[idx].num_samples, 1u));
+}
+
+fn color(idx: u32) -> vec3<f32> {
+    if params.read_image == 1u {
+        return inv_num_samples(idx) * vec3(image_buffer[idx][0], image_buffer[idx][1], image_buffer[idx][2]);
+    }
+    return vec3(src_buffer[idx][0], src_buffer[idx][1], src_buffer[idx][2]);
+}
+
+fn albedo(idx: u32) -> vec3<f32> {
+    return inv_num_samples(idx) * vec3(feature_buffer[idx][0], feature_buffer[idx][1], feature_buffer[idx][2]);
+}
+
+fn normal(idx: u32) -> vec3<f32> {
+    return inv_num_samples(idx) * vec3(feature_buffer[idx][3], feature_buffer[idx][4], feature_buffer[idx][5]);
+}
@@ -1,4 +1,4 @@
-#import sampling::SamplingParams
+#import sampling::{SamplingParams, PixelStats}
 #import tonemap
 
 @group(0) @binding(0) var<uniform> vertex_uniforms: VertexUniforms;
@@ -28,6 +28,20 @@ fn vs_main(model: VertexInput) -> VertexOutput {
 
 @group(1) @binding(0) var<uniform> frame_data: vec4<u32>;
 @group(1) @binding(1) var<storage, read_write> image_buffer: array<array<f32, 3>>;
+@group(1) @binding(2) var<storage, read_write> pixel_stats: array<PixelStats>;
+@group(1) @binding(3) var<storage, read_write> feature_buffer: array<array<f32, 6>>;
+@group(1) @binding(4) var<storage, read> denoised_buffer: array<array<f32, 3>>;
+@group(1) @binding(5) var<storage, read_write> aov_buffer: array<array<f32, 9>>;
+
+// Must be kept in sync with `RenderPass`.
+const PASS_BEAUTY = 0u;
+const PASS_DEPTH = 1u;
+const PASS_NORMAL = 2u;
+const PASS_ALBEDO = 3u;
+const PASS_MATERIAL_INDEX = 4u;
+const PASS_OBJECT_INDEX = 5u;
+const PASS_DIRECT = 6u;
+const PASS_INDIRECT = 7u;
 
 @group(2) @binding(0) var<uniform> sampling_params: SamplingParams;
 
@@ -43,11 +57,74 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let y = min(u32(v * f32(image_height)), image_height - 1u);
     let idx = image_width * y + x;
 
-    let inv_n = 1f / f32(sampling_params.accumulated_samples_per_pixel);
-    let pixel = vec3(image_buffer[idx][0], image_buffer[idx][1], image_buffer[idx][2]);
+    let inv_n = 1f / f32(max(pixel_stats[idx].num_samples, 1u));
+    let display_pass = frame_data.w;
+    let has_aovs = sampling_params.aovs == 1u;
 
-    return vec4(
-        tonemap::uncharted2(inv_n * pixel),
-        1f
-    );
+    switch display_pass {
+        case PASS_NORMAL: {
+            let normal = inv_n * vec3(feature_buffer[idx][3], feature_buffer[idx][4], feature_buffer[idx][5]);
+            return vec4(0.5f * normal + 0.5f, 1f);
+        }
+
+        case PASS_ALBEDO: {
+            return vec4(inv_n * vec3(feature_buffer[idx][0], feature_buffer[idx][1], feature_buffer[idx][2]), 1f);
+        }
+
+        case PASS_DEPTH, PASS_MATERIAL_INDEX, PASS_OBJECT_INDEX, PASS_DIRECT, PASS_INDIRECT: {
+            if !has_aovs {
+                return vec4(0f, 0f, 0f, 1f);
+            }
+            return vec4(display_aov(idx, display_pass, inv_n), 1f);
+        }
+
+        default: {
+            var pixel = vec3(0f);
+            if sampling_params.denoise == 1u {
+                pixel = vec3(denoised_buffer[idx][0], denoised_buffer[idx][1], denoised_buffer[idx][2]);
+            } else {
+                pixel = inv_n * vec3(image_buffer[idx][0], image_buffer[idx][1], image_buffer[idx][2]);
+            }
+
+            return vec4(
+                tonemap::uncharted2(pixel),
+                1f
+            );
+        }
+    }
+}
+
+fn display_aov(idx: u32, display_pass: u32, inv_n: f32) -> vec3<f32> {
+    let aov = aov_buffer[idx];
+    switch display_pass {
+        case PASS_DEPTH: {
+            return vec3(1f / (1f + 0.1f * aov[0]));
+        }
+
+        case PASS_MATERIAL_INDEX: {
+            return index_color(aov[1]);
+        }
+
+        case PASS_OBJECT_INDEX: {
+            return index_color(aov[2]);
+        }
+
+        case PASS_DIRECT: {
+            return tonemap::uncharted2(inv_n * vec3(aov[3], aov[4], aov[5]));
+        }
+
+        default: {
+            return tonemap::uncharted2(inv_n * vec3(aov[6], aov[7], aov[8]));
+        }
+    }
+}
+
+// Gives every index a distinct color, negative indices mean that nothing was hit.
+fn index_color(index: f32) -> vec3<f32> {
+    if index < 0f {
+        return vec3(0f);
+    }
+
+    let hash = (u32(index) + 1u) * 2654435761u;
+    return vec3(f32(hash & 0xffu), f32((hash >> 8u) & 0xffu), f32((hash >> 16u) & 0xffu)) / 255f;
 }
@@ -5,4 +5,16 @@ struct SamplingParams {
     num_bounces: u32,
     accumulated_samples_per_pixel: u32,
     clear_accumulated_samples: u32,
+    min_samples_per_pixel: u32,
+    adaptive_threshold: f32,
+    denoise: u32,
+    aovs: u32,
+}
+
+// Running luminance statistics of a pixel, used to stop sampling converged pixels.
+struct PixelStats {
+    luminance_sum: f32,
+    luminance_sq_sum: f32,
+    num_samples: u32,
+    converged: u32,
 }
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+
+use image::Rgb32FImage;
+use reactor_types::rect::RectSize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tile::PixelRect;
+
+/// An image the renderer produces next to the beauty image. Passes which are not needed by the denoiser
+/// are only rendered when the AOV buffer is allocated.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RenderPass {
+    #[default]
+    Beauty = 0,
+    Depth,
+    Normal,
+    Albedo,
+    MaterialIndex,
+    ObjectIndex,
+    Direct,
+    Indirect,
+}
+
+impl RenderPass {
+    pub const ALL: [Self; 8] = [
+        Self::Beauty,
+        Self::Depth,
+        Self::Normal,
+        Self::Albedo,
+        Self::MaterialIndex,
+        Self::ObjectIndex,
+        Self::Direct,
+        Self::Indirect,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Beauty => "Beauty",
+            Self::Depth => "Depth",
+            Self::Normal => "Normal",
+            Self::Albedo => "Albedo",
+            Self::MaterialIndex => "Material Index",
+            Self::ObjectIndex => "Object Index",
+            Self::Direct => "Direct Lighting",
+            Self::Indirect => "Indirect Lighting",
+        }
+    }
+
+    pub fn file_stem(self) -> &'static str {
+        match self {
+            Self::Beauty => "beauty",
+            Self::Depth => "depth",
+            Self::Normal => "normal",
+            Self::Albedo => "albedo",
+            Self::MaterialIndex => "material_index",
+            Self::ObjectIndex => "object_index",
+            Self::Direct => "direct",
+            Self::Indirect => "indirect",
+        }
+    }
+
+    pub fn needs_aov_buffer(self) -> bool {
+        matches!(
+            self,
+            Self::Depth | Self::MaterialIndex | Self::ObjectIndex | Self::Direct | Self::Indirect
+        )
+    }
+}
+
+/// Passes read back from the GPU. Pixels are stored row by row, starting from the top row.
+/// Single channel passes repeat their value in all three channels; the index passes store -1 where nothing was hit.
+pub struct RenderedPasses {
+    pub size: RectSize<u32>,
+    pub passes: Vec<(RenderPass, Vec<[f32; 3]>)>,
+}
+
+impl RenderedPasses {
+    /// Creates an image without passes, which are added with [`RenderedPasses::insert_tile`].
+    pub fn new(size: RectSize<u32>) -> Self {
+        Self {
+            size,
+            passes: Vec::new(),
+        }
+    }
+
+    /// Copies the passes of a tile into the image. Passes missing from the image are created filled with zeros.
+    pub fn insert_tile(&mut self, tile: PixelRect, tile_passes: &RenderedPasses) {
+        let num_pixels = self.size.width as usize * self.size.height as usize;
+        for (pass, tile_pixels) in &tile_passes.passes {
+            let pixels = match self.passes.iter().position(|(rendered_pass, _)| rendered_pass == pass) {
+                Some(idx) => &mut self.passes[idx].1,
+                None => {
+                    self.passes.push((*pass, vec![[0.0; 3]; num_pixels]));
+                    &mut self.passes.last_mut().unwrap().1
+                },
+            };
+
+            for (row, tile_row) in tile_pixels.chunks_exact(tile.width as usize).enumerate() {
+                let start = (tile.y as usize + row) * self.size.width as usize + tile.x as usize;
+                pixels[start..start + tile_row.len()].copy_from_slice(tile_row);
+            }
+        }
+    }
+
+    pub fn get(&self, pass: RenderPass) -> Option<&[[f32; 3]]> {
+        self.passes
+            .iter()
+            .find(|(rendered_pass, _)| *rendered_pass == pass)
+            .map(|(_, pixels)| pixels.as_slice())
+    }
+
+    /// Writes every pass into the directory as a linear OpenEXR image named after the pass.
+    pub fn save(&self, directory: &Path) -> Result<(), ExportError> {
+        self.save_named(directory, |pass| pass.file_stem().to_owned())
+    }
+
+    /// Writes every pass into the directory as a linear OpenEXR image with the file stem given for the pass.
+    pub fn save_named(&self, directory: &Path, file_stem: impl Fn(RenderPass) -> String) -> Result<(), ExportError> {
+        fs::create_dir_all(directory)?;
+
+        for (pass, pixels) in &self.passes {
+            let image = Rgb32FImage::from_raw(
+                self.size.width,
+                self.size.height,
+                pixels.iter().flatten().copied().collect(),
+            )
+            .expect("Pass must have a pixel for every point of the image");
+            image.save(directory.join(format!("{}.exr", file_stem(*pass))))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("failed to read the image from the GPU: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_tile_places_rows() {
+        let mut image = RenderedPasses::new(RectSize { width: 4, height: 3 });
+        let tile = PixelRect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+        let tile_passes = RenderedPasses {
+            size: tile.size(),
+            passes: vec![(RenderPass::Beauty, vec![[1.0; 3], [2.0; 3], [3.0; 3], [4.0; 3]])],
+        };
+        image.insert_tile(tile, &tile_passes);
+
+        let beauty = image.get(RenderPass::Beauty).unwrap();
+        let red = beauty.iter().map(|pixel| pixel[0]).collect::<Vec<_>>();
+        assert_eq!(red, [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 0.0, 3.0, 4.0, 0.0]);
+    }
+}
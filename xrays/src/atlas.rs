@@ -0,0 +1,311 @@
+use crate::Texture;
+
+/// Smallest side of an atlas layer, small textures such as solid colors share a layer.
+pub const MIN_LAYER_SIZE: u32 = 256;
+
+/// Texel format of an atlas, both take 4 bytes per texel instead of 12 bytes of three floats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtlasFormat {
+    /// 8 bit sRGB encoded colors, for textures with all values in `0..=1`.
+    Ldr,
+    /// Floats with a shared exponent, for textures with values above 1.
+    Hdr,
+}
+
+impl AtlasFormat {
+    pub const ALL: [Self; 2] = [Self::Ldr, Self::Hdr];
+
+    pub fn for_texture(texture: &Texture) -> Self {
+        let is_ldr = texture
+            .as_slice()
+            .iter()
+            .flatten()
+            .all(|value| (0.0..=1.0).contains(value));
+        if is_ldr { Self::Ldr } else { Self::Hdr }
+    }
+
+    /// Same as the `atlas` of texture placements in the compute shader.
+    pub fn index(self) -> usize {
+        match self {
+            Self::Ldr => 0,
+            Self::Hdr => 1,
+        }
+    }
+
+    fn texture_format(self) -> wgpu::TextureFormat {
+        match self {
+            Self::Ldr => wgpu::TextureFormat::Rgba8UnormSrgb,
+            Self::Hdr => wgpu::TextureFormat::Rgb9e5Ufloat,
+        }
+    }
+
+    fn encode(self, texture: &Texture) -> Vec<u8> {
+        match self {
+            Self::Ldr => texture
+                .as_slice()
+                .iter()
+                .flat_map(|&[r, g, b]| [linear_to_srgb8(r), linear_to_srgb8(g), linear_to_srgb8(b), u8::MAX])
+                .collect(),
+            Self::Hdr => texture
+                .as_slice()
+                .iter()
+                .flat_map(|&rgb| pack_rgb9e5(rgb).to_le_bytes())
+                .collect(),
+        }
+    }
+}
+
+pub fn linear_to_srgb8(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (255.0 * srgb).round() as u8
+}
+
+/// Packs a color into the `Rgb9e5Ufloat` format: three 9 bit mantissas and a shared 5 bit exponent.
+/// Negative values become 0 and values above the largest representable one are clamped.
+pub fn pack_rgb9e5(rgb: [f32; 3]) -> u32 {
+    const MANTISSA_BITS: i32 = 9;
+    const EXPONENT_BIAS: i32 = 15;
+    const MAX_EXPONENT: i32 = 31;
+    // (2^9 - 1) / 2^9 * 2^(31 - 15)
+    const MAX_VALUE: f32 = 65408.0;
+
+    // NaNs become 0 as well.
+    let [r, g, b] = rgb.map(|channel| if channel > 0.0 { channel.min(MAX_VALUE) } else { 0.0 });
+    let max_channel = r.max(g).max(b);
+
+    let mut exponent = (max_channel.log2().floor() as i32).max(-EXPONENT_BIAS - 1) + 1 + EXPONENT_BIAS;
+    let scale = |exponent: i32| 2f32.powi(exponent - EXPONENT_BIAS - MANTISSA_BITS);
+    if (max_channel / scale(exponent) + 0.5).floor() as i32 == 1 << MANTISSA_BITS {
+        exponent += 1;
+    }
+    let exponent = exponent.min(MAX_EXPONENT);
+
+    let mantissa = |channel: f32| ((channel / scale(exponent) + 0.5).floor() as u32).min(511);
+    mantissa(r) | mantissa(g) << 9 | mantissa(b) << 18 | (exponent as u32) << 27
+}
+
+/// Position of a texture in an atlas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AtlasPlacement {
+    pub layer: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Places rectangles into square layers row by row, the tallest rectangles first. Each row ("shelf") is as high
+/// as its first rectangle. Returns the placements in the order of `sizes` and the number of used layers.
+/// Every rectangle must fit into a layer.
+pub fn pack_shelves(sizes: &[(u32, u32)], layer_size: u32) -> (Vec<AtlasPlacement>, u32) {
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| std::cmp::Reverse(sizes[idx].1));
+
+    let mut placements = vec![AtlasPlacement::default(); sizes.len()];
+    let mut layer = 0;
+    let (mut cursor_x, mut shelf_y, mut shelf_height) = (0, 0, 0);
+
+    for idx in order {
+        let (width, height) = sizes[idx];
+        debug_assert!(width <= layer_size && height <= layer_size);
+
+        if cursor_x + width > layer_size {
+            shelf_y += shelf_height;
+            cursor_x = 0;
+            shelf_height = 0;
+        }
+        if shelf_y + height > layer_size {
+            layer += 1;
+            shelf_y = 0;
+            cursor_x = 0;
+            shelf_height = 0;
+        }
+
+        placements[idx] = AtlasPlacement {
+            layer,
+            x: cursor_x,
+            y: shelf_y,
+        };
+        cursor_x += width;
+        shelf_height = shelf_height.max(height);
+    }
+
+    let layers = if sizes.is_empty() { 0 } else { layer + 1 };
+    (placements, layers)
+}
+
+/// A 2D texture array holding many textures of one format, read by the compute shader with `textureLoad`.
+pub struct TextureAtlas {
+    format: AtlasFormat,
+    layer_size: u32,
+    layers: u32,
+    binding_idx: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl TextureAtlas {
+    pub fn new(device: &wgpu::Device, format: AtlasFormat, layer_size: u32, layers: u32, binding_idx: u32) -> Self {
+        // Texture arrays can not be empty.
+        let layers = layers.max(1);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture atlas"),
+            size: wgpu::Extent3d {
+                width: layer_size,
+                height: layer_size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: format.texture_format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("texture atlas view"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        Self {
+            format,
+            layer_size,
+            layers,
+            binding_idx,
+            texture,
+            view,
+        }
+    }
+
+    /// Whether the atlas can be reused for textures packed into layers of the size.
+    pub fn fits(&self, layer_size: u32, layers: u32) -> bool {
+        self.layer_size == layer_size && self.layers >= layers
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, placement: AtlasPlacement, texture: &Texture) {
+        let (width, height) = texture.dimensions();
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: placement.x,
+                    y: placement.y,
+                    z: placement.layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.format.encode(texture),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn layout(&self, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: self.binding_idx,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        }
+    }
+
+    pub fn binding(&self) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding: self.binding_idx,
+            resource: wgpu::BindingResource::TextureView(&self.view),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack_rgb9e5(packed: u32) -> [f32; 3] {
+        let scale = 2f32.powi((packed >> 27) as i32 - 15 - 9);
+        [0, 9, 18].map(|shift| ((packed >> shift) & 511) as f32 * scale)
+    }
+
+    #[test]
+    fn test_rgb9e5_round_trip() {
+        for rgb in [[0.5, 0.25, 1.0], [50.0, 0.0, 0.1], [1000.0, 3.0, 1.5], [
+            0.001, 0.002, 0.003,
+        ]] {
+            let unpacked = unpack_rgb9e5(pack_rgb9e5(rgb));
+            let max_channel = rgb.iter().copied().fold(0.0, f32::max);
+            for (original, unpacked) in rgb.iter().zip(unpacked) {
+                // Channels share the precision of the largest one.
+                assert!(
+                    (original - unpacked).abs() <= max_channel / 256.0,
+                    "{rgb:?} became {unpacked:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rgb9e5_clamps_out_of_range_values() {
+        assert_eq!(unpack_rgb9e5(pack_rgb9e5([0.0, 0.0, 0.0])), [0.0, 0.0, 0.0]);
+        assert_eq!(unpack_rgb9e5(pack_rgb9e5([-1.0, f32::NAN, 1e9])), [0.0, 0.0, 65408.0]);
+    }
+
+    #[test]
+    fn test_srgb8_encoding() {
+        assert_eq!(linear_to_srgb8(0.0), 0);
+        assert_eq!(linear_to_srgb8(1.0), 255);
+        assert_eq!(linear_to_srgb8(0.216), 128);
+    }
+
+    #[test]
+    fn test_shelves_do_not_overlap() {
+        let sizes = [
+            (100, 60),
+            (200, 120),
+            (56, 56),
+            (256, 10),
+            (1, 1),
+            (128, 128),
+            (30, 200),
+        ];
+        let (placements, layers) = pack_shelves(&sizes, 256);
+
+        let mut coverage = vec![0; (layers * 256 * 256) as usize];
+        for (placement, (width, height)) in placements.iter().zip(sizes) {
+            assert!(placement.x + width <= 256 && placement.y + height <= 256);
+            for y in placement.y..placement.y + height {
+                for x in placement.x..placement.x + width {
+                    coverage[(placement.layer * 256 * 256 + y * 256 + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(coverage.iter().all(|&count| count <= 1));
+    }
+
+    #[test]
+    fn test_full_layers_start_new_ones() {
+        let (placements, layers) = pack_shelves(&[(256, 256), (256, 256), (10, 10)], 256);
+        assert_eq!(layers, 3);
+        assert_eq!(
+            placements.iter().map(|placement| placement.layer).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(pack_shelves(&[], 256), (vec![], 0));
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use wgpu::util::DeviceExt;
 
 pub struct UniformBuffer {
@@ -64,7 +66,7 @@ impl StorageBuffer {
         let bytes = if bytes.is_empty() { &EMPTY } else { bytes };
         let handle = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             contents: bytes,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
             label,
         });
 
@@ -95,3 +97,111 @@ impl StorageBuffer {
         }
     }
 }
+
+/// A storage buffer which keeps a copy of its elements to upload only the changed ones.
+/// It grows when the elements outgrow its capacity, so the number of elements has to be passed to shaders separately.
+pub struct GrowableStorageBuffer<T> {
+    buffer: StorageBuffer,
+    elements: Vec<T>,
+    capacity: usize,
+    binding_idx: u32,
+    label: Option<&'static str>,
+}
+
+impl<T: bytemuck::NoUninit> GrowableStorageBuffer<T> {
+    pub fn new(device: &wgpu::Device, elements: Vec<T>, binding_idx: u32, label: Option<&'static str>) -> Self {
+        let capacity = elements.len().next_power_of_two();
+        let buffer = Self::allocate(device, &elements, capacity, binding_idx, label);
+
+        Self {
+            buffer,
+            elements,
+            capacity,
+            binding_idx,
+            label,
+        }
+    }
+
+    fn allocate(
+        device: &wgpu::Device,
+        elements: &[T],
+        capacity: usize,
+        binding_idx: u32,
+        label: Option<&str>,
+    ) -> StorageBuffer {
+        let mut bytes = bytemuck::cast_slice::<T, u8>(elements).to_vec();
+        bytes.resize(capacity * size_of::<T>(), 0);
+        StorageBuffer::new_from_bytes(device, &bytes, binding_idx, label)
+    }
+
+    /// Uploads the elements which differ from the previous ones.
+    /// Returns `true` if the buffer was reallocated and the bind groups using it have to be recreated.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, elements: Vec<T>) -> bool {
+        let reallocated = if elements.len() > self.capacity {
+            self.capacity = elements.len().next_power_of_two();
+            self.buffer = Self::allocate(device, &elements, self.capacity, self.binding_idx, self.label);
+            true
+        } else {
+            for range in changed_ranges(&self.elements, &elements) {
+                let offset = (range.start * size_of::<T>()) as wgpu::BufferAddress;
+                queue.write_buffer(self.buffer.handle(), offset, bytemuck::cast_slice(&elements[range]));
+            }
+            false
+        };
+
+        self.elements = elements;
+        reallocated
+    }
+
+    pub fn elements(&self) -> &[T] {
+        &self.elements
+    }
+
+    pub fn buffer(&self) -> &StorageBuffer {
+        &self.buffer
+    }
+}
+
+/// Returns the ranges of `new` elements which differ from `old`, merging adjacent changed elements.
+pub fn changed_ranges<T: bytemuck::NoUninit>(old: &[T], new: &[T]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    for (idx, element) in new.iter().enumerate() {
+        let changed = old
+            .get(idx)
+            .is_none_or(|old_element| bytemuck::bytes_of(old_element) != bytemuck::bytes_of(element));
+
+        if changed {
+            match ranges.last_mut() {
+                Some(range) if range.end == idx => range.end += 1,
+                _ => ranges.push(idx..idx + 1),
+            }
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_ranges_of_equal_slices_are_empty() {
+        assert!(changed_ranges(&[1_u32, 2, 3], &[1, 2, 3]).is_empty());
+    }
+
+    #[test]
+    fn test_changed_ranges_merge_adjacent_elements() {
+        let old = [0_u32, 1, 2, 3, 4, 5];
+        let new = [0_u32, 9, 9, 3, 9, 5];
+        assert_eq!(changed_ranges(&old, &new), vec![1..3, 4..5]);
+    }
+
+    #[test]
+    fn test_changed_ranges_include_appended_elements() {
+        let old = [0_u32, 1];
+        let new = [0_u32, 7, 2, 3];
+        assert_eq!(changed_ranges(&old, &new), vec![1..4]);
+    }
+}
@@ -1,7 +1,7 @@
 use reactor_types::rect::RectSize;
 use serde::{Deserialize, Serialize};
 
-use crate::{Angle, Vector3};
+use crate::{Angle, Ray, Vector3};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Camera {
@@ -16,6 +16,37 @@ pub struct Camera {
     pub focus_distance: f32,
 }
 
+impl Camera {
+    /// Makes a ray through the viewport point `(u, v)`, where both coordinates are in `0..=1` and `v` grows upwards.
+    /// The lens is treated as a pinhole, so the ray always starts at the eye position.
+    pub fn make_ray(&self, viewport_size: RectSize<u32>, u: f32, v: f32) -> Ray {
+        let camera = GpuCamera::new(self, viewport_size);
+        let direction = camera.lower_left_corner + u * camera.horizontal + v * camera.vertical - camera.eye;
+
+        Ray::new(camera.eye, direction)
+    }
+
+    /// Projects a world point onto the viewport, the inverse of [`Camera::make_ray`]. Returns `None` for points
+    /// behind the camera.
+    pub fn project(&self, viewport_size: RectSize<u32>, point: Vector3) -> Option<(f32, f32)> {
+        let camera = GpuCamera::new(self, viewport_size);
+        let forward = self.eye_dir.normalize();
+        let direction = point - camera.eye;
+
+        let depth = direction.dot(&forward);
+        if depth <= f32::EPSILON {
+            return None;
+        }
+
+        let on_focus_plane = camera.eye + direction * (self.focus_distance / depth);
+        let from_corner = on_focus_plane - camera.lower_left_corner;
+        let u = from_corner.dot(&camera.horizontal) / camera.horizontal.norm_squared();
+        let v = from_corner.dot(&camera.vertical) / camera.vertical.norm_squared();
+
+        Some((u, v))
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GpuCamera {
@@ -0,0 +1,439 @@
+use reactor_types::rect::RectSize;
+use serde::{Deserialize, Serialize};
+
+use crate::Vector3;
+use crate::buffer::{StorageBuffer, UniformBuffer};
+
+/// Bind groups of the denoise pipeline, the indices are the `@group` numbers of the shader.
+const PARAMETER_GROUP: u32 = 0;
+const SWAP_GROUP: u32 = 1;
+const INPUT_GROUP: u32 = 2;
+
+/// Every iteration doubles the step width of the filter, so five iterations cover a 65x65 pixels footprint.
+pub const MAX_DENOISE_ITERATIONS: u32 = 5;
+
+/// Parameters of the edge-avoiding à-trous wavelet filter.
+/// The `*_phi` values control how much a difference in the corresponding buffer is tolerated before
+/// neighbouring pixels stop contributing to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DenoiseParams {
+    pub iterations: u32,
+    /// Color tolerance of the first iteration. It is halved with every next iteration.
+    pub color_phi: f32,
+    pub normal_phi: f32,
+    pub albedo_phi: f32,
+}
+
+impl Default for DenoiseParams {
+    fn default() -> Self {
+        Self {
+            iterations: MAX_DENOISE_ITERATIONS,
+            color_phi: 0.5,
+            normal_phi: 0.1,
+            albedo_phi: 0.1,
+        }
+    }
+}
+
+impl DenoiseParams {
+    fn iteration(&self, image_size: RectSize<u32>, iteration: u32) -> GpuDenoiseParams {
+        let step_width = 1 << iteration;
+        GpuDenoiseParams {
+            image_width: image_size.width,
+            image_height: image_size.height,
+            step_width,
+            read_image: (iteration == 0) as u32,
+            color_phi: self.color_phi / step_width as f32,
+            normal_phi: self.normal_phi,
+            albedo_phi: self.albedo_phi,
+            _padding: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuDenoiseParams {
+    image_width: u32,
+    image_height: u32,
+    step_width: u32,
+    read_image: u32,
+    color_phi: f32,
+    normal_phi: f32,
+    albedo_phi: f32,
+    _padding: u32,
+}
+
+/// Averaged radiance and first bounce features of an image, stored row by row.
+pub struct DenoiseInput<'a> {
+    pub size: RectSize<u32>,
+    pub color: &'a [[f32; 3]],
+    pub albedo: &'a [[f32; 3]],
+    pub normal: &'a [[f32; 3]],
+}
+
+/// Filters the image on the CPU with the same filter the GPU denoise pass uses.
+pub fn denoise(input: &DenoiseInput, params: &DenoiseParams) -> Vec<[f32; 3]> {
+    let mut color = input.color.to_vec();
+    for iteration in 0..params.iterations {
+        color = denoise_iteration(input, &color, &params.iteration(input.size, iteration));
+    }
+    color
+}
+
+fn denoise_iteration(input: &DenoiseInput, color: &[[f32; 3]], params: &GpuDenoiseParams) -> Vec<[f32; 3]> {
+    // B3 spline coefficients, indexed by the distance from the center.
+    const KERNEL: [f32; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+    let width = params.image_width as i32;
+    let height = params.image_height as i32;
+    let step_width = params.step_width as i32;
+    let mut output = Vec::with_capacity(color.len());
+
+    for y in 0..height {
+        for x in 0..width {
+            let p = (width * y + x) as usize;
+            let color_p = Vector3::from(color[p]);
+            let albedo_p = Vector3::from(input.albedo[p]);
+            let normal_p = Vector3::from(input.normal[p]);
+
+            let mut color_sum = Vector3::zeros();
+            let mut weight_sum = 0.0;
+            for j in -2..=2_i32 {
+                for i in -2..=2_i32 {
+                    let qx = (x + i * step_width).clamp(0, width - 1);
+                    let qy = (y + j * step_width).clamp(0, height - 1);
+                    let q = (width * qy + qx) as usize;
+
+                    let color_q = Vector3::from(color[q]);
+                    let color_diff = color_p - color_q;
+                    let albedo_diff = albedo_p - Vector3::from(input.albedo[q]);
+                    let normal_diff = normal_p - Vector3::from(input.normal[q]);
+
+                    let weight = KERNEL[i.unsigned_abs() as usize]
+                        * KERNEL[j.unsigned_abs() as usize]
+                        * (-color_diff.norm_squared() / params.color_phi).exp()
+                        * (-albedo_diff.norm_squared() / params.albedo_phi).exp()
+                        * (-normal_diff.norm_squared() / params.normal_phi).exp();
+
+                    color_sum += weight * color_q;
+                    weight_sum += weight;
+                }
+            }
+
+            output.push((color_sum / weight_sum).into());
+        }
+    }
+
+    output
+}
+
+/// GPU resources of the denoise pass. The result is always written into the denoised buffer,
+/// which is bound to the image group for the render pipeline.
+pub struct Denoiser {
+    pipeline: wgpu::ComputePipeline,
+    parameter_buffers: Vec<UniformBuffer>,
+    parameter_bind_groups: Vec<wgpu::BindGroup>,
+    /// Ping-pong bind groups: the first one writes into the denoised buffer, the second one into the scratch buffer.
+    swap_bind_groups: [wgpu::BindGroup; 2],
+    input_bind_group: wgpu::BindGroup,
+    denoised_buffer: StorageBuffer,
+}
+
+impl Denoiser {
+    pub fn new(
+        device: &wgpu::Device,
+        image_buffer: &StorageBuffer,
+        pixel_stats_buffer: &StorageBuffer,
+        feature_buffer: &StorageBuffer,
+        denoised_binding_idx: u32,
+        max_viewport_resolution: u32,
+    ) -> Self {
+        let zero_image = vec![[0.0_f32; 3]; max_viewport_resolution as usize];
+        let denoised_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(zero_image.as_slice()),
+            denoised_binding_idx,
+            Some("denoised buffer"),
+        );
+        let scratch_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(zero_image.as_slice()),
+            0,
+            Some("denoise scratch buffer"),
+        );
+
+        let parameter_buffers = (0..MAX_DENOISE_ITERATIONS)
+            .map(|_| {
+                UniformBuffer::new(
+                    device,
+                    size_of::<GpuDenoiseParams>() as wgpu::BufferAddress,
+                    0,
+                    Some("denoise parameter buffer"),
+                )
+            })
+            .collect::<Vec<_>>();
+        let [parameter_entries, swap_entries, input_entries] = layout_entries();
+        let parameter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &parameter_entries,
+            label: Some("denoise parameter layout"),
+        });
+        let parameter_bind_groups = parameter_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &parameter_layout,
+                    entries: &[buffer.binding()],
+                    label: Some("denoise parameter bind group"),
+                })
+            })
+            .collect();
+
+        let swap_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &swap_entries,
+            label: Some("denoise swap layout"),
+        });
+        let swap_bind_group = |src: &StorageBuffer, dst: &StorageBuffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &swap_layout,
+                entries: &[storage_binding(1, src), storage_binding(2, dst)],
+                label: Some("denoise swap bind group"),
+            })
+        };
+        let swap_bind_groups = [
+            swap_bind_group(&scratch_buffer, &denoised_buffer),
+            swap_bind_group(&denoised_buffer, &scratch_buffer),
+        ];
+
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &input_entries,
+            label: Some("denoise input layout"),
+        });
+        let input_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &input_layout,
+            entries: &[
+                storage_binding(0, image_buffer),
+                storage_binding(1, pixel_stats_buffer),
+                storage_binding(2, feature_buffer),
+            ],
+            label: Some("denoise input bind group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            source: wgpu::ShaderSource::Wgsl(include_str!(concat!(env!("OUT_DIR"), "/denoise_shader.wgsl")).into()),
+            label: Some("denoise_shader.wgsl"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&parameter_layout, &swap_layout, &input_layout],
+            push_constant_ranges: &[],
+            label: Some("denoise layout"),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("denoise compute pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            parameter_buffers,
+            parameter_bind_groups,
+            swap_bind_groups,
+            input_bind_group,
+            denoised_buffer,
+        }
+    }
+
+    pub fn denoised_buffer(&self) -> &StorageBuffer {
+        &self.denoised_buffer
+    }
+
+    pub fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        image_size: RectSize<u32>,
+        params: &DenoiseParams,
+    ) {
+        let iterations = params.iterations.min(MAX_DENOISE_ITERATIONS);
+        for iteration in 0..iterations {
+            queue.write_buffer(
+                self.parameter_buffers[iteration as usize].handle(),
+                0,
+                bytemuck::bytes_of(&params.iteration(image_size, iteration)),
+            );
+        }
+
+        let workgroup_size = 8;
+        let workgroups_x = image_size.width.div_ceil(workgroup_size);
+        let workgroups_y = image_size.height.div_ceil(workgroup_size);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(INPUT_GROUP, &self.input_bind_group, &[]);
+        for iteration in 0..iterations {
+            // The last iteration must write into the denoised buffer.
+            let swap_idx = ((iterations - 1 - iteration) % 2) as usize;
+            compute_pass.set_bind_group(PARAMETER_GROUP, &self.parameter_bind_groups[iteration as usize], &[]);
+            compute_pass.set_bind_group(SWAP_GROUP, &self.swap_bind_groups[swap_idx], &[]);
+            compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+        }
+    }
+}
+
+/// Entries of the layouts of the parameter, swap and input groups, in the order of the groups.
+fn layout_entries() -> [Vec<wgpu::BindGroupLayoutEntry>; 3] {
+    let mut layouts = [const { Vec::new() }; 3];
+    layouts[PARAMETER_GROUP as usize] = vec![buffer_layout(0, wgpu::BufferBindingType::Uniform)];
+    layouts[SWAP_GROUP as usize] = vec![storage_layout(1, true), storage_layout(2, false)];
+    layouts[INPUT_GROUP as usize] = vec![
+        storage_layout(0, true),
+        storage_layout(1, true),
+        storage_layout(2, true),
+    ];
+    layouts
+}
+
+fn storage_layout(binding: u32, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    buffer_layout(binding, wgpu::BufferBindingType::Storage { read_only })
+}
+
+fn buffer_layout(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn storage_binding(binding: u32, buffer: &StorageBuffer) -> wgpu::BindGroupEntry<'_> {
+    wgpu::BindGroupEntry {
+        binding,
+        resource: buffer.handle().as_entire_binding(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: RectSize<u32> = RectSize { width: 8, height: 8 };
+
+    fn pixel_count() -> usize {
+        (SIZE.width * SIZE.height) as usize
+    }
+
+    /// Deterministic noise in `-0.5..0.5`.
+    fn noise(idx: usize) -> f32 {
+        let hash = (idx as u32).wrapping_mul(2654435761) >> 16;
+        (hash % 1000) as f32 / 1000.0 - 0.5
+    }
+
+    fn luminance_variance(image: &[[f32; 3]]) -> f32 {
+        let mean = image.iter().map(|c| c[0]).sum::<f32>() / image.len() as f32;
+        image.iter().map(|c| (c[0] - mean).powi(2)).sum::<f32>() / image.len() as f32
+    }
+
+    #[test]
+    fn test_shader_bindings_match_the_pipeline_layout() {
+        use wgpu::naga::{AddressSpace, StorageAccess};
+
+        let shader = include_str!(concat!(env!("OUT_DIR"), "/denoise_shader.wgsl"));
+        let module = wgpu::naga::front::wgsl::parse_str(shader).expect("The denoise shader should parse");
+        let layouts = layout_entries();
+
+        let mut num_bindings = 0;
+        for (_, variable) in module.global_variables.iter() {
+            let Some(binding) = &variable.binding else {
+                continue;
+            };
+            let name = variable.name.as_deref().unwrap_or_default();
+            let entry = layouts
+                .get(binding.group as usize)
+                .and_then(|entries| entries.iter().find(|entry| entry.binding == binding.binding))
+                .unwrap_or_else(|| panic!("`{name}` at {binding:?} is not in the pipeline layout"));
+
+            let ty = match variable.space {
+                AddressSpace::Uniform => wgpu::BufferBindingType::Uniform,
+                AddressSpace::Storage { access } => wgpu::BufferBindingType::Storage {
+                    read_only: !access.contains(StorageAccess::STORE),
+                },
+                space => panic!("`{name}` is in the unexpected address space {space:?}"),
+            };
+            assert_eq!(entry.ty, buffer_layout(0, ty).ty, "`{name}` at {binding:?}");
+            num_bindings += 1;
+        }
+        assert_eq!(num_bindings, layouts.iter().map(Vec::len).sum::<usize>());
+    }
+
+    #[test]
+    fn test_denoise_keeps_constant_image() {
+        let color = vec![[0.25, 0.5, 0.75]; pixel_count()];
+        let albedo = vec![[0.8; 3]; pixel_count()];
+        let normal = vec![[0.0, 1.0, 0.0]; pixel_count()];
+        let input = DenoiseInput {
+            size: SIZE,
+            color: &color,
+            albedo: &albedo,
+            normal: &normal,
+        };
+
+        for pixel in denoise(&input, &DenoiseParams::default()) {
+            for (channel, expected) in pixel.iter().zip([0.25, 0.5, 0.75]) {
+                assert!((channel - expected).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_denoise_reduces_noise_on_flat_surface() {
+        let color = (0..pixel_count())
+            .map(|idx| [0.5 + 0.1 * noise(idx); 3])
+            .collect::<Vec<_>>();
+        let albedo = vec![[0.8; 3]; pixel_count()];
+        let normal = vec![[0.0, 1.0, 0.0]; pixel_count()];
+        let input = DenoiseInput {
+            size: SIZE,
+            color: &color,
+            albedo: &albedo,
+            normal: &normal,
+        };
+
+        let denoised = denoise(&input, &DenoiseParams::default());
+        assert!(luminance_variance(&denoised) < 0.25 * luminance_variance(&color));
+    }
+
+    #[test]
+    fn test_denoise_preserves_edges_between_surfaces() {
+        let is_left = |idx: usize| (idx as u32 % SIZE.width) < SIZE.width / 2;
+        let color = (0..pixel_count())
+            .map(|idx| if is_left(idx) { [0.1; 3] } else { [0.9; 3] })
+            .collect::<Vec<_>>();
+        let albedo = (0..pixel_count())
+            .map(|idx| if is_left(idx) { [0.1; 3] } else { [0.9; 3] })
+            .collect::<Vec<_>>();
+        let normal = (0..pixel_count())
+            .map(|idx| if is_left(idx) { [1.0, 0.0, 0.0] } else { [0.0, 0.0, 1.0] })
+            .collect::<Vec<_>>();
+        let input = DenoiseInput {
+            size: SIZE,
+            color: &color,
+            albedo: &albedo,
+            normal: &normal,
+        };
+
+        let denoised = denoise(&input, &DenoiseParams::default());
+        for (idx, pixel) in denoised.iter().enumerate() {
+            let expected = if is_left(idx) { 0.1 } else { 0.9 };
+            assert!((pixel[0] - expected).abs() < 1e-3, "pixel {idx} is {}", pixel[0]);
+        }
+    }
+}
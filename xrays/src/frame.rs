@@ -0,0 +1,373 @@
+use std::sync::mpsc;
+
+use reactor_types::rect::RectSize;
+
+use crate::aov::{ExportError, RenderPass, RenderedPasses};
+use crate::buffer::{StorageBuffer, UniformBuffer};
+use crate::denoise::Denoiser;
+use crate::sampling::GpuPixelStats;
+use crate::tile::GpuTile;
+
+/// Number of floats stored per pixel in the AOV buffer: depth, material index, object index,
+/// direct and indirect lighting.
+const AOV_STRIDE: usize = 9;
+
+/// Buffers holding the accumulated image and everything derived from it.
+pub struct FrameBuffersGroup {
+    frame_data_buffer: UniformBuffer,
+    tile_buffer: UniformBuffer,
+    image_buffer: StorageBuffer,
+    pixel_stats_buffer: StorageBuffer,
+    feature_buffer: StorageBuffer,
+    /// A tiny placeholder unless the auxiliary passes are enabled.
+    aov_buffer: StorageBuffer,
+    aovs_enabled: bool,
+    converged_pixels: ConvergedPixelCounter,
+    denoiser: Denoiser,
+
+    max_viewport_resolution: u32,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl FrameBuffersGroup {
+    pub fn new(device: &wgpu::Device, max_viewport_resolution: u32, aovs_enabled: bool) -> Self {
+        let frame_data_buffer = UniformBuffer::new(device, 16_u64, 0, Some("frame data buffer"));
+        let tile_buffer = UniformBuffer::new(device, size_of::<GpuTile>() as u64, 6, Some("tile buffer"));
+
+        let image_buffer = {
+            let buffer = vec![[0.0_f32; 3]; max_viewport_resolution as usize];
+            StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(buffer.as_slice()), 1, Some("image buffer"))
+        };
+
+        let pixel_stats_buffer = {
+            let buffer = vec![GpuPixelStats::default(); max_viewport_resolution as usize];
+            StorageBuffer::new_from_bytes(
+                device,
+                bytemuck::cast_slice(buffer.as_slice()),
+                2,
+                Some("pixel stats buffer"),
+            )
+        };
+
+        let feature_buffer = {
+            let buffer = vec![[0.0_f32; 6]; max_viewport_resolution as usize];
+            StorageBuffer::new_from_bytes(
+                device,
+                bytemuck::cast_slice(buffer.as_slice()),
+                3,
+                Some("feature buffer"),
+            )
+        };
+
+        let aov_buffer = create_aov_buffer(device, max_viewport_resolution, aovs_enabled);
+        let converged_pixels = ConvergedPixelCounter::new(device, 7);
+
+        let denoiser = Denoiser::new(
+            device,
+            &image_buffer,
+            &pixel_stats_buffer,
+            &feature_buffer,
+            4,
+            max_viewport_resolution,
+        );
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                frame_data_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT),
+                image_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT, false),
+                pixel_stats_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT, false),
+                feature_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT, false),
+                denoiser.denoised_buffer().layout(wgpu::ShaderStages::FRAGMENT, true),
+                aov_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT, false),
+                tile_buffer.layout(wgpu::ShaderStages::COMPUTE),
+                converged_pixels.buffer().layout(wgpu::ShaderStages::COMPUTE, false),
+            ],
+            label: Some("image layout"),
+        });
+
+        let mut group = Self {
+            frame_data_buffer,
+            tile_buffer,
+            image_buffer,
+            pixel_stats_buffer,
+            feature_buffer,
+            aov_buffer,
+            aovs_enabled,
+            converged_pixels,
+            denoiser,
+            max_viewport_resolution,
+            bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &layout,
+                entries: &[],
+                label: Some("empty image bind group"),
+            }),
+            layout,
+        };
+        group.bind_group = group.create_bind_group(device);
+        group
+    }
+
+    fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                self.frame_data_buffer.binding(),
+                self.image_buffer.binding(),
+                self.pixel_stats_buffer.binding(),
+                self.feature_buffer.binding(),
+                self.denoiser.denoised_buffer().binding(),
+                self.aov_buffer.binding(),
+                self.tile_buffer.binding(),
+                self.converged_pixels.buffer().binding(),
+            ],
+            label: Some("image bind group"),
+        })
+    }
+
+    pub fn set_aovs_enabled(&mut self, device: &wgpu::Device, aovs_enabled: bool) {
+        if self.aovs_enabled != aovs_enabled {
+            self.aovs_enabled = aovs_enabled;
+            self.aov_buffer = create_aov_buffer(device, self.max_viewport_resolution, aovs_enabled);
+            self.bind_group = self.create_bind_group(device);
+        }
+    }
+
+    pub fn aovs_enabled(&self) -> bool {
+        self.aovs_enabled
+    }
+
+    pub fn frame_data_buffer(&self) -> &UniformBuffer {
+        &self.frame_data_buffer
+    }
+
+    pub fn tile_buffer(&self) -> &UniformBuffer {
+        &self.tile_buffer
+    }
+
+    pub fn max_viewport_resolution(&self) -> u32 {
+        self.max_viewport_resolution
+    }
+
+    pub fn converged_pixels(&self) -> &ConvergedPixelCounter {
+        &self.converged_pixels
+    }
+
+    pub fn converged_pixels_mut(&mut self) -> &mut ConvergedPixelCounter {
+        &mut self.converged_pixels
+    }
+
+    pub fn denoiser(&self) -> &Denoiser {
+        &self.denoiser
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Reads the accumulated image back from the GPU. Blocks until the GPU has finished all submitted work.
+    pub fn read_passes(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image_size: RectSize<u32>,
+        denoised: bool,
+    ) -> Result<RenderedPasses, ExportError> {
+        let num_pixels = (image_size.width * image_size.height) as usize;
+        let stats = read_buffer::<GpuPixelStats>(device, queue, &self.pixel_stats_buffer, num_pixels)?;
+        let features = read_buffer::<[f32; 6]>(device, queue, &self.feature_buffer, num_pixels)?;
+        let inv_num_samples = |idx: usize| 1.0 / stats[idx].num_samples.max(1) as f32;
+        let average = |values: Vec<[f32; 3]>| {
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(idx, value)| value.map(|channel| channel * inv_num_samples(idx)))
+                .collect::<Vec<_>>()
+        };
+
+        let beauty = if denoised {
+            read_buffer(device, queue, self.denoiser.denoised_buffer(), num_pixels)?
+        } else {
+            average(read_buffer(device, queue, &self.image_buffer, num_pixels)?)
+        };
+        let albedo = features
+            .iter()
+            .map(|feature| [feature[0], feature[1], feature[2]])
+            .collect();
+        let normal = features
+            .iter()
+            .map(|feature| [feature[3], feature[4], feature[5]])
+            .collect();
+
+        let mut passes = vec![
+            (RenderPass::Beauty, beauty),
+            (RenderPass::Normal, average(normal)),
+            (RenderPass::Albedo, average(albedo)),
+        ];
+
+        if self.aovs_enabled {
+            let aovs = read_buffer::<[f32; AOV_STRIDE]>(device, queue, &self.aov_buffer, num_pixels)?;
+            let channels = |range: std::ops::Range<usize>| {
+                aovs.iter()
+                    .map(|aov| match range.len() {
+                        1 => [aov[range.start]; 3],
+                        _ => [aov[range.start], aov[range.start + 1], aov[range.start + 2]],
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            passes.extend([
+                (RenderPass::Depth, channels(0..1)),
+                (RenderPass::MaterialIndex, channels(1..2)),
+                (RenderPass::ObjectIndex, channels(2..3)),
+                (RenderPass::Direct, average(channels(3..6))),
+                (RenderPass::Indirect, average(channels(6..9))),
+            ]);
+        }
+
+        Ok(RenderedPasses {
+            size: image_size,
+            passes,
+        })
+    }
+}
+
+/// Counts the pixels which are converged after a sampling dispatch. The viewport reads the count back a frame or two
+/// later without waiting for the GPU, and stops the render once every pixel of the region has converged.
+pub struct ConvergedPixelCounter {
+    buffer: StorageBuffer,
+    staging_buffer: wgpu::Buffer,
+    readback: CountReadback,
+}
+
+enum CountReadback {
+    Idle,
+    /// Submitted commands copy the count of the render with this generation to the staging buffer.
+    Copied(u32),
+    Mapping(u32, mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+impl ConvergedPixelCounter {
+    const SIZE: wgpu::BufferAddress = size_of::<u32>() as wgpu::BufferAddress;
+
+    fn new(device: &wgpu::Device, binding_idx: u32) -> Self {
+        Self {
+            buffer: StorageBuffer::new_from_bytes(
+                device,
+                bytemuck::bytes_of(&0_u32),
+                binding_idx,
+                Some("converged pixels"),
+            ),
+            staging_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                size: Self::SIZE,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+                label: Some("converged pixels readback buffer"),
+            }),
+            readback: CountReadback::Idle,
+        }
+    }
+
+    fn buffer(&self) -> &StorageBuffer {
+        &self.buffer
+    }
+
+    /// Starts the count of the next sampling dispatch from zero.
+    pub fn encode_clear(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(self.buffer.handle(), 0, None);
+    }
+
+    /// Copies the count of the sampling dispatch for [`ConvergedPixelCounter::poll`], unless an earlier count is
+    /// still being read.
+    pub fn encode_copy(&mut self, encoder: &mut wgpu::CommandEncoder, generation: u32) {
+        if let CountReadback::Idle = self.readback {
+            encoder.copy_buffer_to_buffer(self.buffer.handle(), 0, &self.staging_buffer, 0, Self::SIZE);
+            self.readback = CountReadback::Copied(generation);
+        }
+    }
+
+    /// Moves the read back of the copied count along without blocking. Returns the count with the generation of the
+    /// render it belongs to once it has arrived. The commands which copied the count must have been submitted.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<(u32, u64)> {
+        match std::mem::replace(&mut self.readback, CountReadback::Idle) {
+            CountReadback::Idle => None,
+            CountReadback::Copied(generation) => {
+                let (sender, receiver) = mpsc::channel();
+                self.staging_buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |result| {
+                        let _ = sender.send(result);
+                    });
+                self.readback = CountReadback::Mapping(generation, receiver);
+                None
+            },
+            CountReadback::Mapping(generation, receiver) => {
+                let _ = device.poll(wgpu::Maintain::Poll);
+                match receiver.try_recv() {
+                    Ok(Ok(())) => {
+                        let count: u32 =
+                            bytemuck::pod_read_unaligned(&self.staging_buffer.slice(..).get_mapped_range());
+                        self.staging_buffer.unmap();
+                        Some((generation, u64::from(count)))
+                    },
+                    Err(mpsc::TryRecvError::Empty) => {
+                        self.readback = CountReadback::Mapping(generation, receiver);
+                        None
+                    },
+                    // A failed read back is dropped, the next one replaces it.
+                    Ok(Err(_)) | Err(mpsc::TryRecvError::Disconnected) => None,
+                }
+            },
+        }
+    }
+}
+
+fn create_aov_buffer(device: &wgpu::Device, max_viewport_resolution: u32, aovs_enabled: bool) -> StorageBuffer {
+    // A disabled buffer still holds one pixel, the binding must fit at least one element of the shader array.
+    let num_pixels = if aovs_enabled {
+        max_viewport_resolution.max(1) as usize
+    } else {
+        1
+    };
+    let buffer = vec![[0.0_f32; AOV_STRIDE]; num_pixels];
+    StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(buffer.as_slice()), 5, Some("aov buffer"))
+}
+
+fn read_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &StorageBuffer,
+    len: usize,
+) -> Result<Vec<T>, wgpu::BufferAsyncError> {
+    let size = (len * size_of::<T>()) as wgpu::BufferAddress;
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+        label: Some("readback buffer"),
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer.handle(), 0, &staging_buffer, 0, size);
+    queue.submit([encoder.finish()]);
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let slice = staging_buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    let _ = device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .expect("Map callback must be called after waiting for the device")?;
+
+    let data = bytemuck::pod_collect_to_vec(&slice.get_mapped_range());
+    staging_buffer.unmap();
+    Ok(data)
+}
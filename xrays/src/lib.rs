@@ -5,21 +5,33 @@ use wgpu;
 use wgpu::util::DeviceExt;
 use world::SkyParams;
 
+pub use crate::aov::{ExportError, RenderPass, RenderedPasses};
 use crate::buffer::{StorageBuffer, UniformBuffer};
 pub use crate::camera::Camera;
 use crate::camera::GpuCamera;
+use crate::denoise::{DenoiseParams, MAX_DENOISE_ITERATIONS};
+use crate::frame::FrameBuffersGroup;
 use crate::sampling::GpuSamplingParams;
 pub use crate::sampling::SamplingParams;
 use crate::scene::SceneBuffersGroup;
-pub use crate::scene::{Material, Scene, Sphere};
+pub use crate::scene::{Material, Scene, SceneDirtyFlags, Sphere};
 pub use crate::texture::Texture;
+use crate::tile::{GpuTile, PixelRect, TiledRender, TiledRenderError};
 use crate::vertex::{Vertex, VertexUniforms};
 
+pub mod aov;
+pub mod atlas;
 pub mod buffer;
 pub mod camera;
+pub mod denoise;
+pub mod frame;
+pub mod procedural;
 pub mod sampling;
 pub mod scene;
+pub mod sequence;
 pub mod texture;
+pub mod texture_cache;
+pub mod tile;
 pub mod vertex;
 pub mod world;
 
@@ -29,15 +41,19 @@ pub type Vector3 = reactor_types::Vector3<Float>;
 pub type Vector4 = reactor_types::Vector4<Float>;
 pub type Matrix4 = reactor_types::Matrix4<Float>;
 pub type Angle = reactor_types::Angle<Float>;
+pub type Ray = reactor_types::Ray<Float>;
+
+/// Sampling batches of a tiled render which are queued on the GPU at once, more would only delay the other work of
+/// the GPU.
+pub const MAX_TILE_BATCHES_IN_FLIGHT: u32 = 4;
 
 pub struct Renderer {
     vertex_bind_group: wgpu::BindGroup,
-    image_bind_group: wgpu::BindGroup,
     parameter_bind_group: wgpu::BindGroup,
+    frame_group: FrameBuffersGroup,
     scene_group: SceneBuffersGroup,
 
     vertex_buffer: wgpu::Buffer,
-    frame_data_buffer: UniformBuffer,
     camera_buffer: UniformBuffer,
     sampling_parameter_buffer: UniformBuffer,
     hw_sky_state_buffer: StorageBuffer,
@@ -47,11 +63,13 @@ pub struct Renderer {
     latest_render_params: RenderParams,
     render_progress: RenderProgress,
     frame_number: u32,
+    denoise_pending: bool,
 }
 
 impl Renderer {
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         target_format: wgpu::TextureFormat,
         scene: &Scene,
         render_params: &RenderParams,
@@ -75,25 +93,7 @@ impl Renderer {
             label: Some("uniforms bind group"),
         });
 
-        let frame_data_buffer = UniformBuffer::new(device, 16_u64, 0, Some("frame data buffer"));
-
-        let image_buffer = {
-            let buffer = vec![[0.0_f32; 3]; max_viewport_resolution as usize];
-            StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(buffer.as_slice()), 1, Some("image buffer"))
-        };
-
-        let image_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                frame_data_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT),
-                image_buffer.layout(wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT, false),
-            ],
-            label: Some("image layout"),
-        });
-        let image_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &image_bind_group_layout,
-            entries: &[frame_data_buffer.binding(), image_buffer.binding()],
-            label: Some("image bind group"),
-        });
+        let frame_group = FrameBuffersGroup::new(device, max_viewport_resolution, render_params.needs_aov_buffer());
 
         let sampling_parameter_buffer = UniformBuffer::new(
             device,
@@ -133,7 +133,7 @@ impl Renderer {
             label: Some("parameter bind group"),
         });
 
-        let scene_group = SceneBuffersGroup::new(scene, device);
+        let scene_group = SceneBuffersGroup::new(scene, device, queue);
 
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             source: wgpu::ShaderSource::Wgsl(include_str!(concat!(env!("OUT_DIR"), "/compute_shader.wgsl")).into()),
@@ -147,7 +147,7 @@ impl Renderer {
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             bind_group_layouts: &[
                 &vertex_uniform_bind_group_layout,
-                &image_bind_group_layout,
+                frame_group.layout(),
                 &parameter_bind_group_layout,
                 &scene_group.layout(),
             ],
@@ -217,8 +217,7 @@ impl Renderer {
 
         Ok(Self {
             vertex_bind_group: vertex_uniform_bind_group,
-            frame_data_buffer,
-            image_bind_group,
+            frame_group,
             camera_buffer,
             sampling_parameter_buffer,
             hw_sky_state_buffer,
@@ -230,6 +229,7 @@ impl Renderer {
             latest_render_params: *render_params,
             render_progress,
             frame_number,
+            denoise_pending: false,
         })
     }
 
@@ -245,6 +245,21 @@ impl Renderer {
 
         render_params.validate()?;
 
+        // The denoiser and the displayed pass only change how the accumulated image is presented,
+        // so changing them does not restart the render.
+        if !render_force
+            && (RenderParams {
+                denoise: self.latest_render_params.denoise,
+                display_pass: self.latest_render_params.display_pass,
+                ..*render_params
+            }) == self.latest_render_params
+        {
+            self.latest_render_params.denoise = render_params.denoise;
+            self.latest_render_params.display_pass = render_params.display_pass;
+            self.denoise_pending = true;
+            return Ok(());
+        }
+
         {
             let sky_state = render_params.sky.to_sky_state()?;
             queue.write_buffer(self.hw_sky_state_buffer.handle(), 0, bytemuck::bytes_of(&sky_state));
@@ -275,16 +290,40 @@ impl Renderer {
         queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
         render_params: &RenderParams,
-        scene: Option<&Scene>,
+        scene: Option<(&Scene, SceneDirtyFlags)>,
     ) {
-        self.set_render_params(queue, scene.is_some(), render_params)
+        // Pixels rendered before the AOV buffer was allocated have no AOVs, so the render restarts.
+        let needs_aov_buffer = render_params.needs_aov_buffer();
+        let aov_buffer_changed = needs_aov_buffer != self.frame_group.aovs_enabled();
+        if aov_buffer_changed {
+            self.frame_group.set_aovs_enabled(device, needs_aov_buffer);
+        }
+
+        self.set_render_params(queue, scene.is_some() || aov_buffer_changed, render_params)
             .expect("Render params should be valid");
 
-        if let Some(scene) = scene {
-            self.scene_group.update(&device, &queue, scene);
+        if let Some((scene, dirty)) = scene {
+            self.scene_group.update(device, queue, scene, dirty);
         }
 
-        let gpu_sampling_params = self.render_progress.next_frame(&self.latest_render_params.sampling);
+        let viewport_size = render_params.viewport_size;
+        let region = render_params
+            .region
+            .map(|region| region.clamp_to(viewport_size))
+            .filter(|region| !region.is_empty())
+            .unwrap_or(PixelRect::from_size(viewport_size));
+
+        // The render is done once every pixel of the region has converged, the count lags a frame or two behind.
+        if let Some((generation, converged_pixels)) = self.frame_group.converged_pixels_mut().poll(device)
+            && generation == self.render_progress.generation()
+            && converged_pixels >= region.area()
+        {
+            self.render_progress.finish(&self.latest_render_params.sampling);
+        }
+
+        let mut gpu_sampling_params = self.render_progress.next_frame(&self.latest_render_params.sampling);
+        gpu_sampling_params.denoise = self.latest_render_params.denoise.is_some() as u32;
+        gpu_sampling_params.aovs = self.frame_group.aovs_enabled() as u32;
 
         queue.write_buffer(
             self.sampling_parameter_buffer.handle(),
@@ -292,36 +331,180 @@ impl Renderer {
             bytemuck::cast_slice(&[gpu_sampling_params]),
         );
 
-        let frame_number = self.frame_number;
+        self.write_frame_data(queue, viewport_size, GpuTile::new_region(viewport_size, region));
+        self.encode_sampling(encoder, region.size());
+        if gpu_sampling_params.num_samples_per_pixel > 0 {
+            let generation = self.render_progress.generation();
+            self.frame_group.converged_pixels_mut().encode_copy(encoder, generation);
+        }
+
+        if let Some(denoise_params) = &self.latest_render_params.denoise
+            && (gpu_sampling_params.num_samples_per_pixel > 0 || self.denoise_pending)
+        {
+            self.frame_group
+                .denoiser()
+                .encode(queue, encoder, render_params.viewport_size, denoise_params);
+        }
+        self.denoise_pending = false;
+    }
+
+    /// Reads every rendered pass back from the GPU. The beauty pass is denoised if the denoiser is enabled.
+    pub fn read_passes(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<RenderedPasses, ExportError> {
+        self.frame_group.read_passes(
+            device,
+            queue,
+            self.latest_render_params.viewport_size,
+            self.latest_render_params.denoise.is_some(),
+        )
+    }
+
+    /// Uploads the changed parts of the scene without rendering, for the renders which don't go through
+    /// [`Renderer::prepare_frame`], like [`Renderer::render_tiled`].
+    pub fn update_scene(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene, dirty: SceneDirtyFlags) {
+        self.scene_group.update(device, queue, scene, dirty);
+    }
+
+    /// Starts an image of any size which is rendered tile by tile, `viewport_size` of the params is the size of the
+    /// whole image. [`Renderer::render_tiled_step`] renders it a few samples at a time, so the caller can stay
+    /// responsive in between.
+    pub fn start_tiled(
+        &self,
+        render_params: &RenderParams,
+        tile_size: RectSize<u32>,
+    ) -> Result<TiledRender, TiledRenderError> {
+        let tile_pixels = u64::from(tile_size.width) * u64::from(tile_size.height);
+        if tile_pixels == 0 || tile_pixels > u64::from(self.frame_group.max_viewport_resolution()) {
+            return Err(TiledRenderError::InvalidTileSize(tile_size.width, tile_size.height));
+        }
+        render_params.validate()?;
+
+        Ok(TiledRender::new(*render_params, self.latest_render_params, tile_size))
+    }
+
+    /// Queues sampling batches of the current tile without waiting for the GPU, at most
+    /// [`MAX_TILE_BATCHES_IN_FLIGHT`] at a time, and reads the tile back once it's done. The counts of converged pixels
+    /// arrive a few batches later. Returns `true` once every tile is rendered, the viewport render restarts then.
+    pub fn render_tiled_step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tiled: &mut TiledRender,
+    ) -> Result<bool, TiledRenderError> {
+        let Some(tile) = tiled.current_tile() else {
+            return Ok(true);
+        };
+        let image_size = tiled.render_params.viewport_size;
+
+        if !tiled.tile_started {
+            self.frame_group
+                .set_aovs_enabled(device, tiled.render_params.needs_aov_buffer());
+            self.set_render_params(queue, true, &tiled.render_params)?;
+            tiled.tile_started = true;
+        }
+
+        let _ = device.poll(wgpu::Maintain::Poll);
+        if let Some((generation, converged_pixels)) = self.frame_group.converged_pixels_mut().poll(device)
+            && generation == self.render_progress.generation()
+            && converged_pixels >= tile.area()
+        {
+            self.render_progress.finish(&tiled.render_params.sampling);
+        }
+
+        loop {
+            if tiled.batches_in_flight() >= MAX_TILE_BATCHES_IN_FLIGHT {
+                return Ok(false);
+            }
+            let mut gpu_sampling_params = self.render_progress.next_frame(&tiled.render_params.sampling);
+            if gpu_sampling_params.num_samples_per_pixel == 0 {
+                break;
+            }
+
+            gpu_sampling_params.aovs = self.frame_group.aovs_enabled() as u32;
+            queue.write_buffer(
+                self.sampling_parameter_buffer.handle(),
+                0,
+                bytemuck::cast_slice(&[gpu_sampling_params]),
+            );
+            self.write_frame_data(queue, tile.size(), GpuTile::new_tile(image_size, tile));
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("tile encoder"),
+            });
+            self.encode_sampling(&mut encoder, tile.size());
+            let generation = self.render_progress.generation();
+            self.frame_group
+                .converged_pixels_mut()
+                .encode_copy(&mut encoder, generation);
+            queue.submit([encoder.finish()]);
+            tiled.track_batch(queue);
+        }
+
+        // The stitched image is denoised as a whole by `TiledRender::finish`.
+        let tile_passes = self.frame_group.read_passes(device, queue, tile.size(), false)?;
+        tiled.image.insert_tile(tile, &tile_passes);
+        tiled.next_tile += 1;
+        tiled.tile_started = false;
+
+        if tiled.is_done() {
+            self.set_render_params(queue, true, &tiled.viewport_params)?;
+        }
+        Ok(tiled.is_done())
+    }
+
+    /// Renders an image of any size tile by tile, blocking until the image is done. The viewport render restarts
+    /// afterwards.
+    pub fn render_tiled(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        render_params: &RenderParams,
+        tile_size: RectSize<u32>,
+    ) -> Result<RenderedPasses, TiledRenderError> {
+        let mut tiled = self.start_tiled(render_params, tile_size)?;
+        while !self.render_tiled_step(device, queue, &mut tiled)? {
+            let _ = device.poll(wgpu::Maintain::Wait);
+        }
+        Ok(tiled.finish())
+    }
+
+    fn write_frame_data(&mut self, queue: &wgpu::Queue, buffer_size: RectSize<u32>, tile: GpuTile) {
         let frame_data = [
-            render_params.viewport_size.width,
-            render_params.viewport_size.height,
-            frame_number,
+            buffer_size.width,
+            buffer_size.height,
+            self.frame_number,
+            self.latest_render_params.display_pass as u32,
         ];
-        queue.write_buffer(self.frame_data_buffer.handle(), 0, bytemuck::cast_slice(&frame_data));
+        queue.write_buffer(
+            self.frame_group.frame_data_buffer().handle(),
+            0,
+            bytemuck::cast_slice(&frame_data),
+        );
+        queue.write_buffer(self.frame_group.tile_buffer().handle(), 0, bytemuck::bytes_of(&tile));
 
         self.frame_number += 1;
+    }
 
-        {
-            let workgroup_size_x = 8;
-            let workgroup_size_y = 8;
-            let workgroups_x = (render_params.viewport_size.width + workgroup_size_x - 1) / workgroup_size_x;
-            let workgroups_y = (render_params.viewport_size.height + workgroup_size_y - 1) / workgroup_size_y;
-
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
-            compute_pass.set_pipeline(&self.compute_pipeline);
-            compute_pass.set_bind_group(0, &self.vertex_bind_group, &[]);
-            compute_pass.set_bind_group(1, &self.image_bind_group, &[]);
-            compute_pass.set_bind_group(2, &self.parameter_bind_group, &[]);
-            compute_pass.set_bind_group(3, self.scene_group.bind_group(), &[]);
-            compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
-        }
+    fn encode_sampling(&self, encoder: &mut wgpu::CommandEncoder, region_size: RectSize<u32>) {
+        let workgroup_size_x = 8;
+        let workgroup_size_y = 8;
+        let workgroups_x = region_size.width.div_ceil(workgroup_size_x);
+        let workgroups_y = region_size.height.div_ceil(workgroup_size_y);
+
+        self.frame_group.converged_pixels().encode_clear(encoder);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.vertex_bind_group, &[]);
+        compute_pass.set_bind_group(1, self.frame_group.bind_group(), &[]);
+        compute_pass.set_bind_group(2, &self.parameter_bind_group, &[]);
+        compute_pass.set_bind_group(3, self.scene_group.bind_group(), &[]);
+        compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
     }
 
     pub fn render_frame(&self, render_pass: &mut wgpu::RenderPass) {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.vertex_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.image_bind_group, &[]);
+        render_pass.set_bind_group(1, self.frame_group.bind_group(), &[]);
         render_pass.set_bind_group(2, &self.parameter_bind_group, &[]);
         render_pass.set_bind_group(3, self.scene_group.bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
@@ -343,6 +526,10 @@ pub enum RenderParamsValidationError {
     ApertureOutOfRange(Float),
     #[error("focus_distance must be greater than zero")]
     FocusDistanceOutOfRange(Float),
+    #[error("adaptive_threshold must not be negative")]
+    AdaptiveThresholdOutOfRange(Float),
+    #[error("denoise iterations must be between 1..={MAX_DENOISE_ITERATIONS}")]
+    DenoiseIterationsOutOfRange(u32),
     #[error(transparent)]
     HwSkyModelValidationError(#[from] hw_skymodel::rgb::Error),
 }
@@ -353,9 +540,19 @@ pub struct RenderParams {
     pub viewport_size: RectSize<u32>,
     pub sky: SkyParams,
     pub sampling: SamplingParams,
+    pub denoise: Option<DenoiseParams>,
+    /// Renders the auxiliary passes so that they can be exported.
+    pub aovs: bool,
+    pub display_pass: RenderPass,
+    /// Part of the viewport to render, the rest of the image keeps its samples.
+    pub region: Option<PixelRect>,
 }
 
 impl RenderParams {
+    fn needs_aov_buffer(&self) -> bool {
+        self.aovs || self.display_pass.needs_aov_buffer()
+    }
+
     fn validate(&self) -> Result<(), RenderParamsValidationError> {
         if self.sampling.max_samples_per_pixel % self.sampling.num_samples_per_pixel != 0 {
             return Err(RenderParamsValidationError::MaxSampleCountNotMultiple(
@@ -380,6 +577,20 @@ impl RenderParams {
             ));
         }
 
+        if self.sampling.adaptive_threshold < 0.0 {
+            return Err(RenderParamsValidationError::AdaptiveThresholdOutOfRange(
+                self.sampling.adaptive_threshold,
+            ));
+        }
+
+        if let Some(denoise) = &self.denoise
+            && !(1..=MAX_DENOISE_ITERATIONS).contains(&denoise.iterations)
+        {
+            return Err(RenderParamsValidationError::DenoiseIterationsOutOfRange(
+                denoise.iterations,
+            ));
+        }
+
         if self.viewport_size.width == 0 || self.viewport_size.height == 0 {
             return Err(RenderParamsValidationError::ViewportSize(
                 self.viewport_size.width,
@@ -393,12 +604,15 @@ impl RenderParams {
 
 struct RenderProgress {
     accumulated_samples_per_pixel: u32,
+    /// Counts the resets, so that results read back from an earlier render can be told apart.
+    generation: u32,
 }
 
 impl RenderProgress {
     pub fn new() -> Self {
         Self {
             accumulated_samples_per_pixel: 0,
+            generation: 0,
         }
     }
 
@@ -416,6 +630,10 @@ impl RenderProgress {
                 num_bounces: sampling_params.num_bounces,
                 accumulated_samples_per_pixel: next_accumulated_samples,
                 clear_accumulated_samples: 1,
+                min_samples_per_pixel: sampling_params.min_samples_per_pixel,
+                adaptive_threshold: sampling_params.adaptive_threshold,
+                denoise: 0,
+                aovs: 0,
             }
         }
         // Progressive render: accumulating samples in the image buffer over multiple
@@ -427,6 +645,10 @@ impl RenderProgress {
                 num_bounces: sampling_params.num_bounces,
                 accumulated_samples_per_pixel: next_accumulated_samples,
                 clear_accumulated_samples: 0,
+                min_samples_per_pixel: sampling_params.min_samples_per_pixel,
+                adaptive_threshold: sampling_params.adaptive_threshold,
+                denoise: 0,
+                aovs: 0,
             }
         }
         // Completed render: we have accumulated max_samples_per_pixel samples. Stop rendering
@@ -437,17 +659,31 @@ impl RenderProgress {
                 num_bounces: sampling_params.num_bounces,
                 accumulated_samples_per_pixel: current_accumulated_samples,
                 clear_accumulated_samples: 0,
+                min_samples_per_pixel: sampling_params.min_samples_per_pixel,
+                adaptive_threshold: sampling_params.adaptive_threshold,
+                denoise: 0,
+                aovs: 0,
             }
         }
     }
 
     pub fn reset(&mut self) {
         self.accumulated_samples_per_pixel = 0;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Completes the render before it reaches the maximum number of samples, once all its pixels have converged.
+    pub fn finish(&mut self, sampling_params: &SamplingParams) {
+        self.accumulated_samples_per_pixel = sampling_params.max_samples_per_pixel;
     }
 
     pub fn accumulated_samples(&self) -> u32 {
         self.accumulated_samples_per_pixel
     }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
 }
 
 fn unit_quad_projection_matrix() -> Matrix4 {
@@ -518,3 +754,27 @@ const FRAME_VERTICES: &[Vertex] = &[
         tex_coords: [1.0, 0.0],
     },
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finished_render_progress_stops_sampling() {
+        let sampling = SamplingParams {
+            max_samples_per_pixel: 8,
+            num_samples_per_pixel: 2,
+            ..Default::default()
+        };
+        let mut progress = RenderProgress::new();
+        assert_eq!(progress.next_frame(&sampling).num_samples_per_pixel, 2);
+
+        progress.finish(&sampling);
+        assert_eq!(progress.next_frame(&sampling).num_samples_per_pixel, 0);
+
+        let generation = progress.generation();
+        progress.reset();
+        assert_ne!(progress.generation(), generation);
+        assert_eq!(progress.next_frame(&sampling).clear_accumulated_samples, 1);
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Color;
+
+/// Pattern of a procedural texture, evaluated in the compute shader at the world position of the hit point.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Fractal Brownian motion of Perlin noise.
+    Noise { octaves: u32, lacunarity: f32, gain: f32 },
+    /// Distance to the closest of randomly placed feature points, one per unit cell.
+    Voronoi { jitter: f32 },
+    /// Linear blend along the direction, from the first color at `-1` to the second at `1`.
+    Gradient { direction: [f32; 3] },
+    /// Bricks of 2x1x2 units; the second color is the mortar.
+    Bricks { mortar: f32, row_offset: f32 },
+    /// Unit cubes of alternating colors.
+    Checker,
+}
+
+impl Pattern {
+    pub const NOISE: Self = Self::Noise {
+        octaves: 5,
+        lacunarity: 2.0,
+        gain: 0.5,
+    };
+    pub const VORONOI: Self = Self::Voronoi { jitter: 1.0 };
+    pub const GRADIENT: Self = Self::Gradient {
+        direction: [0.0, 1.0, 0.0],
+    };
+    pub const BRICKS: Self = Self::Bricks {
+        mortar: 0.05,
+        row_offset: 0.5,
+    };
+    pub const CHECKER: Self = Self::Checker;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Noise { .. } => "Noise",
+            Self::Voronoi { .. } => "Voronoi",
+            Self::Gradient { .. } => "Gradient",
+            Self::Bricks { .. } => "Bricks",
+            Self::Checker => "Checker",
+        }
+    }
+
+    /// Same as `kind` of the `TextureDescriptor` shader struct, 0 is an image.
+    pub(crate) fn kind(&self) -> u32 {
+        match self {
+            Self::Noise { .. } => 1,
+            Self::Voronoi { .. } => 2,
+            Self::Gradient { .. } => 3,
+            Self::Bricks { .. } => 4,
+            Self::Checker => 5,
+        }
+    }
+
+    fn parameters(&self) -> [f32; 5] {
+        match *self {
+            Self::Noise {
+                octaves,
+                lacunarity,
+                gain,
+            } => [octaves as f32, lacunarity, gain, 0.0, 0.0],
+            Self::Voronoi { jitter } => [jitter, 0.0, 0.0, 0.0, 0.0],
+            Self::Gradient { direction } => {
+                let [x, y, z] = direction;
+                let length = (x * x + y * y + z * z).sqrt();
+                if length > 0.0 {
+                    [x / length, y / length, z / length, 0.0, 0.0]
+                } else {
+                    [0.0, 1.0, 0.0, 0.0, 0.0]
+                }
+            },
+            Self::Bricks { mortar, row_offset } => [mortar, row_offset, 0.0, 0.0, 0.0],
+            Self::Checker => [0.0; 5],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProceduralTexture {
+    pub pattern: Pattern,
+    pub colors: [Color; 2],
+    /// World positions are multiplied by the scale before the pattern is evaluated.
+    pub scale: f32,
+}
+
+impl ProceduralTexture {
+    /// Parameters stored in the texture buffer in place of texels: both colors, then the scale followed by the
+    /// parameters of the pattern.
+    pub(crate) fn gpu_data(&self) -> [[f32; 3]; 4] {
+        let [first, second] = self.colors;
+        let [p0, p1, p2, p3, p4] = self.pattern.parameters();
+
+        [
+            [first.x, first.y, first.z],
+            [second.x, second.y, second.z],
+            [self.scale, p0, p1],
+            [p2, p3, p4],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_data_layout() {
+        let texture = ProceduralTexture {
+            pattern: Pattern::Bricks {
+                mortar: 0.1,
+                row_offset: 0.25,
+            },
+            colors: [Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0)],
+            scale: 3.0,
+        };
+
+        assert_eq!(texture.gpu_data(), [
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [3.0, 0.1, 0.25],
+            [0.0, 0.0, 0.0]
+        ]);
+    }
+
+    #[test]
+    fn test_gradient_direction_is_normalized() {
+        let pattern = Pattern::Gradient {
+            direction: [0.0, 0.0, 2.0],
+        };
+        assert_eq!(pattern.parameters()[..3], [0.0, 0.0, 1.0]);
+
+        let degenerate = Pattern::Gradient { direction: [0.0; 3] };
+        assert_eq!(degenerate.parameters()[..3], [0.0, 1.0, 0.0]);
+    }
+}
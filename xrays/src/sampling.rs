@@ -5,6 +5,11 @@ pub struct SamplingParams {
     pub max_samples_per_pixel: u32,
     pub num_samples_per_pixel: u32,
     pub num_bounces: u32,
+    /// Pixels stop being sampled once the relative standard error of their luminance falls below this threshold.
+    /// Zero disables adaptive sampling.
+    pub adaptive_threshold: f32,
+    /// Number of samples every pixel gets before it can be considered converged.
+    pub min_samples_per_pixel: u32,
 }
 
 impl Default for SamplingParams {
@@ -13,6 +18,8 @@ impl Default for SamplingParams {
             max_samples_per_pixel: 256,
             num_samples_per_pixel: 1,
             num_bounces: 8,
+            adaptive_threshold: 0.01,
+            min_samples_per_pixel: 16,
         }
     }
 }
@@ -24,4 +31,19 @@ pub struct GpuSamplingParams {
     pub num_bounces: u32,
     pub accumulated_samples_per_pixel: u32,
     pub clear_accumulated_samples: u32,
+    pub min_samples_per_pixel: u32,
+    pub adaptive_threshold: f32,
+    pub denoise: u32,
+    /// Whether the AOV buffer is allocated, its binding is never empty so its length can't tell.
+    pub aovs: u32,
+}
+
+/// Mirrors the `PixelStats` shader struct.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuPixelStats {
+    pub luminance_sum: f32,
+    pub luminance_sq_sum: f32,
+    pub num_samples: u32,
+    pub converged: u32,
 }
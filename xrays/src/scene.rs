@@ -1,39 +1,95 @@
 use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
+use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 
-use crate::buffer::StorageBuffer;
-use crate::texture::TextureId;
-use crate::{Texture, Vector3, Vector4};
+use crate::atlas::{AtlasFormat, AtlasPlacement, MIN_LAYER_SIZE, TextureAtlas, pack_shelves};
+use crate::buffer::{GrowableStorageBuffer, StorageBuffer, UniformBuffer};
+use crate::procedural::ProceduralTexture;
+use crate::texture::{ColorSpace, TextureError, TextureFilter, TextureId, TextureSampler, TextureWrap};
+use crate::{Color, Float, Ray, Texture, Vector3, Vector4};
+
+/// Index of a material in [`Scene::materials`].
+pub type MaterialId = usize;
+
+/// The same ray parameter bounds as `MIN_T` and `MAX_T` in the compute shader.
+const MIN_T: Float = 0.001;
+const MAX_T: Float = 1000.0;
+
+/// Texels of an image, or the parameters of a pattern evaluated by the renderer. Images are shared with the texture
+/// cache, so cloning a scene doesn't copy their texels.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureSource {
+    Image(Arc<Texture>),
+    Procedural(ProceduralTexture),
+}
+
+impl From<Texture> for TextureSource {
+    fn from(texture: Texture) -> Self {
+        Self::Image(Arc::new(texture))
+    }
+}
+
+impl From<Arc<Texture>> for TextureSource {
+    fn from(texture: Arc<Texture>) -> Self {
+        Self::Image(texture)
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TextureData {
-    pub texture: Texture,
+    #[serde(alias = "texture")]
+    pub source: TextureSource,
     pub key: Option<Cow<'static, str>>,
     pub scale: f32,
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    #[serde(default)]
+    pub sampler: TextureSampler,
 }
 
 impl TextureData {
     pub fn new(texture: Texture) -> Self {
+        Self::new_from_source(texture.into())
+    }
+
+    pub fn new_procedural(texture: ProceduralTexture) -> Self {
+        Self::new_from_source(TextureSource::Procedural(texture))
+    }
+
+    fn new_from_source(source: TextureSource) -> Self {
         Self {
-            texture,
+            source,
             key: None,
             scale: 1.0,
+            color_space: ColorSpace::default(),
+            sampler: TextureSampler::default(),
         }
     }
 
-    pub fn load_scaled(path: impl Into<Cow<'static, str>>, scale: f32) -> Self {
+    pub fn load_scaled(
+        path: impl Into<Cow<'static, str>>,
+        scale: f32,
+        color_space: ColorSpace,
+    ) -> Result<Self, TextureError> {
         let path = path.into();
-        let texture = Texture::new_from_scaled_image(&path, scale).expect("Failed to load texture from file");
-        Self {
-            texture,
+        let texture = Texture::new_from_scaled_image(path.as_ref(), scale, color_space)?;
+        Ok(Self {
+            source: texture.into(),
             key: Some(path),
             scale,
-        }
+            color_space,
+            sampler: TextureSampler::default(),
+        })
     }
 
-    pub fn load(path: impl Into<Cow<'static, str>>) -> Self {
-        Self::load_scaled(path, 1.0)
+    pub fn load(path: impl Into<Cow<'static, str>>) -> Result<Self, TextureError> {
+        Self::load_scaled(path, 1.0, ColorSpace::default())
     }
 }
 
@@ -53,7 +109,13 @@ pub struct Scene {
 impl Scene {
     pub fn stub() -> Self {
         let textures = vec![Texture::new_from_color(Vector3::new(0.0, 0.0, 0.0)).into()];
-        let materials = vec![Material::Lambertian { albedo: 0 }, Material::Emissive { emit: 0 }];
+        let materials = vec![
+            Material::Lambertian {
+                albedo: 0,
+                normal_map: None,
+            },
+            Material::Emissive { emit: 0 },
+        ];
         let spheres = vec![
             Sphere::new(Vector3::new(0.0, 0.0, 0.0), 0.0, 0),
             Sphere::new(Vector3::new(0.0, 0.0, 0.0), 0.0, 1),
@@ -70,10 +132,11 @@ impl Scene {
         let textures = vec![
             TextureData::new(Texture::new_from_color(Vector3::new(0.5, 0.7, 0.8))),
             TextureData::new(Texture::new_from_color(Vector3::new(0.9, 0.9, 0.9))),
-            TextureData::load("assets/moon.jpeg"),
+            TextureData::load("assets/moon.jpeg").expect("Test scene textures must be present"),
             TextureData::new(Texture::new_from_color(Vector3::new(1.0, 0.85, 0.57))),
-            TextureData::load("assets/earthmap.jpeg"),
-            TextureData::load_scaled("assets/sun.jpeg", 50.0),
+            TextureData::load("assets/earthmap.jpeg").expect("Test scene textures must be present"),
+            TextureData::load_scaled("assets/sun.jpeg", 50.0, ColorSpace::default())
+                .expect("Test scene textures must be present"),
             TextureData::new(Texture::new_from_color(Vector3::new(0.3, 0.9, 0.9))),
             TextureData::new(Texture::new_from_color(Vector3::new(50.0, 0.0, 0.0))),
             TextureData::new(Texture::new_from_color(Vector3::new(0.0, 50.0, 0.0))),
@@ -82,12 +145,30 @@ impl Scene {
 
         let materials = vec![
             Material::Checkerboard { even: 0, odd: 1 },
-            Material::Lambertian { albedo: 2 },
-            Material::Metal { albedo: 3, fuzz: 0.4 },
-            Material::Dielectric { refraction_index: 1.5 },
-            Material::Lambertian { albedo: 4 },
+            Material::Lambertian {
+                albedo: 2,
+                normal_map: None,
+            },
+            Material::Metal {
+                albedo: 3,
+                fuzz: 0.4,
+                normal_map: None,
+            },
+            Material::Dielectric {
+                refraction_index: 1.5,
+                absorption: None,
+                abbe_number: None,
+                thin_film: None,
+            },
+            Material::Lambertian {
+                albedo: 4,
+                normal_map: None,
+            },
             Material::Emissive { emit: 5 },
-            Material::Lambertian { albedo: 6 },
+            Material::Lambertian {
+                albedo: 6,
+                normal_map: None,
+            },
             Material::Emissive { emit: 7 },
             Material::Emissive { emit: 8 },
             Material::Emissive { emit: 9 },
@@ -115,6 +196,21 @@ impl Scene {
             textures,
         }
     }
+
+    /// Returns the index of the closest sphere hit by the ray, the same one the compute shader sees first.
+    pub fn pick(&self, ray: &Ray) -> Option<usize> {
+        let mut closest_t = MAX_T;
+        let mut closest_idx = None;
+
+        for (idx, sphere) in self.spheres.iter().enumerate() {
+            if let Some(t) = sphere.intersect(ray, MIN_T, closest_t) {
+                closest_t = t;
+                closest_idx = Some(idx);
+            }
+        }
+
+        closest_idx
+    }
 }
 
 #[repr(C)]
@@ -138,151 +234,309 @@ impl Sphere {
             _padding: [0; 2],
         }
     }
+
+    pub fn center(&self) -> Vector3 {
+        self.center.xyz()
+    }
+
+    pub fn intersect(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<Float> {
+        let oc = ray.origin - self.center();
+        let a = ray.direction.dot(&ray.direction);
+        let b = oc.dot(&ray.direction);
+        let c = oc.dot(&oc) - self.radius * self.radius;
+        let discriminant = b * b - a * c;
+
+        if discriminant > 0.0 {
+            let sqrt_discriminant = discriminant.sqrt();
+            for t in [(-b - sqrt_discriminant) / a, (-b + sqrt_discriminant) / a] {
+                if t < t_max && t > t_min {
+                    return Some(t);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// How the texture of a [`NormalMap`] perturbs the shading normal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NormalMapKind {
+    /// Texels are tangent space normals, the usual blue-ish normal map images.
+    #[default]
+    TangentSpace,
+    /// Texels are heights, the normal follows the slope of their luminance.
+    Height,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NormalMap {
+    pub texture: TextureId,
+    pub kind: NormalMapKind,
+    /// Scales the slope of the perturbation, 0 keeps the geometric normal.
+    pub strength: f32,
+}
+
+/// Beer-Lambert absorption of light traveling through a dielectric.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Absorption {
+    /// Color of white light after traveling the distance through the material.
+    pub color: Color,
+    pub distance: f32,
+}
+
+impl Absorption {
+    /// Absorption coefficients per unit of distance.
+    fn coefficients(&self) -> [f32; 3] {
+        let distance = self.distance.max(1e-6);
+        [self.color.x, self.color.y, self.color.z].map(|channel| -channel.clamp(1e-6, 1.0).ln() / distance)
+    }
+}
+
+/// A thin layer on the surface of a dielectric, like the soap film of a bubble.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThinFilm {
+    /// Thickness in nanometers, comparable to the wavelengths of visible light.
+    pub thickness: f32,
+    pub refraction_index: f32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Material {
-    Lambertian { albedo: TextureId },
-    Metal { albedo: TextureId, fuzz: f32 },
-    Dielectric { refraction_index: f32 },
-    Checkerboard { even: TextureId, odd: TextureId },
-    Emissive { emit: TextureId },
-}
-
-pub struct GroupData {
-    sphere_buffer: StorageBuffer,
-    material_buffer: StorageBuffer,
-    texture_buffer: StorageBuffer,
-    light_buffer: StorageBuffer,
-    layout: wgpu::BindGroupLayout,
+    Lambertian {
+        albedo: TextureId,
+        #[serde(default)]
+        normal_map: Option<NormalMap>,
+    },
+    Metal {
+        albedo: TextureId,
+        fuzz: f32,
+        #[serde(default)]
+        normal_map: Option<NormalMap>,
+    },
+    Dielectric {
+        /// Refraction index at the Fraunhofer d line, 587.6 nm.
+        refraction_index: f32,
+        #[serde(default)]
+        absorption: Option<Absorption>,
+        /// Abbe number of the dispersion, lower numbers split colors more. Dispersive paths trace a single
+        /// sampled wavelength.
+        #[serde(default)]
+        abbe_number: Option<f32>,
+        #[serde(default)]
+        thin_film: Option<ThinFilm>,
+    },
+    Checkerboard {
+        even: TextureId,
+        odd: TextureId,
+    },
+    Emissive {
+        emit: TextureId,
+    },
+    /// Either of two materials, `second` is picked with the probability of the factor, or of the luminance of the
+    /// mask where it is set.
+    Mix {
+        first: MaterialId,
+        second: MaterialId,
+        factor: f32,
+        mask: Option<TextureId>,
+    },
+    /// A coat on top of a base, such as varnish or dust. The coat is picked with the Fresnel reflectance of a
+    /// dielectric with the refraction index, scaled by the weight.
+    Layered {
+        base: MaterialId,
+        coat: MaterialId,
+        refraction_index: f32,
+        weight: f32,
+    },
 }
 
-pub struct SceneBuffersGroup {
-    data: GroupData,
-    bind_group: wgpu::BindGroup,
-}
+bitflags! {
+    /// Parts of a scene changed since the previous update. Value changes keep the number of elements,
+    /// layout changes can add or remove them.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct SceneDirtyFlags: u32 {
+        const NONE = 0;
 
-impl GroupData {
-    pub fn from_scene(scene: &Scene, device: &wgpu::Device) -> Self {
-        let sphere_buffer = StorageBuffer::new_from_bytes(
-            device,
-            bytemuck::cast_slice(scene.spheres.as_slice()),
-            0,
-            Some("scene buffer"),
-        );
+        const TEXTURE_VALUE = 1;
+        const TEXTURE_LAYOUT = Self::TEXTURE_VALUE.bits() << 1;
 
-        let mut global_texture_data = Vec::new();
-        let mut texture_descriptors = Vec::new();
-        let mut material_data = Vec::with_capacity(scene.materials.len());
+        const MATERIAL_VALUE = Self::TEXTURE_LAYOUT.bits() << 1;
+        const MATERIAL_LAYOUT = Self::MATERIAL_VALUE.bits() << 1;
 
-        for texture in &scene.textures {
-            texture_descriptors.push(append_to_global_texture_data(
-                &texture.texture,
-                &mut global_texture_data,
-            ));
-        }
+        const PRIMITIVE_VALUE = Self::MATERIAL_LAYOUT.bits() << 1;
+        const PRIMITIVE_LAYOUT = Self::PRIMITIVE_VALUE.bits() << 1;
 
-        for material in &scene.materials {
-            let gpu_material = match material {
-                Material::Lambertian { albedo } => GpuMaterial::lambertian(texture_descriptors[*albedo]),
-                Material::Metal { albedo, fuzz } => GpuMaterial::metal(texture_descriptors[*albedo], *fuzz),
-                Material::Dielectric { refraction_index } => GpuMaterial::dielectric(*refraction_index),
-                Material::Checkerboard { odd, even } => {
-                    GpuMaterial::checkerboard(texture_descriptors[*odd], texture_descriptors[*even])
-                },
-                Material::Emissive { emit } => GpuMaterial::emissive(texture_descriptors[*emit]),
-            };
+        const ALL = u32::MAX;
+        const INIT = Self::ALL.bits() - 1;
 
-            material_data.push(gpu_material);
-        }
+        const TEXTURE = Self::TEXTURE_VALUE.bits() | Self::TEXTURE_LAYOUT.bits();
+        const MATERIAL = Self::MATERIAL_VALUE.bits() | Self::MATERIAL_LAYOUT.bits();
+        const PRIMITIVE = Self::PRIMITIVE_VALUE.bits() | Self::PRIMITIVE_LAYOUT.bits();
+    }
+}
 
-        let material_buffer = StorageBuffer::new_from_bytes(
-            device,
-            bytemuck::cast_slice(material_data.as_slice()),
-            1,
-            Some("materials buffer"),
-        );
+impl Default for SceneDirtyFlags {
+    fn default() -> Self {
+        SceneDirtyFlags::INIT
+    }
+}
 
-        let texture_buffer = StorageBuffer::new_from_bytes(
-            device,
-            bytemuck::cast_slice(global_texture_data.as_slice()),
-            2,
-            Some("textures buffer"),
-        );
+/// Mirrors the `SceneCounts` shader struct.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuSceneCounts {
+    num_spheres: u32,
+    num_lights: u32,
+    _padding: [u32; 2],
+}
 
-        let light_indices: Vec<u32> = scene
-            .spheres
-            .iter()
-            .enumerate()
-            .filter(|(_, s)| matches!(scene.materials[s.material_idx as usize], Material::Emissive { .. }))
-            .map(|(idx, _)| idx as u32)
-            .collect();
+pub struct SceneBuffersGroup {
+    sphere_buffer: GrowableStorageBuffer<Sphere>,
+    material_buffer: GrowableStorageBuffer<GpuMaterial>,
+    texture_info_buffer: GrowableStorageBuffer<[u32; 4]>,
+    light_buffer: GrowableStorageBuffer<u32>,
+    counts_buffer: UniformBuffer,
+    atlases: [TextureAtlas; 2],
+    /// Images written to the atlases, the unchanged ones are not written again.
+    atlas_contents: [AtlasContents; 2],
+    /// Mip levels of the image textures, reused while their images stay the same.
+    texture_levels: Vec<TextureLevels>,
+    texture_descriptors: Vec<TextureDescriptor>,
 
-        let light_buffer = StorageBuffer::new_from_bytes(
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl SceneBuffersGroup {
+    pub fn new(scene: &Scene, device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let textures = pack_textures(scene, device.limits().max_texture_dimension_2d, &[]);
+        let material_data = material_data(scene, &textures.descriptors);
+
+        let sphere_buffer = GrowableStorageBuffer::new(device, scene.spheres.clone(), 0, Some("scene buffer"));
+        let material_buffer = GrowableStorageBuffer::new(device, material_data, 1, Some("materials buffer"));
+        let texture_info_buffer = GrowableStorageBuffer::new(device, textures.info, 2, Some("texture info buffer"));
+        let light_buffer = GrowableStorageBuffer::new(device, light_indices(scene), 3, Some("lights buffer"));
+        let counts_buffer = UniformBuffer::new_from_bytes(
             device,
-            bytemuck::cast_slice(light_indices.as_slice()),
-            3,
-            Some("lights buffer"),
+            bytemuck::bytes_of(&scene_counts(scene, light_buffer.elements())),
+            4,
+            Some("scene counts buffer"),
         );
+        let atlases = AtlasFormat::ALL.map(|format| {
+            let contents = &textures.atlases[format.index()];
+            let atlas = TextureAtlas::new(
+                device,
+                format,
+                textures.layer_size,
+                contents.layers,
+                5 + format.index() as u32,
+            );
+            contents.write(&atlas, queue, None);
+            atlas
+        });
 
-        let scene_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
-                sphere_buffer.layout(wgpu::ShaderStages::COMPUTE, true),
-                material_buffer.layout(wgpu::ShaderStages::COMPUTE, true),
-                texture_buffer.layout(wgpu::ShaderStages::COMPUTE, true),
-                light_buffer.layout(wgpu::ShaderStages::COMPUTE, true),
+                sphere_buffer.buffer().layout(wgpu::ShaderStages::COMPUTE, true),
+                material_buffer.buffer().layout(wgpu::ShaderStages::COMPUTE, true),
+                texture_info_buffer.buffer().layout(wgpu::ShaderStages::COMPUTE, true),
+                light_buffer.buffer().layout(wgpu::ShaderStages::COMPUTE, true),
+                counts_buffer.layout(wgpu::ShaderStages::COMPUTE),
+                atlases[0].layout(wgpu::ShaderStages::COMPUTE),
+                atlases[1].layout(wgpu::ShaderStages::COMPUTE),
             ],
             label: Some("scene layout"),
         });
+        let bind_group = create_bind_group(
+            device,
+            &layout,
+            [
+                sphere_buffer.buffer(),
+                material_buffer.buffer(),
+                texture_info_buffer.buffer(),
+                light_buffer.buffer(),
+            ],
+            &counts_buffer,
+            &atlases,
+        );
 
         Self {
             sphere_buffer,
             material_buffer,
-            texture_buffer,
+            texture_info_buffer,
             light_buffer,
-            layout: scene_bind_group_layout,
+            counts_buffer,
+            atlases,
+            atlas_contents: textures.atlases,
+            texture_levels: textures.levels,
+            texture_descriptors: textures.descriptors,
+            layout,
+            bind_group,
         }
     }
 
-    pub fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
-        device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.layout,
-            entries: &[
-                self.sphere_buffer.binding(),
-                self.material_buffer.binding(),
-                self.texture_buffer.binding(),
-                self.light_buffer.binding(),
-            ],
-            label: Some("scene bind group"),
-        })
-    }
-}
+    /// Uploads the parts of the scene marked as dirty. Only the changed elements are written, buffers are
+    /// reallocated only when the scene outgrows them.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene, dirty: SceneDirtyFlags) {
+        let mut reallocated = false;
 
-impl SceneBuffersGroup {
-    pub fn new(scene: &Scene, device: &wgpu::Device) -> Self {
-        let data = GroupData::from_scene(scene, device);
-        let scene_bind_group = data.create_bind_group(device);
+        // Material colors are stored as solid color textures. Packing keeps the placements of the images as long as
+        // the sizes stay the same, and only the images which aren't in the atlases yet are written.
+        let materials_dirty = dirty.intersects(SceneDirtyFlags::TEXTURE | SceneDirtyFlags::MATERIAL);
+        if materials_dirty {
+            let textures = pack_textures(scene, device.limits().max_texture_dimension_2d, &self.texture_levels);
+            reallocated |= self.texture_info_buffer.update(device, queue, textures.info);
+            for format in AtlasFormat::ALL {
+                let contents = &textures.atlases[format.index()];
+                let atlas = &mut self.atlases[format.index()];
+                let previous = if atlas.fits(textures.layer_size, contents.layers) {
+                    Some(&self.atlas_contents[format.index()])
+                } else {
+                    *atlas = TextureAtlas::new(
+                        device,
+                        format,
+                        textures.layer_size,
+                        contents.layers,
+                        5 + format.index() as u32,
+                    );
+                    reallocated = true;
+                    None
+                };
+                contents.write(atlas, queue, previous);
+            }
+            self.atlas_contents = textures.atlases;
+            self.texture_levels = textures.levels;
+            self.texture_descriptors = textures.descriptors;
 
-        Self {
-            data,
-            bind_group: scene_bind_group,
+            // Materials refer to textures by their offsets in the texture info buffer.
+            let material_data = material_data(scene, &self.texture_descriptors);
+            reallocated |= self.material_buffer.update(device, queue, material_data);
         }
-    }
 
-    pub fn update(&mut self, device: &wgpu::Device, _queue: &wgpu::Queue, scene: &Scene) {
-        // if new_spheres.len() > self.data.sphere_count {
-        //     self.data.sphere_count = new_spheres.len();
-        // let new_size = (self.capacity * std::mem::size_of::<T>()) as u64;
+        // Lights are the spheres with emissive materials.
+        if materials_dirty || dirty.intersects(SceneDirtyFlags::PRIMITIVE) {
+            reallocated |= self.sphere_buffer.update(device, queue, scene.spheres.clone());
+            reallocated |= self.light_buffer.update(device, queue, light_indices(scene));
 
-        // self.data.need_recreate
-        self.data = GroupData::from_scene(scene, device);
-        // self.data.sphere_buffer =
-        //     StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(new_spheres), 0, Some("scene buffer"));
+            let counts = scene_counts(scene, self.light_buffer.elements());
+            queue.write_buffer(self.counts_buffer.handle(), 0, bytemuck::bytes_of(&counts));
+        }
 
-        self.bind_group = self.data.create_bind_group(device);
-        // } else {
-        //     queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(new_data));
-        // }
+        if reallocated {
+            self.bind_group = create_bind_group(
+                device,
+                &self.layout,
+                [
+                    self.sphere_buffer.buffer(),
+                    self.material_buffer.buffer(),
+                    self.texture_info_buffer.buffer(),
+                    self.light_buffer.buffer(),
+                ],
+                &self.counts_buffer,
+                &self.atlases,
+            );
+        }
     }
 
     pub fn bind_group(&self) -> &wgpu::BindGroup {
@@ -290,7 +544,272 @@ impl SceneBuffersGroup {
     }
 
     pub fn layout(&self) -> &wgpu::BindGroupLayout {
-        &self.data.layout
+        &self.layout
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    storage_buffers: [&StorageBuffer; 4],
+    counts_buffer: &UniformBuffer,
+    atlases: &[TextureAtlas; 2],
+) -> wgpu::BindGroup {
+    let [sphere_buffer, material_buffer, texture_info_buffer, light_buffer] = storage_buffers;
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            sphere_buffer.binding(),
+            material_buffer.binding(),
+            texture_info_buffer.binding(),
+            light_buffer.binding(),
+            counts_buffer.binding(),
+            atlases[0].binding(),
+            atlases[1].binding(),
+        ],
+        label: Some("scene bind group"),
+    })
+}
+
+/// Textures of a scene laid out for the renderer: placements of images and parameters of procedural textures in
+/// the texture info buffer, texels in the atlases.
+struct PackedTextures {
+    info: Vec<[u32; 4]>,
+    descriptors: Vec<TextureDescriptor>,
+    layer_size: u32,
+    /// Indexed by [`AtlasFormat::index`].
+    atlases: [AtlasContents; 2],
+    levels: Vec<TextureLevels>,
+}
+
+#[derive(Default)]
+struct AtlasContents {
+    layers: u32,
+    images: Vec<(AtlasPlacement, Arc<Texture>)>,
+}
+
+impl AtlasContents {
+    /// Writes the images, skipping those which the `previous` contents of the atlas hold at the same placements.
+    fn write(&self, atlas: &TextureAtlas, queue: &wgpu::Queue, previous: Option<&AtlasContents>) {
+        let written = previous.map_or_else(HashSet::new, |previous| {
+            previous
+                .images
+                .iter()
+                .map(|(placement, image)| (*placement, Arc::as_ptr(image)))
+                .collect()
+        });
+        for (placement, image) in &self.images {
+            if !written.contains(&(*placement, Arc::as_ptr(image))) {
+                atlas.write(queue, *placement, image);
+            }
+        }
+    }
+}
+
+/// Mip levels of an image texture, fitted into an atlas layer.
+#[derive(Clone)]
+struct TextureLevels {
+    image: Arc<Texture>,
+    /// See [`content_hash`].
+    hash: u64,
+    mipmaps: bool,
+    format: AtlasFormat,
+    levels: Vec<Arc<Texture>>,
+}
+
+/// Hash of the texels of an image. Equal images may still hash differently, e.g. for zeros of different signs, which
+/// only costs a cache miss.
+fn content_hash(image: &Texture) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.dimensions().hash(&mut hasher);
+    bytemuck::cast_slice::<_, u8>(image.as_slice()).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Finds the mip levels of images among the ones of the previous packing, so unchanged images are neither
+/// downsampled nor written to the atlases again.
+struct LevelCache {
+    max_layer_size: u32,
+    /// Hashes of the images of the previous packing, which are kept alive by `cached`, so shared images aren't
+    /// hashed again.
+    hashes: HashMap<*const Texture, u64>,
+    /// Levels of the previous packing and of the current one, by the hash of the image and whether it has mipmaps.
+    cached: HashMap<(u64, bool), Vec<TextureLevels>>,
+    current: Vec<TextureLevels>,
+}
+
+impl LevelCache {
+    fn new(max_layer_size: u32, previous: &[TextureLevels]) -> Self {
+        let mut cache = Self {
+            max_layer_size,
+            hashes: HashMap::new(),
+            cached: HashMap::new(),
+            current: Vec::new(),
+        };
+        for levels in previous {
+            cache.hashes.insert(Arc::as_ptr(&levels.image), levels.hash);
+            cache.insert(levels.clone());
+        }
+        cache
+    }
+
+    fn insert(&mut self, levels: TextureLevels) {
+        self.cached
+            .entry((levels.hash, levels.mipmaps))
+            .or_default()
+            .push(levels);
+    }
+
+    fn levels(&mut self, image: &Arc<Texture>, mipmaps: bool) -> TextureLevels {
+        let hash = match self.hashes.get(&Arc::as_ptr(image)) {
+            Some(&hash) => hash,
+            None => content_hash(image),
+        };
+        let cached = self.cached.get(&(hash, mipmaps)).and_then(|cached| {
+            cached
+                .iter()
+                .find(|cached| Arc::ptr_eq(&cached.image, image) || cached.image == *image)
+        });
+
+        let levels = match cached {
+            Some(cached) => TextureLevels {
+                image: image.clone(),
+                ..cached.clone()
+            },
+            None => {
+                // Images larger than an atlas layer are downsampled to fit.
+                let mut level = image.clone();
+                while let (width, height) = level.dimensions()
+                    && width.max(height) > self.max_layer_size
+                {
+                    level = Arc::new(level.downsampled());
+                }
+                let levels = TextureLevels {
+                    image: image.clone(),
+                    hash,
+                    mipmaps,
+                    // Downsampled levels stay within the range of the full image.
+                    format: AtlasFormat::for_texture(&level),
+                    levels: if mipmaps { level.mip_chain() } else { vec![level] },
+                };
+                self.insert(levels.clone());
+                levels
+            },
+        };
+        self.current.push(levels.clone());
+        levels
+    }
+}
+
+/// Packs the images of all textures into atlas layers of a common size, the smallest power of two holding the
+/// largest image but not more than `max_layer_size`. The mip levels of the images found in `previous_levels` are
+/// reused.
+fn pack_textures(scene: &Scene, max_layer_size: u32, previous_levels: &[TextureLevels]) -> PackedTextures {
+    let mut info = Vec::new();
+    // Images of each format along with the index of their placement in `info`.
+    let mut images: [Vec<(usize, Arc<Texture>)>; 2] = Default::default();
+    let mut level_cache = LevelCache::new(max_layer_size, previous_levels);
+    let descriptors = scene
+        .textures
+        .iter()
+        .map(|texture| append_texture(texture, &mut level_cache, &mut info, &mut images))
+        .collect();
+
+    let largest_side = images
+        .iter()
+        .flatten()
+        .map(|(_, image)| {
+            let (width, height) = image.dimensions();
+            width.max(height)
+        })
+        .max()
+        .unwrap_or(1);
+    let layer_size = largest_side.next_power_of_two().clamp(MIN_LAYER_SIZE, max_layer_size);
+
+    let atlases = AtlasFormat::ALL.map(|format| {
+        let images = std::mem::take(&mut images[format.index()]);
+        let sizes = images.iter().map(|(_, image)| image.dimensions()).collect::<Vec<_>>();
+        let (placements, layers) = pack_shelves(&sizes, layer_size);
+
+        let images = images
+            .into_iter()
+            .zip(placements)
+            .map(|((info_idx, image), placement)| {
+                info[info_idx] = [placement.layer, placement.x, placement.y, format.index() as u32];
+                (placement, image)
+            })
+            .collect();
+        AtlasContents { layers, images }
+    });
+
+    PackedTextures {
+        info,
+        descriptors,
+        layer_size,
+        atlases,
+        levels: level_cache.current,
+    }
+}
+
+fn material_data(scene: &Scene, texture_descriptors: &[TextureDescriptor]) -> Vec<GpuMaterial> {
+    scene
+        .materials
+        .iter()
+        .map(|material| match material {
+            Material::Lambertian { albedo, normal_map } => {
+                GpuMaterial::lambertian(texture_descriptors[*albedo]).with_normal_map(*normal_map, texture_descriptors)
+            },
+            Material::Metal {
+                albedo,
+                fuzz,
+                normal_map,
+            } => GpuMaterial::metal(texture_descriptors[*albedo], *fuzz)
+                .with_normal_map(*normal_map, texture_descriptors),
+            Material::Dielectric {
+                refraction_index,
+                absorption,
+                abbe_number,
+                thin_film,
+            } => GpuMaterial::dielectric(*refraction_index, *absorption, *abbe_number, *thin_film),
+            Material::Checkerboard { odd, even } => {
+                GpuMaterial::checkerboard(texture_descriptors[*odd], texture_descriptors[*even])
+            },
+            Material::Emissive { emit } => GpuMaterial::emissive(texture_descriptors[*emit]),
+            Material::Mix {
+                first,
+                second,
+                factor,
+                mask,
+            } => GpuMaterial::mix(
+                [*first, *second],
+                *factor,
+                mask.map_or_else(TextureDescriptor::empty, |mask| texture_descriptors[mask]),
+            ),
+            Material::Layered {
+                base,
+                coat,
+                refraction_index,
+                weight,
+            } => GpuMaterial::layered([*base, *coat], *refraction_index, *weight),
+        })
+        .collect()
+}
+
+fn light_indices(scene: &Scene) -> Vec<u32> {
+    scene
+        .spheres
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| matches!(scene.materials[s.material_idx as usize], Material::Emissive { .. }))
+        .map(|(idx, _)| idx as u32)
+        .collect()
+}
+
+fn scene_counts(scene: &Scene, light_indices: &[u32]) -> GpuSceneCounts {
+    GpuSceneCounts {
+        num_spheres: scene.spheres.len() as u32,
+        num_lights: light_indices.len() as u32,
+        _padding: [0; 2],
     }
 }
 
@@ -301,80 +820,293 @@ struct GpuMaterial {
     desc1: TextureDescriptor,
     desc2: TextureDescriptor,
     x: f32,
+    normal_map: TextureDescriptor,
+    /// 0 is no normal map, 1 is a tangent space normal map and 2 is a height map.
+    normal_map_kind: u32,
+    normal_strength: f32,
+    /// Blended materials of mix and layered materials.
+    materials: [u32; 2],
+    y: f32,
+    absorption: [f32; 3],
+    /// 0 is no thin film.
+    film_thickness: f32,
+    film_refraction_index: f32,
 }
 
 impl GpuMaterial {
-    pub fn lambertian(albedo: TextureDescriptor) -> Self {
+    fn new(id: u32, desc1: TextureDescriptor, desc2: TextureDescriptor, x: f32) -> Self {
         Self {
-            id: 0,
-            desc1: albedo,
-            desc2: TextureDescriptor::empty(),
-            x: 0.0,
+            id,
+            desc1,
+            desc2,
+            x,
+            normal_map: TextureDescriptor::empty(),
+            normal_map_kind: 0,
+            normal_strength: 0.0,
+            materials: [0; 2],
+            y: 0.0,
+            absorption: [0.0; 3],
+            film_thickness: 0.0,
+            film_refraction_index: 0.0,
         }
     }
 
-    pub fn metal(albedo: TextureDescriptor, fuzz: f32) -> Self {
-        Self {
-            id: 1,
-            desc1: albedo,
-            desc2: TextureDescriptor::empty(),
-            x: fuzz,
+    pub fn with_normal_map(self, normal_map: Option<NormalMap>, texture_descriptors: &[TextureDescriptor]) -> Self {
+        match normal_map {
+            Some(normal_map) => Self {
+                normal_map: texture_descriptors[normal_map.texture],
+                normal_map_kind: match normal_map.kind {
+                    NormalMapKind::TangentSpace => 1,
+                    NormalMapKind::Height => 2,
+                },
+                normal_strength: normal_map.strength,
+                ..self
+            },
+            None => self,
         }
     }
 
-    pub fn dielectric(refraction_index: f32) -> Self {
+    pub fn lambertian(albedo: TextureDescriptor) -> Self {
+        Self::new(0, albedo, TextureDescriptor::empty(), 0.0)
+    }
+
+    pub fn metal(albedo: TextureDescriptor, fuzz: f32) -> Self {
+        Self::new(1, albedo, TextureDescriptor::empty(), fuzz)
+    }
+
+    /// The Abbe number is stored as `y`, 0 is no dispersion.
+    pub fn dielectric(
+        refraction_index: f32,
+        absorption: Option<Absorption>,
+        abbe_number: Option<f32>,
+        thin_film: Option<ThinFilm>,
+    ) -> Self {
         Self {
-            id: 2,
-            desc1: TextureDescriptor::empty(),
-            desc2: TextureDescriptor::empty(),
-            x: refraction_index,
+            y: abbe_number.map_or(0.0, |abbe_number| abbe_number.max(1.0)),
+            absorption: absorption.map_or([0.0; 3], |absorption| absorption.coefficients()),
+            film_thickness: thin_film.map_or(0.0, |film| film.thickness.max(0.0)),
+            film_refraction_index: thin_film.map_or(0.0, |film| film.refraction_index),
+            ..Self::new(
+                2,
+                TextureDescriptor::empty(),
+                TextureDescriptor::empty(),
+                refraction_index,
+            )
         }
     }
 
     pub fn checkerboard(even: TextureDescriptor, odd: TextureDescriptor) -> Self {
+        Self::new(3, even, odd, 0.0)
+    }
+
+    pub fn emissive(emit: TextureDescriptor) -> Self {
+        Self::new(4, emit, TextureDescriptor::empty(), 0.0)
+    }
+
+    pub fn mix(materials: [MaterialId; 2], factor: f32, mask: TextureDescriptor) -> Self {
         Self {
-            id: 3,
-            desc1: even,
-            desc2: odd,
-            x: 0.0,
+            materials: materials.map(|material| material as u32),
+            ..Self::new(5, mask, TextureDescriptor::empty(), factor)
         }
     }
 
-    pub fn emissive(emit: TextureDescriptor) -> Self {
+    pub fn layered(materials: [MaterialId; 2], refraction_index: f32, weight: f32) -> Self {
         Self {
-            id: 4,
-            desc1: emit,
-            desc2: TextureDescriptor::empty(),
-            x: 0.0,
+            materials: materials.map(|material| material as u32),
+            y: weight,
+            ..Self::new(
+                6,
+                TextureDescriptor::empty(),
+                TextureDescriptor::empty(),
+                refraction_index,
+            )
         }
     }
 }
 
-fn append_to_global_texture_data(texture: &Texture, global_texture_data: &mut Vec<[f32; 3]>) -> TextureDescriptor {
-    let dimensions = texture.dimensions();
-    let offset = global_texture_data.len() as u32;
-    global_texture_data.extend_from_slice(texture.as_slice());
+/// Appends an info entry for the texture and each of its mip levels, if they are enabled, and queues their images for
+/// packing into the atlas of their format. Procedural textures store their parameters in the info entries instead.
+fn append_texture(
+    texture: &TextureData,
+    level_cache: &mut LevelCache,
+    info: &mut Vec<[u32; 4]>,
+    images: &mut [Vec<(usize, Arc<Texture>)>; 2],
+) -> TextureDescriptor {
+    let offset = info.len() as u32;
+
+    let TextureLevels { format, levels, .. } = match &texture.source {
+        TextureSource::Image(image) => level_cache.levels(image, texture.sampler.mipmaps),
+        TextureSource::Procedural(procedural) => {
+            info.extend(
+                procedural
+                    .gpu_data()
+                    .map(|[x, y, z]| [x.to_bits(), y.to_bits(), z.to_bits(), 0]),
+            );
+            return TextureDescriptor {
+                kind: procedural.pattern.kind(),
+                offset,
+                levels: 1,
+                ..TextureDescriptor::empty()
+            };
+        },
+    };
+    let dimensions = levels[0].dimensions();
+    let level_count = levels.len() as u32;
+
+    for level in levels {
+        images[format.index()].push((info.len(), level));
+        // The placement is filled in once all images are packed.
+        info.push([0; 4]);
+    }
+
+    let sampler = texture.sampler;
     TextureDescriptor {
+        kind: 0,
         width: dimensions.0,
         height: dimensions.1,
         offset,
+        levels: level_count,
+        filter_mode: match sampler.filter {
+            TextureFilter::Nearest => 0,
+            TextureFilter::Bilinear => 1,
+        },
+        wrap_mode: match sampler.wrap {
+            TextureWrap::Repeat => 0,
+            TextureWrap::Clamp => 1,
+            TextureWrap::Mirror => 2,
+        },
+        uv_scale: sampler.uv_scale,
+        uv_offset: sampler.uv_offset,
     }
 }
 
+/// Mirrors the `TextureDescriptor` shader struct, which has scalar fields only so that it stays 4 byte aligned.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct TextureDescriptor {
+    /// 0 is an image, others are procedural patterns with parameters at `offset`.
+    kind: u32,
     width: u32,
     height: u32,
+    /// Index of the first entry in the texture info buffer.
     offset: u32,
+    /// Number of mip levels, each with its atlas placement in the entries following `offset`.
+    levels: u32,
+    filter_mode: u32,
+    wrap_mode: u32,
+    uv_scale: [f32; 2],
+    uv_offset: [f32; 2],
 }
 
 impl TextureDescriptor {
     pub fn empty() -> Self {
         Self {
+            kind: 0,
             width: 0,
             height: 0,
             offset: 0xffffffff,
+            levels: 0,
+            filter_mode: 0,
+            wrap_mode: 0,
+            uv_scale: [1.0, 1.0],
+            uv_offset: [0.0, 0.0],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reactor_types::rect::RectSize;
+
+    use super::*;
+    use crate::{Angle, Camera};
+
+    fn scene_of(spheres: Vec<Sphere>) -> Scene {
+        Scene {
+            spheres,
+            ..Scene::default()
+        }
+    }
+
+    #[test]
+    fn test_pick_misses_spheres_off_the_ray() {
+        let scene = scene_of(vec![Sphere::new(Vector3::new(0.0, 0.0, -5.0), 1.0, 0)]);
+        let ray = Ray::new(Vector3::zeros(), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(scene.pick(&ray), None);
+    }
+
+    #[test]
+    fn test_pick_returns_the_closest_of_overlapping_spheres() {
+        let scene = scene_of(vec![
+            Sphere::new(Vector3::new(0.0, 0.0, -5.0), 1.0, 0),
+            Sphere::new(Vector3::new(0.0, 0.0, -4.5), 1.0, 0),
+        ]);
+        let ray = Ray::new(Vector3::zeros(), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(scene.pick(&ray), Some(1));
+    }
+
+    #[test]
+    fn test_ray_from_inside_hits_the_far_side() {
+        let sphere = Sphere::new(Vector3::zeros(), 2.0, 0);
+        let ray = Ray::new(Vector3::zeros(), Vector3::new(1.0, 0.0, 0.0));
+        let t = sphere.intersect(&ray, MIN_T, MAX_T).unwrap();
+        assert!((t - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_projection_inverts_camera_rays() {
+        let camera = Camera {
+            eye_pos: Vector3::new(1.0, 2.0, 3.0),
+            eye_dir: Vector3::new(0.0, 0.0, -1.0),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            vfov: Angle::degrees(60.0),
+            aperture: 0.0,
+            focus_distance: 2.0,
+        };
+        let viewport_size = RectSize::from((200, 100));
+
+        for (u, v) in [(0.5, 0.5), (0.1, 0.9), (0.8, 0.25)] {
+            let ray = camera.make_ray(viewport_size, u, v);
+            let point = ray.origin + 7.0 * ray.direction;
+            let (projected_u, projected_v) = camera.project(viewport_size, point).unwrap();
+            assert!((projected_u - u).abs() < 1e-5, "{projected_u} != {u}");
+            assert!((projected_v - v).abs() < 1e-5, "{projected_v} != {v}");
+        }
+    }
+
+    #[test]
+    fn test_absorption_reaches_color_at_distance() {
+        let absorption = Absorption {
+            color: Color::new(0.5, 1.0, 0.25),
+            distance: 2.0,
+        };
+
+        let transmittance = absorption.coefficients().map(|coefficient| (-coefficient * 2.0).exp());
+        for (transmitted, expected) in transmittance.iter().zip([0.5, 1.0, 0.25]) {
+            assert!((transmitted - expected).abs() < 1e-5);
         }
     }
+
+    #[test]
+    fn test_packing_reuses_unchanged_images() {
+        let color = |red| TextureData::new(Texture::new_from_color(Vector3::new(red, 0.5, 0.5)));
+        let mut scene = Scene {
+            textures: vec![color(0.1), color(0.2)],
+            ..Scene::default()
+        };
+        let first = pack_textures(&scene, MIN_LAYER_SIZE, &[]);
+
+        // Scenes are rebuilt on every change, so even the unchanged colors are new images.
+        scene.textures = vec![color(0.1), color(0.3)];
+        let second = pack_textures(&scene, MIN_LAYER_SIZE, &first.levels);
+
+        let [first, second] = [first, second].map(|packed| packed.atlases[AtlasFormat::Ldr.index()].images.clone());
+        assert_eq!(first.len(), 2);
+        assert_eq!(
+            first.iter().map(|(placement, _)| placement).collect::<Vec<_>>(),
+            second.iter().map(|(placement, _)| placement).collect::<Vec<_>>()
+        );
+        assert!(Arc::ptr_eq(&first[0].1, &second[0].1));
+        assert!(!Arc::ptr_eq(&first[1].1, &second[1].1));
+    }
 }
@@ -0,0 +1,394 @@
+//! Numbered image sequences of rendered animations and the videos made from them.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbImage, RgbaImage};
+use reactor_types::rect::RectSize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::aov::{ExportError, RenderPass, RenderedPasses};
+use crate::atlas::linear_to_srgb8;
+
+/// Frames of an animation from `start` to `end` seconds, both included.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameRange {
+    pub start: f32,
+    pub end: f32,
+    pub fps: u32,
+}
+
+impl FrameRange {
+    pub fn len(&self) -> u32 {
+        if self.fps == 0 || self.end < self.start {
+            return 0;
+        }
+        // The tolerance keeps the end frame when the duration isn't exactly representable.
+        ((self.end - self.start) * self.fps as f32 + 1e-3).floor() as u32 + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Time of the frame with the index, counted from the start of the range.
+    pub fn time(&self, frame: u32) -> f32 {
+        self.start + frame as f32 / self.fps as f32
+    }
+
+    pub fn times(&self) -> impl Iterator<Item = f32> {
+        (0..self.len()).map(|frame| self.time(frame))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImageFormat {
+    /// 8-bit sRGB image of the beauty pass.
+    #[default]
+    Png,
+    /// Linear OpenEXR images of every rendered pass.
+    Exr,
+}
+
+impl ImageFormat {
+    pub const ALL: [Self; 2] = [Self::Png, Self::Exr];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Exr => "OpenEXR",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Exr => "exr",
+        }
+    }
+}
+
+/// Video encoded from the beauty pass of the frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoFormat {
+    /// Looping animated GIF, limited to 256 colors per frame.
+    Gif,
+    /// Uncompressed YUV4MPEG2 stream, which video encoders like FFmpeg read directly.
+    Y4m,
+}
+
+impl VideoFormat {
+    pub const ALL: [Self; 2] = [Self::Gif, Self::Y4m];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gif => "Animated GIF",
+            Self::Y4m => "YUV4MPEG2",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Y4m => "y4m",
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SequenceError {
+    #[error("the frame has size {0}x{1} but the video has size {2}x{3}")]
+    FrameSize(u32, u32, u32, u32),
+    #[error("the frame has no beauty pass")]
+    MissingBeauty,
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+}
+
+/// Writes rendered frames into a directory as numbered images, like `beauty_0000.png`, and optionally encodes them
+/// into `animation.gif` or `animation.y4m` next to the images.
+pub struct SequenceWriter {
+    directory: PathBuf,
+    image_format: ImageFormat,
+    video_format: Option<VideoFormat>,
+    fps: u32,
+    video: Option<VideoWriter>,
+    next_frame: u32,
+}
+
+impl SequenceWriter {
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        image_format: ImageFormat,
+        video_format: Option<VideoFormat>,
+        fps: u32,
+    ) -> Result<Self, SequenceError> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            image_format,
+            video_format,
+            fps: fps.max(1),
+            video: None,
+            next_frame: 0,
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Number of the frames written so far.
+    pub fn frames(&self) -> u32 {
+        self.next_frame
+    }
+
+    pub fn write_frame(&mut self, passes: &RenderedPasses) -> Result<(), SequenceError> {
+        let frame = self.next_frame;
+        match self.image_format {
+            ImageFormat::Png => {
+                let image = beauty_srgb8(passes)?;
+                image.save(
+                    self.directory
+                        .join(frame_file_name(RenderPass::Beauty, frame, ImageFormat::Png)),
+                )?;
+            },
+            ImageFormat::Exr => passes.save_named(&self.directory, |pass| frame_file_stem(pass, frame))?,
+        }
+
+        if let Some(video_format) = self.video_format {
+            let image = beauty_srgb8(passes)?;
+            let video = match &mut self.video {
+                Some(video) => video,
+                None => self.video.insert(VideoWriter::new(
+                    &self.directory.join(format!("animation.{}", video_format.extension())),
+                    video_format,
+                    passes.size,
+                    self.fps,
+                )?),
+            };
+            video.write_frame(&image)?;
+        }
+
+        self.next_frame += 1;
+        Ok(())
+    }
+
+    /// Flushes the video, returns its path when one was written.
+    pub fn finish(self) -> Result<Option<PathBuf>, SequenceError> {
+        match self.video {
+            Some(video) => video.finish().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn frame_file_stem(pass: RenderPass, frame: u32) -> String {
+    format!("{}_{frame:04}", pass.file_stem())
+}
+
+pub fn frame_file_name(pass: RenderPass, frame: u32, format: ImageFormat) -> String {
+    format!("{}.{}", frame_file_stem(pass, frame), format.extension())
+}
+
+fn beauty_srgb8(passes: &RenderedPasses) -> Result<RgbImage, SequenceError> {
+    let pixels = passes.get(RenderPass::Beauty).ok_or(SequenceError::MissingBeauty)?;
+    let bytes = pixels.iter().flatten().map(|&value| linear_to_srgb8(value)).collect();
+    Ok(RgbImage::from_raw(passes.size.width, passes.size.height, bytes)
+        .expect("Pass must have a pixel for every point of the image"))
+}
+
+enum VideoWriter {
+    Gif {
+        path: PathBuf,
+        size: RectSize<u32>,
+        delay: Delay,
+        encoder: GifEncoder<BufWriter<File>>,
+    },
+    Y4m {
+        path: PathBuf,
+        size: RectSize<u32>,
+        file: BufWriter<File>,
+    },
+}
+
+impl VideoWriter {
+    fn new(path: &Path, format: VideoFormat, size: RectSize<u32>, fps: u32) -> Result<Self, SequenceError> {
+        let file = BufWriter::new(File::create(path)?);
+        let path = path.to_owned();
+
+        match format {
+            VideoFormat::Gif => {
+                let mut encoder = GifEncoder::new(file);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Ok(Self::Gif {
+                    path,
+                    size,
+                    delay: Delay::from_numer_denom_ms(1000, fps),
+                    encoder,
+                })
+            },
+            VideoFormat::Y4m => {
+                let mut file = file;
+                writeln!(file, "YUV4MPEG2 W{} H{} F{fps}:1 Ip A1:1 C444", size.width, size.height)?;
+                Ok(Self::Y4m { path, size, file })
+            },
+        }
+    }
+
+    fn write_frame(&mut self, image: &RgbImage) -> Result<(), SequenceError> {
+        let (Self::Gif { size, .. } | Self::Y4m { size, .. }) = self;
+        if image.dimensions() != (size.width, size.height) {
+            return Err(SequenceError::FrameSize(
+                image.width(),
+                image.height(),
+                size.width,
+                size.height,
+            ));
+        }
+
+        match self {
+            Self::Gif { delay, encoder, .. } => {
+                let rgba = RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+                    let [r, g, b] = image.get_pixel(x, y).0;
+                    [r, g, b, u8::MAX].into()
+                });
+                encoder.encode_frame(Frame::from_parts(rgba, 0, 0, *delay))?;
+            },
+            Self::Y4m { file, .. } => {
+                // Planar 4:4:4, every plane has a sample for every pixel.
+                let yuv = image.pixels().map(|pixel| rgb_to_yuv(pixel.0)).collect::<Vec<_>>();
+                file.write_all(b"FRAME\n")?;
+                for plane in 0..3 {
+                    let samples = yuv.iter().map(|sample| sample[plane]).collect::<Vec<_>>();
+                    file.write_all(&samples)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PathBuf, SequenceError> {
+        match self {
+            Self::Gif { path, encoder, .. } => {
+                // The encoder writes the trailer of the GIF when it's dropped.
+                drop(encoder);
+                Ok(path)
+            },
+            Self::Y4m { path, mut file, .. } => {
+                file.flush()?;
+                Ok(path)
+            },
+        }
+    }
+}
+
+/// Converts a gamma encoded color to the limited range BT.601 luma and chroma, which Y4M players assume.
+fn rgb_to_yuv([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let [r, g, b] = [r, g, b].map(|channel| f32::from(channel) / 255.0);
+    let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+    let u = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+    let v = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+    [y, u, v].map(|sample| sample.round().clamp(0.0, 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passes(value: f32) -> RenderedPasses {
+        RenderedPasses {
+            size: RectSize { width: 2, height: 1 },
+            passes: vec![(RenderPass::Beauty, vec![[value; 3]; 2])],
+        }
+    }
+
+    #[test]
+    fn test_frame_range_includes_both_ends() {
+        let range = FrameRange {
+            start: 1.0,
+            end: 2.0,
+            fps: 4,
+        };
+        assert_eq!(range.len(), 5);
+        assert_eq!(range.times().collect::<Vec<_>>(), [1.0, 1.25, 1.5, 1.75, 2.0]);
+
+        let tenths = FrameRange {
+            start: 0.0,
+            end: 0.3,
+            fps: 10,
+        };
+        assert_eq!(tenths.len(), 4);
+        assert!(
+            FrameRange {
+                start: 1.0,
+                end: 0.0,
+                fps: 24
+            }
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_rgb_to_yuv_limited_range() {
+        assert_eq!(rgb_to_yuv([0, 0, 0]), [16, 128, 128]);
+        assert_eq!(rgb_to_yuv([255, 255, 255]), [235, 128, 128]);
+    }
+
+    #[test]
+    fn test_sequence_writes_numbered_frames_and_video() {
+        let dir = std::env::temp_dir().join(format!("xrays-sequence-{}", std::process::id()));
+        let mut writer = SequenceWriter::new(&dir, ImageFormat::Png, Some(VideoFormat::Y4m), 24).unwrap();
+        writer.write_frame(&passes(0.0)).unwrap();
+        writer.write_frame(&passes(1.0)).unwrap();
+        assert_eq!(writer.frames(), 2);
+
+        let video = writer.finish().unwrap().unwrap();
+        assert!(dir.join("beauty_0000.png").exists());
+        assert_eq!(
+            image::open(dir.join("beauty_0001.png"))
+                .unwrap()
+                .to_rgb8()
+                .get_pixel(1, 0)
+                .0,
+            [255; 3]
+        );
+
+        let header = "YUV4MPEG2 W2 H1 F24:1 Ip A1:1 C444\n";
+        let frame = "FRAME\n".len() + 2 * 3;
+        let bytes = fs::read(&video).unwrap();
+        assert!(bytes.starts_with(header.as_bytes()));
+        assert_eq!(bytes.len(), header.len() + 2 * frame);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_video_rejects_frames_of_another_size() {
+        let dir = std::env::temp_dir().join(format!("xrays-sequence-size-{}", std::process::id()));
+        let mut writer = SequenceWriter::new(&dir, ImageFormat::Exr, Some(VideoFormat::Gif), 24).unwrap();
+        writer.write_frame(&passes(0.5)).unwrap();
+        assert!(dir.join("beauty_0000.exr").exists());
+
+        let larger = RenderedPasses {
+            size: RectSize { width: 1, height: 2 },
+            ..passes(0.5)
+        };
+        assert!(matches!(
+            writer.write_frame(&larger),
+            Err(SequenceError::FrameSize(1, 2, 2, 1))
+        ));
+        assert!(writer.finish().unwrap().is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
-use std::{fs, io};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
 
-use image::RgbaImage;
+use image::ImageReader;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -8,31 +10,29 @@ use crate::Color;
 
 pub type TextureId = usize;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Texture {
     dimensions: (u32, u32),
     data: Vec<[f32; 3]>,
 }
 
 impl Texture {
-    pub fn new_from_image(path: &str) -> Result<Self, TextureError> {
-        Self::new_from_scaled_image(path, 1.0)
+    pub fn new_from_image(path: impl AsRef<Path>) -> Result<Self, TextureError> {
+        Self::new_from_scaled_image(path, 1.0, ColorSpace::default())
     }
 
-    pub fn new_from_scaled_image(path: &str, scale: f32) -> Result<Self, TextureError> {
-        let file = fs::File::open(path)?;
-        let pixels: RgbaImage = image::load(io::BufReader::new(file), image::ImageFormat::Jpeg)?.into_rgba8();
-        let tex_scale = scale / 255_f32;
+    /// Decodes an image in any supported format, detected from the file contents. High bit depth and float images
+    /// keep their precision; values are converted from the color space to linear and multiplied by the scale.
+    pub fn new_from_scaled_image(
+        path: impl AsRef<Path>,
+        scale: f32,
+        color_space: ColorSpace,
+    ) -> Result<Self, TextureError> {
+        let pixels = ImageReader::open(path)?.with_guessed_format()?.decode()?.into_rgb32f();
         let dimensions = pixels.dimensions();
         let data = pixels
             .pixels()
-            .map(|p| -> [f32; 3] {
-                [
-                    tex_scale * (p[0] as f32),
-                    tex_scale * (p[1] as f32),
-                    tex_scale * (p[2] as f32),
-                ]
-            })
+            .map(|p| p.0.map(|channel| scale * color_space.to_linear(channel)))
             .collect();
 
         Ok(Self { dimensions, data })
@@ -52,6 +52,133 @@ impl Texture {
     pub fn dimensions(&self) -> (u32, u32) {
         self.dimensions
     }
+
+    /// Returns the texture halved in both dimensions, each texel is the average of up to four texels.
+    pub fn downsampled(&self) -> Self {
+        let (width, height) = self.dimensions;
+        let dimensions = ((width / 2).max(1), (height / 2).max(1));
+
+        let mut data = Vec::with_capacity((dimensions.0 * dimensions.1) as usize);
+        for y in 0..dimensions.1 {
+            for x in 0..dimensions.0 {
+                let (x0, y0) = ((2 * x).min(width - 1), (2 * y).min(height - 1));
+                let (x1, y1) = ((2 * x + 1).min(width - 1), (2 * y + 1).min(height - 1));
+
+                let mut texel = [0.0; 3];
+                for (tx, ty) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+                    let source = self.data[(ty * width + tx) as usize];
+                    for channel in 0..3 {
+                        texel[channel] += 0.25 * source[channel];
+                    }
+                }
+                data.push(texel);
+            }
+        }
+
+        Self { dimensions, data }
+    }
+
+    /// Returns the texture followed by its halved copies down to a single texel.
+    pub fn mip_chain(self: Arc<Self>) -> Vec<Arc<Self>> {
+        let mut levels = vec![self];
+        while let Some(last) = levels.last()
+            && last.dimensions != (1, 1)
+        {
+            levels.push(Arc::new(last.downsampled()));
+        }
+        levels
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureFilter {
+    #[default]
+    Nearest,
+    Bilinear,
+}
+
+impl TextureFilter {
+    pub const ALL: [Self; 2] = [Self::Nearest, Self::Bilinear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nearest => "Nearest",
+            Self::Bilinear => "Bilinear",
+        }
+    }
+}
+
+/// How texture coordinates outside of `0..=1` are mapped onto the texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureWrap {
+    Repeat,
+    #[default]
+    Clamp,
+    Mirror,
+}
+
+impl TextureWrap {
+    pub const ALL: [Self; 3] = [Self::Repeat, Self::Clamp, Self::Mirror];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Repeat => "Repeat",
+            Self::Clamp => "Clamp",
+            Self::Mirror => "Mirror",
+        }
+    }
+}
+
+/// Describes how the renderer samples a texture. Texture coordinates are multiplied by `uv_scale` and then shifted by
+/// `uv_offset` before the lookup.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextureSampler {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    pub uv_scale: [f32; 2],
+    pub uv_offset: [f32; 2],
+    /// Mip levels are selected by the width of the ray cone at the hit point.
+    pub mipmaps: bool,
+}
+
+impl Default for TextureSampler {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::default(),
+            wrap: TextureWrap::default(),
+            uv_scale: [1.0, 1.0],
+            uv_offset: [0.0, 0.0],
+            mipmaps: false,
+        }
+    }
+}
+
+/// Color space the values of an image file are stored in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ColorSpace {
+    Srgb,
+    /// Values are used as they are, like they have always been before color spaces were supported.
+    #[default]
+    Linear,
+}
+
+impl ColorSpace {
+    pub const ALL: [Self; 2] = [Self::Srgb, Self::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Srgb => "sRGB",
+            Self::Linear => "Linear",
+        }
+    }
+
+    pub fn to_linear(self, value: f32) -> f32 {
+        match self {
+            Self::Srgb if value <= 0.04045 => value / 12.92,
+            Self::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Self::Linear => value,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -61,3 +188,38 @@ pub enum TextureError {
     #[error(transparent)]
     ImageLoadError(#[from] image::ImageError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mip_chain_ends_with_single_texel() {
+        let texture = Arc::new(Texture {
+            dimensions: (5, 2),
+            data: vec![[1.0, 0.0, 0.5]; 10],
+        });
+
+        let levels = texture.mip_chain();
+        let dimensions = levels.iter().map(|level| level.dimensions()).collect::<Vec<_>>();
+        assert_eq!(dimensions, vec![(5, 2), (2, 1), (1, 1)]);
+        assert_eq!(levels[2].as_slice(), &[[1.0, 0.0, 0.5]]);
+    }
+
+    #[test]
+    fn test_downsampled_averages_texels() {
+        let texture = Texture {
+            dimensions: (2, 2),
+            data: vec![[0.0; 3], [1.0; 3], [2.0; 3], [3.0; 3]],
+        };
+        assert_eq!(texture.downsampled().as_slice(), &[[1.5; 3]]);
+    }
+
+    #[test]
+    fn test_srgb_to_linear() {
+        assert_eq!(ColorSpace::Srgb.to_linear(0.0), 0.0);
+        assert!((ColorSpace::Srgb.to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((ColorSpace::Srgb.to_linear(0.5) - 0.214).abs() < 1e-3);
+        assert_eq!(ColorSpace::Linear.to_linear(0.5), 0.5);
+    }
+}
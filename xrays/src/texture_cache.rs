@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use std::{fs, io, thread};
+
+use crate::texture::{ColorSpace, Texture, TextureError};
+
+/// How often the watcher checks the modification times of the loaded files.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Textures are cached per canonical file path, scale and color space, the same file can be loaded differently.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureKey {
+    pub path: PathBuf,
+    scale_bits: u32,
+    pub color_space: ColorSpace,
+}
+
+impl TextureKey {
+    pub fn new(path: impl AsRef<Path>, scale: f32, color_space: ColorSpace) -> io::Result<Self> {
+        Ok(Self {
+            path: fs::canonicalize(path)?,
+            scale_bits: scale.to_bits(),
+            color_space,
+        })
+    }
+
+    pub fn scale(&self) -> f32 {
+        f32::from_bits(self.scale_bits)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum CachedTexture {
+    /// The file is being decoded for the first time.
+    Loading,
+    Ready(Arc<Texture>),
+    Failed(Arc<TextureError>),
+}
+
+/// Decodes image files on background threads and reloads them when they change on disk.
+/// [`TextureCache::poll`] has to be called regularly to pick up the results.
+pub struct TextureCache {
+    entries: HashMap<TextureKey, CachedTexture>,
+    /// Number of decodes which have not finished yet.
+    pending: usize,
+    sender: Sender<(TextureKey, Result<Texture, TextureError>)>,
+    receiver: Receiver<(TextureKey, Result<Texture, TextureError>)>,
+    watcher: FileWatcher,
+}
+
+impl Default for TextureCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            entries: HashMap::new(),
+            pending: 0,
+            sender,
+            receiver,
+            watcher: FileWatcher::spawn(WATCH_INTERVAL),
+        }
+    }
+
+    /// Returns the cached texture, starting to decode it if the file has not been requested before.
+    pub fn get(&mut self, path: impl AsRef<Path>, scale: f32, color_space: ColorSpace) -> CachedTexture {
+        let key = match TextureKey::new(path, scale, color_space) {
+            Ok(key) => key,
+            Err(err) => return CachedTexture::Failed(Arc::new(err.into())),
+        };
+
+        if let Some(texture) = self.entries.get(&key) {
+            return texture.clone();
+        }
+
+        self.watcher.watch(&key.path);
+        self.decode(key.clone());
+        self.entries.insert(key, CachedTexture::Loading);
+        CachedTexture::Loading
+    }
+
+    /// Returns the cached texture without starting to decode it.
+    pub fn peek(&self, path: impl AsRef<Path>, scale: f32, color_space: ColorSpace) -> Option<CachedTexture> {
+        match TextureKey::new(path, scale, color_space) {
+            Ok(key) => self.entries.get(&key).cloned(),
+            Err(err) => Some(CachedTexture::Failed(Arc::new(err.into()))),
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.pending > 0
+    }
+
+    /// Stores finished decodes and starts reloading the files changed on disk.
+    /// Returns the keys whose textures have been replaced since the previous call.
+    pub fn poll(&mut self) -> Vec<TextureKey> {
+        for path in self.watcher.changed_paths() {
+            let keys = self
+                .entries
+                .keys()
+                .filter(|key| key.path == path)
+                .cloned()
+                .collect::<Vec<_>>();
+            for key in keys {
+                self.decode(key);
+            }
+        }
+
+        let mut updated = Vec::new();
+        for (key, result) in self.receiver.try_iter() {
+            self.pending -= 1;
+            let texture = match result {
+                Ok(texture) => CachedTexture::Ready(Arc::new(texture)),
+                Err(err) => CachedTexture::Failed(Arc::new(err)),
+            };
+            self.entries.insert(key.clone(), texture);
+            updated.push(key);
+        }
+
+        updated
+    }
+
+    fn decode(&mut self, key: TextureKey) {
+        let sender = self.sender.clone();
+        self.pending += 1;
+
+        thread::spawn(move || {
+            let result = Texture::new_from_scaled_image(&key.path, key.scale(), key.color_space);
+            // The cache may have been dropped in the meantime.
+            let _ = sender.send((key, result));
+        });
+    }
+}
+
+/// Polls modification times of the watched files on a background thread.
+struct FileWatcher {
+    files: Arc<Mutex<HashMap<PathBuf, Option<SystemTime>>>>,
+    changes: Receiver<PathBuf>,
+}
+
+impl FileWatcher {
+    fn spawn(interval: Duration) -> Self {
+        let files = Arc::new(Mutex::new(HashMap::<PathBuf, Option<SystemTime>>::new()));
+        let (sender, changes) = mpsc::channel();
+
+        let watched_files = Arc::downgrade(&files);
+        thread::spawn(move || {
+            // The thread stops together with the watcher.
+            while let Some(files) = watched_files.upgrade() {
+                for (path, modified) in files.lock().expect("Watcher lock is poisoned").iter_mut() {
+                    let current = modified_time(path);
+                    if current != *modified {
+                        *modified = current;
+                        if sender.send(path.clone()).is_err() {
+                            return;
+                        }
+                    }
+                }
+                drop(files);
+                thread::sleep(interval);
+            }
+        });
+
+        Self { files, changes }
+    }
+
+    fn watch(&self, path: &Path) {
+        self.files
+            .lock()
+            .expect("Watcher lock is poisoned")
+            .entry(path.to_path_buf())
+            .or_insert_with(|| modified_time(path));
+    }
+
+    fn changed_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.changes.try_iter().collect::<Vec<_>>();
+        paths.dedup();
+        paths
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn poll_until_updated(cache: &mut TextureCache) -> Vec<TextureKey> {
+        let start = Instant::now();
+        loop {
+            let updated = cache.poll();
+            if !updated.is_empty() || start.elapsed() > Duration::from_secs(10) {
+                return updated;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_missing_file_fails_without_panic() {
+        let mut cache = TextureCache::new();
+        assert!(matches!(
+            cache.get("definitely/missing/texture.jpeg", 1.0, ColorSpace::Linear),
+            CachedTexture::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_texture_is_decoded_in_background_and_cached() {
+        let dir = std::env::temp_dir().join(format!("xrays-texture-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("red.jpeg");
+        RgbImage::from_pixel(2, 2, Rgb([255, 0, 0])).save(&path).unwrap();
+
+        let mut cache = TextureCache::new();
+        assert!(matches!(
+            cache.get(&path, 1.0, ColorSpace::Linear),
+            CachedTexture::Loading
+        ));
+        assert!(cache.is_loading());
+
+        let updated = poll_until_updated(&mut cache);
+        assert_eq!(updated, vec![TextureKey::new(&path, 1.0, ColorSpace::Linear).unwrap()]);
+        assert!(!cache.is_loading());
+
+        match cache.get(&path, 1.0, ColorSpace::Linear) {
+            CachedTexture::Ready(texture) => assert_eq!(texture.dimensions(), (2, 2)),
+            other => panic!("Texture must be ready, got {other:?}"),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
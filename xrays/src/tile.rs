@@ -0,0 +1,324 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use reactor_types::rect::RectSize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::aov::{ExportError, RenderPass, RenderedPasses};
+use crate::denoise::{DenoiseInput, denoise};
+use crate::{RenderParams, RenderParamsValidationError};
+
+/// A rectangle of pixels, `(x, y)` is its top left corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    pub fn from_size(size: RectSize<u32>) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    pub fn size(&self) -> RectSize<u32> {
+        RectSize {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Number of pixels of the rectangle.
+    pub fn area(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+
+    /// Returns the part of the rectangle which lies inside an image of the given size.
+    pub fn clamp_to(&self, size: RectSize<u32>) -> Self {
+        let x = self.x.min(size.width);
+        let y = self.y.min(size.height);
+        Self {
+            x,
+            y,
+            width: self.width.min(size.width - x),
+            height: self.height.min(size.height - y),
+        }
+    }
+}
+
+/// Splits an image into tiles which fit into the image buffer of the renderer.
+/// Tiles go row by row from the top left corner; the tiles of the last column and row can be smaller.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileGrid {
+    pub image_size: RectSize<u32>,
+    pub tile_size: RectSize<u32>,
+}
+
+impl TileGrid {
+    pub fn columns(&self) -> u32 {
+        self.image_size.width.div_ceil(self.tile_size.width)
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.image_size.height.div_ceil(self.tile_size.height)
+    }
+
+    pub fn len(&self) -> usize {
+        (self.columns() * self.rows()) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = PixelRect> + '_ {
+        (0..self.rows()).flat_map(move |row| {
+            (0..self.columns()).map(move |column| {
+                PixelRect {
+                    x: column * self.tile_size.width,
+                    y: row * self.tile_size.height,
+                    width: self.tile_size.width,
+                    height: self.tile_size.height,
+                }
+                .clamp_to(self.image_size)
+            })
+        })
+    }
+}
+
+/// Mirrors the `Tile` shader struct.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuTile {
+    pub offset: [u32; 2],
+    pub image_size: [u32; 2],
+    pub region_min: [u32; 2],
+    pub region_max: [u32; 2],
+}
+
+impl GpuTile {
+    /// The image buffer holds the whole image, only the region is sampled.
+    pub fn new_region(image_size: RectSize<u32>, region: PixelRect) -> Self {
+        Self {
+            offset: [0, 0],
+            image_size: [image_size.width, image_size.height],
+            region_min: [region.x, region.y],
+            region_max: [region.x + region.width, region.y + region.height],
+        }
+    }
+
+    /// The image buffer holds a single tile of the image.
+    pub fn new_tile(image_size: RectSize<u32>, tile: PixelRect) -> Self {
+        Self {
+            offset: [tile.x, tile.y],
+            image_size: [image_size.width, image_size.height],
+            region_min: [0, 0],
+            region_max: [tile.width, tile.height],
+        }
+    }
+}
+
+/// Image of any size which is rendered tile by tile and stitched on the CPU, see [`crate::Renderer::start_tiled`].
+/// Every tile gets all of its samples before the next one starts.
+pub struct TiledRender {
+    pub(crate) render_params: RenderParams,
+    /// Params of the viewport render, which restarts once the image is done.
+    pub(crate) viewport_params: RenderParams,
+    pub(crate) tiles: Vec<PixelRect>,
+    pub(crate) next_tile: usize,
+    pub(crate) tile_started: bool,
+    pub(crate) image: RenderedPasses,
+    /// Sampling batches which are submitted but not done on the GPU yet.
+    batches_in_flight: Arc<AtomicU32>,
+}
+
+impl TiledRender {
+    pub(crate) fn new(render_params: RenderParams, viewport_params: RenderParams, tile_size: RectSize<u32>) -> Self {
+        let image_size = render_params.viewport_size;
+        let grid = TileGrid { image_size, tile_size };
+        Self {
+            render_params,
+            viewport_params,
+            tiles: grid.tiles().collect(),
+            next_tile: 0,
+            tile_started: false,
+            image: RenderedPasses::new(image_size),
+            batches_in_flight: Arc::default(),
+        }
+    }
+
+    pub(crate) fn batches_in_flight(&self) -> u32 {
+        self.batches_in_flight.load(Ordering::Acquire)
+    }
+
+    /// Counts the batch submitted last until the GPU is done with it, the device must be polled to notice that.
+    pub(crate) fn track_batch(&self, queue: &wgpu::Queue) {
+        self.batches_in_flight.fetch_add(1, Ordering::AcqRel);
+        let batches_in_flight = self.batches_in_flight.clone();
+        queue.on_submitted_work_done(move || {
+            batches_in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+
+    pub(crate) fn current_tile(&self) -> Option<PixelRect> {
+        self.tiles.get(self.next_tile).copied()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_tile >= self.tiles.len()
+    }
+
+    /// Share of the tiles which are rendered.
+    pub fn progress(&self) -> f32 {
+        self.next_tile as f32 / self.tiles.len().max(1) as f32
+    }
+
+    /// Returns the stitched image. The beauty pass is denoised as a whole if the denoiser is enabled, denoising the
+    /// tiles one by one would leave seams along their edges.
+    pub fn finish(mut self) -> RenderedPasses {
+        let image = &self.image;
+        let denoised = match (
+            &self.render_params.denoise,
+            image.get(RenderPass::Beauty),
+            image.get(RenderPass::Albedo),
+            image.get(RenderPass::Normal),
+        ) {
+            (Some(params), Some(color), Some(albedo), Some(normal)) => {
+                let input = DenoiseInput {
+                    size: image.size,
+                    color,
+                    albedo,
+                    normal,
+                };
+                Some(denoise(&input, params))
+            },
+            _ => None,
+        };
+
+        if let Some(denoised) = denoised
+            && let Some((_, beauty)) = self
+                .image
+                .passes
+                .iter_mut()
+                .find(|(pass, _)| *pass == RenderPass::Beauty)
+        {
+            *beauty = denoised;
+        }
+        self.image
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TiledRenderError {
+    #[error("tile of {0}x{1} pixels must not be empty and must fit into the image buffer")]
+    InvalidTileSize(u32, u32),
+    #[error(transparent)]
+    RenderParams(#[from] RenderParamsValidationError),
+    #[error(transparent)]
+    Export(#[from] ExportError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles_cover_image_once() {
+        let grid = TileGrid {
+            image_size: RectSize { width: 100, height: 70 },
+            tile_size: RectSize { width: 32, height: 32 },
+        };
+        assert_eq!(grid.columns(), 4);
+        assert_eq!(grid.rows(), 3);
+        assert_eq!(grid.len(), 12);
+
+        let mut coverage = vec![0; 100 * 70];
+        for tile in grid.tiles() {
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    coverage[(y * 100 + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(coverage.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_edge_tiles_are_clamped() {
+        let grid = TileGrid {
+            image_size: RectSize { width: 100, height: 70 },
+            tile_size: RectSize { width: 32, height: 32 },
+        };
+        let last = grid.tiles().last().unwrap();
+        assert_eq!(last, PixelRect {
+            x: 96,
+            y: 64,
+            width: 4,
+            height: 6,
+        });
+    }
+
+    #[test]
+    fn test_tiled_render_denoises_the_stitched_image() {
+        let size = RectSize { width: 4, height: 2 };
+        let render_params = RenderParams {
+            camera: Default::default(),
+            viewport_size: size,
+            sky: Default::default(),
+            sampling: Default::default(),
+            denoise: Some(Default::default()),
+            aovs: false,
+            display_pass: RenderPass::Beauty,
+            region: None,
+        };
+        let mut tiled = TiledRender::new(render_params, render_params, RectSize { width: 2, height: 2 });
+        assert_eq!(tiled.tiles.len(), 2);
+
+        // The tiles are flat but differ, a denoiser which saw them one by one would keep them flat.
+        for (idx, tile) in tiled.tiles.clone().into_iter().enumerate() {
+            let value = idx as f32;
+            let tile_passes = RenderedPasses {
+                size: tile.size(),
+                passes: vec![
+                    (RenderPass::Beauty, vec![[value; 3]; 4]),
+                    (RenderPass::Normal, vec![[0.0, 0.0, 1.0]; 4]),
+                    (RenderPass::Albedo, vec![[1.0; 3]; 4]),
+                ],
+            };
+            tiled.image.insert_tile(tile, &tile_passes);
+            tiled.next_tile += 1;
+        }
+        assert!(tiled.is_done());
+        assert_eq!(tiled.progress(), 1.0);
+
+        let image = tiled.finish();
+        let beauty = image.get(RenderPass::Beauty).unwrap();
+        assert!(beauty[1][0] > 0.0);
+        assert!(beauty[2][0] < 1.0);
+    }
+
+    #[test]
+    fn test_clamp_region_outside_image() {
+        let size = RectSize { width: 10, height: 10 };
+        let region = PixelRect {
+            x: 8,
+            y: 12,
+            width: 5,
+            height: 5,
+        };
+        let clamped = region.clamp_to(size);
+        assert_eq!(clamped.width, 2);
+        assert!(clamped.is_empty());
+    }
+}